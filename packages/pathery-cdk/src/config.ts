@@ -14,6 +14,14 @@ export interface FieldConfig<K, Flags> {
    * `text` - Indexes field values as `string`.
    *
    * `date` - Indexes field values as ints but serialized as ISO 80601 strings in transit.
+   *
+   * `i64`, `u64` and `f64` - Indexes field values as numbers, also accepted as numeric strings.
+   *
+   * `bool` - Indexes `true` and `false` field values.
+   *
+   * `bytes` - Indexes base64 encoded field values as bytes.
+   *
+   * `ip` - Indexes IPv4 and IPv6 address field values.
    */
   kind: K;
 
@@ -27,24 +35,121 @@ export interface FieldConfig<K, Flags> {
    *
    * `STRING`  - (only for `text`) Marks this field for exact-string indexing.
    *
-   * `INDEXED` - (only for `date`) Marks this field for ordered search indexing.
+   * `POSITIONS` - (only for `text`) Indexes token positions, needed by phrase queries. `TEXT`
+   * fields have positions already.
+   *
+   * `INDEXED` - (not for `text`) Marks this field for ordered search indexing.
+   *
+   * `FAST`    - Stores values in a fast field. Matches can be sorted by fast `date`, `i64`,
+   * `u64`, `f64` and `bool` fields.
+   *
+   * `STORED`  - (not for `text`) Stores values in the index alongside their document.
    */
   flags: Flags[];
+
+  /**
+   * Whether documents may have several values of the field, given as an array.
+   *
+   * Defaults to `false`, in which case arrays are rejected. `bytes` and `ip` fields cannot be
+   * both multi-valued and `FAST`.
+   */
+  multi?: boolean;
 }
 
-export type TextFieldConfig = FieldConfig<"text", "STRING" | "TEXT" | "FAST">;
+export type TextFieldConfig = FieldConfig<
+  "text",
+  "STRING" | "TEXT" | "FAST" | "POSITIONS"
+>;
+
+export type NumericFieldFlag = "INDEXED" | "FAST" | "STORED";
+
+export type DateFieldConfig = FieldConfig<"date", NumericFieldFlag>;
+
+export type IntegerFieldConfig = FieldConfig<"i64", NumericFieldFlag>;
 
-export type DateFieldConfig = FieldConfig<"date", "INDEXED" | "FAST">;
+export type UnsignedFieldConfig = FieldConfig<"u64", NumericFieldFlag>;
 
-export type IntegerFieldConfig = FieldConfig<"i64", "INDEXED" | "FAST">;
+export type FloatFieldConfig = FieldConfig<"f64", NumericFieldFlag>;
+
+export type BoolFieldConfig = FieldConfig<"bool", NumericFieldFlag>;
+
+export type BytesFieldConfig = FieldConfig<"bytes", NumericFieldFlag>;
+
+export type IpFieldConfig = FieldConfig<"ip", NumericFieldFlag>;
 
 export type JsonFieldConfig = FieldConfig<"json", "TEXT">;
 
+export interface VectorFieldConfig {
+  /**
+   * The name of the field to index.
+   */
+  name: string;
+
+  /**
+   * Dense vectors of numbers, searched with `knn` queries.
+   */
+  kind: "vector";
+
+  /**
+   * The number of values of each vector, from 1 to 4096.
+   */
+  dimensions: number;
+
+  /**
+   * How vectors are compared.
+   *
+   * `cosine` - The cosine of the angle between vectors.
+   *
+   * `dot_product` - The dot product of vectors, for vectors of the same length.
+   *
+   * `euclidean` - The euclidean distance between vectors, scored as `1 / (1 + d²)`.
+   */
+  metric: "cosine" | "dot_product" | "euclidean";
+}
+
+export interface GeoPointFieldConfig {
+  /**
+   * The name of the field to index.
+   */
+  name: string;
+
+  /**
+   * Points of a `lat` and a `lon`, filtered and sorted by distance.
+   */
+  kind: "geo_point";
+}
+
+export interface ObjectFieldConfig {
+  /**
+   * The name of the field to index. Must not contain `.`.
+   */
+  name: string;
+
+  /**
+   * Objects of typed sub-fields, indexed as fields named by their dotted path, e.g. `props.price`.
+   */
+  kind: "object";
+
+  /**
+   * The sub-fields of the object, of any kind but `json`, `vector` and `geo_point`. Their names
+   * must not contain `.`.
+   */
+  fields: IndexFieldConfig[];
+}
+
 export type IndexFieldConfig =
   | TextFieldConfig
   | DateFieldConfig
   | IntegerFieldConfig
-  | JsonFieldConfig;
+  | UnsignedFieldConfig
+  | FloatFieldConfig
+  | BoolFieldConfig
+  | BytesFieldConfig
+  | IpFieldConfig
+  | JsonFieldConfig
+  | VectorFieldConfig
+  | GeoPointFieldConfig
+  | ObjectFieldConfig;
 
 export interface IndexConfig {
   /**
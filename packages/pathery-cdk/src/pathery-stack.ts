@@ -56,6 +56,15 @@ export interface PatheryStackProps extends StackProps {
     timeout?: Duration;
   };
 
+  /**
+   * Require tenant API keys, created with the `api-key` binary, in the
+   * `Authorization` header of API requests. Each key can only access the
+   * indexes of its tenant.
+   *
+   * @default false
+   */
+  tenantApiKeys?: boolean;
+
   /**
    * QueryHandler configuration overrides.
    */
@@ -78,6 +87,8 @@ export class PatheryStack extends Stack {
 
   private indexWriterQueue: IQueue;
 
+  private indexWriterDeadLetterQueue: IQueue;
+
   private deleteQueue: IQueue;
 
   constructor(scope: Construct, id: string, props: PatheryStackProps) {
@@ -101,9 +112,23 @@ export class PatheryStack extends Stack {
       visibilityTimeout: Duration.minutes(2),
     });
 
+    this.indexWriterDeadLetterQueue = new Queue(
+      this,
+      "IndexWriterDeadLetterQueue",
+      {
+        fifo: true,
+        contentBasedDeduplication: true,
+        retentionPeriod: Duration.days(14),
+      }
+    );
+
     this.indexWriterQueue = new Queue(this, "IndexWriterQueue", {
       fifo: true,
       contentBasedDeduplication: true,
+      deadLetterQueue: {
+        queue: this.indexWriterDeadLetterQueue,
+        maxReceiveCount: 3,
+      },
     });
 
     const vpc = new Vpc(this, "Vpc", {
@@ -190,30 +215,37 @@ export class PatheryStack extends Stack {
       this.deleteQueue.queueUrl
     );
 
-    const queryIndex = new RustFunction(this, "query-index", {
-      memorySize: props.queryHandler?.memorySize ?? 3008,
-      timeout: Duration.seconds(5),
-      vpc,
-      vpcSubnets: {
-        subnets: vpc.isolatedSubnets,
-      },
-      filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
-        accessPoint,
-        "/mnt/pathery-data"
-      ),
-    });
-    queryIndex.addLayers(configLayer);
-    this.table.grantReadData(queryIndex);
-    queryIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
-    queryIndex.addEnvironment(
-      "ASYNC_DELETE_QUEUE_URL",
-      this.deleteQueue.queueUrl
-    );
-    queryIndexPartition.grantInvoke(queryIndex);
-    queryIndex.addEnvironment(
-      "QUERY_INDEX_PARTITION_NAME",
-      queryIndexPartition.functionName
-    );
+    const queryCoordinator = (name: string) => {
+      const lambda = new RustFunction(this, name, {
+        memorySize: props.queryHandler?.memorySize ?? 3008,
+        timeout: Duration.seconds(5),
+        vpc,
+        vpcSubnets: {
+          subnets: vpc.isolatedSubnets,
+        },
+        filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+          accessPoint,
+          "/mnt/pathery-data"
+        ),
+      });
+      lambda.addLayers(configLayer);
+      // Queries take from the request rate limits, which are stored in the table.
+      this.table.grantReadWriteData(lambda);
+      lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+      lambda.addEnvironment(
+        "ASYNC_DELETE_QUEUE_URL",
+        this.deleteQueue.queueUrl
+      );
+      queryIndexPartition.grantInvoke(lambda);
+      lambda.addEnvironment(
+        "QUERY_INDEX_PARTITION_NAME",
+        queryIndexPartition.functionName
+      );
+      return lambda;
+    };
+
+    const queryIndex = queryCoordinator("query-index");
+    const similarDocs = queryCoordinator("similar-docs");
 
     const statsIndex = new RustFunction(this, "stats-index", {
       vpc,
@@ -226,6 +258,8 @@ export class PatheryStack extends Stack {
       ),
     });
     statsIndex.addLayers(configLayer);
+    this.table.grantReadData(statsIndex);
+    statsIndex.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
     // FIXME: This doesn't actually get used but is required to be
     //        set because of some tangled internal dependencies.
     statsIndex.addEnvironment(
@@ -233,10 +267,89 @@ export class PatheryStack extends Stack {
       this.deleteQueue.queueUrl
     );
 
+    const indexManager = (name: string) => {
+      const lambda = new RustFunction(this, name, {
+        vpc,
+        vpcSubnets: {
+          subnets: vpc.isolatedSubnets,
+        },
+        filesystem: aws_lambda.FileSystem.fromEfsAccessPoint(
+          accessPoint,
+          "/mnt/pathery-data"
+        ),
+      });
+      lambda.addLayers(configLayer);
+      this.table.grantReadData(lambda);
+      lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+      this.deleteQueue.grantSendMessages(lambda);
+      lambda.addEnvironment(
+        "ASYNC_DELETE_QUEUE_URL",
+        this.deleteQueue.queueUrl
+      );
+      return lambda;
+    };
+
+    // Creating, deleting and reindexing an index updates its directory in the table.
+    const listIndexes = indexManager("list-indexes");
+    const createIndex = indexManager("create-index");
+    this.table.grantReadWriteData(createIndex);
+    const describeIndex = indexManager("describe-index");
+    const deleteIndex = indexManager("delete-index");
+    this.table.grantReadWriteData(deleteIndex);
+    const reindexIndex = indexManager("reindex-index");
+    this.table.grantReadWriteData(reindexIndex);
+    this.indexWriterProducer(reindexIndex);
+
+    const putSchema = new RustFunction(this, "put-schema");
+    putSchema.addLayers(configLayer);
+    this.table.grantReadWriteData(putSchema);
+    putSchema.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+
+    const getSchema = new RustFunction(this, "get-schema");
+    getSchema.addLayers(configLayer);
+    this.table.grantReadData(getSchema);
+    getSchema.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+
+    const aliasManager = (name: string) => {
+      const lambda = new RustFunction(this, name);
+      lambda.addLayers(configLayer);
+      this.table.grantReadWriteData(lambda);
+      lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
+      return lambda;
+    };
+
+    const putAlias = aliasManager("put-alias");
+    const getAlias = aliasManager("get-alias");
+    const deleteAlias = aliasManager("delete-alias");
+
     const deleteDoc = new RustFunction(this, "delete-doc");
     deleteDoc.addLayers(configLayer);
     this.indexWriterProducer(deleteDoc);
 
+    if (props.tenantApiKeys) {
+      const apiFunctions = [
+        postIndex,
+        batchIndex,
+        queryIndex,
+        statsIndex,
+        listIndexes,
+        createIndex,
+        describeIndex,
+        deleteIndex,
+        reindexIndex,
+        putSchema,
+        getSchema,
+        putAlias,
+        getAlias,
+        deleteAlias,
+        deleteDoc,
+        similarDocs,
+      ];
+      for (const lambda of apiFunctions) {
+        lambda.addEnvironment("PATHERY_AUTH", "api_key");
+      }
+    }
+
     const api = new RestApi(this, "PatheryApi", {
       restApiName: id,
       endpointConfiguration: {
@@ -264,16 +377,44 @@ export class PatheryStack extends Stack {
 
     this.apiKey = apiKey;
 
+    const aliasRoute = api.root.addResource("alias");
+
+    const aliasSingleRoute = aliasRoute.addResource("{alias}");
+
+    aliasSingleRoute.addMethod("PUT", new LambdaIntegration(putAlias));
+
+    aliasSingleRoute.addMethod("GET", new LambdaIntegration(getAlias));
+
+    aliasSingleRoute.addMethod("DELETE", new LambdaIntegration(deleteAlias));
+
     const indexRoute = api.root.addResource("index");
 
+    indexRoute.addMethod("GET", new LambdaIntegration(listIndexes));
+
     const indexSingleRoute = indexRoute.addResource("{index_id}");
 
+    indexSingleRoute.addMethod("PUT", new LambdaIntegration(createIndex));
+
+    indexSingleRoute.addMethod("GET", new LambdaIntegration(describeIndex));
+
+    indexSingleRoute.addMethod("DELETE", new LambdaIntegration(deleteIndex));
+
     indexSingleRoute.addMethod("POST", new LambdaIntegration(postIndex));
 
     const queryActionRoute = indexSingleRoute.addResource("query");
 
     queryActionRoute.addMethod("POST", new LambdaIntegration(queryIndex));
 
+    const reindexActionRoute = indexSingleRoute.addResource("reindex");
+
+    reindexActionRoute.addMethod("POST", new LambdaIntegration(reindexIndex));
+
+    const schemaActionRoute = indexSingleRoute.addResource("schema");
+
+    schemaActionRoute.addMethod("PUT", new LambdaIntegration(putSchema));
+
+    schemaActionRoute.addMethod("GET", new LambdaIntegration(getSchema));
+
     const statsActionRoute = indexSingleRoute.addResource("stats");
 
     statsActionRoute.addMethod("GET", new LambdaIntegration(statsIndex));
@@ -288,6 +429,10 @@ export class PatheryStack extends Stack {
 
     documentSingleRoute.addMethod("DELETE", new LambdaIntegration(deleteDoc));
 
+    const similarDocsRoute = documentSingleRoute.addResource("similar");
+
+    similarDocsRoute.addMethod("POST", new LambdaIntegration(similarDocs));
+
     const indexWriterWorker = new RustFunction(this, "index-writer-worker", {
       memorySize: props.indexWriter?.memorySize ?? 2048,
       timeout: props.indexWriter?.timeout ?? Duration.minutes(1),
@@ -304,6 +449,7 @@ export class PatheryStack extends Stack {
     indexWriterWorker.addEventSource(
       new SqsEventSource(this.indexWriterQueue, {
         batchSize: 10,
+        reportBatchItemFailures: true,
       })
     );
     this.table.grantReadWriteData(indexWriterWorker);
@@ -340,10 +486,15 @@ export class PatheryStack extends Stack {
     new CfnOutput(this, "ApiKeyOutput", {
       value: apiKey.keyId,
     });
+
+    new CfnOutput(this, "IndexWriterDeadLetterQueueUrl", {
+      value: this.indexWriterDeadLetterQueue.queueUrl,
+    });
   }
 
   private indexWriterProducer(lambda: Function) {
-    this.table.grantWriteData(lambda);
+    // Reads are needed to load schemas registered at runtime.
+    this.table.grantReadWriteData(lambda);
     lambda.addEnvironment("DATA_TABLE_NAME", this.table.tableName);
 
     this.indexWriterQueue.grantSendMessages(lambda);
@@ -0,0 +1,151 @@
+//! Compares the snippet generation of a query page with the highlighter against indexing every
+//! matched document in an in-RAM index, as queries previously did.
+//!
+//! ```bash
+//! cargo bench --bench snippets
+//! ```
+//!
+//! There is no benchmark harness among the dependencies, so each approach is timed over a fixed
+//! number of iterations and the mean time per page of matches is printed.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use pathery::highlight::{HighlightOptions, Highlighter};
+use tantivy::query::{Query, QueryParser};
+use tantivy::schema::{Field, Schema, TEXT};
+use tantivy::{doc, Document, Index, SnippetGenerator};
+
+/// Matches returned per query page.
+const MATCHES: usize = 10;
+
+const ITERATIONS: u32 = 200;
+
+/// Queries by name, the phrase query matching its first term often in every body.
+const QUERIES: [(&str, &str); 2] = [
+    ("terms", "art motorcycle quality"),
+    ("phrase", "\"art caring quality\"~2"),
+];
+
+const WORDS: [&str; 16] = [
+    "zen",
+    "and",
+    "the",
+    "art",
+    "of",
+    "motorcycle",
+    "maintenance",
+    "road",
+    "trip",
+    "across",
+    "country",
+    "caring",
+    "about",
+    "quality",
+    "work",
+    "philosophy",
+];
+
+struct Fixture {
+    index: Index,
+    fields: Vec<Field>,
+    query: Box<dyn Query>,
+    documents: Vec<Document>,
+}
+
+fn fixture(query: &str) -> Fixture {
+    let mut schema = Schema::builder();
+    let title = schema.add_text_field("title", TEXT);
+    let body = schema.add_text_field("body", TEXT);
+    let index = Index::create_in_ram(schema.build());
+
+    let documents = (0..MATCHES)
+        .map(|n| {
+            let text: Vec<&str> = (0..400).map(|i| WORDS[(i * 7 + n) % WORDS.len()]).collect();
+
+            doc!(
+                title => format!("Zen and the art of motorcycle maintenance {n}"),
+                body => text.join(" "),
+            )
+        })
+        .collect();
+
+    let fields = vec![title, body];
+    let query = QueryParser::for_index(&index, fields.clone())
+        .parse_query(query)
+        .unwrap();
+
+    Fixture {
+        index,
+        fields,
+        query,
+        documents,
+    }
+}
+
+/// Snippets as generated before the highlighter, with a commit and reload per match.
+fn in_ram_index(fixture: &Fixture) {
+    let index = Index::create_in_ram(fixture.index.schema());
+    let mut writer = index.writer_with_num_threads(1, 10_000_000).unwrap();
+    let reader = index.reader().unwrap();
+
+    for document in &fixture.documents {
+        writer.add_document(document.clone()).unwrap();
+        writer.commit().unwrap();
+        reader.reload().unwrap();
+        let searcher = reader.searcher();
+
+        for field in &fixture.fields {
+            let generator = SnippetGenerator::create(&searcher, &fixture.query, *field).unwrap();
+
+            for text in document.get_all(*field).filter_map(|value| value.as_text()) {
+                black_box(generator.snippet(text).to_html());
+            }
+        }
+    }
+}
+
+fn highlighter(fixture: &Fixture) {
+    let highlighter = Highlighter::create(
+        &fixture.index,
+        fixture.query.as_ref(),
+        &HighlightOptions::default(),
+    )
+    .unwrap();
+
+    for document in &fixture.documents {
+        black_box(highlighter.highlight(document));
+    }
+}
+
+fn bench(name: &str, fixture: &Fixture, run: fn(&Fixture)) -> Duration {
+    // Warms up allocations and caches before timing.
+    run(fixture);
+
+    let start = Instant::now();
+
+    for _ in 0..ITERATIONS {
+        run(fixture);
+    }
+
+    let mean = start.elapsed() / ITERATIONS;
+    println!("{name:<16} {mean:>12.2?} per page of {MATCHES} matches");
+
+    mean
+}
+
+fn main() {
+    for (name, query) in QUERIES {
+        println!("{name} query: {query}");
+
+        let fixture = fixture(query);
+
+        let before = bench("in_ram_index", &fixture, in_ram_index);
+        let after = bench("highlighter", &fixture, highlighter);
+
+        println!(
+            "highlighter is {:.1}x faster",
+            before.as_secs_f64() / after.as_secs_f64()
+        );
+    }
+}
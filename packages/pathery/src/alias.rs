@@ -0,0 +1,399 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, ConfigError};
+use crate::index::validate_index_id;
+use crate::service::ServiceError;
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::store::record::{RecordCache, RecordStore};
+use crate::util;
+
+/// How long a loaded alias is used before it is read from the store again.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// A name that resolves to one or more indexes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexAlias {
+    pub alias: String,
+
+    /// Indexes searched by queries of the alias.
+    pub indexes: Vec<String>,
+
+    /// Index that documents written to the alias are indexed in, if writes are allowed.
+    pub write_index: Option<String>,
+
+    pub updated_at: String,
+}
+
+/// The directory that an index id resolves to.
+///
+/// Index ids are themselves aliases of the directory that holds their index, which reindexing
+/// flips to the rebuilt directory.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexDirectory {
+    pub index_id: String,
+
+    /// Directory of the index, relative to the data directory.
+    pub directory: String,
+
+    pub updated_at: String,
+}
+
+fn alias_key(alias: &str) -> String {
+    format!("alias|{alias}")
+}
+
+fn directory_key(index_id: &str) -> String {
+    format!("directory|{index_id}")
+}
+
+/// Stores index aliases, and the directories that index ids resolve to, in the document store.
+///
+/// Loaded aliases are cached for a short time, so updates can take up to 30 seconds to be seen by
+/// other processes. Directories are not cached, so that writers switch to a rebuilt directory as
+/// soon as it is flipped.
+pub struct AliasRegistry {
+    records: RecordStore,
+
+    cache: RecordCache<IndexAlias>,
+}
+
+impl AliasRegistry {
+    pub async fn create(config: &Config) -> Result<AliasRegistry, ConfigError> {
+        let document_store = DDBDocumentStore::create(config).await?;
+
+        Ok(AliasRegistry::new(Arc::new(document_store)))
+    }
+
+    pub fn new(document_store: Arc<dyn DocumentStore>) -> AliasRegistry {
+        AliasRegistry {
+            records: RecordStore::new(document_store),
+            cache: RecordCache::new(CACHE_TTL),
+        }
+    }
+
+    pub async fn get(&self, alias: &str) -> Result<Option<IndexAlias>, ServiceError> {
+        if let Some(index_alias) = self.cache.get(alias) {
+            return Ok(index_alias);
+        }
+
+        let index_alias: Option<IndexAlias> = self.records.get(&alias_key(alias)).await?;
+
+        self.cache.insert(alias, index_alias.clone());
+
+        Ok(index_alias)
+    }
+
+    /// Points an alias at the indexes, replacing any previous indexes of the alias in a single
+    /// write.
+    ///
+    /// The write index defaults to the index of the alias when it has exactly one.
+    pub async fn put(
+        &self,
+        alias: &str,
+        indexes: Vec<String>,
+        write_index: Option<String>,
+    ) -> Result<IndexAlias, ServiceError> {
+        validate_index_id(alias)?;
+
+        if self.index_directory(alias).await?.is_some() {
+            return Err(ServiceError::conflict(&format!(
+                "Alias [{alias}] cannot use the id of an existing index"
+            )));
+        }
+
+        if indexes.is_empty() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Alias [{alias}] must have at least one index"
+            )));
+        }
+
+        let mut seen = HashSet::new();
+
+        for index_id in &indexes {
+            validate_index_id(index_id)?;
+
+            if !seen.insert(index_id) {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Index [{index_id}] is listed more than once"
+                )));
+            }
+
+            // Aliases resolve in a single step, so they cannot point to other aliases.
+            if index_id == alias
+                || self
+                    .records
+                    .get::<IndexAlias>(&alias_key(index_id))
+                    .await?
+                    .is_some()
+            {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Alias [{alias}] cannot point to alias [{index_id}]"
+                )));
+            }
+        }
+
+        let write_index = match write_index {
+            Some(write_index) if !indexes.contains(&write_index) => {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Write index [{write_index}] is not an index of alias [{alias}]"
+                )));
+            }
+            Some(write_index) => Some(write_index),
+            None if indexes.len() == 1 => Some(indexes[0].clone()),
+            None => None,
+        };
+
+        let index_alias = IndexAlias {
+            alias: alias.to_string(),
+            indexes,
+            write_index,
+            updated_at: util::timestamp(),
+        };
+
+        self.records
+            .put(vec![(alias_key(alias), &index_alias)])
+            .await?;
+
+        self.cache.insert(alias, Some(index_alias.clone()));
+
+        Ok(index_alias)
+    }
+
+    /// Removes an alias, returning the removed alias.
+    pub async fn delete(&self, alias: &str) -> Result<IndexAlias, ServiceError> {
+        let index_alias: IndexAlias = self
+            .records
+            .get(&alias_key(alias))
+            .await?
+            .ok_or_else(|| ServiceError::not_found(&format!("Alias [{alias}] not found")))?;
+
+        self.records.delete(&[alias_key(alias)]).await?;
+
+        self.cache.insert(alias, None);
+
+        Ok(index_alias)
+    }
+
+    /// Returns the directory of an index, if the index has one.
+    pub async fn index_directory(&self, index_id: &str) -> Result<Option<String>, ServiceError> {
+        let index_directory: Option<IndexDirectory> =
+            self.records.get(&directory_key(index_id)).await?;
+
+        Ok(index_directory.map(|index_directory| index_directory.directory))
+    }
+
+    /// Points an index at a directory in a single write, failing if the index id is used by an
+    /// alias.
+    pub async fn put_index_directory(
+        &self,
+        index_id: &str,
+        directory: &str,
+    ) -> Result<(), ServiceError> {
+        if self.get(index_id).await?.is_some() {
+            return Err(ServiceError::conflict(&format!(
+                "Index [{index_id}] cannot use the name of an existing alias"
+            )));
+        }
+
+        let index_directory = IndexDirectory {
+            index_id: index_id.to_string(),
+            directory: directory.to_string(),
+            updated_at: util::timestamp(),
+        };
+
+        self.records
+            .put(vec![(directory_key(index_id), &index_directory)])
+            .await
+    }
+
+    pub async fn delete_index_directory(&self, index_id: &str) -> Result<(), ServiceError> {
+        self.records.delete(&[directory_key(index_id)]).await
+    }
+
+    /// Returns the index that writes to the name go to. Names that are not aliases are returned
+    /// unchanged.
+    pub async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError> {
+        match self.get(name).await? {
+            Some(index_alias) => index_alias.write_index.ok_or_else(|| {
+                ServiceError::invalid_request(&format!("Alias [{name}] has no write index"))
+            }),
+            None => Ok(name.to_string()),
+        }
+    }
+
+    /// Returns the indexes that reads of the name go to. Names that are not aliases resolve to
+    /// themselves.
+    pub async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError> {
+        match self.get(name).await? {
+            Some(index_alias) => Ok(index_alias.indexes),
+            None => Ok(vec![name.to_string()]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn aliases(ctx: &TestContext) -> AliasRegistry {
+        AliasRegistry::new(Arc::new(ctx.document_store().clone()))
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn resolve_aliases() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        aliases
+            .put("books", ids(&["books-v1"]), None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            "books-v1",
+            aliases.resolve_write_index("books").await.unwrap()
+        );
+        assert_eq!(
+            ids(&["books-v1"]),
+            aliases.resolve_read_indexes("books").await.unwrap()
+        );
+
+        // Names that are not aliases resolve to themselves.
+        assert_eq!(
+            "books-v1",
+            aliases.resolve_write_index("books-v1").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn swap_alias() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        aliases
+            .put("books", ids(&["books-v1"]), None)
+            .await
+            .unwrap();
+        aliases
+            .put(
+                "books",
+                ids(&["books-v1", "books-v2"]),
+                Some("books-v2".into()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            "books-v2",
+            aliases.resolve_write_index("books").await.unwrap()
+        );
+        assert_eq!(
+            ids(&["books-v1", "books-v2"]),
+            aliases.resolve_read_indexes("books").await.unwrap()
+        );
+
+        // Another registry reads the alias from the store.
+        let other = AliasRegistry::new(Arc::new(ctx.document_store().clone()));
+        assert_eq!(
+            "books-v2",
+            other.resolve_write_index("books").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn alias_without_write_index() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        let index_alias = aliases
+            .put("books", ids(&["books-a", "books-b"]), None)
+            .await
+            .unwrap();
+
+        assert_eq!(None, index_alias.write_index);
+        assert_eq!(
+            400,
+            aliases
+                .resolve_write_index("books")
+                .await
+                .unwrap_err()
+                .status()
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_aliases() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        aliases
+            .put("books", ids(&["books-v1"]), None)
+            .await
+            .unwrap();
+
+        let invalid = [
+            ("all", ids(&[]), None),
+            ("all", ids(&["books"]), None),
+            ("all", ids(&["books-v1", "books-v1"]), None),
+            ("all", ids(&["books-v1"]), Some("books-v2".to_string())),
+        ];
+
+        for (alias, indexes, write_index) in invalid {
+            let err = aliases.put(alias, indexes, write_index).await.unwrap_err();
+            assert_eq!(400, err.status());
+        }
+    }
+
+    #[tokio::test]
+    async fn aliases_and_indexes_cannot_share_names() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        aliases
+            .put_index_directory("books-v1", ".physical/books-v1-1")
+            .await
+            .unwrap();
+
+        let err = aliases
+            .put("books-v1", ids(&["books-v2"]), None)
+            .await
+            .unwrap_err();
+        assert_eq!(409, err.status());
+
+        aliases
+            .put("books", ids(&["books-v1"]), None)
+            .await
+            .unwrap();
+
+        let err = aliases
+            .put_index_directory("books", ".physical/books-1")
+            .await
+            .unwrap_err();
+        assert_eq!(409, err.status());
+    }
+
+    #[tokio::test]
+    async fn delete_alias() {
+        let ctx = setup();
+        let aliases = aliases(&ctx);
+
+        aliases
+            .put("books", ids(&["books-v1"]), None)
+            .await
+            .unwrap();
+
+        aliases.delete("books").await.unwrap();
+
+        assert_eq!("books", aliases.resolve_write_index("books").await.unwrap());
+        assert_eq!(404, aliases.delete("books").await.unwrap_err().status());
+    }
+}
@@ -0,0 +1,483 @@
+//! Tenant API keys that restrict which indexes a caller can read, write and manage.
+//!
+//! Authentication is enabled with the `auth` setting, see [`crate::config`]. Requests then need an
+//! `Authorization: Bearer <api key>` header. Each API key belongs to a tenant, which may only
+//! access indexes and aliases whose id starts with one of its index prefixes.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{AuthMode, Config, ConfigError};
+use crate::quota::Limits;
+use crate::service::ServiceError;
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::store::record::{RecordCache, RecordStore};
+use crate::util;
+
+/// How long a loaded API key is used before it is read from the store again.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Number of random bytes in a generated API key.
+const API_KEY_BYTES: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    /// Query and describe indexes.
+    Read,
+
+    /// Index and delete documents.
+    Write,
+
+    /// Create, delete and reindex indexes and manage schemas and aliases. Implies the other
+    /// permissions.
+    Admin,
+}
+
+impl Permission {
+    fn name(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub tenant_id: String,
+
+    /// Prefixes of the index ids and aliases the tenant can access. An empty prefix matches every
+    /// index.
+    pub index_prefixes: Vec<String>,
+
+    pub permissions: Vec<Permission>,
+
+    /// Limits shared by all API keys of the tenant. They are stored once per tenant rather than
+    /// with each key, see [`ApiKeyStore::set_limits`].
+    #[serde(skip)]
+    pub limits: Limits,
+}
+
+impl Tenant {
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission) || self.permissions.contains(&Permission::Admin)
+    }
+
+    pub fn can_access(&self, index_id: &str) -> bool {
+        self.index_prefixes
+            .iter()
+            .any(|prefix| index_id.starts_with(prefix.as_str()))
+    }
+
+    /// Checks that the tenant has the permission on every index.
+    pub fn authorize<'a, I>(
+        &self,
+        permission: Permission,
+        index_ids: I,
+    ) -> Result<(), ServiceError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        if !self.has_permission(permission) {
+            return Err(ServiceError::forbidden(&format!(
+                "API key does not have [{}] permission",
+                permission.name()
+            )));
+        }
+
+        for index_id in index_ids {
+            if !self.can_access(index_id) {
+                return Err(ServiceError::forbidden(&format!(
+                    "API key does not have access to index [{index_id}]"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An API key as stored. Only a hash of the key itself is kept.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    #[serde(flatten)]
+    pub tenant: Tenant,
+
+    pub created_at: String,
+}
+
+fn hash_api_key(api_key: &str) -> String {
+    hex::encode(digest::digest(&digest::SHA256, api_key.as_bytes()))
+}
+
+fn api_key_key(key_hash: &str) -> String {
+    format!("api_key|{key_hash}")
+}
+
+fn tenant_limits_key(tenant_id: &str) -> String {
+    format!("tenant_limits|{tenant_id}")
+}
+
+/// Limits of a tenant as stored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct TenantLimits {
+    limits: Limits,
+
+    updated_at: String,
+}
+
+/// Stores hashed API keys and the limits of their tenants in the document store.
+///
+/// Loaded keys and limits are cached for a short time, so revoked keys can keep working and
+/// changed limits take effect after up to 30 seconds in processes that used them recently.
+pub struct ApiKeyStore {
+    records: RecordStore,
+
+    cache: RecordCache<ApiKey>,
+
+    limits_cache: RecordCache<Limits>,
+}
+
+impl ApiKeyStore {
+    pub async fn create(config: &Config) -> Result<ApiKeyStore, ConfigError> {
+        let document_store = DDBDocumentStore::create(config).await?;
+
+        Ok(ApiKeyStore::new(Arc::new(document_store)))
+    }
+
+    pub fn new(document_store: Arc<dyn DocumentStore>) -> ApiKeyStore {
+        ApiKeyStore {
+            records: RecordStore::new(document_store),
+            cache: RecordCache::new(CACHE_TTL),
+            limits_cache: RecordCache::new(CACHE_TTL),
+        }
+    }
+
+    /// Generates a new API key for the tenant. The returned key cannot be recovered later, and the
+    /// limits of the tenant are not stored with it.
+    pub async fn create_key(&self, tenant: Tenant) -> Result<(String, ApiKey), ServiceError> {
+        let mut bytes = [0u8; API_KEY_BYTES];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .map_err(|_| ServiceError::internal_error(ApiKeyError::Random))?;
+
+        let api_key = hex::encode(bytes);
+        let key_hash = hash_api_key(&api_key);
+
+        let record = ApiKey {
+            tenant,
+            created_at: util::timestamp(),
+        };
+
+        self.records
+            .put(vec![(api_key_key(&key_hash), &record)])
+            .await?;
+
+        self.cache.insert(&key_hash, Some(record.clone()));
+
+        Ok((api_key, record))
+    }
+
+    pub async fn get(&self, api_key: &str) -> Result<Option<ApiKey>, ServiceError> {
+        let key_hash = hash_api_key(api_key);
+
+        if let Some(record) = self.cache.get(&key_hash) {
+            return Ok(record);
+        }
+
+        let record: Option<ApiKey> = self.records.get(&api_key_key(&key_hash)).await?;
+
+        self.cache.insert(&key_hash, record.clone());
+
+        Ok(record)
+    }
+
+    pub async fn revoke(&self, api_key: &str) -> Result<(), ServiceError> {
+        let key_hash = hash_api_key(api_key);
+
+        self.records.delete(&[api_key_key(&key_hash)]).await?;
+
+        self.cache.insert(&key_hash, None);
+
+        Ok(())
+    }
+
+    /// Sets the limits shared by all API keys of the tenant, replacing its previous limits.
+    pub async fn set_limits(&self, tenant_id: &str, limits: Limits) -> Result<(), ServiceError> {
+        let record = TenantLimits {
+            limits: limits.clone(),
+            updated_at: util::timestamp(),
+        };
+
+        self.records
+            .put(vec![(tenant_limits_key(tenant_id), record)])
+            .await?;
+
+        self.limits_cache.insert(tenant_id, Some(limits));
+
+        Ok(())
+    }
+
+    /// Returns the limits of the tenant, which has no limits unless they were set.
+    pub async fn limits(&self, tenant_id: &str) -> Result<Limits, ServiceError> {
+        let limits = match self.limits_cache.get(tenant_id) {
+            Some(limits) => limits,
+            None => {
+                let record: Option<TenantLimits> =
+                    self.records.get(&tenant_limits_key(tenant_id)).await?;
+                let limits = record.map(|record| record.limits);
+
+                self.limits_cache.insert(tenant_id, limits.clone());
+
+                limits
+            }
+        };
+
+        Ok(limits.unwrap_or_default())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+enum ApiKeyError {
+    #[error("failed to generate random API key")]
+    Random,
+}
+
+/// Authenticates API requests and checks the permissions of their tenant.
+pub struct Authorizer {
+    api_keys: Option<Arc<ApiKeyStore>>,
+}
+
+impl Authorizer {
+    pub async fn create(config: &Config) -> Result<Authorizer, ConfigError> {
+        Ok(match config.auth() {
+            AuthMode::None => Authorizer::disabled(),
+            AuthMode::ApiKey => Authorizer::new(Arc::new(ApiKeyStore::create(config).await?)),
+        })
+    }
+
+    pub fn new(api_keys: Arc<ApiKeyStore>) -> Authorizer {
+        Authorizer {
+            api_keys: Some(api_keys),
+        }
+    }
+
+    /// An authorizer that allows every request.
+    pub fn disabled() -> Authorizer {
+        Authorizer { api_keys: None }
+    }
+
+    /// Returns the tenant of the request's API key after checking that it has the permission on
+    /// the indexes, or `None` if authentication is disabled.
+    pub async fn authorize<'a, I>(
+        &self,
+        authorization: Option<&str>,
+        permission: Permission,
+        index_ids: I,
+    ) -> Result<Option<Tenant>, ServiceError>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let Some(api_keys) = &self.api_keys else {
+            return Ok(None);
+        };
+
+        let api_key = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|api_key| !api_key.is_empty())
+            .ok_or_else(|| ServiceError::unauthorized("Missing API key"))?;
+
+        let mut tenant = api_keys
+            .get(api_key)
+            .await?
+            .ok_or_else(|| ServiceError::unauthorized("Invalid API key"))?
+            .tenant;
+
+        tenant.authorize(permission, index_ids)?;
+
+        tenant.limits = api_keys.limits(&tenant.tenant_id).await?;
+
+        Ok(Some(tenant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn api_keys(ctx: &TestContext) -> Arc<ApiKeyStore> {
+        Arc::new(ApiKeyStore::new(Arc::new(ctx.document_store().clone())))
+    }
+
+    fn tenant(prefixes: &[&str], permissions: Vec<Permission>) -> Tenant {
+        Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: prefixes.iter().map(|prefix| prefix.to_string()).collect(),
+            permissions,
+            limits: Limits::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_get_and_revoke_key() {
+        let ctx = setup();
+        let api_keys = api_keys(&ctx);
+
+        let (api_key, record) = api_keys
+            .create_key(tenant(&["acme-"], vec![Permission::Read]))
+            .await
+            .unwrap();
+
+        // Another store reads the key from the document store.
+        let other = ApiKeyStore::new(Arc::new(ctx.document_store().clone()));
+        assert_eq!(Some(record), other.get(&api_key).await.unwrap());
+        assert_eq!(None, other.get("unknown").await.unwrap());
+
+        api_keys.revoke(&api_key).await.unwrap();
+
+        assert_eq!(None, api_keys.get(&api_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn keys_are_stored_hashed() {
+        let ctx = setup();
+        let api_keys = api_keys(&ctx);
+
+        let (api_key, _) = api_keys
+            .create_key(tenant(&["acme-"], vec![Permission::Read]))
+            .await
+            .unwrap();
+
+        let records = RecordStore::new(Arc::new(ctx.document_store().clone()));
+
+        assert!(records
+            .get::<ApiKey>(&api_key_key(&api_key))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(records
+            .get::<ApiKey>(&api_key_key(&hash_api_key(&api_key)))
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn authorize_requests() {
+        let ctx = setup();
+        let api_keys = api_keys(&ctx);
+        let authorizer = Authorizer::new(api_keys.clone());
+
+        let (api_key, _) = api_keys
+            .create_key(tenant(&["acme-"], vec![Permission::Read]))
+            .await
+            .unwrap();
+        let bearer = format!("Bearer {api_key}");
+        let bearer = Some(bearer.as_str());
+
+        let tenant = authorizer
+            .authorize(bearer, Permission::Read, ["acme-books"])
+            .await
+            .unwrap();
+        assert_eq!("acme", tenant.unwrap().tenant_id);
+
+        let status = |result: Result<Option<Tenant>, ServiceError>| result.unwrap_err().status();
+
+        assert_eq!(
+            401,
+            status(authorizer.authorize(None, Permission::Read, []).await)
+        );
+        assert_eq!(
+            401,
+            status(
+                authorizer
+                    .authorize(Some("Bearer unknown"), Permission::Read, [])
+                    .await
+            )
+        );
+        assert_eq!(
+            403,
+            status(
+                authorizer
+                    .authorize(bearer, Permission::Write, ["acme-books"])
+                    .await
+            )
+        );
+        assert_eq!(
+            403,
+            status(
+                authorizer
+                    .authorize(bearer, Permission::Read, ["acme-books", "other-books"])
+                    .await
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_are_shared_by_the_keys_of_a_tenant() {
+        let ctx = setup();
+        let api_keys = api_keys(&ctx);
+        let authorizer = Authorizer::new(api_keys.clone());
+
+        let limits: Limits = json::from_value(json!({ "documents_per_day": 100 })).unwrap();
+
+        let (first, _) = api_keys
+            .create_key(tenant(&["acme-"], vec![Permission::Read]))
+            .await
+            .unwrap();
+
+        api_keys.set_limits("acme", limits.clone()).await.unwrap();
+
+        let (second, record) = api_keys
+            .create_key(tenant(&["acme-"], vec![Permission::Write]))
+            .await
+            .unwrap();
+
+        // Limits are not stored with the key itself.
+        let other = ApiKeyStore::new(Arc::new(ctx.document_store().clone()));
+        assert_eq!(Some(record), other.get(&second).await.unwrap());
+        assert_eq!(limits, other.limits("acme").await.unwrap());
+        assert_eq!(Limits::default(), other.limits("other").await.unwrap());
+
+        for (api_key, permission) in [(first, Permission::Read), (second, Permission::Write)] {
+            let bearer = format!("Bearer {api_key}");
+
+            let tenant = authorizer
+                .authorize(Some(&bearer), permission, ["acme-books"])
+                .await
+                .unwrap()
+                .unwrap();
+
+            assert_eq!(limits, tenant.limits);
+        }
+    }
+
+    #[tokio::test]
+    async fn disabled_authorizer_allows_requests() {
+        let tenant = Authorizer::disabled()
+            .authorize(None, Permission::Admin, ["books"])
+            .await
+            .unwrap();
+
+        assert_eq!(None, tenant);
+    }
+
+    #[test]
+    fn admin_implies_other_permissions() {
+        let admin = tenant(&[""], vec![Permission::Admin]);
+        let writer = tenant(&["acme-"], vec![Permission::Write]);
+
+        assert!(admin.has_permission(Permission::Read));
+        assert!(admin.can_access("other-books"));
+        assert!(writer.has_permission(Permission::Write));
+        assert!(!writer.has_permission(Permission::Read));
+    }
+}
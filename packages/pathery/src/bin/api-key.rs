@@ -0,0 +1,84 @@
+//! Creates and revokes tenant API keys and sets tenant limits in the configured data table.
+//!
+//! ```text
+//! api-key create <tenant_id> <permissions> [index_prefix...]
+//! api-key revoke <api_key>
+//! api-key limits <tenant_id> [--<limit>=<value>...]
+//! ```
+//!
+//! Permissions are a comma separated list of `read`, `write` and `admin`. The created key is
+//! printed once and cannot be recovered later. Limits are shared by all API keys of the tenant and
+//! set with `--requests-per-second`, `--documents-per-day` and `--max-index-bytes`, see
+//! [`pathery::quota::Limits`]. Limits that are not given are removed.
+
+use pathery::auth::{ApiKeyStore, Permission, Tenant};
+use pathery::config::Config;
+use pathery::quota::Limits;
+
+const USAGE: &str = "usage: api-key create <tenant_id> <permissions> [index_prefix...]
+       api-key revoke <api_key>
+       api-key limits <tenant_id> [--<limit>=<value>...]";
+
+fn parse_permissions(permissions: &str) -> Result<Vec<Permission>, serde_json::Error> {
+    permissions
+        .split(',')
+        .map(|permission| serde_json::from_value(serde_json::Value::from(permission)))
+        .collect()
+}
+
+fn parse_limits(args: &[String]) -> Result<Limits, Box<dyn std::error::Error>> {
+    let mut limits = serde_json::Map::new();
+
+    for arg in args {
+        let (name, value) = arg
+            .strip_prefix("--")
+            .and_then(|arg| arg.split_once('='))
+            .ok_or_else(|| format!("invalid limit [{arg}]\n{USAGE}"))?;
+
+        limits.insert(name.replace('-', "_"), serde_json::from_str(value)?);
+    }
+
+    Ok(serde_json::from_value(limits.into())?)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let config = Config::from_env()?;
+    let api_keys = ApiKeyStore::create(&config).await?;
+
+    match args.as_slice() {
+        [command, tenant_id, permissions, index_prefixes @ ..] if command == "create" => {
+            let tenant = Tenant {
+                tenant_id: tenant_id.clone(),
+                index_prefixes: index_prefixes.to_vec(),
+                permissions: parse_permissions(permissions)?,
+                limits: Limits::default(),
+            };
+
+            let (api_key, record) = api_keys.create_key(tenant).await?;
+
+            println!(
+                "{}",
+                serde_json::json!({ "api_key": api_key, "key": record })
+            );
+        }
+        [command, api_key] if command == "revoke" => {
+            api_keys.revoke(api_key).await?;
+        }
+        [command, tenant_id, args @ ..] if command == "limits" => {
+            let limits = parse_limits(args)?;
+
+            api_keys.set_limits(tenant_id, limits.clone()).await?;
+
+            println!("{}", serde_json::json!({ "limits": limits }));
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    }
+
+    Ok(())
+}
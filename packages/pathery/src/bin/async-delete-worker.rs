@@ -7,5 +7,5 @@ use pathery::worker::async_delete::handle_event;
 async fn main() -> Result<(), sqs::Error> {
     lambda::init_tracing();
 
-    run(service_fn(|event| handle_event(event))).await
+    run(service_fn(handle_event)).await
 }
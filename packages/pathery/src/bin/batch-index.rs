@@ -1,9 +1,11 @@
+use pathery::config::Config;
 use pathery::service::index::BatchIndexService;
 use pathery::service::start_service;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = BatchIndexService::create().await;
+    let config = Config::from_env()?;
+    let service = BatchIndexService::create(&config).await?;
 
-    start_service(&service).await
+    start_service(&config, &service).await
 }
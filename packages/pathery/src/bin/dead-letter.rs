@@ -0,0 +1,78 @@
+//! Inspect and replay failed index writer jobs.
+//!
+//! ```text
+//! dead-letter list [--file PATH] [--max N]
+//! dead-letter replay [--file PATH] [--max N] [ID...]
+//! ```
+//!
+//! Dead letters are read from the configured index writer dead-letter queue unless `--file` is
+//! provided, in which case they are read from a file with one job payload per line. Replayed jobs
+//! are submitted to the configured index writer queue.
+
+use pathery::config::Config;
+use pathery::worker::index_writer::client::LambdaIndexWriterClient;
+use pathery::worker::index_writer::dead_letter::{
+    self, DeadLetterSource, FileDeadLetterSource, SqsDeadLetterSource,
+};
+
+const USAGE: &str = "usage: dead-letter <list|replay> [--file PATH] [--max N] [ID...]";
+
+struct Args {
+    command: String,
+    file: Option<String>,
+    max: usize,
+    ids: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = std::env::args().skip(1);
+
+    let command = args.next().ok_or(USAGE)?;
+
+    let mut parsed = Args {
+        command,
+        file: None,
+        max: 100,
+        ids: vec![],
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => parsed.file = Some(args.next().ok_or(USAGE)?),
+            "--max" => parsed.max = args.next().and_then(|max| max.parse().ok()).ok_or(USAGE)?,
+            _ => parsed.ids.push(arg),
+        }
+    }
+
+    Ok(parsed)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = parse_args()?;
+    let config = Config::from_env()?;
+
+    let source: Box<dyn DeadLetterSource> = match &args.file {
+        Some(path) => Box::new(FileDeadLetterSource::open(path)?),
+        None => Box::new(SqsDeadLetterSource::create(&config).await?),
+    };
+
+    match args.command.as_str() {
+        "list" => {
+            for letter in source.receive(args.max).await? {
+                println!("{}", serde_json::to_string(&letter)?);
+            }
+        }
+        "replay" => {
+            let client = LambdaIndexWriterClient::create(&config).await?;
+            let ids = (!args.ids.is_empty()).then_some(args.ids.as_slice());
+
+            for result in dead_letter::replay(source.as_ref(), &client, args.max, ids).await? {
+                println!("{}", serde_json::to_string(&result)?);
+            }
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    Ok(())
+}
@@ -1,9 +1,11 @@
+use pathery::config::Config;
 use pathery::service::doc::DeleteDocService;
 use pathery::service::start_service;
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
-    let service = DeleteDocService::create().await;
+    let config = Config::from_env()?;
+    let service = DeleteDocService::create(&config).await?;
 
-    start_service(&service).await
+    start_service(&config, &service).await
 }
@@ -1,3 +1,4 @@
+use pathery::config::Config;
 use pathery::index::LambdaIndexLoader;
 use pathery::lambda;
 use pathery::lambda::lambda_runtime::{run, service_fn};
@@ -9,8 +10,9 @@ use pathery::worker::index_writer::handle_event;
 async fn main() -> Result<(), sqs::Error> {
     lambda::init_tracing();
 
-    let document_store = DDBDocumentStore::create(None).await;
-    let index_loader = LambdaIndexLoader::create().await;
+    let config = Config::from_env()?;
+    let document_store = DDBDocumentStore::create(&config).await?;
+    let index_loader = LambdaIndexLoader::create(&config).await?;
 
     run(service_fn(|event| {
         handle_event(&document_store, &index_loader, event)
@@ -0,0 +1,11 @@
+use pathery::config::Config;
+use pathery::service::index::ListIndexesService;
+use pathery::service::start_service;
+
+#[tokio::main]
+async fn main() -> Result<(), lambda_http::Error> {
+    let config = Config::from_env()?;
+    let service = ListIndexesService::create(&config).await?;
+
+    start_service(&config, &service).await
+}
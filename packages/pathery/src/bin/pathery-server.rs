@@ -0,0 +1,46 @@
+//! Serves the pathery API over HTTP from a single process.
+//!
+//! Settings are loaded as described in [`pathery::config`], except that indexes default to
+//! `pathery-data`, the index config to `pathery.json` in the working directory and the workers to
+//! in-process queues. Documents are stored in the configured DynamoDB table when set, otherwise on
+//! local disk under the configured document directory. The server listens on `PATHERY_ADDR`
+//! (default `127.0.0.1:8080`).
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use pathery::config::Config;
+use pathery::lambda;
+use pathery::server::Server;
+use pathery::store::document::{DDBDocumentStore, DocumentStore};
+use pathery::store::file::FileDocumentStore;
+use pathery::worker::QueueKind;
+
+fn env_or(var_name: &str, default: &str) -> String {
+    std::env::var(var_name).unwrap_or_else(|_| default.to_string())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    lambda::init_tracing();
+
+    let addr: SocketAddr = env_or("PATHERY_ADDR", "127.0.0.1:8080").parse()?;
+    let config = Config::builder()
+        .data_dir("pathery-data")
+        .index_config_path("pathery.json")
+        .queue_kind(QueueKind::Local)
+        .env()
+        .build()?;
+    let document_store: Arc<dyn DocumentStore> = match config.data_table_name() {
+        Some(_) => Arc::new(DDBDocumentStore::create(&config).await?),
+        None => Arc::new(FileDocumentStore::open(config.document_dir())?),
+    };
+
+    let server = Server::local(&config, document_store).await?;
+
+    tracing::info!(message = "server_listening", %addr);
+
+    server.serve(addr).await?;
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use pathery::config::Config;
 use pathery::function::query_index_partition::handle_event;
 use pathery::index::LambdaIndexLoader;
 use pathery::lambda;
@@ -8,7 +9,8 @@ use pathery::lambda::sqs;
 async fn main() -> Result<(), sqs::Error> {
     lambda::init_tracing();
 
-    let index_loader = LambdaIndexLoader::create().await;
+    let config = Config::from_env()?;
+    let index_loader = LambdaIndexLoader::create(&config).await?;
 
     run(service_fn(|event| handle_event(&index_loader, event))).await
 }
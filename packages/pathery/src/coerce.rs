@@ -0,0 +1,209 @@
+//! Coercion of the JSON values of documents and queries to the values tantivy parses for fields.
+//!
+//! Booleans are stored in u64 fields as `0` and `1`. IP addresses are stored in bytes fields as the
+//! 16 bytes of their IPv6 address, IPv4 addresses being mapped to IPv6, so addresses compare in
+//! order in range queries. Numbers may also be given as strings.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use base64::Engine;
+use serde_json::{Map, Number, Value};
+use tantivy::schema::{FieldType, Schema};
+
+use crate::schema::FieldConfig;
+use crate::search_doc::SearchDocError;
+
+/// Returns the 16 bytes an IP address is stored as.
+pub fn encode_ip(addr: IpAddr) -> [u8; 16] {
+    match addr {
+        IpAddr::V4(addr) => addr.to_ipv6_mapped().octets(),
+        IpAddr::V6(addr) => addr.octets(),
+    }
+}
+
+/// Returns the number of a JSON number, or of a string of one.
+fn number<T: FromStr>(value: &Value, from_json: impl Fn(&Number) -> Option<T>) -> Option<T> {
+    match value {
+        Value::Number(number) => from_json(number),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Coerces a value to the JSON value tantivy parses for a field of the type, or returns what the
+/// field expects.
+pub fn coerce_value(field_type: &FieldType, value: &Value) -> Result<Value, &'static str> {
+    match field_type {
+        FieldType::U64(_) => match value {
+            Value::Bool(value) => Ok(Value::from(*value as u64)),
+            _ => number(value, Number::as_u64)
+                .map(Value::from)
+                .ok_or("an unsigned integer"),
+        },
+        FieldType::I64(_) => number(value, Number::as_i64)
+            .map(Value::from)
+            .ok_or("an integer"),
+        FieldType::F64(_) => number(value, Number::as_f64)
+            .filter(|value: &f64| value.is_finite())
+            .map(Value::from)
+            .ok_or("a number"),
+        FieldType::Bytes(_) => {
+            let Value::String(text) = value else {
+                return Err("base64 encoded bytes or an IP address");
+            };
+
+            match text.parse::<IpAddr>() {
+                Ok(addr) => Ok(Value::String(
+                    base64::engine::general_purpose::STANDARD.encode(encode_ip(addr)),
+                )),
+                Err(_)
+                    if base64::engine::general_purpose::STANDARD
+                        .decode(text)
+                        .is_ok() =>
+                {
+                    Ok(value.clone())
+                }
+                Err(_) => Err("base64 encoded bytes or an IP address"),
+            }
+        }
+        _ => Ok(value.clone()),
+    }
+}
+
+fn invalid_value(name: &str, expected: &str, value: &Value) -> SearchDocError {
+    SearchDocError::SchemaValidationError(format!("Field [{name}] expects {expected}, got {value}"))
+}
+
+/// Coerces the values of the numeric and bytes fields of a document, and of each value of their
+/// arrays.
+pub fn coerce_document(
+    schema: &Schema,
+    content: &mut Map<String, Value>,
+) -> Result<(), SearchDocError> {
+    for (name, value) in content.iter_mut() {
+        let Some(field) = schema.get_field(name) else {
+            continue;
+        };
+
+        let field_type = schema.get_field_entry(field).field_type();
+
+        let values = match value {
+            Value::Array(values) => values.iter_mut().collect(),
+            value => vec![value],
+        };
+
+        for value in values {
+            *value = coerce_value(field_type, value)
+                .map_err(|expected| invalid_value(name, expected, value))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks the values of the fields whose tantivy field is shared with other kinds: booleans and
+/// unsigned integers, and IP addresses and bytes.
+pub fn check_document(
+    fields: &[FieldConfig],
+    content: &Map<String, Value>,
+) -> Result<(), SearchDocError> {
+    for field in fields {
+        let Some(value) = content.get(field.name()) else {
+            continue;
+        };
+
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+
+        for value in values {
+            let is_ip = value
+                .as_str()
+                .is_some_and(|text| text.parse::<IpAddr>().is_ok());
+
+            let expected = match field {
+                FieldConfig::UnsignedFieldConfig { .. } if value.is_boolean() => {
+                    "an unsigned integer"
+                }
+                FieldConfig::BoolFieldConfig { .. } if !value.is_boolean() => "a boolean",
+                FieldConfig::IpFieldConfig { .. } if !is_ip => "an IP address",
+                FieldConfig::BytesFieldConfig { .. } if is_ip => "base64 encoded bytes",
+                _ => continue,
+            };
+
+            return Err(invalid_value(field.name(), expected, value));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tantivy::schema::{BytesOptions, NumericOptions};
+
+    use super::*;
+
+    #[test]
+    fn coerce_values_by_field_type() {
+        let u64_type = FieldType::U64(NumericOptions::default());
+        let f64_type = FieldType::F64(NumericOptions::default());
+        let bytes_type = FieldType::Bytes(BytesOptions::default());
+
+        assert_eq!(Ok(json!(1)), coerce_value(&u64_type, &json!(true)));
+        assert_eq!(Ok(json!(42)), coerce_value(&u64_type, &json!("42")));
+        assert_eq!(
+            Err("an unsigned integer"),
+            coerce_value(&u64_type, &json!(-1))
+        );
+        assert_eq!(Ok(json!(2.0)), coerce_value(&f64_type, &json!(2)));
+        assert_eq!(Ok(json!(9.5)), coerce_value(&f64_type, &json!("9.5")));
+        assert_eq!(Err("a number"), coerce_value(&f64_type, &json!("NaN")));
+        assert!(coerce_value(&f64_type, &json!("cheap")).is_err());
+
+        // IPv4 addresses are stored as IPv4 mapped IPv6 addresses.
+        let ipv4 = coerce_value(&bytes_type, &json!("192.168.0.1")).unwrap();
+        let ipv6 = coerce_value(&bytes_type, &json!("::ffff:192.168.0.1")).unwrap();
+
+        assert_eq!(ipv4, ipv6);
+        assert_eq!(Ok(json!("aGk=")), coerce_value(&bytes_type, &json!("aGk=")));
+        assert!(coerce_value(&bytes_type, &json!("not base64!")).is_err());
+    }
+
+    #[test]
+    fn check_document_kinds() {
+        let fields: Vec<FieldConfig> = serde_json::from_value(json!([
+            { "name": "views", "kind": "u64", "flags": ["INDEXED"] },
+            { "name": "in_stock", "kind": "bool", "flags": ["INDEXED"] },
+            { "name": "client_ip", "kind": "ip", "flags": ["INDEXED"] },
+            { "name": "checksum", "kind": "bytes", "flags": ["INDEXED"] },
+        ]))
+        .unwrap();
+        let content = |value: Value| match value {
+            Value::Object(content) => content,
+            _ => unreachable!(),
+        };
+
+        let valid = content(json!({
+            "views": 3,
+            "in_stock": true,
+            "client_ip": "2001:db8::1",
+            "checksum": "aGk="
+        }));
+
+        assert!(check_document(&fields, &valid).is_ok());
+
+        for invalid in [
+            json!({ "views": false }),
+            json!({ "in_stock": 1 }),
+            json!({ "client_ip": "aGk=" }),
+            json!({ "client_ip": "10.0.0" }),
+            json!({ "checksum": "10.0.0.1" }),
+        ] {
+            assert!(check_document(&fields, &content(invalid)).is_err());
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Deployment configuration shared by the lambdas, workers and the local server.
+//!
+//! A [`Config`] is built from defaults suitable for the lambda deployment, optionally overlaid
+//! with a JSON settings file and environment variables:
+//!
+//! | Setting                      | Environment variable         | Default                      |
+//! | ---------------------------- | ---------------------------- | ---------------------------- |
+//! | `data_dir`                   | `PATHERY_DATA_DIR`           | `/mnt/pathery-data`          |
+//! | `index_config_path`          | `PATHERY_CONFIG`             | `/opt/pathery/config.json`   |
+//! | `document_dir`               | `PATHERY_DOCUMENT_DIR`       | `pathery-documents`          |
+//! | `data_table_name`            | `DATA_TABLE_NAME`            |                              |
+//! | `data_table_endpoint_url`    | `DATA_TABLE_ENDPOINT_URL`    |                              |
+//! | `index_writer_queue_url`     | `INDEX_WRITER_QUEUE_URL`     |                              |
+//! | `index_writer_dlq_url`       | `INDEX_WRITER_DLQ_URL`       |                              |
+//! | `async_delete_queue_url`     | `ASYNC_DELETE_QUEUE_URL`     |                              |
+//! | `query_index_partition_name` | `QUERY_INDEX_PARTITION_NAME` |                              |
+//! | `auth`                       | `PATHERY_AUTH`               | `none`                       |
+//! | `queue_kind`                 | `PATHERY_QUEUE_KIND`         | `sqs`                        |
+//!
+//! The settings file is read from `PATHERY_SETTINGS` when set, and environment variables take
+//! precedence over it. An unreadable settings file, invalid settings or a missing required setting
+//! are returned as a [`ConfigError`].
+
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::json;
+use crate::worker::QueueKind;
+
+/// Environment variable naming a JSON settings file to overlay before other environment variables.
+const SETTINGS_ENV: &str = "PATHERY_SETTINGS";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{0:?} should be configured")]
+    Missing(&'static str),
+
+    #[error("settings file {path:?} cannot be read: {source}")]
+    Unreadable { path: PathBuf, source: io::Error },
+
+    #[error("settings file {path:?} is not valid JSON: {source}")]
+    Malformed { path: PathBuf, source: json::Error },
+
+    #[error("invalid settings: {0}")]
+    Invalid(String),
+}
+
+/// How API requests are authenticated.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// Requests are not authenticated and can access every index.
+    #[default]
+    None,
+
+    /// Requests must provide a tenant API key, see [`crate::auth`].
+    ApiKey,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// Directory containing a directory per index.
+    data_dir: PathBuf,
+
+    /// Path of the index config describing the schema of each index.
+    index_config_path: PathBuf,
+
+    /// Directory of the file document store used when no table is configured.
+    document_dir: PathBuf,
+
+    data_table_name: Option<String>,
+
+    /// Overrides the DynamoDB endpoint, e.g. to use DynamoDB Local.
+    data_table_endpoint_url: Option<String>,
+
+    index_writer_queue_url: Option<String>,
+
+    index_writer_dlq_url: Option<String>,
+
+    async_delete_queue_url: Option<String>,
+
+    query_index_partition_name: Option<String>,
+
+    auth: AuthMode,
+
+    /// Whether worker jobs are sent to the SQS queues or handled in-process.
+    queue_kind: QueueKind,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: PathBuf::from("/mnt/pathery-data"),
+            index_config_path: PathBuf::from("/opt/pathery/config.json"),
+            document_dir: PathBuf::from("pathery-documents"),
+            data_table_name: None,
+            data_table_endpoint_url: None,
+            index_writer_queue_url: None,
+            index_writer_dlq_url: None,
+            async_delete_queue_url: None,
+            query_index_partition_name: None,
+            auth: AuthMode::None,
+            queue_kind: QueueKind::Sqs,
+        }
+    }
+}
+
+fn require<'a>(value: &'a Option<String>, setting: &'static str) -> Result<&'a str, ConfigError> {
+    value.as_deref().ok_or(ConfigError::Missing(setting))
+}
+
+impl Config {
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Loads the config from the default settings overlaid with the settings file and environment.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        Config::builder().env().build()
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn index_config_path(&self) -> &Path {
+        &self.index_config_path
+    }
+
+    pub fn document_dir(&self) -> &Path {
+        &self.document_dir
+    }
+
+    pub fn data_table_name(&self) -> Option<&str> {
+        self.data_table_name.as_deref()
+    }
+
+    /// Returns the data table name, failing if none is configured.
+    pub fn require_data_table_name(&self) -> Result<&str, ConfigError> {
+        require(&self.data_table_name, "data_table_name")
+    }
+
+    pub fn data_table_endpoint_url(&self) -> Option<&str> {
+        self.data_table_endpoint_url.as_deref()
+    }
+
+    pub fn index_writer_queue_url(&self) -> Result<&str, ConfigError> {
+        require(&self.index_writer_queue_url, "index_writer_queue_url")
+    }
+
+    pub fn index_writer_dlq_url(&self) -> Result<&str, ConfigError> {
+        require(&self.index_writer_dlq_url, "index_writer_dlq_url")
+    }
+
+    pub fn async_delete_queue_url(&self) -> Result<&str, ConfigError> {
+        require(&self.async_delete_queue_url, "async_delete_queue_url")
+    }
+
+    pub fn query_index_partition_name(&self) -> Result<&str, ConfigError> {
+        require(
+            &self.query_index_partition_name,
+            "query_index_partition_name",
+        )
+    }
+
+    pub fn auth(&self) -> AuthMode {
+        self.auth
+    }
+
+    pub fn queue_kind(&self) -> QueueKind {
+        self.queue_kind
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+
+    /// The first error overlaying settings, returned by [`ConfigBuilder::build`].
+    error: Option<ConfigError>,
+}
+
+impl ConfigBuilder {
+    pub fn data_dir<P>(mut self, data_dir: P) -> Self
+    where P: Into<PathBuf> {
+        self.config.data_dir = data_dir.into();
+        self
+    }
+
+    pub fn index_config_path<P>(mut self, index_config_path: P) -> Self
+    where P: Into<PathBuf> {
+        self.config.index_config_path = index_config_path.into();
+        self
+    }
+
+    pub fn document_dir<P>(mut self, document_dir: P) -> Self
+    where P: Into<PathBuf> {
+        self.config.document_dir = document_dir.into();
+        self
+    }
+
+    pub fn data_table_name(mut self, data_table_name: &str) -> Self {
+        self.config.data_table_name = Some(data_table_name.into());
+        self
+    }
+
+    pub fn data_table_endpoint_url(mut self, data_table_endpoint_url: &str) -> Self {
+        self.config.data_table_endpoint_url = Some(data_table_endpoint_url.into());
+        self
+    }
+
+    pub fn index_writer_queue_url(mut self, index_writer_queue_url: &str) -> Self {
+        self.config.index_writer_queue_url = Some(index_writer_queue_url.into());
+        self
+    }
+
+    pub fn index_writer_dlq_url(mut self, index_writer_dlq_url: &str) -> Self {
+        self.config.index_writer_dlq_url = Some(index_writer_dlq_url.into());
+        self
+    }
+
+    pub fn async_delete_queue_url(mut self, async_delete_queue_url: &str) -> Self {
+        self.config.async_delete_queue_url = Some(async_delete_queue_url.into());
+        self
+    }
+
+    pub fn query_index_partition_name(mut self, query_index_partition_name: &str) -> Self {
+        self.config.query_index_partition_name = Some(query_index_partition_name.into());
+        self
+    }
+
+    pub fn auth(mut self, auth: AuthMode) -> Self {
+        self.config.auth = auth;
+        self
+    }
+
+    pub fn queue_kind(mut self, queue_kind: QueueKind) -> Self {
+        self.config.queue_kind = queue_kind;
+        self
+    }
+
+    /// Overlays the settings present in a JSON settings file.
+    pub fn file<P>(mut self, path: P) -> Self
+    where P: AsRef<Path> {
+        let path = path.as_ref().to_owned();
+
+        let settings = fs::read_to_string(&path)
+            .map_err(|source| ConfigError::Unreadable {
+                path: path.clone(),
+                source,
+            })
+            .and_then(|content| {
+                json::from_str(&content).map_err(|source| ConfigError::Malformed { path, source })
+            });
+
+        match settings {
+            Ok(settings) => self.overlay(settings),
+            Err(err) => {
+                self.error.get_or_insert(err);
+                self
+            }
+        }
+    }
+
+    /// Overlays the settings file named by `PATHERY_SETTINGS` and then any settings set in
+    /// environment variables.
+    pub fn env(self) -> Self {
+        let builder = match std::env::var(SETTINGS_ENV) {
+            Ok(path) => self.file(path),
+            Err(_) => self,
+        };
+
+        let settings: json::Map<String, json::Value> = [
+            ("data_dir", "PATHERY_DATA_DIR"),
+            ("index_config_path", "PATHERY_CONFIG"),
+            ("document_dir", "PATHERY_DOCUMENT_DIR"),
+            ("data_table_name", "DATA_TABLE_NAME"),
+            ("data_table_endpoint_url", "DATA_TABLE_ENDPOINT_URL"),
+            ("index_writer_queue_url", "INDEX_WRITER_QUEUE_URL"),
+            ("index_writer_dlq_url", "INDEX_WRITER_DLQ_URL"),
+            ("async_delete_queue_url", "ASYNC_DELETE_QUEUE_URL"),
+            ("query_index_partition_name", "QUERY_INDEX_PARTITION_NAME"),
+            ("auth", "PATHERY_AUTH"),
+            ("queue_kind", "PATHERY_QUEUE_KIND"),
+        ]
+        .into_iter()
+        .filter_map(|(setting, var_name)| {
+            std::env::var(var_name)
+                .ok()
+                .map(|value| (setting.to_string(), json::Value::String(value)))
+        })
+        .collect();
+
+        builder.overlay(json::Value::Object(settings))
+    }
+
+    fn overlay(mut self, settings: json::Value) -> Self {
+        let mut config = json::to_value(&self.config).expect("config should serialize");
+
+        if let (Some(config), json::Value::Object(settings)) = (config.as_object_mut(), settings) {
+            config.extend(settings);
+        }
+
+        match json::from_value(config) {
+            Ok(config) => self.config = config,
+            Err(err) => {
+                self.error
+                    .get_or_insert(ConfigError::Invalid(err.to_string()));
+            }
+        }
+
+        self
+    }
+
+    pub fn build(self) -> Result<Config, ConfigError> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::util;
+
+    #[test]
+    fn builder_overrides_defaults() {
+        let config = Config::builder()
+            .data_dir("data")
+            .index_writer_queue_url("https://queue")
+            .build()
+            .unwrap();
+
+        assert_eq!(Path::new("data"), config.data_dir());
+        assert_eq!(
+            Path::new("/opt/pathery/config.json"),
+            config.index_config_path()
+        );
+        assert_eq!("https://queue", config.index_writer_queue_url().unwrap());
+        assert_eq!(None, config.data_table_name());
+    }
+
+    #[test]
+    fn file_overlays_present_settings() {
+        let path = std::env::temp_dir().join(format!("pathery-settings-{}", util::generate_id()));
+        fs::write(
+            &path,
+            json!({
+                "data_dir": "file-data",
+                "data_table_name": "table",
+                "auth": "api_key",
+                "queue_kind": "local"
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let config = Config::builder()
+            .data_dir("data")
+            .document_dir("documents")
+            .file(&path)
+            .build()
+            .unwrap();
+
+        assert_eq!(Path::new("file-data"), config.data_dir());
+        assert_eq!(Path::new("documents"), config.document_dir());
+        assert_eq!(Some("table"), config.data_table_name());
+        assert_eq!(AuthMode::ApiKey, config.auth());
+        assert_eq!(QueueKind::Local, config.queue_kind());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_required_setting_is_an_error() {
+        let config = Config::builder().build().unwrap();
+
+        assert_eq!(
+            "\"async_delete_queue_url\" should be configured",
+            config.async_delete_queue_url().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        let path = std::env::temp_dir().join(format!("pathery-settings-{}", util::generate_id()));
+
+        assert!(matches!(
+            Config::builder().file(&path).build(),
+            Err(ConfigError::Unreadable { .. })
+        ));
+
+        fs::write(&path, "{ not json").unwrap();
+
+        assert!(matches!(
+            Config::builder().file(&path).build(),
+            Err(ConfigError::Malformed { .. })
+        ));
+
+        fs::write(&path, json!({ "auth": "password" }).to_string()).unwrap();
+
+        assert!(matches!(
+            Config::builder().file(&path).build(),
+            Err(ConfigError::Invalid(_))
+        ));
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -58,7 +58,7 @@ impl Directory for PatheryDirectory {
     }
 
     fn delete(&self, path: &std::path::Path) -> Result<(), tantivy::directory::error::DeleteError> {
-        let path = self.directory_path.join(path.to_path_buf());
+        let path = self.directory_path.join(path);
         let job = AsyncDeleteJob::fs_delete(path);
         self.handle
             .block_on(self.async_delete_client.submit_job(job))
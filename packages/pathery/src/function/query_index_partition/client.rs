@@ -1,8 +1,20 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use aws_smithy_types::Blob;
 
-use super::{PartitionQueryResponse, QueryRequest};
-use crate::pagination::SegmentMeta;
-use crate::util;
+use super::{load_partition, search_partition, PartitionQueryResponse, QueryRequest};
+use crate::config::{Config, ConfigError};
+use crate::index::IndexLoader;
+use crate::service::ServiceError;
+
+#[async_trait]
+pub trait QueryIndexPartitionClient: Send + Sync {
+    async fn query_partition(
+        &self,
+        request: QueryRequest,
+    ) -> Result<PartitionQueryResponse, ServiceError>;
+}
 
 pub struct LambdaQueryIndexPartitionClient {
     function_name: String,
@@ -11,44 +23,61 @@ pub struct LambdaQueryIndexPartitionClient {
 }
 
 impl LambdaQueryIndexPartitionClient {
-    pub async fn create() -> LambdaQueryIndexPartitionClient {
+    pub async fn create(config: &Config) -> Result<LambdaQueryIndexPartitionClient, ConfigError> {
+        let function_name = config.query_index_partition_name()?.to_string();
         let sdk_config = aws_config::load_from_env().await;
-        let function_name = util::require_env("QUERY_INDEX_PARTITION_NAME");
 
-        LambdaQueryIndexPartitionClient {
+        Ok(LambdaQueryIndexPartitionClient {
             function_name,
             client: aws_sdk_lambda::Client::new(&sdk_config),
-        }
+        })
     }
+}
 
-    pub async fn query_partition(
+#[async_trait]
+impl QueryIndexPartitionClient for LambdaQueryIndexPartitionClient {
+    async fn query_partition(
         &self,
-        index_id: String,
-        query: String,
-        offset: usize,
-        partition_n: usize,
-        segments: Vec<SegmentMeta>,
-    ) -> PartitionQueryResponse {
+        input: QueryRequest,
+    ) -> Result<PartitionQueryResponse, ServiceError> {
         // TODO: Error handling and retries
         let request = self.client.invoke();
         let request = request.function_name(&self.function_name);
-        let input = QueryRequest {
-            index_id,
-            query,
-            offset,
-            partition_n,
-            segments,
-        };
         let input = serde_json::to_vec(&input).expect("should serialize");
         let input = Blob::new(input);
         let request = request.payload(input);
         let response = tokio::spawn(request.send());
-        let response = response.await.unwrap().expect("should succeed");
+        let response = response.await.unwrap()?;
 
         let payload = response.payload().expect("payload should exist");
         let payload = payload.to_owned().into_inner();
         let payload: PartitionQueryResponse =
             serde_json::from_slice(&payload).expect("payload should parse");
-        payload
+        Ok(payload)
+    }
+}
+
+/// Queries partitions in the current process instead of invoking the partition lambda.
+pub struct LocalQueryIndexPartitionClient {
+    index_loader: Arc<dyn IndexLoader>,
+}
+
+impl LocalQueryIndexPartitionClient {
+    pub fn new(index_loader: Arc<dyn IndexLoader>) -> LocalQueryIndexPartitionClient {
+        LocalQueryIndexPartitionClient { index_loader }
+    }
+}
+
+#[async_trait]
+impl QueryIndexPartitionClient for LocalQueryIndexPartitionClient {
+    async fn query_partition(
+        &self,
+        input: QueryRequest,
+    ) -> Result<PartitionQueryResponse, ServiceError> {
+        let index = load_partition(self.index_loader.as_ref(), &input).await?;
+
+        tokio::task::spawn_blocking(move || search_partition(index, input))
+            .await
+            .map_err(ServiceError::internal_error)?
     }
 }
@@ -3,22 +3,35 @@ pub mod client;
 use lambda_runtime::{Error, LambdaEvent};
 use serde::{Deserialize, Serialize};
 use tantivy::collector::TopDocs;
-use tantivy::query::QueryParser;
-use tantivy::schema::{Field, FieldType};
-use tantivy::{DocAddress, Score};
+use tantivy::fastfield::FastFieldReader;
+use tantivy::{DocAddress, DocId, Index, Score, SegmentReader};
 
+use crate::geo::GeoPoint;
 use crate::index::IndexLoader;
 use crate::pagination::SegmentMeta;
+use crate::query::{bytes_field, fast_value_field, SearchQuery, Sort, SortOrder};
 use crate::service::ServiceError;
 use crate::store::document::SearchDocRef;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QueryRequest {
     pub index_id: String,
-    pub query: String,
+    /// Directory of the index to search, or the current directory of the index if not set.
+    #[serde(default)]
+    pub directory: Option<String>,
+    pub query: SearchQuery,
     pub offset: usize,
+    /// Number of matches to return from the offset.
+    #[serde(default = "default_limit")]
+    pub limit: usize,
     pub partition_n: usize,
     pub segments: Vec<SegmentMeta>,
+    #[serde(default)]
+    pub sort: Option<Sort>,
+}
+
+fn default_limit() -> usize {
+    10
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -26,6 +39,13 @@ pub struct PartitionSearchHit {
     pub doc_ref: SearchDocRef,
     pub score: f32,
     pub partition_n: usize,
+    /// Distance to the origin of a geo distance sort, if the document has a point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
+    /// Key of a value sort, greatest first, that orders values like the `u64` tantivy maps them
+    /// to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_key: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -37,11 +57,42 @@ pub async fn handle_event(
     index_loader: &dyn IndexLoader,
     event: LambdaEvent<QueryRequest>,
 ) -> Result<PartitionQueryResponse, Error> {
-    let body = event.payload;
-    let index_id = body.index_id;
+    Ok(query_partition(index_loader, event.payload).await?)
+}
 
-    let mut index = index_loader.load_index(&index_id, Some(body.segments))?;
+/// Searches the segments of a single partition of the index.
+pub async fn query_partition(
+    index_loader: &dyn IndexLoader,
+    body: QueryRequest,
+) -> Result<PartitionQueryResponse, ServiceError> {
+    let index = load_partition(index_loader, &body).await?;
+
+    search_partition(index, body)
+}
 
+/// Opens the index of a partition with the segments of the partition.
+pub async fn load_partition(
+    index_loader: &dyn IndexLoader,
+    body: &QueryRequest,
+) -> Result<Index, ServiceError> {
+    let segments = Some(body.segments.clone());
+
+    // Partitions are only searched for indexes that exist, which are not created by queries.
+    let directory = match &body.directory {
+        Some(directory) => directory.clone(),
+        None => index_loader.index_directory(&body.index_id).await?,
+    };
+
+    index_loader
+        .load_index_directory(&body.index_id, &directory, segments)
+        .await
+}
+
+/// Searches an index opened with the segments of a single partition.
+pub fn search_partition(
+    mut index: Index,
+    body: QueryRequest,
+) -> Result<PartitionQueryResponse, ServiceError> {
     index.set_default_multithread_executor().unwrap();
 
     let reader = index.reader().expect("Reader should load");
@@ -50,35 +101,90 @@ pub async fn handle_event(
 
     let schema = index.schema();
 
-    let query_parser = QueryParser::for_index(
-        &index,
-        schema
-            .fields()
-            .filter_map(|(field, entry)| {
-                if !entry.is_indexed() {
-                    return None;
+    let query = body.query.parse(&index)?;
+
+    let collector = TopDocs::with_limit(body.limit).and_offset(body.offset);
+
+    let top_docs: Vec<(Score, Option<f64>, Option<u64>, DocAddress)> = match &body.sort {
+        None => searcher
+            .search(&query, &collector)
+            .expect("search should succeed")
+            .into_iter()
+            .map(|(score, address)| (score, None, None, address))
+            .collect(),
+        Some(Sort::GeoDistance { field, origin }) => {
+            let field = bytes_field(&index, field, "geo point")?;
+            let origin = *origin;
+
+            // Nearest points have the greatest negated distance, and documents without a point
+            // come last.
+            let collector = collector.tweak_score(move |segment_reader: &SegmentReader| {
+                let points = segment_reader
+                    .fast_fields()
+                    .bytes(field)
+                    .expect("geo point field should be a bytes fast field");
+
+                move |doc: DocId, score: Score| {
+                    let distance = GeoPoint::decode(points.get_bytes(doc))
+                        .map(|point| origin.distance_km(&point));
+
+                    (
+                        distance.map_or(f64::NEG_INFINITY, |distance| -distance),
+                        score,
+                    )
                 }
-                match entry.field_type() {
-                    FieldType::Str(_) => Some(field),
-                    _ => None,
+            });
+
+            searcher
+                .search(&query, &collector)
+                .expect("search should succeed")
+                .into_iter()
+                .map(|((distance, score), address)| {
+                    (
+                        score,
+                        distance.is_finite().then_some(-distance),
+                        None,
+                        address,
+                    )
+                })
+                .collect()
+        }
+        Some(Sort::Value { field, order }) => {
+            let field = fast_value_field(&index, field)?;
+            let order = *order;
+
+            // Values are read as the u64 tantivy maps them to in order, and ascending sorts
+            // negate them so the least values have the greatest keys.
+            let collector = collector.tweak_score(move |segment_reader: &SegmentReader| {
+                let values = segment_reader
+                    .fast_fields()
+                    .u64_lenient(field)
+                    .expect("sort field should be a fast field");
+
+                move |doc: DocId, score: Score| {
+                    let value = values.get(doc);
+
+                    let key = match order {
+                        SortOrder::Asc => !value,
+                        SortOrder::Desc => value,
+                    };
+
+                    (key, score)
                 }
-            })
-            .collect::<Vec<Field>>(),
-    );
-
-    let query = query_parser
-        .parse_query(&body.query)
-        .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
-
-    let collector = TopDocs::with_limit(10).and_offset(body.offset);
+            });
 
-    let top_docs: Vec<(Score, DocAddress)> = searcher
-        .search(&query, &collector)
-        .expect("search should succeed");
+            searcher
+                .search(&query, &collector)
+                .expect("search should succeed")
+                .into_iter()
+                .map(|((key, score), address)| (score, None, Some(key), address))
+                .collect()
+        }
+    };
 
     let matches: Vec<_> = top_docs
         .into_iter()
-        .map(|(score, address)| {
+        .map(|(score, distance_km, sort_key, address)| {
             let document = searcher.doc(address).expect("doc should exist");
 
             let named_doc = schema.to_named_doc(&document);
@@ -89,11 +195,13 @@ pub async fn handle_event(
                 doc_ref: stored_ref,
                 score,
                 partition_n: body.partition_n,
+                distance_km,
+                sort_key,
             }
         })
         .collect();
 
-    if matches.len() == 0 {
+    if matches.is_empty() {
         return Ok(PartitionQueryResponse { matches: vec![] });
     }
 
@@ -0,0 +1,368 @@
+//! Geo point fields, filtered by distance or bounding box and sorted by distance.
+//!
+//! Points are stored as their latitude and longitude, little endian `f64` values, in a bytes fast
+//! field. [`GeoQuery`] filters them by scanning the documents of a segment, like
+//! [`crate::vector::VectorQuery`].
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tantivy::fastfield::BytesFastFieldReader;
+use tantivy::query::{Explanation, Query, Scorer, Weight};
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, TantivyError, TERMINATED};
+
+use crate::schema::FieldConfig;
+use crate::search_doc::SearchDocError;
+use crate::service::ServiceError;
+
+/// Mean radius of the earth.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Number of bytes a point is stored in.
+const POINT_BYTES: usize = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct GeoPoint {
+    pub lat: f64,
+
+    pub lon: f64,
+}
+
+impl GeoPoint {
+    /// Checks that the latitude and longitude are in range.
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if !(-90.0..=90.0).contains(&self.lat) || !(-180.0..=180.0).contains(&self.lon) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Invalid geo point [{}, {}], lat must be within -90 and 90 and lon within -180 \
+                 and 180",
+                self.lat, self.lon
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the great circle distance between two points, by the haversine formula.
+    pub fn distance_km(&self, other: &GeoPoint) -> f64 {
+        let (lat_a, lat_b) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat_b - lat_a;
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let a =
+            (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        [self.lat.to_le_bytes(), self.lon.to_le_bytes()].concat()
+    }
+
+    /// Decodes the bytes stored in the fast field of a point, if the document has one.
+    pub fn decode(bytes: &[u8]) -> Option<GeoPoint> {
+        if bytes.len() != POINT_BYTES {
+            return None;
+        }
+
+        let (lat, lon) = bytes.split_at(8);
+
+        Some(GeoPoint {
+            lat: f64::from_le_bytes(lat.try_into().ok()?),
+            lon: f64::from_le_bytes(lon.try_into().ok()?),
+        })
+    }
+}
+
+/// Returns the point of a JSON value, if it is an object of a `lat` and a `lon`.
+fn json_point(value: &Value) -> Option<GeoPoint> {
+    // Structs also deserialize from arrays, which are vectors rather than points.
+    if !value.is_object() {
+        return None;
+    }
+
+    serde_json::from_value(value.clone()).ok()
+}
+
+/// Replaces the points of the bytes fields of a document with the base64 encoded bytes that
+/// tantivy parses bytes fields from.
+pub fn encode_document(schema: &Schema, content: &mut Map<String, Value>) {
+    for (name, value) in content.iter_mut() {
+        let Some(field) = schema.get_field(name) else {
+            continue;
+        };
+
+        if !matches!(
+            schema.get_field_entry(field).field_type(),
+            FieldType::Bytes(_)
+        ) {
+            continue;
+        }
+
+        if let Some(point) = json_point(value) {
+            *value =
+                Value::String(base64::engine::general_purpose::STANDARD.encode(point.encode()));
+        }
+    }
+}
+
+/// Checks that the points of a document are objects of a `lat` and a `lon` in range.
+pub fn check_document(
+    fields: &[FieldConfig],
+    content: &Map<String, Value>,
+) -> Result<(), SearchDocError> {
+    for field in fields {
+        let FieldConfig::GeoPointFieldConfig { name } = field else {
+            continue;
+        };
+
+        let Some(value) = content.get(name) else {
+            continue;
+        };
+
+        let point = json_point(value).ok_or_else(|| {
+            SearchDocError::SchemaValidationError(format!(
+                "Geo point field [{name}] expects an object of a lat and a lon"
+            ))
+        })?;
+
+        point
+            .validate()
+            .map_err(|err| SearchDocError::SchemaValidationError(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Documents a geo filter keeps.
+#[derive(Clone, Debug)]
+pub enum GeoFilter {
+    /// Points within a distance of the origin.
+    Distance { origin: GeoPoint, distance_km: f64 },
+
+    /// Points within a box. Boxes whose left longitude is greater than their right longitude
+    /// cross the antimeridian.
+    BoundingBox {
+        top_left: GeoPoint,
+        bottom_right: GeoPoint,
+    },
+}
+
+impl GeoFilter {
+    pub fn matches(&self, point: &GeoPoint) -> bool {
+        match self {
+            GeoFilter::Distance {
+                origin,
+                distance_km,
+            } => origin.distance_km(point) <= *distance_km,
+            GeoFilter::BoundingBox {
+                top_left,
+                bottom_right,
+            } => {
+                let lat = (bottom_right.lat..=top_left.lat).contains(&point.lat);
+
+                let lon = if top_left.lon <= bottom_right.lon {
+                    (top_left.lon..=bottom_right.lon).contains(&point.lon)
+                } else {
+                    point.lon >= top_left.lon || point.lon <= bottom_right.lon
+                };
+
+                lat && lon
+            }
+        }
+    }
+}
+
+/// Matches the documents with a point of a field that the filter keeps, with a score of 1.
+#[derive(Clone, Debug)]
+pub struct GeoQuery {
+    field: Field,
+    filter: GeoFilter,
+}
+
+impl GeoQuery {
+    pub fn new(field: Field, filter: GeoFilter) -> GeoQuery {
+        GeoQuery { field, filter }
+    }
+}
+
+impl Query for GeoQuery {
+    fn weight(&self, _: &Searcher, _: bool) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl Weight for GeoQuery {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let mut scorer = GeoScorer {
+            filter: self.filter.clone(),
+            points: reader.fast_fields().bytes(self.field)?,
+            doc: 0,
+            max_doc: reader.max_doc(),
+            boost,
+        };
+
+        if scorer.max_doc == 0 {
+            scorer.doc = TERMINATED;
+        } else if !scorer.matches(0) {
+            scorer.advance();
+        }
+
+        Ok(Box::new(scorer))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+
+        Ok(Explanation::new("GeoQuery", scorer.score()))
+    }
+}
+
+struct GeoScorer {
+    filter: GeoFilter,
+    points: BytesFastFieldReader,
+    doc: DocId,
+    max_doc: DocId,
+    boost: Score,
+}
+
+impl GeoScorer {
+    fn matches(&self, doc: DocId) -> bool {
+        GeoPoint::decode(self.points.get_bytes(doc))
+            .is_some_and(|point| self.filter.matches(&point))
+    }
+}
+
+impl DocSet for GeoScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            if self.doc + 1 >= self.max_doc {
+                self.doc = TERMINATED;
+                return TERMINATED;
+            }
+
+            self.doc += 1;
+
+            if self.matches(self.doc) {
+                return self.doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.max_doc
+    }
+}
+
+impl Scorer for GeoScorer {
+    fn score(&mut self) -> Score {
+        self.boost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const AMSTERDAM: GeoPoint = GeoPoint {
+        lat: 52.3676,
+        lon: 4.9041,
+    };
+
+    const ROTTERDAM: GeoPoint = GeoPoint {
+        lat: 51.9244,
+        lon: 4.4777,
+    };
+
+    #[test]
+    fn distance_between_points() {
+        let distance = AMSTERDAM.distance_km(&ROTTERDAM);
+
+        assert!((57.0..58.0).contains(&distance), "{distance}");
+        assert_eq!(0.0, AMSTERDAM.distance_km(&AMSTERDAM));
+    }
+
+    #[test]
+    fn bounding_box_across_antimeridian() {
+        let filter = GeoFilter::BoundingBox {
+            top_left: GeoPoint {
+                lat: 10.0,
+                lon: 170.0,
+            },
+            bottom_right: GeoPoint {
+                lat: -10.0,
+                lon: -170.0,
+            },
+        };
+
+        assert!(filter.matches(&GeoPoint {
+            lat: 0.0,
+            lon: 179.0
+        }));
+        assert!(filter.matches(&GeoPoint {
+            lat: 0.0,
+            lon: -175.0
+        }));
+        assert!(!filter.matches(&GeoPoint { lat: 0.0, lon: 0.0 }));
+        assert!(!filter.matches(&GeoPoint {
+            lat: 20.0,
+            lon: 179.0
+        }));
+    }
+
+    #[test]
+    fn filter_points_by_distance() {
+        let (index, location) = bytes_field_index(
+            "location",
+            vec![
+                ("rotterdam", ROTTERDAM.encode()),
+                ("amsterdam", AMSTERDAM.encode()),
+            ],
+        );
+
+        let query = GeoQuery::new(
+            location,
+            GeoFilter::Distance {
+                origin: GeoPoint {
+                    lat: 52.37,
+                    lon: 4.89,
+                },
+                distance_km: 10.0,
+            },
+        );
+
+        assert_eq!(vec!["amsterdam"], search_ids(&index, &query));
+    }
+
+    #[test]
+    fn check_document_points() {
+        let fields = vec![FieldConfig::GeoPointFieldConfig {
+            name: "location".into(),
+        }];
+        let content = |value| field_content("location", value);
+
+        assert!(check_document(
+            &fields,
+            &content(serde_json::json!({ "lat": 52.3, "lon": 4.9 }))
+        )
+        .is_ok());
+        assert!(check_document(
+            &fields,
+            &content(serde_json::json!({ "lat": 91, "lon": 4.9 }))
+        )
+        .is_err());
+        assert!(check_document(&fields, &content(serde_json::json!([52.3, 4.9]))).is_err());
+    }
+}
@@ -0,0 +1,788 @@
+//! Highlighted snippets returned with query matches.
+//!
+//! Snippets are computed from the terms of the query and the stored text of each matched document,
+//! by tokenizing the text with the field's tokenizer and selecting the fragments with the most
+//! matching tokens. Terms of phrases are only highlighted where the phrase matches.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use tantivy::query::{BooleanQuery, Occur, Query};
+use tantivy::schema::{Field, FieldType, Schema, Value};
+use tantivy::tokenizer::{TextAnalyzer, Token};
+use tantivy::{Document, Index, Term};
+
+use crate::json;
+use crate::query::{term_text, Phrase};
+use crate::service::ServiceError;
+
+/// Largest number of fragments that can be requested per field.
+const MAX_NUMBER_OF_FRAGMENTS: usize = 10;
+
+/// Separates the fragments of a field in HTML snippets.
+const FRAGMENT_SEPARATOR: &str = " … ";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightFormat {
+    /// Snippets are HTML, with matches wrapped in the pre and post tags.
+    #[default]
+    Html,
+
+    /// Snippets are lists of plain text fragments with the character offsets of their matches.
+    Offsets,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighlightOptions {
+    /// Fields to highlight. Defaults to every indexed text and JSON field.
+    pub fields: Option<Vec<String>>,
+
+    /// Maximum number of characters in a fragment.
+    pub fragment_size: usize,
+
+    /// Maximum number of fragments per field.
+    pub number_of_fragments: usize,
+
+    pub pre_tag: String,
+
+    pub post_tag: String,
+
+    pub format: HighlightFormat,
+}
+
+impl Default for HighlightOptions {
+    fn default() -> Self {
+        HighlightOptions {
+            fields: None,
+            fragment_size: 150,
+            number_of_fragments: 1,
+            pre_tag: "<b>".into(),
+            post_tag: "</b>".into(),
+            format: HighlightFormat::Html,
+        }
+    }
+}
+
+/// A fragment of a field with the character offsets of its matches.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub text: String,
+
+    /// Start and end offsets, in characters, of the matches in the text.
+    pub highlights: Vec<[usize; 2]>,
+}
+
+/// A fragment of a field value with the byte ranges of its matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    fragment: String,
+
+    highlighted: Vec<Range<usize>>,
+}
+
+impl Snippet {
+    pub fn fragment(&self) -> &str {
+        &self.fragment
+    }
+
+    pub fn highlighted(&self) -> &[Range<usize>] {
+        &self.highlighted
+    }
+}
+
+fn is_highlightable(schema: &Schema, field: Field) -> bool {
+    let entry = schema.get_field_entry(field);
+
+    entry.is_indexed()
+        && matches!(
+            entry.field_type(),
+            FieldType::Str(_) | FieldType::JsonObject(_)
+        )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+impl HighlightOptions {
+    /// Checks the options and returns the fields to highlight.
+    pub fn fields(&self, schema: &Schema) -> Result<Vec<Field>, ServiceError> {
+        if self.fragment_size == 0 {
+            return Err(ServiceError::invalid_request(
+                "Highlight fragment_size must be at least 1",
+            ));
+        }
+
+        if !(1..=MAX_NUMBER_OF_FRAGMENTS).contains(&self.number_of_fragments) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Highlight number_of_fragments must be between 1 and {MAX_NUMBER_OF_FRAGMENTS}"
+            )));
+        }
+
+        let Some(names) = &self.fields else {
+            return Ok(schema
+                .fields()
+                .map(|(field, _)| field)
+                .filter(|field| is_highlightable(schema, *field))
+                .collect());
+        };
+
+        names
+            .iter()
+            .map(|name| match schema.get_field(name) {
+                Some(field) if is_highlightable(schema, field) => Ok(field),
+                _ => Err(ServiceError::invalid_request(&format!(
+                    "Field [{name}] is not an indexed text or JSON field and cannot be highlighted"
+                ))),
+            })
+            .collect()
+    }
+
+    /// Formats the fragments of a field, or returns `None` if there are none.
+    pub fn format(&self, fragments: &[Snippet]) -> Option<json::Value> {
+        if fragments.is_empty() {
+            return None;
+        }
+
+        let value = match self.format {
+            HighlightFormat::Html => json::Value::String(
+                fragments
+                    .iter()
+                    .map(|snippet| self.to_html(snippet))
+                    .collect::<Vec<_>>()
+                    .join(FRAGMENT_SEPARATOR),
+            ),
+            HighlightFormat::Offsets => {
+                json::to_value(fragments.iter().map(Self::to_fragment).collect::<Vec<_>>())
+                    .expect("fragments should serialize")
+            }
+        };
+
+        Some(value)
+    }
+
+    fn to_html(&self, snippet: &Snippet) -> String {
+        let text = snippet.fragment();
+        let mut html = String::new();
+        let mut position = 0;
+
+        for range in snippet.highlighted() {
+            html.push_str(&escape_html(&text[position..range.start]));
+            html.push_str(&self.pre_tag);
+            html.push_str(&escape_html(&text[range.clone()]));
+            html.push_str(&self.post_tag);
+            position = range.end;
+        }
+
+        html.push_str(&escape_html(&text[position..]));
+        html
+    }
+
+    fn to_fragment(snippet: &Snippet) -> Fragment {
+        let text = snippet.fragment();
+        let char_offset = |byte_offset: usize| text[..byte_offset].chars().count();
+
+        Fragment {
+            text: text.to_string(),
+            highlights: snippet
+                .highlighted()
+                .iter()
+                .map(|range| [char_offset(range.start), char_offset(range.end)])
+                .collect(),
+        }
+    }
+}
+
+/// A range of a field value considered as a fragment.
+struct Candidate {
+    /// Position of the value among the values of the field.
+    value: usize,
+
+    range: Range<usize>,
+
+    highlighted: Vec<Range<usize>>,
+}
+
+/// Terms of a phrase at their offsets in the phrase.
+struct PhraseTerms {
+    terms: Vec<(usize, String)>,
+
+    slop: u32,
+}
+
+/// Query terms of a text field or of a path of a JSON field.
+#[derive(Default)]
+struct PathTerms {
+    /// Terms highlighted wherever they appear.
+    terms: HashSet<String>,
+
+    /// Phrases whose terms are only highlighted where the phrase matches.
+    phrases: Vec<PhraseTerms>,
+}
+
+/// Query terms of a highlighted field and the tokenizer that produced them.
+struct FieldTerms {
+    field: Field,
+
+    name: String,
+
+    is_json: bool,
+
+    tokenizer: TextAnalyzer,
+
+    /// Terms by dotted path in JSON fields, or under an empty path in text fields.
+    terms: HashMap<String, PathTerms>,
+}
+
+/// Computes the snippets of matched documents for a query.
+///
+/// A highlighter is created once per query and reused for every match, so no index is needed to
+/// highlight a document.
+pub struct Highlighter {
+    options: HighlightOptions,
+
+    fields: Vec<FieldTerms>,
+}
+
+/// Whether the text is longer than `max_chars` characters, without counting the characters of
+/// short texts.
+fn exceeds(text: &str, max_chars: usize) -> bool {
+    text.len() > max_chars && text.chars().count() > max_chars
+}
+
+/// Collects the terms and phrases of the query, leaving out the terms of excluded clauses.
+fn collect_terms(query: &dyn Query, terms: &mut BTreeMap<Term, bool>, phrases: &mut Vec<Phrase>) {
+    if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+        for (occur, clause) in boolean.clauses() {
+            if *occur != Occur::MustNot {
+                collect_terms(clause.as_ref(), terms, phrases);
+            }
+        }
+    } else if let Some(phrase) = query.downcast_ref::<Phrase>() {
+        phrases.push(phrase.clone());
+    } else {
+        query.query_terms(terms);
+    }
+}
+
+/// Marks the tokens of every match of the phrase. As in tantivy's phrase queries, each term is at
+/// most `slop` positions further than expected from the previous term.
+///
+/// Tokens are in position order, so each term is only searched for after the previous term and
+/// until the slop is exceeded.
+fn mark_phrase(tokens: &[Token], phrase: &PhraseTerms, matched: &mut [bool]) {
+    let Some(((first_offset, first), rest)) = phrase.terms.split_first() else {
+        return;
+    };
+
+    let normalized = |token: &Token, offset: usize| token.position as i64 - offset as i64;
+
+    for (start, token) in tokens.iter().enumerate() {
+        if token.text != *first {
+            continue;
+        }
+
+        let mut expected = normalized(token, *first_offset);
+        let mut previous = start;
+        let mut found = vec![start];
+
+        for (offset, text) in rest {
+            let next = tokens[previous..]
+                .iter()
+                .take_while(|token| normalized(token, *offset) <= expected + phrase.slop as i64)
+                .position(|token| token.text == *text && normalized(token, *offset) >= expected)
+                .map(|n| previous + n);
+
+            let Some(next) = next else {
+                found.clear();
+                break;
+            };
+
+            expected = normalized(&tokens[next], *offset);
+            previous = next;
+            found.push(next);
+        }
+
+        for n in found {
+            matched[n] = true;
+        }
+    }
+}
+
+/// Collects the text leaves of a JSON value by dotted path. Texts in arrays share the path of the
+/// array.
+fn json_texts<'a>(path: &str, value: &'a json::Value, texts: &mut BTreeMap<String, Vec<&'a str>>) {
+    match value {
+        json::Value::String(text) => texts.entry(path.to_string()).or_default().push(text),
+        json::Value::Array(values) => {
+            for value in values {
+                json_texts(path, value, texts);
+            }
+        }
+        json::Value::Object(object) => {
+            for (key, value) in object {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+
+                json_texts(&path, value, texts);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Highlighter {
+    /// Creates a highlighter for the terms of the query in the fields of the options.
+    pub fn create(
+        index: &Index,
+        query: &dyn Query,
+        options: &HighlightOptions,
+    ) -> Result<Highlighter, ServiceError> {
+        let schema = index.schema();
+
+        let mut query_terms = BTreeMap::new();
+        let mut phrases = vec![];
+        collect_terms(query, &mut query_terms, &mut phrases);
+
+        let mut fields = vec![];
+
+        for field in options.fields(&schema)? {
+            let is_json = matches!(
+                schema.get_field_entry(field).field_type(),
+                FieldType::JsonObject(_)
+            );
+
+            let mut terms: HashMap<String, PathTerms> = HashMap::new();
+
+            for term in query_terms.keys().filter(|term| term.field() == field) {
+                if let Some((path, text)) = term_text(term) {
+                    terms
+                        .entry(path)
+                        .or_default()
+                        .terms
+                        .insert(text.to_string());
+                }
+            }
+
+            for phrase in phrases.iter().filter(|phrase| phrase.field() == field) {
+                let Some(phrase_terms) = phrase
+                    .terms()
+                    .iter()
+                    .map(|(offset, term)| term_text(term).map(|text| (*offset, text)))
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+
+                // Every term of a phrase has the same path.
+                let path = phrase_terms[0].1 .0.clone();
+
+                terms.entry(path).or_default().phrases.push(PhraseTerms {
+                    terms: phrase_terms
+                        .into_iter()
+                        .map(|(offset, (_, text))| (offset, text.to_string()))
+                        .collect(),
+                    slop: phrase.slop(),
+                });
+            }
+
+            // Fields without query terms never have highlights.
+            if terms.is_empty() {
+                continue;
+            }
+
+            fields.push(FieldTerms {
+                field,
+                name: schema.get_field_name(field).to_string(),
+                is_json,
+                tokenizer: index
+                    .tokenizer_for_field(field)
+                    .map_err(ServiceError::internal_error)?,
+                terms,
+            });
+        }
+
+        Ok(Highlighter {
+            options: options.clone(),
+            fields,
+        })
+    }
+
+    /// Returns the formatted snippets of the document by field name, or by dotted path for the
+    /// leaves of JSON fields. Fields without matches have no snippet.
+    pub fn highlight(&self, document: &Document) -> HashMap<String, json::Value> {
+        let mut snippets = HashMap::new();
+
+        for field in &self.fields {
+            let values = document.get_all(field.field);
+
+            let texts = if field.is_json {
+                let mut texts = BTreeMap::new();
+
+                for object in values.filter_map(Value::as_json) {
+                    for (key, value) in object {
+                        json_texts(key, value, &mut texts);
+                    }
+                }
+
+                texts
+            } else {
+                BTreeMap::from([(String::new(), values.filter_map(Value::as_text).collect())])
+            };
+
+            for (path, values) in texts {
+                let Some(terms) = field.terms.get(&path) else {
+                    continue;
+                };
+
+                let Some(snippet) =
+                    self.options
+                        .format(&self.snippets(&field.tokenizer, terms, &values))
+                else {
+                    continue;
+                };
+
+                let name = if path.is_empty() {
+                    field.name.clone()
+                } else {
+                    format!("{}.{path}", field.name)
+                };
+
+                snippets.insert(name, snippet);
+            }
+        }
+
+        snippets
+    }
+
+    /// Returns the fragments with the most matches across the values of a field, in the order
+    /// they appear in the document.
+    fn snippets(
+        &self,
+        tokenizer: &TextAnalyzer,
+        terms: &PathTerms,
+        values: &[&str],
+    ) -> Vec<Snippet> {
+        let mut candidates: Vec<Candidate> = values
+            .iter()
+            .enumerate()
+            .flat_map(|(value, text)| self.candidates(tokenizer, terms, value, text))
+            .collect();
+
+        // Among fragments with as many matches, the first ones are selected.
+        candidates.sort_by(|a, b| {
+            b.highlighted
+                .len()
+                .cmp(&a.highlighted.len())
+                .then_with(|| (a.value, a.range.start).cmp(&(b.value, b.range.start)))
+        });
+        candidates.truncate(self.options.number_of_fragments);
+        candidates.sort_by_key(|candidate| (candidate.value, candidate.range.start));
+
+        candidates
+            .into_iter()
+            .map(|candidate| {
+                let start = candidate.range.start;
+
+                Snippet {
+                    fragment: values[candidate.value][candidate.range].to_string(),
+                    highlighted: candidate
+                        .highlighted
+                        .into_iter()
+                        .map(|range| range.start - start..range.end - start)
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// Splits the text into consecutive fragments of at most the fragment size and returns those
+    /// with matches.
+    fn candidates(
+        &self,
+        tokenizer: &TextAnalyzer,
+        terms: &PathTerms,
+        value: usize,
+        text: &str,
+    ) -> Vec<Candidate> {
+        let mut candidates = vec![];
+        let mut candidate = Candidate {
+            value,
+            range: 0..0,
+            highlighted: vec![],
+        };
+
+        let mut tokens = vec![];
+        let mut stream = tokenizer.token_stream(text);
+
+        while let Some(token) = stream.next() {
+            tokens.push(token.clone());
+        }
+
+        let mut matched: Vec<bool> = tokens
+            .iter()
+            .map(|token| terms.terms.contains(&token.text))
+            .collect();
+
+        for phrase in &terms.phrases {
+            mark_phrase(&tokens, phrase, &mut matched);
+        }
+
+        for (token, matched) in tokens.iter().zip(matched) {
+            let fragment = &text[candidate.range.start..token.offset_to];
+
+            if exceeds(fragment, self.options.fragment_size) {
+                let next = Candidate {
+                    value,
+                    range: token.offset_from..token.offset_from,
+                    highlighted: vec![],
+                };
+
+                let previous = std::mem::replace(&mut candidate, next);
+
+                if !previous.highlighted.is_empty() {
+                    candidates.push(previous);
+                }
+            }
+
+            candidate.range.end = token.offset_to;
+
+            if matched {
+                candidate
+                    .highlighted
+                    .push(token.offset_from..token.offset_to);
+            }
+        }
+
+        if !candidate.highlighted.is_empty() {
+            candidates.push(candidate);
+        }
+
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{STRING, TEXT};
+    use tantivy::{doc, Index};
+
+    use super::*;
+    use crate::query::SearchQuery;
+    use crate::test_utils::*;
+
+    const TEXT_VALUE: &str = "Zen and the art of motorcycle maintenance. A long road trip across \
+                              the country. The art of caring about <quality> work.";
+
+    fn index() -> Index {
+        let mut schema = Schema::builder();
+        schema.add_text_field("title", TEXT);
+        schema.add_text_field("author", TEXT);
+        schema.add_text_field("isbn", STRING);
+        schema.add_json_field("props", TEXT);
+
+        Index::create_in_ram(schema.build())
+    }
+
+    fn highlighter(index: &Index, options: &HighlightOptions, query: &str) -> Highlighter {
+        let query = SearchQuery::from(query).parse(index).unwrap();
+
+        Highlighter::create(index, query.as_ref(), options).unwrap()
+    }
+
+    fn options(value: json::Value) -> HighlightOptions {
+        json::from_value(value).unwrap()
+    }
+
+    fn highlight(value: json::Value, query: &str, text: &str) -> Option<json::Value> {
+        let index = index();
+        let title = index.schema().get_field("title").unwrap();
+
+        highlighter(&index, &options(value), query)
+            .highlight(&doc!(title => text))
+            .remove("title")
+    }
+
+    #[test]
+    fn html_with_custom_tags() {
+        let html = highlight(
+            json!({ "pre_tag": "<mark>", "post_tag": "</mark>" }),
+            "quality",
+            TEXT_VALUE,
+        )
+        .unwrap();
+
+        assert!(html
+            .as_str()
+            .unwrap()
+            .contains("&lt;<mark>quality</mark>&gt;"));
+    }
+
+    #[test]
+    fn several_fragments_in_text_order() {
+        let value = highlight(
+            json!({ "fragment_size": 30, "number_of_fragments": 3, "format": "offsets" }),
+            "art",
+            TEXT_VALUE,
+        )
+        .unwrap();
+
+        let fragments: Vec<Fragment> = json::from_value(value).unwrap();
+
+        assert_eq!(2, fragments.len());
+        assert!(TEXT_VALUE.find(&fragments[0].text) < TEXT_VALUE.find(&fragments[1].text));
+        assert!(fragments.iter().all(|fragment| fragment.text.len() <= 30));
+    }
+
+    #[test]
+    fn best_fragment_has_most_matches() {
+        let text = "Zen and motorcycles, nothing else. Art, art and art.";
+        let html = highlight(json!({ "fragment_size": 20 }), "art", text).unwrap();
+
+        assert_eq!(json!("<b>art</b> and <b>art</b>"), html);
+    }
+
+    #[test]
+    fn only_phrase_matches() {
+        assert_eq!(None, highlight(json!({}), "\"art motorcycle\"", TEXT_VALUE));
+
+        let html = highlight(
+            json!({ "fragment_size": 40 }),
+            "\"art motorcycle\"~1",
+            TEXT_VALUE,
+        )
+        .unwrap();
+
+        assert_eq!(json!("Zen and the <b>art</b> of <b>motorcycle</b>"), html);
+
+        let html = highlight(
+            json!({}),
+            "\"art motorcycle\"~1",
+            "Art of zen, art of motorcycles and art of motorcycle maintenance",
+        )
+        .unwrap();
+
+        assert_eq!(
+            json!("Art of zen, art of motorcycles and <b>art</b> of <b>motorcycle</b> maintenance"),
+            html
+        );
+    }
+
+    #[test]
+    fn offsets_are_in_characters() {
+        let value = highlight(json!({ "format": "offsets" }), "zen", "Café zen").unwrap();
+
+        assert_eq!(
+            json!([{ "text": "Café zen", "highlights": [[5, 8]] }]),
+            value
+        );
+    }
+
+    #[test]
+    fn only_fields_with_matches() {
+        let index = index();
+        let schema = index.schema();
+        let title = schema.get_field("title").unwrap();
+        let author = schema.get_field("author").unwrap();
+        let isbn = schema.get_field("isbn").unwrap();
+
+        let document = doc!(
+            title => "Zen and the art of motorcycle maintenance",
+            author => "Robert Pirsig",
+            isbn => "0-06-058946-4",
+        );
+
+        let highlighter = highlighter(&index, &options(json!({})), "title:zen 0-06-058946-4");
+        let snippets = highlighter.highlight(&document);
+
+        assert_eq!(
+            HashMap::from([
+                (
+                    "title".to_string(),
+                    json!("<b>Zen</b> and the art of motorcycle maintenance")
+                ),
+                ("isbn".to_string(), json!("<b>0-06-058946-4</b>")),
+            ]),
+            snippets
+        );
+    }
+
+    #[test]
+    fn json_leaves_by_path() {
+        let index = index();
+        let props = index.schema().get_field("props").unwrap();
+
+        let document = doc!(props => json!({
+            "description": "A book about quality",
+            "tags": ["quality", "zen"],
+            "publisher": { "name": "Quality Books" },
+            "pages": 400,
+        }).as_object().unwrap().clone());
+
+        let highlighter = highlighter(
+            &index,
+            &options(json!({})),
+            "props.description:quality props.publisher.name:quality",
+        );
+
+        assert_eq!(
+            HashMap::from([
+                (
+                    "props.description".to_string(),
+                    json!("A book about <b>quality</b>")
+                ),
+                (
+                    "props.publisher.name".to_string(),
+                    json!("<b>Quality</b> Books")
+                ),
+            ]),
+            highlighter.highlight(&document)
+        );
+    }
+
+    #[test]
+    fn reject_invalid_options() {
+        let mut schema = Schema::builder();
+        schema.add_text_field("title", TEXT);
+        schema.add_i64_field("year", tantivy::schema::INDEXED);
+        schema.add_json_field("props", TEXT);
+        schema.add_json_field("meta", tantivy::schema::STORED);
+        let schema = schema.build();
+
+        assert_eq!(
+            2,
+            HighlightOptions::default().fields(&schema).unwrap().len()
+        );
+
+        let invalid = [
+            json!({ "fields": ["year"] }),
+            json!({ "fields": ["unknown"] }),
+            json!({ "fields": ["meta"] }),
+            json!({ "fragment_size": 0 }),
+            json!({ "number_of_fragments": 11 }),
+        ];
+
+        for value in invalid {
+            assert_eq!(400, options(value).fields(&schema).unwrap_err().status());
+        }
+    }
+}
@@ -1,60 +1,308 @@
-use std::fs;
-use std::path::Path;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::{fs, io};
 
+use async_trait::async_trait;
+use tantivy::collector::DocSetCollector;
 use tantivy::merge_policy::DefaultMergePolicy;
-use tantivy::schema::Field;
+use tantivy::query::AllQuery;
+use tantivy::schema::{Field, Schema};
 use tantivy::{Index, IndexWriter};
 
+use crate::alias::AliasRegistry;
+use crate::config::{Config, ConfigError};
 use crate::directory::PatheryDirectory;
 use crate::pagination::SegmentMeta;
-use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::quota::Limits;
+use crate::schema::registry::SchemaRegistry;
+use crate::schema::{FieldConfig, SchemaLoader};
+use crate::search_doc::SearchDocId;
 use crate::service::ServiceError;
-use crate::worker::async_delete::client::{AsyncDeleteClient, LambdaAsyncDeleteClient};
+use crate::util;
+use crate::worker::async_delete::client::{self, AsyncDeleteClient};
+use crate::worker::async_delete::job::AsyncDeleteJob;
 
+/// Name of the directory under the data directory that deleted indexes named after their index
+/// are moved to until the async delete worker removes them.
+const DELETED_DIR: &str = ".deleted";
+
+/// Name of the directory under the data directory that holds the directories of indexes, named
+/// `{index_id}.{id}`. The [`AliasRegistry`] resolves each index id to its current directory.
+const PHYSICAL_DIR: &str = ".physical";
+
+/// Checks that an index id can be used as a directory name.
+pub fn validate_index_id(index_id: &str) -> Result<(), ServiceError> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+
+    if index_id.is_empty() || index_id.len() > 128 || !index_id.chars().all(valid_char) {
+        return Err(ServiceError::invalid_request(&format!(
+            "Invalid index id [{index_id}], expected 1 to 128 alphanumeric, '-' or '_' characters"
+        )));
+    }
+
+    Ok(())
+}
+
+#[async_trait]
 pub trait IndexLoader: Send + Sync {
-    fn load_index(
+    /// Opens an index, creating it if it does not exist yet. Creating an index registers its
+    /// directory, so requests that only read check that the index exists first.
+    async fn load_index(
+        &self,
+        index_id: &str,
+        segments: Option<Vec<SegmentMeta>>,
+    ) -> Result<Index, ServiceError>;
+
+    /// Returns the directory the index is currently read from. The directory stays readable for a
+    /// while after the index is reindexed, see [`IndexLoader::promote_index`].
+    async fn index_directory(&self, index_id: &str) -> Result<String, ServiceError>;
+
+    /// Opens the index in a directory returned by [`IndexLoader::index_directory`], failing if the
+    /// directory has been deleted since.
+    async fn load_index_directory(
         &self,
         index_id: &str,
+        directory: &str,
         segments: Option<Vec<SegmentMeta>>,
     ) -> Result<Index, ServiceError>;
+
+    /// Creates an empty index, failing if the index already exists.
+    async fn create_index(&self, index_id: &str) -> Result<Index, ServiceError>;
+
+    async fn index_exists(&self, index_id: &str) -> Result<bool, ServiceError>;
+
+    /// Returns the ids of all existing indexes in sorted order.
+    async fn list_indexes(&self) -> Result<Vec<String>, ServiceError>;
+
+    /// Removes an index so that it can no longer be loaded, failing if it does not exist.
+    async fn delete_index(&self, index_id: &str) -> Result<(), ServiceError>;
+
+    /// Creates an empty index with the latest schema for the index id, without affecting the
+    /// existing index until it is promoted.
+    async fn stage_index(&self, index_id: &str) -> Result<StagedIndex, ServiceError>;
+
+    /// Atomically replaces the index with the staged index. The previous directory of the index is
+    /// removed by the async delete worker after the delivery delay of its queue, so that queries
+    /// that already resolved it, such as the pages of a pagination token, keep reading it until
+    /// then.
+    async fn promote_index(&self, staged: StagedIndex) -> Result<(), ServiceError>;
+
+    /// Removes a staged index that will not be promoted.
+    async fn discard_index(&self, staged: StagedIndex) -> Result<(), ServiceError>;
+
+    /// Resolves an index id or alias to the index that writes go to.
+    async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError>;
+
+    /// Resolves an index id or alias to the indexes that reads go to.
+    async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError>;
+
+    /// Resolves an index id or alias to the index that reads go to, for requests that describe a
+    /// single index.
+    async fn resolve_read_index(&self, name: &str) -> Result<String, ServiceError> {
+        match self.resolve_read_indexes(name).await?.as_slice() {
+            [index_id] => Ok(index_id.clone()),
+            _ => Err(ServiceError::invalid_request(&format!(
+                "Alias [{name}] reads from more than one index"
+            ))),
+        }
+    }
+
+    /// Returns the limits of an index, see [`crate::quota`].
+    async fn index_limits(&self, index_id: &str) -> Result<Limits, ServiceError>;
+
+    /// Returns the fields the schema of an index is built from.
+    async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError>;
+
+    /// Returns the latest schema of an index, which indexes that do not exist yet are created
+    /// with.
+    async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError>;
+}
+
+/// An index created by [`IndexLoader::stage_index`] that is not used by the index id yet.
+pub struct StagedIndex {
+    index_id: String,
+
+    index: Index,
+
+    /// Directory of the index, relative to the data directory.
+    directory: PathBuf,
+}
+
+impl StagedIndex {
+    pub fn index_id(&self) -> &str {
+        &self.index_id
+    }
+
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
 }
 
 pub struct LambdaIndexLoader {
-    schema_loader: SchemaProvider,
+    data_dir: PathBuf,
+
+    schema_loader: Arc<dyn SchemaLoader>,
+
+    aliases: Arc<AliasRegistry>,
 
     async_delete_client: Arc<dyn AsyncDeleteClient>,
 }
 
 impl LambdaIndexLoader {
-    pub async fn create() -> Self {
-        let async_delete_client = LambdaAsyncDeleteClient::create(None).await;
-        let async_delete_client = Arc::new(async_delete_client);
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let registry = SchemaRegistry::create(config).await?;
+        let aliases = registry.aliases();
+
+        Ok(Self::new(
+            config.data_dir(),
+            Arc::new(registry),
+            aliases,
+            client::create_client(config).await?,
+        ))
+    }
 
+    /// Creates a loader storing each index in a directory under `data_dir` that the index id is
+    /// resolved to with `aliases`.
+    pub fn new<P>(
+        data_dir: P,
+        schema_loader: Arc<dyn SchemaLoader>,
+        aliases: Arc<AliasRegistry>,
+        async_delete_client: Arc<dyn AsyncDeleteClient>,
+    ) -> Self
+    where
+        P: Into<PathBuf>,
+    {
         Self {
-            schema_loader: SchemaProvider::lambda(),
+            data_dir: data_dir.into(),
+            schema_loader,
+            aliases,
             async_delete_client,
         }
     }
 }
 
+impl LambdaIndexLoader {
+    /// Returns the directory currently used by the index, relative to the data directory. Indexes
+    /// created before their directory was registered use a directory named after the index.
+    async fn current_directory(&self, index_id: &str) -> Result<PathBuf, ServiceError> {
+        validate_index_id(index_id)?;
+
+        Ok(self
+            .aliases
+            .index_directory(index_id)
+            .await?
+            .map_or_else(|| PathBuf::from(index_id), PathBuf::from))
+    }
+
+    async fn index_path(&self, index_id: &str) -> Result<PathBuf, ServiceError> {
+        Ok(self.data_dir.join(self.current_directory(index_id).await?))
+    }
+
+    /// Returns a new directory for the index, relative to the data directory.
+    ///
+    /// Directories are never reused, so a directory that is pending deletion cannot be confused
+    /// with a later index of the same id.
+    fn new_directory(index_id: &str) -> PathBuf {
+        Path::new(PHYSICAL_DIR).join(format!("{index_id}.{}", util::generate_id()))
+    }
+
+    /// Points the index at a directory with a single write to the alias registry, so that readers
+    /// see either the previous or the new directory.
+    async fn flip_directory(&self, index_id: &str, directory: &Path) -> Result<(), ServiceError> {
+        let directory = directory
+            .to_str()
+            .expect("index directory should be valid unicode");
+
+        self.aliases.put_index_directory(index_id, directory).await
+    }
+
+    /// Checks that a directory is one that [`IndexLoader::index_directory`] can return for the
+    /// index, so that it cannot be used to open other paths.
+    fn check_directory(index_id: &str, directory: &Path) -> Result<(), ServiceError> {
+        let mut components = directory.components().map(|c| c.as_os_str().to_str());
+
+        let valid = match (components.next(), components.next(), components.next()) {
+            (Some(Some(name)), None, None) => name == index_id,
+            (Some(Some(PHYSICAL_DIR)), Some(Some(name)), None) => {
+                name.split_once('.').is_some_and(|(name_id, suffix)| {
+                    name_id == index_id && validate_index_id(suffix).is_ok()
+                })
+            }
+            _ => false,
+        };
+
+        if !valid {
+            return Err(ServiceError::invalid_request(&format!(
+                "Invalid directory for index [{index_id}]"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the index in a new directory and points the index at it.
+    async fn create_in_new_dir(&self, index_id: &str) -> Result<Index, ServiceError> {
+        let directory = Self::new_directory(index_id);
+
+        let directory_path = self.data_dir.join(&directory);
+
+        let index = self.create_in_dir(index_id, &directory_path).await?;
+
+        if let Err(err) = self.flip_directory(index_id, &directory).await {
+            drop(index);
+            fs::remove_dir_all(directory_path)?;
+            return Err(err);
+        }
+
+        Ok(index)
+    }
+
+    /// Moves an index directory out of the way and schedules it for deletion.
+    ///
+    /// The directory is moved first so it disappears immediately while readers that already
+    /// opened its files can keep using them until the async delete worker runs. Only directories
+    /// named after their index need this, others disappear with their registered directory.
+    async fn retire_directory(&self, index_id: &str, path: &Path) -> Result<(), ServiceError> {
+        let deleted_dir = self.data_dir.join(DELETED_DIR);
+        fs::create_dir_all(&deleted_dir)?;
+
+        let deleted_path = deleted_dir.join(format!("{index_id}-{}", util::generate_id()));
+        fs::rename(path, &deleted_path)?;
+
+        self.async_delete_client
+            .submit_job(AsyncDeleteJob::fs_delete(deleted_path))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn create_in_dir(
+        &self,
+        index_id: &str,
+        directory_path: &Path,
+    ) -> Result<Index, ServiceError> {
+        let schema = self.schema_loader.load_schema(index_id).await?;
+        fs::create_dir_all(directory_path)?;
+        Index::create_in_dir(directory_path, schema).map_err(ServiceError::internal_error)
+    }
+}
+
+#[async_trait]
 impl IndexLoader for LambdaIndexLoader {
-    fn load_index(
+    async fn load_index(
         &self,
         index_id: &str,
         segments: Option<Vec<SegmentMeta>>,
     ) -> Result<Index, ServiceError> {
-        let directory_path = format!("/mnt/pathery-data/{index_id}");
+        let directory_path = self.index_path(index_id).await?;
 
         let mut index = if let Ok(existing_dir) =
             PatheryDirectory::open(&directory_path, &self.async_delete_client, segments)
         {
             Index::open(existing_dir).expect("Index should be openable")
         } else {
-            fs::create_dir(&directory_path).expect("Directory should be creatable");
-            let schema = self.schema_loader.load_schema(index_id)?;
-            Index::create_in_dir(Path::new(&directory_path), schema)
-                .expect("Index should be creatable")
+            self.create_in_new_dir(index_id).await?
         };
 
         index
@@ -63,12 +311,188 @@ impl IndexLoader for LambdaIndexLoader {
 
         Ok(index)
     }
+
+    async fn index_directory(&self, index_id: &str) -> Result<String, ServiceError> {
+        Ok(self
+            .current_directory(index_id)
+            .await?
+            .to_str()
+            .expect("index directory should be valid unicode")
+            .to_string())
+    }
+
+    async fn load_index_directory(
+        &self,
+        index_id: &str,
+        directory: &str,
+        segments: Option<Vec<SegmentMeta>>,
+    ) -> Result<Index, ServiceError> {
+        validate_index_id(index_id)?;
+        Self::check_directory(index_id, Path::new(directory))?;
+
+        let directory_path = self.data_dir.join(directory);
+
+        if !directory_path.join("meta.json").exists() {
+            return Err(ServiceError::invalid_request(&format!(
+                "Directory [{directory}] of index [{index_id}] no longer exists"
+            )));
+        }
+
+        let directory =
+            PatheryDirectory::open(&directory_path, &self.async_delete_client, segments)
+                .map_err(ServiceError::internal_error)?;
+
+        let mut index = Index::open(directory).map_err(ServiceError::internal_error)?;
+
+        index
+            .set_default_multithread_executor()
+            .expect("default multithread executor should succeed");
+
+        Ok(index)
+    }
+
+    async fn create_index(&self, index_id: &str) -> Result<Index, ServiceError> {
+        if self.index_exists(index_id).await? {
+            return Err(ServiceError::conflict(&format!(
+                "Index [{index_id}] already exists"
+            )));
+        }
+
+        self.create_in_new_dir(index_id).await
+    }
+
+    async fn index_exists(&self, index_id: &str) -> Result<bool, ServiceError> {
+        Ok(self.index_path(index_id).await?.join("meta.json").exists())
+    }
+
+    async fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+        let mut index_ids = BTreeSet::new();
+
+        // Indexes created before their directory was registered have a directory named after
+        // them, others a directory named after them with a suffix. The directories of deleted and
+        // reindexed indexes remain until the async delete worker runs, so every index id found is
+        // checked against its current directory.
+        for dir in [self.data_dir.clone(), self.data_dir.join(PHYSICAL_DIR)] {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            for entry in entries {
+                let entry = entry?;
+
+                if let Some(name) = entry.file_name().to_str() {
+                    let index_id = name.split_once('.').map_or(name, |(index_id, _)| index_id);
+
+                    if validate_index_id(index_id).is_ok()
+                        && !index_ids.contains(index_id)
+                        && self.index_exists(index_id).await?
+                    {
+                        index_ids.insert(index_id.to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(index_ids.into_iter().collect())
+    }
+
+    async fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+        if !self.index_exists(index_id).await? {
+            return Err(ServiceError::not_found(&format!(
+                "Index [{index_id}] not found"
+            )));
+        }
+
+        match self.aliases.index_directory(index_id).await? {
+            Some(directory) => {
+                self.aliases.delete_index_directory(index_id).await?;
+
+                self.async_delete_client
+                    .submit_job(AsyncDeleteJob::fs_delete(self.data_dir.join(directory)))
+                    .await?;
+
+                Ok(())
+            }
+            None => {
+                self.retire_directory(index_id, &self.data_dir.join(index_id))
+                    .await
+            }
+        }
+    }
+
+    async fn stage_index(&self, index_id: &str) -> Result<StagedIndex, ServiceError> {
+        validate_index_id(index_id)?;
+
+        let directory = Self::new_directory(index_id);
+
+        let index = self
+            .create_in_dir(index_id, &self.data_dir.join(&directory))
+            .await?;
+
+        Ok(StagedIndex {
+            index_id: index_id.to_string(),
+            index,
+            directory,
+        })
+    }
+
+    async fn promote_index(&self, staged: StagedIndex) -> Result<(), ServiceError> {
+        let index_id = staged.index_id();
+        let previous_path = self.index_path(index_id).await?;
+
+        self.flip_directory(index_id, &staged.directory).await?;
+
+        // The previous directory is not moved, so that readers can keep opening it until the
+        // async delete worker runs.
+        if previous_path.exists() {
+            self.async_delete_client
+                .submit_job(AsyncDeleteJob::fs_delete(previous_path))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn discard_index(&self, staged: StagedIndex) -> Result<(), ServiceError> {
+        let path = self.data_dir.join(&staged.directory);
+
+        // The staged index was never visible to readers so it can be removed right away.
+        drop(staged);
+        fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+
+    async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError> {
+        self.schema_loader.resolve_write_index(name).await
+    }
+
+    async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError> {
+        self.schema_loader.resolve_read_indexes(name).await
+    }
+
+    async fn index_limits(&self, index_id: &str) -> Result<Limits, ServiceError> {
+        self.schema_loader.index_limits(index_id).await
+    }
+
+    async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError> {
+        self.schema_loader.load_fields(index_id).await
+    }
+
+    async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError> {
+        self.schema_loader.load_schema(index_id).await
+    }
 }
 
 pub trait IndexExt {
     fn default_writer(&self) -> IndexWriter;
 
     fn id_field(&self) -> Field;
+
+    /// Returns the ids of every document in the index.
+    fn document_ids(&self) -> Result<Vec<SearchDocId>, ServiceError>;
 }
 
 impl IndexExt for Index {
@@ -90,6 +514,185 @@ impl IndexExt for Index {
             .get_field("__id")
             .expect("__id field should exist")
     }
+
+    fn document_ids(&self) -> Result<Vec<SearchDocId>, ServiceError> {
+        let searcher = self
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+
+        let id_field = self.id_field();
+
+        searcher
+            .search(&AllQuery, &DocSetCollector)
+            .map_err(ServiceError::internal_error)?
+            .into_iter()
+            .map(|address| {
+                let doc = searcher
+                    .doc(address)
+                    .map_err(ServiceError::internal_error)?;
+
+                let id = doc
+                    .get_first(id_field)
+                    .and_then(|value| value.as_text())
+                    .expect("__id should be stored");
+
+                Ok(SearchDocId::parse(id))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::schema::FieldConfig;
+    use crate::test_utils::*;
+    use crate::worker::async_delete::client::LocalAsyncDeleteClient;
+
+    fn fields(value: json::Value) -> Vec<FieldConfig> {
+        json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn promote_staged_index_with_new_schema() {
+        let ctx = setup();
+        let data_dir = std::env::temp_dir().join(format!("pathery-index-{}", util::generate_id()));
+
+        let registry = Arc::new(SchemaRegistry::new(
+            Arc::new(ctx.document_store().clone()),
+            None,
+        ));
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+        let year = json!({ "name": "year", "kind": "i64", "flags": ["INDEXED"] });
+
+        registry
+            .register("books", fields(json!([title])))
+            .await
+            .unwrap();
+
+        let async_delete_client = Arc::new(LocalAsyncDeleteClient::with_delay(Duration::ZERO));
+
+        let loader = LambdaIndexLoader::new(
+            &data_dir,
+            registry.clone(),
+            registry.aliases(),
+            async_delete_client.clone(),
+        );
+
+        loader.create_index("books").await.unwrap();
+
+        registry
+            .register("books", fields(json!([title, year])))
+            .await
+            .unwrap();
+
+        let staged = loader.stage_index("books").await.unwrap();
+
+        // The existing index is used until the staged index is promoted.
+        let index = loader.load_index("books", None).await.unwrap();
+        assert!(index.schema().get_field("year").is_none());
+
+        let previous = loader.index_directory("books").await.unwrap();
+
+        loader.promote_index(staged).await.unwrap();
+
+        let index = loader.load_index("books", None).await.unwrap();
+        assert!(index.schema().get_field("year").is_some());
+        assert_ne!(previous, loader.index_directory("books").await.unwrap());
+        assert_eq!(vec!["books"], loader.list_indexes().await.unwrap());
+
+        // The previous directory stays readable until the async delete worker removes it.
+        let index = loader
+            .load_index_directory("books", &previous, None)
+            .await
+            .unwrap();
+        assert!(index.schema().get_field("year").is_none());
+
+        async_delete_client.flush().await.unwrap();
+
+        let err = loader
+            .load_index_directory("books", &previous, None)
+            .await
+            .unwrap_err();
+        assert_eq!(400, err.status());
+
+        loader.delete_index("books").await.unwrap();
+
+        assert!(!loader.index_exists("books").await.unwrap());
+        assert!(loader.list_indexes().await.unwrap().is_empty());
+
+        async_delete_client.flush().await.unwrap();
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn promoted_index_keeps_previous_directory_until_delay() {
+        let ctx = setup();
+        let data_dir = std::env::temp_dir().join(format!("pathery-index-{}", util::generate_id()));
+
+        let registry = Arc::new(SchemaRegistry::new(
+            Arc::new(ctx.document_store().clone()),
+            None,
+        ));
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+
+        registry
+            .register("books", fields(json!([title])))
+            .await
+            .unwrap();
+
+        let async_delete_client = Arc::new(LocalAsyncDeleteClient::spawn());
+
+        let loader = LambdaIndexLoader::new(
+            &data_dir,
+            registry.clone(),
+            registry.aliases(),
+            async_delete_client.clone(),
+        );
+
+        loader.create_index("books").await.unwrap();
+
+        // A pagination token pins the directory that was current when the first page was read.
+        let pinned = loader.index_directory("books").await.unwrap();
+
+        let staged = loader.stage_index("books").await.unwrap();
+        loader.promote_index(staged).await.unwrap();
+        assert_ne!(pinned, loader.index_directory("books").await.unwrap());
+
+        // Give the delete task a chance to run, the delay keeps it from deleting the directory.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(loader
+            .load_index_directory("books", &pinned, None)
+            .await
+            .is_ok());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn directories_must_belong_to_the_index() {
+        for directory in ["books", ".physical/books.1", ".physical/books.1/"] {
+            assert!(LambdaIndexLoader::check_directory("books", Path::new(directory)).is_ok());
+        }
+
+        for directory in [
+            "other",
+            "../books",
+            "/books",
+            ".physical/other.1",
+            ".physical/books-v2.1",
+            ".physical/books.1/..",
+            ".physical/books.",
+        ] {
+            assert!(LambdaIndexLoader::check_directory("books", Path::new(directory)).is_err());
+        }
+    }
 }
 
 #[cfg(test)]
@@ -99,11 +702,14 @@ pub mod test_util {
 
     use super::*;
 
-    #[derive(Debug)]
     pub struct TestIndexLoader {
-        schema_loader: SchemaProvider,
+        schema_loader: Arc<SchemaRegistry>,
+
+        /// The directory and index of each index id.
+        table: Arc<Mutex<HashMap<String, (String, Index)>>>,
 
-        table: Arc<Mutex<HashMap<String, Index>>>,
+        /// Indexes replaced by a promoted index, by directory.
+        previous: Arc<Mutex<HashMap<String, Index>>>,
     }
 
     impl Clone for TestIndexLoader {
@@ -111,33 +717,178 @@ pub mod test_util {
             Self {
                 schema_loader: self.schema_loader.clone(),
                 table: self.table.clone(),
+                previous: self.previous.clone(),
             }
         }
     }
 
+    #[async_trait]
     impl IndexLoader for TestIndexLoader {
-        fn load_index(
+        async fn load_index(
             &self,
             index_id: &str,
             _segments: Option<Vec<SegmentMeta>>,
         ) -> Result<Index, ServiceError> {
-            let mut table = self.table.lock().unwrap();
+            let schema = self.schema_loader.load_schema(index_id).await?;
+
+            let existing = self
+                .table
+                .lock()
+                .unwrap()
+                .get(index_id)
+                .map(|(_, index)| index.clone());
 
-            let entry = (*table).entry(index_id.into());
+            if let Some(index) = existing {
+                return Ok(index);
+            }
 
-            let schema = self.schema_loader.load_schema(index_id)?;
+            self.schema_loader
+                .aliases()
+                .put_index_directory(index_id, index_id)
+                .await?;
 
-            let index = entry.or_insert_with(|| Index::create_in_ram(schema));
+            let mut table = self.table.lock().unwrap();
+
+            let (_, index) = table
+                .entry(index_id.into())
+                .or_insert_with(|| (index_id.to_string(), Index::create_in_ram(schema)));
 
             Ok(index.clone())
         }
+
+        async fn index_directory(&self, index_id: &str) -> Result<String, ServiceError> {
+            let table = self.table.lock().unwrap();
+
+            Ok(table
+                .get(index_id)
+                .map_or(index_id.to_string(), |(directory, _)| directory.clone()))
+        }
+
+        async fn load_index_directory(
+            &self,
+            index_id: &str,
+            directory: &str,
+            _segments: Option<Vec<SegmentMeta>>,
+        ) -> Result<Index, ServiceError> {
+            let current = self.table.lock().unwrap().get(index_id).cloned();
+
+            match current {
+                Some((current, index)) if current == directory => Ok(index),
+                _ => self
+                    .previous
+                    .lock()
+                    .unwrap()
+                    .get(directory)
+                    .cloned()
+                    .ok_or_else(|| {
+                        ServiceError::invalid_request(&format!(
+                            "Directory [{directory}] of index [{index_id}] no longer exists"
+                        ))
+                    }),
+            }
+        }
+
+        async fn create_index(&self, index_id: &str) -> Result<Index, ServiceError> {
+            validate_index_id(index_id)?;
+
+            if self.index_exists(index_id).await? {
+                return Err(ServiceError::conflict(&format!(
+                    "Index [{index_id}] already exists"
+                )));
+            }
+
+            self.load_index(index_id, None).await
+        }
+
+        async fn index_exists(&self, index_id: &str) -> Result<bool, ServiceError> {
+            Ok(self.table.lock().unwrap().contains_key(index_id))
+        }
+
+        async fn list_indexes(&self) -> Result<Vec<String>, ServiceError> {
+            let mut index_ids: Vec<_> = self.table.lock().unwrap().keys().cloned().collect();
+            index_ids.sort();
+            Ok(index_ids)
+        }
+
+        async fn delete_index(&self, index_id: &str) -> Result<(), ServiceError> {
+            self.table
+                .lock()
+                .unwrap()
+                .remove(index_id)
+                .ok_or_else(|| ServiceError::not_found(&format!("Index [{index_id}] not found")))?;
+
+            self.schema_loader
+                .aliases()
+                .delete_index_directory(index_id)
+                .await
+        }
+
+        async fn stage_index(&self, index_id: &str) -> Result<StagedIndex, ServiceError> {
+            let schema = self.schema_loader.load_schema(index_id).await?;
+
+            Ok(StagedIndex {
+                index_id: index_id.to_string(),
+                index: Index::create_in_ram(schema),
+                directory: PathBuf::from(format!("{index_id}-{}", util::generate_id())),
+            })
+        }
+
+        async fn promote_index(&self, staged: StagedIndex) -> Result<(), ServiceError> {
+            let directory = staged
+                .directory
+                .to_str()
+                .expect("staged directory should be valid unicode")
+                .to_string();
+
+            self.schema_loader
+                .aliases()
+                .put_index_directory(&staged.index_id, &directory)
+                .await?;
+
+            let previous = self
+                .table
+                .lock()
+                .unwrap()
+                .insert(staged.index_id, (directory, staged.index));
+
+            if let Some((directory, index)) = previous {
+                self.previous.lock().unwrap().insert(directory, index);
+            }
+
+            Ok(())
+        }
+
+        async fn discard_index(&self, _staged: StagedIndex) -> Result<(), ServiceError> {
+            Ok(())
+        }
+
+        async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError> {
+            self.schema_loader.resolve_write_index(name).await
+        }
+
+        async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError> {
+            self.schema_loader.resolve_read_indexes(name).await
+        }
+
+        async fn index_limits(&self, index_id: &str) -> Result<Limits, ServiceError> {
+            self.schema_loader.index_limits(index_id).await
+        }
+
+        async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError> {
+            self.schema_loader.load_fields(index_id).await
+        }
+
+        async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError> {
+            self.schema_loader.load_schema(index_id).await
+        }
     }
 
     impl TestIndexLoader {
-        pub fn create(schema_loader: SchemaProvider) -> Self {
+        pub fn create(schema_loader: Arc<SchemaRegistry>) -> Self {
             TestIndexLoader {
                 schema_loader,
                 table: Arc::new(Mutex::new(HashMap::new())),
+                previous: Arc::new(Mutex::new(HashMap::new())),
             }
         }
     }
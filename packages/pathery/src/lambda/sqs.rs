@@ -1,5 +1,19 @@
 use aws_lambda_events::event::sqs;
+pub use aws_lambda_events::event::streams::{SqsBatchItemFailure, SqsEventResponse};
 pub use lambda_runtime::Error;
 use lambda_runtime::LambdaEvent;
 
 pub type SqsEvent = LambdaEvent<sqs::SqsEvent>;
+
+/// Builds a partial batch response reporting the given message ids as failed.
+pub fn batch_item_failures<I>(message_ids: I) -> SqsEventResponse
+where I: IntoIterator<Item = String> {
+    SqsEventResponse {
+        batch_item_failures: message_ids
+            .into_iter()
+            .map(|message_id| SqsBatchItemFailure {
+                item_identifier: Some(message_id),
+            })
+            .collect(),
+    }
+}
@@ -1,24 +1,41 @@
+pub mod alias;
+pub mod auth;
+pub mod coerce;
+pub mod config;
 pub mod directory;
 pub mod function;
+pub mod geo;
+pub mod highlight;
 pub mod index;
 pub mod lambda;
 pub mod pagination;
+pub mod query;
+pub mod quota;
 pub mod schema;
 pub mod search_doc;
 pub mod serialize;
+pub mod server;
 pub mod service;
 pub mod store;
 pub mod util;
+pub mod vector;
 pub mod worker;
 
 pub(crate) use serde_json as json;
 
 #[cfg(test)]
 pub mod test_utils {
+    use std::sync::Arc;
+
     pub use serde_json as json;
     pub use serde_json::json;
+    use tantivy::collector::TopDocs;
+    use tantivy::query::Query;
+    use tantivy::schema::{BytesOptions, Field, STORED, STRING};
+    use tantivy::{doc, Index};
 
     use crate::index::test_util::TestIndexLoader;
+    use crate::schema::registry::SchemaRegistry;
     use crate::schema::{SchemaLoader, SchemaProvider};
     use crate::search_doc::SearchDoc;
     use crate::store::document::test_util::TestDocumentStore;
@@ -30,6 +47,8 @@ pub mod test_utils {
     pub struct TestContext {
         schema_loader: SchemaProvider,
 
+        schema_registry: Arc<SchemaRegistry>,
+
         document_store: TestDocumentStore,
 
         writer_client: TestIndexWriterClient,
@@ -39,12 +58,16 @@ pub mod test_utils {
 
     impl TestContext {
         pub async fn with_documents(self, index_id: &str, docs: Vec<json::Value>) -> TestContext {
-            let schema = self.schema_loader.load_schema(index_id).unwrap();
+            let schema = self.schema_loader.load_schema(index_id).await.unwrap();
             let documents: Vec<_> = docs
                 .into_iter()
                 .map(|value| SearchDoc::from_json(&schema, value).unwrap())
                 .collect();
-            let doc_refs = self.document_store.save_documents(documents).await.unwrap();
+            let doc_refs = self
+                .document_store
+                .save_documents(index_id, documents)
+                .await
+                .unwrap();
             let mut job = Job::create(index_id);
             for doc_ref in doc_refs {
                 job.index_doc(doc_ref);
@@ -57,6 +80,12 @@ pub mod test_utils {
             &self.schema_loader
         }
 
+        /// Registry that falls back to [`TestContext::schema_loader`] and is used by the index
+        /// loader, so aliases registered with it are resolved by the index loader.
+        pub fn schema_registry(&self) -> &Arc<SchemaRegistry> {
+            &self.schema_registry
+        }
+
         pub fn document_store(&self) -> &TestDocumentStore {
             &self.document_store
         }
@@ -70,6 +99,52 @@ pub mod test_utils {
         }
     }
 
+    /// Builds an in-RAM index with a fast bytes field `name` holding a document per entry of
+    /// `docs`, plus a document with id `none` that has no value for the field.
+    pub fn bytes_field_index(name: &str, docs: Vec<(&str, Vec<u8>)>) -> (Index, Field) {
+        let mut schema = tantivy::schema::Schema::builder();
+        let id = schema.add_text_field("__id", STRING | STORED);
+        let field = schema.add_bytes_field(name, BytesOptions::default().set_fast());
+        let index = Index::create_in_ram(schema.build());
+
+        let mut writer = index.writer_with_num_threads(1, 10_000_000).unwrap();
+        writer.add_document(doc!(id => "none")).unwrap();
+        for (doc_id, value) in docs {
+            writer
+                .add_document(doc!(id => doc_id, field => value))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+
+        (index, field)
+    }
+
+    /// Returns the ids of the documents of `index` matching `query` in score order.
+    pub fn search_ids(index: &Index, query: &dyn Query) -> Vec<String> {
+        let searcher = index.reader().unwrap().searcher();
+        let id = index.schema().get_field("__id").unwrap();
+
+        searcher
+            .search(query, &TopDocs::with_limit(10))
+            .unwrap()
+            .into_iter()
+            .map(|(_, address)| {
+                searcher
+                    .doc(address)
+                    .unwrap()
+                    .get_first(id)
+                    .and_then(|value| value.as_text())
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Document content with `value` as its only field `name`.
+    pub fn field_content(name: &str, value: json::Value) -> json::Map<String, json::Value> {
+        json::Map::from_iter([(name.to_string(), value)])
+    }
+
     pub fn setup() -> TestContext {
         let config = json!({
             "indexes": [
@@ -84,7 +159,8 @@ pub mod test_utils {
                         {
                             "name": "author",
                             "kind": "text",
-                            "flags": ["TEXT"]
+                            "flags": ["TEXT"],
+                            "multi": true
                         },
                         {
                             "name": "isbn",
@@ -110,6 +186,62 @@ pub mod test_utils {
                             "name": "props",
                             "kind": "json",
                             "flags": ["TEXT"]
+                        },
+                        {
+                            "name": "embedding",
+                            "kind": "vector",
+                            "dimensions": 3,
+                            "metric": "cosine"
+                        },
+                        {
+                            "name": "location",
+                            "kind": "geo_point"
+                        },
+                        {
+                            "name": "copies",
+                            "kind": "u64",
+                            "flags": ["INDEXED", "FAST"]
+                        },
+                        {
+                            "name": "price",
+                            "kind": "f64",
+                            "flags": ["INDEXED", "FAST"]
+                        },
+                        {
+                            "name": "in_print",
+                            "kind": "bool",
+                            "flags": ["INDEXED", "FAST"]
+                        },
+                        {
+                            "name": "checksum",
+                            "kind": "bytes",
+                            "flags": ["INDEXED", "STORED"]
+                        },
+                        {
+                            "name": "publisher_ip",
+                            "kind": "ip",
+                            "flags": ["INDEXED"]
+                        },
+                        {
+                            "name": "details",
+                            "kind": "object",
+                            "fields": [
+                                {
+                                    "name": "publisher",
+                                    "kind": "text",
+                                    "flags": ["STRING"]
+                                },
+                                {
+                                    "name": "pages",
+                                    "kind": "i64",
+                                    "flags": ["INDEXED", "FAST"]
+                                },
+                                {
+                                    "name": "published",
+                                    "kind": "date",
+                                    "flags": ["INDEXED", "FAST"]
+                                }
+                            ]
                         }
                     ]
                 }
@@ -118,12 +250,18 @@ pub mod test_utils {
 
         let schema_loader = SchemaProvider::from_json(config);
 
-        let index_loader = TestIndexLoader::create(schema_loader.clone());
-
         let document_store = TestDocumentStore::create();
 
+        let schema_registry = Arc::new(SchemaRegistry::new(
+            Arc::new(document_store.clone()),
+            Some(schema_loader.clone()),
+        ));
+
+        let index_loader = TestIndexLoader::create(schema_registry.clone());
+
         TestContext {
             schema_loader,
+            schema_registry,
             writer_client: TestIndexWriterClient::create(
                 index_loader.clone(),
                 document_store.clone(),
@@ -4,6 +4,8 @@ use base64::Engine;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::query::{SearchQuery, Sort};
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct SegmentMeta {
     segment_id: String,
@@ -12,50 +14,133 @@ pub struct SegmentMeta {
     extra: HashMap<String, Value>,
 }
 
+/// Pagination state of one of the indexes searched by a query.
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-pub struct PaginationToken {
-    query: String,
+pub struct IndexPagination {
+    index_id: String,
+    /// Directory the index was read from when the token was created, which later pages keep
+    /// reading even if the index is reindexed in between. Tokens created before directories were
+    /// pinned read the current directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    directory: Option<String>,
     segments: Vec<SegmentMeta>,
     partition_state: Vec<usize>,
 }
 
-impl PaginationToken {
-    pub fn new<T>(query: T, total_partitions: usize) -> PaginationToken
-    where T: Into<String> {
-        let mut partition_state: Vec<usize> = vec![];
-        partition_state.resize(total_partitions, 0);
-        PaginationToken {
-            query: query.into(),
-            segments: vec![],
-            partition_state,
-        }
+impl IndexPagination {
+    pub fn index_id(&self) -> &str {
+        &self.index_id
     }
 
-    pub fn import_segments_json(&mut self, segments_json: Value) {
-        let segments: Vec<SegmentMeta> = serde_json::from_value(segments_json).unwrap();
-        self.segments = segments;
+    pub fn directory(&self) -> Option<&str> {
+        self.directory.as_deref()
+    }
+
+    pub fn total_partitions(&self) -> usize {
+        self.partition_state.len()
     }
 
     pub fn segments_for_partition(&self, n: usize) -> Vec<SegmentMeta> {
         self.segments
             .iter()
             .enumerate()
-            .filter(|(idx, _)| (idx + n) % self.partition_state.len() == 0)
+            .filter(|(idx, _)| (idx + n).is_multiple_of(self.partition_state.len()))
             .map(|(_, x)| x.clone())
             .collect()
     }
 
-    pub fn inc_offset(&mut self, partition_n: usize) {
-        let value = self.partition_state.get_mut(partition_n).unwrap();
-        *value = *value + 1;
-    }
-
     pub fn get_offset(&self, partition_n: usize) -> usize {
         *self.partition_state.get(partition_n).unwrap()
     }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct PaginationToken {
+    query: SearchQuery,
+    indexes: Vec<IndexPagination>,
+    /// Number of matches of a hybrid query already returned. Hybrid queries fuse the top matches
+    /// of every partition again for each page, so partition offsets are not used.
+    #[serde(default)]
+    fused_offset: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sort: Option<Sort>,
+}
+
+impl PaginationToken {
+    pub fn new<T>(query: T) -> PaginationToken
+    where T: Into<SearchQuery> {
+        PaginationToken {
+            query: query.into(),
+            indexes: vec![],
+            fused_offset: 0,
+            sort: None,
+        }
+    }
+
+    /// Sorts every page of matches of the query.
+    pub fn with_sort(mut self, sort: Option<Sort>) -> PaginationToken {
+        self.sort = sort;
+        self
+    }
 
-    pub fn get_query(&self) -> String {
-        self.query.to_string()
+    pub fn sort(&self) -> Option<&Sort> {
+        self.sort.as_ref()
+    }
+
+    /// Adds an index to the searched indexes, pinning the directory and segments it is searched
+    /// with.
+    pub fn add_index<T>(
+        &mut self,
+        index_id: T,
+        directory: T,
+        total_partitions: usize,
+        segments_json: Value,
+    ) where
+        T: Into<String>,
+    {
+        let segments: Vec<SegmentMeta> = serde_json::from_value(segments_json).unwrap();
+
+        self.indexes.push(IndexPagination {
+            index_id: index_id.into(),
+            directory: Some(directory.into()),
+            segments,
+            partition_state: vec![0; total_partitions],
+        });
+    }
+
+    /// Adds an index that does not exist yet, which has no partitions to search.
+    pub fn add_missing_index<T>(&mut self, index_id: T)
+    where T: Into<String> {
+        self.indexes.push(IndexPagination {
+            index_id: index_id.into(),
+            directory: None,
+            segments: vec![],
+            partition_state: vec![],
+        });
+    }
+
+    pub fn indexes(&self) -> &[IndexPagination] {
+        &self.indexes
+    }
+
+    pub fn inc_offset(&mut self, index_n: usize, partition_n: usize) {
+        let value = self.indexes[index_n]
+            .partition_state
+            .get_mut(partition_n)
+            .unwrap();
+        *value += 1;
+    }
+
+    pub fn fused_offset(&self) -> usize {
+        self.fused_offset
+    }
+
+    pub fn inc_fused_offset(&mut self, n: usize) {
+        self.fused_offset += n;
+    }
+
+    pub fn get_query(&self) -> SearchQuery {
+        self.query.clone()
     }
 
     pub fn serialize(&self) -> String {
@@ -64,13 +149,14 @@ impl PaginationToken {
         base64::engine::general_purpose::STANDARD.encode(compressed)
     }
 
-    pub fn parse<T>(from: T) -> PaginationToken
+    /// Parses a serialized token, returning `None` if it is malformed.
+    pub fn parse<T>(from: T) -> Option<PaginationToken>
     where T: Into<String> {
         let decoded = base64::engine::general_purpose::STANDARD
             .decode(from.into())
-            .unwrap();
-        let decompressed = zstd::decode_all(decoded.as_slice()).unwrap();
-        serde_json::from_slice(&decompressed).unwrap()
+            .ok()?;
+        let decompressed = zstd::decode_all(decoded.as_slice()).ok()?;
+        serde_json::from_slice(&decompressed).ok()
     }
 }
 
@@ -82,19 +168,48 @@ mod tests {
 
     #[test]
     fn test_round_trip() {
-        let mut token = PaginationToken::new("foobar", 2);
-        token.import_segments_json(json!([
-            {
-                "segment_id": "abc123",
-                "foo": "bar"
-            }
-        ]));
+        let mut token = PaginationToken::new("foobar");
+        token.add_index(
+            "test",
+            "test",
+            2,
+            json!([
+                {
+                    "segment_id": "abc123",
+                    "foo": "bar"
+                }
+            ]),
+        );
 
         let token_str = token.serialize();
         let parsed = PaginationToken::parse(token_str);
 
         println!("{:?}", parsed);
 
-        assert_eq!(token, parsed);
+        assert_eq!(Some(token), parsed);
+    }
+
+    #[test]
+    fn offsets_are_tracked_per_index() {
+        let mut token = PaginationToken::new("foobar");
+        token.add_index("a", "a", 2, json!([]));
+        token.add_index("b", "b", 1, json!([]));
+
+        token.inc_offset(0, 1);
+        token.inc_offset(1, 0);
+        token.inc_offset(1, 0);
+
+        let indexes = token.indexes();
+
+        assert_eq!(
+            vec![0, 1],
+            vec![indexes[0].get_offset(0), indexes[0].get_offset(1)]
+        );
+        assert_eq!(2, indexes[1].get_offset(0));
+    }
+
+    #[test]
+    fn parse_malformed_token() {
+        assert_eq!(None, PaginationToken::parse("not a token"));
     }
 }
@@ -0,0 +1,1089 @@
+//! Queries of the query API, given as a query string or as a structured query.
+//!
+//! Query strings use tantivy's query syntax, extended with a slop for phrases: `"art motorcycle"~2`
+//! matches the words of the phrase in order, with up to 2 other words between consecutive words.
+//! Phrases need positions, which are indexed for `TEXT` and `POSITIONS` fields, see
+//! [`crate::schema::TextFieldOption`].
+//!
+//! Structured queries also search vector and geo point fields, see [`crate::vector`] and
+//! [`crate::geo`], match the values of fields coerced like the values of documents, see
+//! [`crate::coerce`], and fuse the matches of several queries in hybrid queries.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use tantivy::query::{
+    BooleanQuery, MoreLikeThisQuery, Occur, PhraseQuery, Query, QueryParser, RangeQuery, TermQuery,
+    Weight,
+};
+use tantivy::schema::{Cardinality, Field, FieldType, IndexRecordOption, Schema, Type, Value};
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::{Index, Searcher, Term};
+
+use crate::coerce::coerce_value;
+use crate::geo::{GeoFilter, GeoPoint, GeoQuery};
+use crate::json;
+use crate::schema::{FieldConfig, SchemaExt, VectorMetric};
+use crate::service::ServiceError;
+use crate::vector::VectorQuery;
+
+/// Ends the path of a term in a JSON field, which is followed by the type code of its value.
+const JSON_END_OF_PATH: u8 = 0;
+
+/// Separates the segments of the path of a term in a JSON field.
+const JSON_PATH_SEGMENT_SEP: char = '\u{1}';
+
+/// Default rank constant of hybrid queries, as suggested by the reciprocal rank fusion paper.
+const DEFAULT_RANK_CONSTANT: u32 = 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SearchQuery {
+    /// A query in tantivy's query syntax, with optional phrase slops.
+    QueryString(String),
+
+    Structured(StructuredQuery),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum StructuredQuery {
+    /// The words of the text in order, with up to `slop` other words between consecutive words.
+    Phrase {
+        field: String,
+
+        text: String,
+
+        #[serde(default)]
+        slop: u32,
+    },
+
+    /// Documents matching every `must` query and no `must_not` query. Without `must` queries,
+    /// documents must match at least one `should` query.
+    Bool {
+        #[serde(default)]
+        must: Vec<SearchQuery>,
+
+        #[serde(default)]
+        should: Vec<SearchQuery>,
+
+        #[serde(default)]
+        must_not: Vec<SearchQuery>,
+    },
+
+    /// Documents sharing the most distinctive words of the text fields of `like`.
+    MoreLikeThis {
+        /// Text or list of texts by field name.
+        like: json::Map<String, json::Value>,
+
+        /// Ids of documents that never match, such as the document the others are similar to.
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+
+    /// Documents with a value of an indexed field, such as `true` in a bool field or an address in
+    /// an IP field. Text is matched as it is indexed, so only `STRING` text fields match whole
+    /// values.
+    Term { field: String, value: json::Value },
+
+    /// Documents with a value of an indexed field within bounds. Numbers, dates and IP addresses
+    /// compare in order, and text and bytes compare lexicographically.
+    Range {
+        field: String,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gt: Option<json::Value>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        gte: Option<json::Value>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lt: Option<json::Value>,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lte: Option<json::Value>,
+    },
+
+    /// Documents with a vector in a vector field, ranked by their similarity to `vector`.
+    Knn {
+        field: String,
+
+        vector: Vec<f32>,
+
+        /// Defaults to the metric of the field, see [`SearchQuery::resolve`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metric: Option<VectorMetric>,
+    },
+
+    /// Documents with a point in a geo point field within `distance_km` of `origin`.
+    GeoDistance {
+        field: String,
+
+        origin: GeoPoint,
+
+        distance_km: f64,
+    },
+
+    /// Documents with a point in a geo point field within a box. Boxes whose left longitude is
+    /// greater than their right longitude cross the antimeridian.
+    GeoBoundingBox {
+        field: String,
+
+        top_left: GeoPoint,
+
+        bottom_right: GeoPoint,
+    },
+
+    /// Documents matching any of the queries, ranked by the reciprocal rank fusion of their ranks
+    /// in the matches of each query, `1 / (rank_constant + rank)` summed over the queries.
+    ///
+    /// Ranks are only known across every partition of the searched indexes, so the matches are
+    /// fused by [`crate::service::index::QueryIndexService`]. Parsed on its own, a hybrid query
+    /// matches like a `bool` query of `should` queries.
+    Hybrid {
+        queries: Vec<SearchQuery>,
+
+        #[serde(default = "default_rank_constant")]
+        rank_constant: u32,
+    },
+}
+
+fn default_rank_constant() -> u32 {
+    DEFAULT_RANK_CONSTANT
+}
+
+/// Orders the matches of a query by other than their score.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Sort {
+    /// Nearest matches first, by the distance of their point in a geo point field to `origin`.
+    /// Matches without a point come last.
+    GeoDistance { field: String, origin: GeoPoint },
+
+    /// Matches by the value of a fast date or numeric field, see
+    /// [`FieldConfig::is_fast_numeric`]. Documents without a value sort as zero.
+    Value {
+        field: String,
+
+        #[serde(default)]
+        order: SortOrder,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Sort {
+    /// Checks the sort against the fields of the index.
+    pub fn check(&self, fields: &[FieldConfig]) -> Result<(), ServiceError> {
+        match self {
+            Sort::GeoDistance { field, origin } => {
+                check_geo_field(fields, field)?;
+                origin.validate()
+            }
+            Sort::Value { field, .. } => {
+                match fields.iter().find(|config| config.name() == field) {
+                    Some(config) if config.is_fast_numeric() && !config.is_multi() => Ok(()),
+                    Some(config) if config.is_multi() => Err(ServiceError::invalid_request(
+                        &format!("Field [{field}] is multi-valued and cannot be sorted by"),
+                    )),
+                    _ => Err(ServiceError::invalid_request(&format!(
+                        "Field [{field}] is not a fast date or numeric field"
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+impl From<&str> for SearchQuery {
+    fn from(query: &str) -> Self {
+        SearchQuery::QueryString(query.to_string())
+    }
+}
+
+impl From<String> for SearchQuery {
+    fn from(query: String) -> Self {
+        SearchQuery::QueryString(query)
+    }
+}
+
+impl SearchQuery {
+    /// Parses the query for the index. Query strings search the indexed text fields by default.
+    pub fn parse(&self, index: &Index) -> Result<Box<dyn Query>, ServiceError> {
+        match self {
+            SearchQuery::QueryString(query) => {
+                let (query, phrases) = extract_phrases(query)?;
+
+                with_slops(parse(index, &query)?, index, &phrases, &mut 0)
+            }
+            SearchQuery::Structured(query) => query.parse(index),
+        }
+    }
+
+    /// Checks the queries of vector and geo point fields against the fields of the index, and sets
+    /// the metric of `knn` queries without one to the metric of their field.
+    ///
+    /// Vector and geo point fields are both stored as bytes, so the tantivy schema of an index
+    /// does not tell them apart, nor know the dimensions and metric of vectors. Queries are
+    /// resolved before they are sent to partitions.
+    pub fn resolve(self, fields: &[FieldConfig]) -> Result<SearchQuery, ServiceError> {
+        let SearchQuery::Structured(query) = self else {
+            return Ok(self);
+        };
+
+        let resolve_all = |queries: Vec<SearchQuery>| {
+            queries
+                .into_iter()
+                .map(|query| query.resolve(fields))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let query = match query {
+            StructuredQuery::Knn {
+                field,
+                vector,
+                metric,
+            } => {
+                let (dimensions, field_metric) = fields
+                    .iter()
+                    .find_map(|config| match config {
+                        FieldConfig::VectorFieldConfig {
+                            name,
+                            dimensions,
+                            metric,
+                        } if *name == field => Some((*dimensions, *metric)),
+                        _ => None,
+                    })
+                    .ok_or_else(|| {
+                        ServiceError::invalid_request(&format!(
+                            "Field [{field}] is not a vector field"
+                        ))
+                    })?;
+
+                if vector.len() != dimensions {
+                    return Err(ServiceError::invalid_request(&format!(
+                        "Vector field [{field}] expects {dimensions} dimensions, got {}",
+                        vector.len()
+                    )));
+                }
+
+                StructuredQuery::Knn {
+                    field,
+                    vector,
+                    metric: Some(metric.unwrap_or(field_metric)),
+                }
+            }
+            StructuredQuery::GeoDistance { ref field, .. }
+            | StructuredQuery::GeoBoundingBox { ref field, .. } => {
+                check_geo_field(fields, field)?;
+                query
+            }
+            StructuredQuery::Bool {
+                must,
+                should,
+                must_not,
+            } => StructuredQuery::Bool {
+                must: resolve_all(must)?,
+                should: resolve_all(should)?,
+                must_not: resolve_all(must_not)?,
+            },
+            StructuredQuery::Hybrid {
+                queries,
+                rank_constant,
+            } => StructuredQuery::Hybrid {
+                queries: resolve_all(queries)?,
+                rank_constant,
+            },
+            query => query,
+        };
+
+        Ok(SearchQuery::Structured(query))
+    }
+}
+
+impl StructuredQuery {
+    fn parse(&self, index: &Index) -> Result<Box<dyn Query>, ServiceError> {
+        match self {
+            StructuredQuery::Phrase { field, text, slop } => {
+                // Quotes end a phrase in the query syntax and are never part of a term.
+                let text = text.replace('"', " ");
+                let query = parse(index, &format!("{field}:\"{text}\""))?;
+
+                with_slops(query, index, &[QuotedPhrase { text, slop: *slop }], &mut 0)
+            }
+            StructuredQuery::Bool {
+                must,
+                should,
+                must_not,
+            } => {
+                let mut clauses = vec![];
+
+                for (occur, queries) in [
+                    (Occur::Must, must),
+                    (Occur::Should, should),
+                    (Occur::MustNot, must_not),
+                ] {
+                    for query in queries {
+                        clauses.push((occur, query.parse(index)?));
+                    }
+                }
+
+                if clauses.is_empty() {
+                    return Err(ServiceError::invalid_request(
+                        "Bool query must have at least one query",
+                    ));
+                }
+
+                Ok(Box::new(BooleanQuery::new(clauses)))
+            }
+            StructuredQuery::MoreLikeThis { like, exclude } => more_like_this(index, like, exclude),
+            StructuredQuery::Term { field, value } => {
+                let schema = index.schema();
+                let field = indexed_field(&schema, field)?;
+
+                Ok(Box::new(TermQuery::new(
+                    value_term(&schema, field, value)?,
+                    IndexRecordOption::Basic,
+                )))
+            }
+            StructuredQuery::Range {
+                field: name,
+                gt,
+                gte,
+                lt,
+                lte,
+            } => {
+                let schema = index.schema();
+                let field = indexed_field(&schema, name)?;
+
+                let bound =
+                    |exclusive: &Option<json::Value>, inclusive: &Option<json::Value>| match (
+                        exclusive, inclusive,
+                    ) {
+                        (Some(_), Some(_)) => Err(ServiceError::invalid_request(&format!(
+                            "Range of field [{name}] must not have both an exclusive and an \
+                             inclusive bound on the same side"
+                        ))),
+                        (Some(value), None) => {
+                            Ok(Bound::Excluded(value_term(&schema, field, value)?))
+                        }
+                        (None, Some(value)) => {
+                            Ok(Bound::Included(value_term(&schema, field, value)?))
+                        }
+                        (None, None) => Ok(Bound::Unbounded),
+                    };
+
+                let lower = bound(gt, gte)?;
+                let upper = bound(lt, lte)?;
+
+                if matches!((&lower, &upper), (Bound::Unbounded, Bound::Unbounded)) {
+                    return Err(ServiceError::invalid_request(&format!(
+                        "Range of field [{name}] must have at least one bound"
+                    )));
+                }
+
+                let value_type = schema.get_field_entry(field).field_type().value_type();
+
+                Ok(Box::new(RangeQuery::new_term_bounds(
+                    field, value_type, &lower, &upper,
+                )))
+            }
+            StructuredQuery::Knn {
+                field,
+                vector,
+                metric,
+            } => {
+                let vector_field = bytes_field(index, field, "vector")?;
+
+                let metric = metric.ok_or_else(|| {
+                    ServiceError::invalid_request(&format!(
+                        "Vector query of field [{field}] must have a metric"
+                    ))
+                })?;
+
+                Ok(Box::new(VectorQuery::new(
+                    vector_field,
+                    vector.clone(),
+                    metric,
+                )))
+            }
+            StructuredQuery::GeoDistance {
+                field,
+                origin,
+                distance_km,
+            } => {
+                let geo_field = bytes_field(index, field, "geo point")?;
+
+                origin.validate()?;
+
+                if *distance_km <= 0.0 {
+                    return Err(ServiceError::invalid_request(
+                        "geo_distance must have a positive distance_km",
+                    ));
+                }
+
+                Ok(Box::new(GeoQuery::new(
+                    geo_field,
+                    GeoFilter::Distance {
+                        origin: *origin,
+                        distance_km: *distance_km,
+                    },
+                )))
+            }
+            StructuredQuery::GeoBoundingBox {
+                field,
+                top_left,
+                bottom_right,
+            } => {
+                let geo_field = bytes_field(index, field, "geo point")?;
+
+                top_left.validate()?;
+                bottom_right.validate()?;
+
+                if top_left.lat < bottom_right.lat {
+                    return Err(ServiceError::invalid_request(
+                        "geo_bounding_box must have a top_left north of its bottom_right",
+                    ));
+                }
+
+                Ok(Box::new(GeoQuery::new(
+                    geo_field,
+                    GeoFilter::BoundingBox {
+                        top_left: *top_left,
+                        bottom_right: *bottom_right,
+                    },
+                )))
+            }
+            StructuredQuery::Hybrid { queries, .. } => {
+                if queries.is_empty() {
+                    return Err(ServiceError::invalid_request(
+                        "Hybrid query must have at least one query",
+                    ));
+                }
+
+                let clauses = queries
+                    .iter()
+                    .map(|query| Ok((Occur::Should, query.parse(index)?)))
+                    .collect::<Result<_, ServiceError>>()?;
+
+                Ok(Box::new(BooleanQuery::new(clauses)))
+            }
+        }
+    }
+}
+
+/// A phrase query that exposes its terms and slop, which [`PhraseQuery`] keeps private, so
+/// snippets only highlight the matches of the phrase.
+#[derive(Clone, Debug)]
+pub struct Phrase {
+    terms: Vec<(usize, Term)>,
+
+    slop: u32,
+
+    query: PhraseQuery,
+}
+
+impl Phrase {
+    /// Creates a phrase of at least two terms of the same field, at their offsets in the phrase.
+    pub fn new(mut terms: Vec<(usize, Term)>, slop: u32) -> Phrase {
+        terms.sort_by_key(|(offset, _)| *offset);
+
+        let mut query = PhraseQuery::new_with_offset(terms.clone());
+        query.set_slop(slop);
+
+        Phrase { terms, slop, query }
+    }
+
+    pub fn field(&self) -> Field {
+        self.query.field()
+    }
+
+    pub fn terms(&self) -> &[(usize, Term)] {
+        &self.terms
+    }
+
+    pub fn slop(&self) -> u32 {
+        self.slop
+    }
+}
+
+impl Query for Phrase {
+    fn weight(
+        &self,
+        searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn Weight>> {
+        self.query.weight(searcher, scoring_enabled)
+    }
+
+    fn query_terms(&self, terms: &mut BTreeMap<Term, bool>) {
+        self.query.query_terms(terms)
+    }
+}
+
+/// Returns the dotted path and text of a text term. The path is empty for terms of text fields.
+pub fn term_text(term: &Term) -> Option<(String, &str)> {
+    if term.typ() != Type::Json {
+        return term.as_str().map(|text| (String::new(), text));
+    }
+
+    let bytes = term.value_bytes();
+    let end = bytes.iter().position(|byte| *byte == JSON_END_OF_PATH)?;
+    let path = std::str::from_utf8(&bytes[..end]).ok()?;
+
+    let (&type_code, text) = bytes[end + 1..].split_first()?;
+
+    if Type::from_code(type_code) != Some(Type::Str) {
+        return None;
+    }
+
+    Some((
+        path.replace(JSON_PATH_SEGMENT_SEP, "."),
+        std::str::from_utf8(text).ok()?,
+    ))
+}
+
+fn parse(index: &Index, query: &str) -> Result<Box<dyn Query>, ServiceError> {
+    let schema = index.schema();
+
+    let default_fields = schema
+        .fields()
+        .filter(|(_, entry)| entry.is_indexed() && matches!(entry.field_type(), FieldType::Str(_)))
+        .map(|(field, _)| field)
+        .collect();
+
+    QueryParser::for_index(index, default_fields)
+        .parse_query(query)
+        .map_err(|err| ServiceError::invalid_request(&err.to_string()))
+}
+
+/// Returns the bytes fast field that values of a `kind` field, such as vectors, are stored in.
+pub(crate) fn bytes_field(index: &Index, name: &str, kind: &str) -> Result<Field, ServiceError> {
+    let schema = index.schema();
+
+    schema
+        .get_field(name)
+        .filter(|field| {
+            let entry = schema.get_field_entry(*field);
+            entry.is_fast() && matches!(entry.field_type(), FieldType::Bytes(_))
+        })
+        .ok_or_else(|| {
+            ServiceError::invalid_request(&format!("Field [{name}] is not a {kind} field"))
+        })
+}
+
+/// Returns the fast field of a single-valued date or numeric field, whose values matches are
+/// sorted by.
+pub(crate) fn fast_value_field(index: &Index, name: &str) -> Result<Field, ServiceError> {
+    let schema = index.schema();
+
+    schema
+        .get_field(name)
+        .filter(|field| match schema.get_field_entry(*field).field_type() {
+            FieldType::U64(options)
+            | FieldType::I64(options)
+            | FieldType::F64(options)
+            | FieldType::Date(options) => {
+                options.get_fastfield_cardinality() == Some(Cardinality::SingleValue)
+            }
+            _ => false,
+        })
+        .ok_or_else(|| {
+            ServiceError::invalid_request(&format!(
+                "Field [{name}] is not a single-valued fast date or numeric field"
+            ))
+        })
+}
+
+fn indexed_field(schema: &Schema, name: &str) -> Result<Field, ServiceError> {
+    schema
+        .get_field(name)
+        .filter(|field| schema.get_field_entry(*field).is_indexed())
+        .ok_or_else(|| {
+            ServiceError::invalid_request(&format!("Field [{name}] is not an indexed field"))
+        })
+}
+
+/// Returns the term of a value of a field, coerced like the values of documents.
+fn value_term(schema: &Schema, field: Field, value: &json::Value) -> Result<Term, ServiceError> {
+    let name = schema.get_field_name(field);
+    let field_type = schema.get_field_entry(field).field_type();
+
+    let invalid = |reason: String| {
+        ServiceError::invalid_request(&format!(
+            "Invalid value {value} of field [{name}], {reason}"
+        ))
+    };
+
+    let coerced = coerce_value(field_type, value)
+        .map_err(|expected| invalid(format!("expected {expected}")))?;
+
+    match field_type
+        .value_from_json(coerced)
+        .map_err(|err| invalid(err.to_string()))?
+    {
+        Value::Str(text) => Ok(Term::from_field_text(field, &text)),
+        Value::U64(value) => Ok(Term::from_field_u64(field, value)),
+        Value::I64(value) => Ok(Term::from_field_i64(field, value)),
+        Value::F64(value) => Ok(Term::from_field_f64(field, value)),
+        Value::Date(value) => Ok(Term::from_field_date(field, value)),
+        Value::Bytes(value) => Ok(Term::from_field_bytes(field, &value)),
+        _ => Err(ServiceError::invalid_request(&format!(
+            "Field [{name}] does not support term and range queries"
+        ))),
+    }
+}
+
+fn check_geo_field(fields: &[FieldConfig], name: &str) -> Result<(), ServiceError> {
+    let is_geo = fields.iter().any(
+        |field| matches!(field, FieldConfig::GeoPointFieldConfig { name: geo } if geo == name),
+    );
+
+    if !is_geo {
+        return Err(ServiceError::invalid_request(&format!(
+            "Field [{name}] is not a geo point field"
+        )));
+    }
+
+    Ok(())
+}
+
+fn more_like_this(
+    index: &Index,
+    like: &json::Map<String, json::Value>,
+    exclude: &[String],
+) -> Result<Box<dyn Query>, ServiceError> {
+    let schema = index.schema();
+    let mut fields = vec![];
+
+    for (name, value) in like {
+        let field = schema
+            .get_field(name)
+            .filter(|field| {
+                let entry = schema.get_field_entry(*field);
+                entry.is_indexed() && matches!(entry.field_type(), FieldType::Str(_))
+            })
+            .ok_or_else(|| {
+                ServiceError::invalid_request(&format!(
+                    "Field [{name}] is not an indexed text field"
+                ))
+            })?;
+
+        let texts = match value {
+            json::Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+
+        let values = texts
+            .into_iter()
+            .map(|text| match text {
+                json::Value::String(text) => Ok(Value::Str(text.clone())),
+                _ => Err(ServiceError::invalid_request(&format!(
+                    "Field [{name}] of more_like_this must be a text or a list of texts"
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        fields.push((field, values));
+    }
+
+    if fields.is_empty() {
+        return Err(ServiceError::invalid_request(
+            "more_like_this must have at least one field",
+        ));
+    }
+
+    // Documents such as product pages are short, so every word of them is considered.
+    let query = MoreLikeThisQuery::builder()
+        .with_min_doc_frequency(1)
+        .with_min_term_frequency(1)
+        .with_document_fields(fields);
+
+    let id_field = schema.id_field();
+
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, Box::new(query))];
+
+    for id in exclude {
+        clauses.push((
+            Occur::MustNot,
+            Box::new(TermQuery::new(
+                Term::from_field_text(id_field, id),
+                IndexRecordOption::Basic,
+            )),
+        ));
+    }
+
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// A phrase of a query string and its slop.
+struct QuotedPhrase {
+    text: String,
+
+    slop: u32,
+}
+
+/// Removes the slops of the phrases of a query string, which tantivy's query syntax does not
+/// support, and returns the phrases in order.
+fn extract_phrases(query: &str) -> Result<(String, Vec<QuotedPhrase>), ServiceError> {
+    let mut stripped = String::with_capacity(query.len());
+    let mut phrases = vec![];
+    let mut rest = query;
+
+    while let Some(start) = rest.find('"') {
+        let Some(len) = rest[start + 1..].find('"') else {
+            break;
+        };
+
+        let end = start + len + 2;
+        let text = rest[start + 1..end - 1].to_string();
+
+        stripped.push_str(&rest[..end]);
+        rest = &rest[end..];
+
+        let mut slop = 0;
+
+        if let Some(after) = rest.strip_prefix('~') {
+            let digits = after
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(after.len());
+
+            slop = after[..digits].parse().map_err(|_| {
+                ServiceError::invalid_request(
+                    "Phrase slop must be a number of words, e.g. \"art motorcycle\"~2",
+                )
+            })?;
+
+            rest = &after[digits..];
+        }
+
+        phrases.push(QuotedPhrase { text, slop });
+    }
+
+    stripped.push_str(rest);
+
+    Ok((stripped, phrases))
+}
+
+/// Returns the offsets of the terms in the phrase if the phrase has exactly these terms.
+fn phrase_offsets(tokenizer: &TextAnalyzer, phrase: &str, terms: &[Term]) -> Option<Vec<usize>> {
+    let mut offsets = vec![];
+    let mut tokens = tokenizer.token_stream(phrase);
+
+    while let Some(token) = tokens.next() {
+        let term = terms.get(offsets.len())?;
+
+        if term_text(term).map(|(_, text)| text) != Some(token.text.as_str()) {
+            return None;
+        }
+
+        offsets.push(token.position);
+    }
+
+    (offsets.len() == terms.len()).then_some(offsets)
+}
+
+/// Replaces the phrase queries of a parsed query by [`Phrase`] queries with the slop of the quoted
+/// phrase they were parsed from.
+///
+/// Phrases are parsed into phrase queries in order, one per default field, so each phrase query
+/// is matched with the first phrase from the cursor that has its terms.
+fn with_slops(
+    query: Box<dyn Query>,
+    index: &Index,
+    phrases: &[QuotedPhrase],
+    cursor: &mut usize,
+) -> Result<Box<dyn Query>, ServiceError> {
+    if let Some(boolean) = query.as_ref().downcast_ref::<BooleanQuery>() {
+        let mut clauses = vec![];
+
+        for (occur, clause) in boolean.clauses() {
+            clauses.push((
+                *occur,
+                with_slops(clause.box_clone(), index, phrases, cursor)?,
+            ));
+        }
+
+        return Ok(Box::new(BooleanQuery::new(clauses)));
+    }
+
+    let Some(phrase) = query.as_ref().downcast_ref::<PhraseQuery>() else {
+        return Ok(query);
+    };
+
+    let terms = phrase.phrase_terms();
+    let tokenizer = index
+        .tokenizer_for_field(phrase.field())
+        .map_err(ServiceError::internal_error)?;
+
+    for (n, quoted) in phrases.iter().enumerate().skip(*cursor) {
+        if let Some(offsets) = phrase_offsets(&tokenizer, &quoted.text, &terms) {
+            *cursor = n;
+
+            return Ok(Box::new(Phrase::new(
+                offsets.into_iter().zip(terms).collect(),
+                quoted.slop,
+            )));
+        }
+    }
+
+    Ok(Box::new(Phrase::new(
+        terms.into_iter().enumerate().collect(),
+        0,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use tantivy::schema::{BytesOptions, Schema, STORED, STRING, TEXT};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn index() -> Index {
+        let mut schema = Schema::builder();
+        schema.add_text_field("title", TEXT);
+        schema.add_text_field("author", TEXT);
+        schema.add_text_field("isbn", STRING);
+        schema.add_json_field("props", TEXT);
+        schema.add_bytes_field("embedding", BytesOptions::default().set_fast());
+        schema.add_text_field("__id", STRING | STORED);
+
+        Index::create_in_ram(schema.build())
+    }
+
+    /// Returns the slops of the phrases of the query by their words.
+    fn slops(query: &dyn Query) -> Vec<(String, u32)> {
+        if let Some(boolean) = query.downcast_ref::<BooleanQuery>() {
+            return boolean
+                .clauses()
+                .iter()
+                .flat_map(|(_, clause)| slops(clause.as_ref()))
+                .collect();
+        }
+
+        let Some(phrase) = query.downcast_ref::<Phrase>() else {
+            return vec![];
+        };
+
+        let words: Vec<_> = phrase
+            .terms()
+            .iter()
+            .map(|(_, term)| term_text(term).unwrap().1)
+            .collect();
+
+        vec![(words.join(" "), phrase.slop())]
+    }
+
+    fn query(value: json::Value) -> SearchQuery {
+        json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn slop_of_each_phrase() {
+        let index = index();
+
+        let parsed = query(json!("\"art motorcycle\"~2 AND title:\"zen art\""))
+            .parse(&index)
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                ("art motorcycle".to_string(), 2),
+                ("art motorcycle".to_string(), 2),
+                ("zen art".to_string(), 0),
+            ],
+            slops(parsed.as_ref())
+        );
+    }
+
+    #[test]
+    fn structured_phrase() {
+        let index = index();
+
+        let parsed = query(json!({
+            "bool": {
+                "must": [{ "phrase": { "field": "props.description", "text": "Art of Zen", "slop": 1 } }],
+                "must_not": ["pirsig"]
+            }
+        }))
+        .parse(&index)
+        .unwrap();
+
+        let boolean = parsed.as_ref().downcast_ref::<BooleanQuery>().unwrap();
+
+        assert_eq!(
+            vec![("art of zen".to_string(), 1)],
+            slops(boolean.clauses()[0].1.as_ref())
+        );
+    }
+
+    #[test]
+    fn json_term_text() {
+        let index = index();
+        let props = index.schema().get_field("props").unwrap();
+
+        let mut terms = BTreeMap::new();
+        query(json!("props.publisher.name:quality"))
+            .parse(&index)
+            .unwrap()
+            .query_terms(&mut terms);
+
+        let term = terms.keys().next().unwrap();
+
+        assert_eq!(props, term.field());
+        assert_eq!(
+            Some(("publisher.name".to_string(), "quality")),
+            term_text(term)
+        );
+    }
+
+    #[test]
+    fn more_like_this_excludes_documents() {
+        let index = index();
+        let title = index.schema().get_field("title").unwrap();
+        let id = index.schema().id_field();
+
+        let mut writer = index.writer_with_num_threads(1, 10_000_000).unwrap();
+        writer
+            .add_document(tantivy::doc!(id => "zen", title => "Zen and the art of motorcycles"))
+            .unwrap();
+        writer
+            .add_document(tantivy::doc!(id => "art", title => "The art of motorcycles"))
+            .unwrap();
+        writer
+            .add_document(tantivy::doc!(id => "dune", title => "Dune"))
+            .unwrap();
+        writer.commit().unwrap();
+
+        let parsed = query(json!({
+            "more_like_this": {
+                "like": { "title": "Zen and the art of motorcycles" },
+                "exclude": ["zen"]
+            }
+        }))
+        .parse(&index)
+        .unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let top_docs = searcher
+            .search(&parsed, &tantivy::collector::TopDocs::with_limit(10))
+            .unwrap();
+
+        let ids: Vec<_> = top_docs
+            .iter()
+            .map(|(_, address)| {
+                let doc = searcher.doc(*address).unwrap();
+                doc.get_first(id).unwrap().as_text().unwrap().to_string()
+            })
+            .collect();
+
+        assert_eq!(vec!["art"], ids);
+    }
+
+    #[test]
+    fn reject_invalid_queries() {
+        let index = index();
+
+        let invalid = [
+            json!("\"art motorcycle\"~"),
+            json!("\"art motorcycle\"~-1"),
+            json!({ "phrase": { "field": "unknown", "text": "art motorcycle" } }),
+            json!({ "bool": {} }),
+            json!({ "more_like_this": { "like": {} } }),
+            json!({ "more_like_this": { "like": { "props": "zen" } } }),
+            json!({ "more_like_this": { "like": { "title": 1 } } }),
+            json!({ "knn": { "field": "embedding", "vector": [1.0, 0.0] } }),
+            json!({ "knn": { "field": "title", "vector": [1.0, 0.0], "metric": "cosine" } }),
+            json!({ "hybrid": { "queries": [] } }),
+            json!({ "term": { "field": "embedding", "value": "aGk=" } }),
+            json!({ "range": { "field": "isbn" } }),
+            json!({ "range": { "field": "isbn", "gt": "a", "gte": "b" } }),
+            json!({
+                "geo_distance": {
+                    "field": "embedding",
+                    "origin": { "lat": 91, "lon": 4.89 },
+                    "distance_km": 10
+                }
+            }),
+            json!({
+                "geo_distance": {
+                    "field": "embedding",
+                    "origin": { "lat": 52.37, "lon": 4.89 },
+                    "distance_km": 0
+                }
+            }),
+            json!({
+                "geo_bounding_box": {
+                    "field": "embedding",
+                    "top_left": { "lat": 50, "lon": 4 },
+                    "bottom_right": { "lat": 53, "lon": 5 }
+                }
+            }),
+        ];
+
+        for value in invalid {
+            assert_eq!(400, query(value).parse(&index).unwrap_err().status());
+        }
+    }
+
+    #[test]
+    fn resolve_vector_and_geo_fields() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "title", "kind": "text", "flags": ["TEXT"] },
+            { "name": "embedding", "kind": "vector", "dimensions": 2, "metric": "cosine" },
+            { "name": "location", "kind": "geo_point" },
+        ]))
+        .unwrap();
+
+        let resolved = query(json!({
+            "hybrid": {
+                "queries": [
+                    "zen",
+                    { "knn": { "field": "embedding", "vector": [1.0, 0.0] } },
+                    { "knn": { "field": "embedding", "vector": [1.0, 0.0], "metric": "euclidean" } },
+                ]
+            }
+        }))
+        .resolve(&fields)
+        .unwrap();
+
+        assert_eq!(
+            query(json!({
+                "hybrid": {
+                    "queries": [
+                        "zen",
+                        { "knn": { "field": "embedding", "vector": [1.0, 0.0], "metric": "cosine" } },
+                        { "knn": { "field": "embedding", "vector": [1.0, 0.0], "metric": "euclidean" } },
+                    ],
+                    "rank_constant": 60
+                }
+            })),
+            resolved
+        );
+
+        assert!(resolved.parse(&index()).is_ok());
+
+        for invalid in [
+            json!({ "knn": { "field": "title", "vector": [1.0, 0.0] } }),
+            json!({ "bool": { "must": [{ "knn": { "field": "embedding", "vector": [1.0] } }] } }),
+            json!({ "knn": { "field": "location", "vector": [1.0, 0.0] } }),
+            json!({
+                "geo_distance": {
+                    "field": "embedding",
+                    "origin": { "lat": 52.37, "lon": 4.89 },
+                    "distance_km": 10
+                }
+            }),
+        ] {
+            assert_eq!(400, query(invalid).resolve(&fields).unwrap_err().status());
+        }
+    }
+}
@@ -0,0 +1,648 @@
+//! Request rate, indexing volume and index size limits for indexes and tenants.
+//!
+//! Limits of an index are set in its index config and limits of a tenant with
+//! [`crate::auth::ApiKeyStore::set_limits`]. Usage is tracked in the document store with token
+//! buckets, which refill continuously, so a limit of 1000 documents per day allows a document about
+//! every 86 seconds once the bucket is empty. Buckets are updated with a conditional write on their
+//! update time, and a request retries when another request updated the bucket first.
+//!
+//! Index sizes are recorded by the index writer. The size of a tenant is the total size of the
+//! indexes it submitted jobs to, which is kept in a single record per tenant.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tantivy::Index;
+use tracing::warn;
+
+use crate::auth::Tenant;
+use crate::service::ServiceError;
+use crate::store::document::DocumentStore;
+use crate::store::record::{
+    delete_records, get_record, record_doc, update_record, RecordStore, RECORD_INDEX_ID,
+};
+use crate::{json, util};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Number of attempts made to update a record, such as a token bucket, that other requests update
+/// concurrently.
+const UPDATE_MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Requests to index or query documents per second, allowing bursts of up to a second of
+    /// requests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_second: Option<f64>,
+
+    /// Documents indexed per day.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents_per_day: Option<u64>,
+
+    /// Size of an index on disk, above which documents are no longer accepted. For a tenant, the
+    /// total size of the indexes it submitted jobs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_index_bytes: Option<u64>,
+}
+
+/// Size of an index as of its last commit by the index writer.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexUsage {
+    pub index_id: String,
+
+    pub bytes: u64,
+
+    pub updated_at: String,
+
+    /// Tenants that submitted jobs to the index, whose usage includes its size.
+    #[serde(default)]
+    pub tenants: Vec<String>,
+}
+
+/// Sizes of the indexes a tenant submitted jobs to, by index id.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TenantUsage {
+    pub indexes: BTreeMap<String, u64>,
+
+    /// Milliseconds since the epoch when the usage was updated, which identifies its version.
+    pub updated_at: i64,
+}
+
+impl TenantUsage {
+    pub fn bytes(&self) -> u64 {
+        self.indexes.values().sum()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+
+    /// Milliseconds since the epoch when `tokens` was computed.
+    updated_at: i64,
+}
+
+fn usage_key(index_id: &str) -> String {
+    format!("usage|{index_id}")
+}
+
+fn tenant_usage_key(tenant_id: &str) -> String {
+    format!("usage|tenant|{tenant_id}")
+}
+
+/// Records the size of an index after a commit, including in the usage of the tenants that
+/// submitted jobs to it, among them `tenant_ids`. Failures are logged since the index itself was
+/// updated.
+pub async fn record_index_usage(
+    document_store: &dyn DocumentStore,
+    index_id: &str,
+    index: &Index,
+    tenant_ids: &[String],
+) {
+    let bytes = match index.reader().and_then(|reader| {
+        reader
+            .searcher()
+            .space_usage()
+            .map_err(tantivy::TantivyError::from)
+    }) {
+        Ok(space_usage) => space_usage.total() as u64,
+        Err(err) => {
+            warn!(message = "index_usage_failed", index = index_id, error = %err);
+            return;
+        }
+    };
+
+    if let Err(err) = save_index_usage(document_store, index_id, bytes, tenant_ids).await {
+        warn!(message = "index_usage_failed", index = index_id, error = %err);
+    }
+}
+
+async fn save_index_usage(
+    document_store: &dyn DocumentStore,
+    index_id: &str,
+    bytes: u64,
+    tenant_ids: &[String],
+) -> Result<(), ServiceError> {
+    let previous: Option<IndexUsage> = get_record(document_store, &usage_key(index_id)).await?;
+
+    let mut tenants = previous.map(|usage| usage.tenants).unwrap_or_default();
+
+    for tenant_id in tenant_ids {
+        if !tenants.contains(tenant_id) {
+            tenants.push(tenant_id.clone());
+        }
+    }
+
+    let usage = IndexUsage {
+        index_id: index_id.to_string(),
+        bytes,
+        updated_at: util::timestamp(),
+        tenants,
+    };
+
+    document_store
+        .save_documents(
+            RECORD_INDEX_ID,
+            vec![record_doc(&usage_key(index_id), &usage)?],
+        )
+        .await?;
+
+    for tenant_id in &usage.tenants {
+        update_tenant_usage(document_store, tenant_id, |tenant_usage| {
+            tenant_usage.indexes.insert(index_id.to_string(), bytes);
+        })
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Removes the usage of a deleted index, including from the usage of the tenants that submitted
+/// jobs to it.
+pub async fn delete_index_usage(
+    document_store: &dyn DocumentStore,
+    index_id: &str,
+) -> Result<(), ServiceError> {
+    let usage: Option<IndexUsage> = get_record(document_store, &usage_key(index_id)).await?;
+
+    for tenant_id in usage.iter().flat_map(|usage| &usage.tenants) {
+        update_tenant_usage(document_store, tenant_id, |tenant_usage| {
+            tenant_usage.indexes.remove(index_id);
+        })
+        .await?;
+    }
+
+    delete_records(document_store, &[usage_key(index_id)]).await
+}
+
+/// Updates the usage of a tenant, which the index writers of its indexes update concurrently.
+async fn update_tenant_usage(
+    document_store: &dyn DocumentStore,
+    tenant_id: &str,
+    update: impl Fn(&mut TenantUsage),
+) -> Result<(), ServiceError> {
+    let key = tenant_usage_key(tenant_id);
+
+    for _ in 0..UPDATE_MAX_ATTEMPTS {
+        let previous: Option<TenantUsage> = get_record(document_store, &key).await?;
+
+        let expected = previous
+            .as_ref()
+            .map(|usage| json::Value::from(usage.updated_at));
+
+        let mut usage = previous.unwrap_or_default();
+        update(&mut usage);
+        usage.updated_at = chrono::Utc::now()
+            .timestamp_millis()
+            .max(usage.updated_at + 1);
+
+        match update_record(
+            document_store,
+            &key,
+            &usage,
+            "updated_at",
+            expected.as_ref(),
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(ServiceError::Conflict(_)) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Err(ServiceError::rate_limit())
+}
+
+/// Checks and consumes the limits of indexes and tenants.
+pub struct Quotas {
+    records: RecordStore,
+}
+
+impl Quotas {
+    pub fn new(document_store: Arc<dyn DocumentStore>) -> Quotas {
+        Quotas {
+            records: RecordStore::new(document_store),
+        }
+    }
+
+    /// Counts a query of the indexes against their request limits and those of the tenant.
+    pub async fn check_read(
+        &self,
+        tenant: Option<&Tenant>,
+        indexes: &[(String, Limits)],
+    ) -> Result<(), ServiceError> {
+        // The tenant's bucket is taken from first, so that a tenant over its limit does not use up
+        // the limits of the indexes.
+        if let Some(tenant) = tenant {
+            self.take_request(&format!("tenant|{}", tenant.tenant_id), &tenant.limits)
+                .await?;
+        }
+
+        for (index_id, limits) in indexes {
+            self.take_request(&format!("index|{index_id}"), limits)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Counts a write of documents to the index against its limits and those of the tenant.
+    pub async fn check_write(
+        &self,
+        tenant: Option<&Tenant>,
+        index_id: &str,
+        limits: &Limits,
+        num_docs: usize,
+    ) -> Result<(), ServiceError> {
+        let mut scopes = vec![];
+
+        if let Some(tenant) = tenant {
+            self.check_tenant_size(tenant).await?;
+            scopes.push((format!("tenant|{}", tenant.tenant_id), &tenant.limits));
+        }
+
+        self.check_index_size(index_id, limits).await?;
+        scopes.push((format!("index|{index_id}"), limits));
+
+        // As for reads, the tenant's buckets are taken from before those of the index.
+        for (scope, limits) in &scopes {
+            self.take_request(scope, limits).await?;
+            self.take_documents(scope, limits, num_docs).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_index_size(&self, index_id: &str, limits: &Limits) -> Result<(), ServiceError> {
+        let Some(max_index_bytes) = limits.max_index_bytes else {
+            return Ok(());
+        };
+
+        let usage: Option<IndexUsage> = self.records.get(&usage_key(index_id)).await?;
+
+        match usage {
+            Some(usage) if usage.bytes >= max_index_bytes => Err(ServiceError::quota_exceeded(
+                &format!(
+                    "Index [{index_id}] has reached its size limit of {max_index_bytes} bytes"
+                ),
+                None,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the total size of the indexes the tenant submitted jobs to against its limit.
+    async fn check_tenant_size(&self, tenant: &Tenant) -> Result<(), ServiceError> {
+        let Some(max_index_bytes) = tenant.limits.max_index_bytes else {
+            return Ok(());
+        };
+
+        let usage: Option<TenantUsage> = self
+            .records
+            .get(&tenant_usage_key(&tenant.tenant_id))
+            .await?;
+        let bytes = usage.map_or(0, |usage| usage.bytes());
+
+        if bytes >= max_index_bytes {
+            return Err(ServiceError::quota_exceeded(
+                &format!(
+                    "Tenant [{}] has reached its size limit of {max_index_bytes} bytes",
+                    tenant.tenant_id
+                ),
+                None,
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn take_request(&self, scope: &str, limits: &Limits) -> Result<(), ServiceError> {
+        let Some(requests_per_second) = limits.requests_per_second else {
+            return Ok(());
+        };
+
+        let taken = self
+            .take(
+                &format!("quota|{scope}|requests"),
+                requests_per_second,
+                // Limits below one request per second still allow a request at a time.
+                requests_per_second.max(1.0),
+                1.0,
+            )
+            .await?;
+
+        taken.map_err(|retry_after| {
+            ServiceError::quota_exceeded(
+                &format!("Request limit of {requests_per_second} per second exceeded"),
+                retry_after,
+            )
+        })
+    }
+
+    async fn take_documents(
+        &self,
+        scope: &str,
+        limits: &Limits,
+        num_docs: usize,
+    ) -> Result<(), ServiceError> {
+        let Some(documents_per_day) = limits.documents_per_day else {
+            return Ok(());
+        };
+
+        let capacity = documents_per_day as f64;
+
+        let taken = self
+            .take(
+                &format!("quota|{scope}|documents"),
+                capacity / SECONDS_PER_DAY,
+                capacity,
+                num_docs as f64,
+            )
+            .await?;
+
+        taken.map_err(|retry_after| {
+            ServiceError::quota_exceeded(
+                &format!("Limit of {documents_per_day} documents per day exceeded"),
+                retry_after,
+            )
+        })
+    }
+
+    /// Takes tokens from a bucket that refills at `rate` tokens per second up to `capacity`.
+    ///
+    /// Returns the time until enough tokens are available if there are too few, or `None` as the
+    /// time if the bucket can never hold enough tokens. Fails with a rate limit if other requests
+    /// keep updating the bucket first.
+    async fn take(
+        &self,
+        key: &str,
+        rate: f64,
+        capacity: f64,
+        cost: f64,
+    ) -> Result<Result<(), Option<Duration>>, ServiceError> {
+        if cost > capacity {
+            return Ok(Err(None));
+        }
+
+        for _ in 0..UPDATE_MAX_ATTEMPTS {
+            let now = chrono::Utc::now().timestamp_millis();
+
+            let bucket: Option<TokenBucket> = self.records.get(key).await?;
+
+            let tokens = match &bucket {
+                Some(bucket) => {
+                    let elapsed = (now - bucket.updated_at).max(0) as f64 / 1000.0;
+                    (bucket.tokens + elapsed * rate).min(capacity)
+                }
+                None => capacity,
+            };
+
+            if tokens < cost {
+                return Ok(Err(Some(Duration::from_secs_f64((cost - tokens) / rate))));
+            }
+
+            // The update time identifies the version of the bucket, so it has to change with
+            // every update.
+            let updated_at = match &bucket {
+                Some(bucket) => now.max(bucket.updated_at + 1),
+                None => now,
+            };
+
+            let taken = TokenBucket {
+                tokens: tokens - cost,
+                updated_at,
+            };
+
+            let expected = bucket.map(|bucket| json::Value::from(bucket.updated_at));
+
+            match self
+                .records
+                .update(key, taken, "updated_at", expected.as_ref())
+                .await
+            {
+                Ok(()) => return Ok(Ok(())),
+                Err(ServiceError::Conflict(_)) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(ServiceError::rate_limit())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Permission;
+    use crate::index::IndexLoader;
+    use crate::service::map_error_response;
+    use crate::test_utils::*;
+
+    fn quotas(ctx: &TestContext) -> Quotas {
+        Quotas::new(Arc::new(ctx.document_store().clone()))
+    }
+
+    fn limits(value: json::Value) -> Limits {
+        json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn request_limit() {
+        let ctx = setup();
+        let quotas = quotas(&ctx);
+        let indexes = vec![(
+            "test".to_string(),
+            limits(json!({ "requests_per_second": 2 })),
+        )];
+
+        quotas.check_read(None, &indexes).await.unwrap();
+        quotas.check_read(None, &indexes).await.unwrap();
+
+        let err = quotas.check_read(None, &indexes).await.unwrap_err();
+
+        assert_eq!(429, err.status());
+        assert!(err.retry_after().unwrap() <= Duration::from_millis(500));
+
+        let response = map_error_response(err).unwrap();
+        assert_eq!("1", response.headers()["Retry-After"]);
+
+        // Other indexes have their own buckets.
+        quotas
+            .check_read(None, &[("test-other".to_string(), indexes[0].1.clone())])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn document_limit_of_tenant() {
+        let ctx = setup();
+        let quotas = quotas(&ctx);
+
+        let tenant = Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: vec!["test".into()],
+            permissions: vec![Permission::Write],
+            limits: limits(json!({ "documents_per_day": 3 })),
+        };
+
+        quotas
+            .check_write(Some(&tenant), "test-a", &Limits::default(), 2)
+            .await
+            .unwrap();
+
+        // The limit is shared by all indexes of the tenant.
+        let err = quotas
+            .check_write(Some(&tenant), "test-b", &Limits::default(), 2)
+            .await
+            .unwrap_err();
+
+        assert_eq!(429, err.status());
+        assert!(err.retry_after().unwrap() > Duration::from_secs(3600));
+
+        // Writes larger than the limit can never succeed.
+        let err = quotas
+            .check_write(None, "test", &limits(json!({ "documents_per_day": 3 })), 4)
+            .await
+            .unwrap_err();
+
+        assert_eq!(None, err.retry_after());
+    }
+
+    #[tokio::test]
+    async fn tenant_limit_is_checked_before_index_limit() {
+        let ctx = setup();
+        let quotas = quotas(&ctx);
+
+        let tenant = Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: vec!["test".into()],
+            permissions: vec![Permission::Write],
+            limits: limits(json!({ "requests_per_second": 1 })),
+        };
+        let index_limits = limits(json!({ "requests_per_second": 2 }));
+
+        quotas
+            .check_write(Some(&tenant), "test", &index_limits, 1)
+            .await
+            .unwrap();
+
+        let err = quotas
+            .check_write(Some(&tenant), "test", &index_limits, 1)
+            .await
+            .unwrap_err();
+
+        assert_eq!(429, err.status());
+
+        // The rejected write did not take from the index's bucket.
+        quotas
+            .check_write(None, "test", &index_limits, 1)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn size_limit_of_tenant() {
+        let ctx = setup()
+            .with_documents("test-a", vec![json!({ "title": "Zen" })])
+            .await
+            .with_documents("test-b", vec![json!({ "title": "Tao" })])
+            .await
+            .with_documents("test-c", vec![json!({ "title": "Te" })])
+            .await;
+        let quotas = quotas(&ctx);
+
+        let mut bytes = vec![];
+
+        for index_id in ["test-a", "test-b", "test-c"] {
+            // Only the first two indexes had jobs submitted by the tenant.
+            let tenant_ids = match index_id {
+                "test-c" => vec![],
+                _ => vec!["acme".to_string()],
+            };
+
+            let index = ctx.index_loader().load_index(index_id, None).await.unwrap();
+            record_index_usage(ctx.document_store(), index_id, &index, &tenant_ids).await;
+
+            let usage: IndexUsage = quotas
+                .records
+                .get(&usage_key(index_id))
+                .await
+                .unwrap()
+                .unwrap();
+            bytes.push(usage.bytes);
+        }
+
+        let tenant = |max_index_bytes: u64| Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: vec!["test".into()],
+            permissions: vec![Permission::Write],
+            limits: limits(json!({ "max_index_bytes": max_index_bytes })),
+        };
+
+        // Each index is below the limit but their total is not.
+        let err = quotas
+            .check_write(
+                Some(&tenant(bytes[0] + bytes[1])),
+                "test-a",
+                &Limits::default(),
+                1,
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(429, err.status());
+
+        quotas
+            .check_write(
+                Some(&tenant(bytes[0] + bytes[1] + 1)),
+                "test-a",
+                &Limits::default(),
+                1,
+            )
+            .await
+            .unwrap();
+
+        // Deleted indexes no longer count towards the size of the tenant.
+        delete_index_usage(ctx.document_store(), "test-b")
+            .await
+            .unwrap();
+
+        quotas
+            .check_write(Some(&tenant(bytes[0] + 1)), "test-a", &Limits::default(), 1)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn index_size_limit() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "Zen" })])
+            .await;
+        let quotas = quotas(&ctx);
+
+        let index = ctx.index_loader().load_index("test", None).await.unwrap();
+        record_index_usage(ctx.document_store(), "test", &index, &[]).await;
+
+        quotas
+            .check_write(
+                None,
+                "test",
+                &limits(json!({ "max_index_bytes": 1_000_000 })),
+                1,
+            )
+            .await
+            .unwrap();
+
+        let err = quotas
+            .check_write(None, "test", &limits(json!({ "max_index_bytes": 1 })), 1)
+            .await
+            .unwrap_err();
+
+        assert_eq!(429, err.status());
+    }
+}
@@ -0,0 +1,919 @@
+pub mod registry;
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use tantivy::schema::{
+    self, BytesOptions, Cardinality, DocParsingError, Field, IndexRecordOption, NumericOptions,
+    Schema, TextOptions,
+};
+use thiserror::Error;
+
+use crate::quota::Limits;
+use crate::service::ServiceError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TextFieldOption {
+    TEXT,
+    STRING,
+    FAST,
+    /// Indexes the positions of tokens, which phrase queries need. `TEXT` fields have positions
+    /// already; on its own, the field is indexed like `TEXT`.
+    POSITIONS,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum NumericFieldOption {
+    INDEXED,
+    FAST,
+    STORED,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum JsonFieldOption {
+    TEXT,
+}
+
+/// How vectors are compared, see [`crate::vector`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorMetric {
+    Cosine,
+    DotProduct,
+    Euclidean,
+}
+
+/// The fields of an index. Fields other than vector and geo point fields are single-valued unless
+/// they are `multi`, in which case documents give their values as an array.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum FieldConfig {
+    #[serde(rename = "text")]
+    TextFieldConfig {
+        name: String,
+        flags: Vec<TextFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    #[serde(rename = "date")]
+    DateFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    #[serde(rename = "i64")]
+    IntegerFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    #[serde(rename = "u64")]
+    UnsignedFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    #[serde(rename = "f64")]
+    FloatFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    /// Booleans, stored as `0` and `1` in a u64 field.
+    #[serde(rename = "bool")]
+    BoolFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    /// Bytes, given as base64 encoded strings.
+    #[serde(rename = "bytes")]
+    BytesFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    /// IPv4 and IPv6 addresses, stored as the 16 bytes of their IPv6 address in a bytes field, see
+    /// [`crate::coerce`].
+    #[serde(rename = "ip")]
+    IpFieldConfig {
+        name: String,
+        flags: Vec<NumericFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    #[serde(rename = "json")]
+    JsonFieldConfig {
+        name: String,
+        flags: Vec<JsonFieldOption>,
+        #[serde(default)]
+        multi: bool,
+    },
+    /// Dense vectors of a fixed number of dimensions, stored in a fast field of each segment.
+    #[serde(rename = "vector")]
+    VectorFieldConfig {
+        name: String,
+        dimensions: usize,
+        metric: VectorMetric,
+    },
+    /// Points of a latitude and a longitude, stored in a fast field of each segment.
+    #[serde(rename = "geo_point")]
+    GeoPointFieldConfig { name: String },
+    /// Objects of typed sub-fields, flattened into fields named by their dotted path, e.g.
+    /// `props.price`, see [`flatten_fields`].
+    #[serde(rename = "object")]
+    ObjectFieldConfig {
+        name: String,
+        fields: Vec<FieldConfig>,
+    },
+}
+
+impl FieldConfig {
+    pub fn name(&self) -> &str {
+        match self {
+            FieldConfig::TextFieldConfig { name, .. }
+            | FieldConfig::DateFieldConfig { name, .. }
+            | FieldConfig::IntegerFieldConfig { name, .. }
+            | FieldConfig::UnsignedFieldConfig { name, .. }
+            | FieldConfig::FloatFieldConfig { name, .. }
+            | FieldConfig::BoolFieldConfig { name, .. }
+            | FieldConfig::BytesFieldConfig { name, .. }
+            | FieldConfig::IpFieldConfig { name, .. }
+            | FieldConfig::JsonFieldConfig { name, .. }
+            | FieldConfig::VectorFieldConfig { name, .. }
+            | FieldConfig::GeoPointFieldConfig { name }
+            | FieldConfig::ObjectFieldConfig { name, .. } => name,
+        }
+    }
+
+    /// Whether values are encoded from their JSON value into values of another type, such as
+    /// bytes, so the tantivy field does not describe the field and documents are returned with
+    /// the values they were indexed with.
+    pub fn is_encoded(&self) -> bool {
+        matches!(
+            self,
+            FieldConfig::VectorFieldConfig { .. }
+                | FieldConfig::GeoPointFieldConfig { .. }
+                | FieldConfig::BoolFieldConfig { .. }
+                | FieldConfig::IpFieldConfig { .. }
+        )
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        match self {
+            FieldConfig::TextFieldConfig { name, .. }
+            | FieldConfig::DateFieldConfig { name, .. }
+            | FieldConfig::IntegerFieldConfig { name, .. }
+            | FieldConfig::UnsignedFieldConfig { name, .. }
+            | FieldConfig::FloatFieldConfig { name, .. }
+            | FieldConfig::BoolFieldConfig { name, .. }
+            | FieldConfig::BytesFieldConfig { name, .. }
+            | FieldConfig::IpFieldConfig { name, .. }
+            | FieldConfig::JsonFieldConfig { name, .. }
+            | FieldConfig::VectorFieldConfig { name, .. }
+            | FieldConfig::GeoPointFieldConfig { name }
+            | FieldConfig::ObjectFieldConfig { name, .. } => name,
+        }
+    }
+
+    /// Whether documents may have several values of the field.
+    pub fn is_multi(&self) -> bool {
+        match self {
+            FieldConfig::TextFieldConfig { multi, .. }
+            | FieldConfig::DateFieldConfig { multi, .. }
+            | FieldConfig::IntegerFieldConfig { multi, .. }
+            | FieldConfig::UnsignedFieldConfig { multi, .. }
+            | FieldConfig::FloatFieldConfig { multi, .. }
+            | FieldConfig::BoolFieldConfig { multi, .. }
+            | FieldConfig::BytesFieldConfig { multi, .. }
+            | FieldConfig::IpFieldConfig { multi, .. }
+            | FieldConfig::JsonFieldConfig { multi, .. } => *multi,
+            FieldConfig::VectorFieldConfig { .. }
+            | FieldConfig::GeoPointFieldConfig { .. }
+            | FieldConfig::ObjectFieldConfig { .. } => false,
+        }
+    }
+
+    /// Whether the values of the field are kept in a numeric fast field. Matches can be sorted
+    /// by single-valued ones.
+    pub fn is_fast_numeric(&self) -> bool {
+        match self {
+            FieldConfig::DateFieldConfig { flags, .. }
+            | FieldConfig::IntegerFieldConfig { flags, .. }
+            | FieldConfig::UnsignedFieldConfig { flags, .. }
+            | FieldConfig::FloatFieldConfig { flags, .. }
+            | FieldConfig::BoolFieldConfig { flags, .. } => {
+                flags.contains(&NumericFieldOption::FAST)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexConfig {
+    prefix: String,
+    fields: Vec<FieldConfig>,
+    #[serde(default)]
+    limits: Limits,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PatheryConfig {
+    indexes: Vec<IndexConfig>,
+}
+
+#[async_trait]
+pub trait SchemaLoader: Send + Sync {
+    async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError>;
+
+    /// Loads the fields the schema of an index is built from.
+    async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError>;
+
+    /// Resolves an index id or alias to the index that writes go to.
+    async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError> {
+        Ok(name.to_string())
+    }
+
+    /// Resolves an index id or alias to the indexes that reads go to.
+    async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError> {
+        Ok(vec![name.to_string()])
+    }
+
+    /// Returns the limits of an index, see [`crate::quota`].
+    async fn index_limits(&self, _index_id: &str) -> Result<Limits, ServiceError> {
+        Ok(Limits::default())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum IndexDocError {
+    #[error("Expected JSON object")]
+    NotJsonObject,
+    #[error("Request JSON object is empty")]
+    EmptyDoc,
+    #[error("Error parsing JSON object document")]
+    DocParsingError(DocParsingError),
+}
+
+fn numeric_field_options(flags: &[NumericFieldOption], multi: bool) -> NumericOptions {
+    flags
+        .iter()
+        .fold(NumericOptions::default(), |acc, opt| match opt {
+            NumericFieldOption::INDEXED => acc | schema::INDEXED,
+            NumericFieldOption::FAST if multi => acc.set_fast(Cardinality::MultiValues),
+            NumericFieldOption::FAST => acc | schema::FAST,
+            NumericFieldOption::STORED => acc | schema::STORED,
+        })
+}
+
+fn bytes_field_options(flags: &[NumericFieldOption]) -> BytesOptions {
+    flags
+        .iter()
+        .fold(BytesOptions::default(), |acc, opt| match opt {
+            NumericFieldOption::INDEXED => acc.set_indexed(),
+            NumericFieldOption::FAST => acc.set_fast(),
+            NumericFieldOption::STORED => acc.set_stored(),
+        })
+}
+
+pub trait SchemaExt {
+    fn id_field(&self) -> Field;
+}
+
+impl SchemaExt for Schema {
+    fn id_field(&self) -> Field {
+        self.get_field("__id")
+            .expect("__id field should be present")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SchemaProvider {
+    config: PatheryConfig,
+}
+
+impl SchemaProvider {
+    pub fn from_file<P>(config_path: P) -> Self
+    where P: AsRef<Path> {
+        let content = fs::read_to_string(config_path).expect("config should exist");
+        let config: PatheryConfig = json::from_str(&content).expect("config should parse");
+
+        SchemaProvider { config }
+    }
+
+    pub fn from_json(config: json::Value) -> Self {
+        let config = json::from_value(config).expect("config should parse");
+        Self { config }
+    }
+}
+
+impl SchemaProvider {
+    /// Returns the first index config whose prefix matches the index id.
+    fn index_config(&self, index_id: &str) -> Option<&IndexConfig> {
+        self.config
+            .indexes
+            .iter()
+            .find(|config| index_id.starts_with(&config.prefix))
+    }
+
+    /// Returns the fields of the index config matching the index id.
+    pub fn fields(&self, index_id: &str) -> Option<&[FieldConfig]> {
+        self.index_config(index_id)
+            .map(|config| config.fields.as_slice())
+    }
+}
+
+#[async_trait]
+impl SchemaLoader for SchemaProvider {
+    async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError> {
+        Ok(build_schema(&self.load_fields(index_id).await?))
+    }
+
+    async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError> {
+        self.fields(index_id)
+            .map(|fields| fields.to_vec())
+            .ok_or_else(|| {
+                ServiceError::not_found(&format!("Schema for index [{}] not found", index_id))
+            })
+    }
+
+    async fn index_limits(&self, index_id: &str) -> Result<Limits, ServiceError> {
+        Ok(self
+            .index_config(index_id)
+            .map(|config| config.limits.clone())
+            .unwrap_or_default())
+    }
+}
+
+/// Builds the tantivy schema for a list of fields, adding the system fields.
+pub fn build_schema(fields: &[FieldConfig]) -> Schema {
+    let mut schema = Schema::builder();
+
+    for field in flatten_fields(fields) {
+        match &field {
+            FieldConfig::TextFieldConfig { name, flags, .. } => {
+                let mut field_opts =
+                    flags
+                        .iter()
+                        .fold(TextOptions::default(), |acc, opt| match opt {
+                            TextFieldOption::TEXT => acc | schema::TEXT,
+                            TextFieldOption::STRING => acc | schema::STRING,
+                            TextFieldOption::FAST => acc | schema::FAST,
+                            TextFieldOption::POSITIONS => acc,
+                        });
+
+                if flags.contains(&TextFieldOption::POSITIONS) {
+                    let indexing = field_opts
+                        .get_indexing_options()
+                        .cloned()
+                        .unwrap_or_default()
+                        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+
+                    field_opts = field_opts.set_indexing_options(indexing);
+                }
+
+                schema.add_text_field(name, field_opts);
+            }
+            FieldConfig::DateFieldConfig { name, flags, multi } => {
+                schema.add_date_field(name, numeric_field_options(flags, *multi));
+            }
+            FieldConfig::IntegerFieldConfig { name, flags, multi } => {
+                schema.add_i64_field(name, numeric_field_options(flags, *multi));
+            }
+            FieldConfig::UnsignedFieldConfig { name, flags, multi }
+            | FieldConfig::BoolFieldConfig { name, flags, multi } => {
+                schema.add_u64_field(name, numeric_field_options(flags, *multi));
+            }
+            FieldConfig::FloatFieldConfig { name, flags, multi } => {
+                schema.add_f64_field(name, numeric_field_options(flags, *multi));
+            }
+            FieldConfig::BytesFieldConfig { name, flags, .. }
+            | FieldConfig::IpFieldConfig { name, flags, .. } => {
+                schema.add_bytes_field(name, bytes_field_options(flags));
+            }
+            FieldConfig::JsonFieldConfig { name, flags, .. } => {
+                let field_opts = flags
+                    .iter()
+                    .fold(TextOptions::default(), |acc, opt| match opt {
+                        JsonFieldOption::TEXT => acc | schema::TEXT,
+                    });
+                schema.add_json_field(name, field_opts);
+            }
+            FieldConfig::VectorFieldConfig { name, .. }
+            | FieldConfig::GeoPointFieldConfig { name } => {
+                schema.add_bytes_field(name, BytesOptions::default().set_fast());
+            }
+            FieldConfig::ObjectFieldConfig { .. } => unreachable!("object fields are flattened"),
+        }
+    }
+
+    // Add system schema fields
+
+    // __id is the document id used for uniqueness
+    schema.add_text_field("__id", schema::STRING | schema::STORED);
+
+    schema.build()
+}
+
+/// Returns the fields with the sub-fields of object fields in place of the object fields, named by
+/// their dotted path.
+pub fn flatten_fields(fields: &[FieldConfig]) -> Vec<FieldConfig> {
+    fn flatten(fields: &[FieldConfig], prefix: &str, flattened: &mut Vec<FieldConfig>) {
+        for field in fields {
+            match field {
+                FieldConfig::ObjectFieldConfig { name, fields } => {
+                    flatten(fields, &format!("{prefix}{name}."), flattened)
+                }
+                field => {
+                    let mut field = field.clone();
+                    field.name_mut().insert_str(0, prefix);
+                    flattened.push(field);
+                }
+            }
+        }
+    }
+
+    let mut flattened = vec![];
+    flatten(fields, "", &mut flattened);
+
+    flattened
+}
+
+/// Largest number of dimensions of a vector field.
+pub const MAX_VECTOR_DIMENSIONS: usize = 4096;
+
+/// Checks that field names are unique and do not use the `__` prefix reserved for system fields.
+pub fn validate_fields(fields: &[FieldConfig]) -> Result<(), ServiceError> {
+    if fields.is_empty() {
+        return Err(ServiceError::invalid_request(
+            "Schema must have at least one field",
+        ));
+    }
+
+    check_objects(fields, false)?;
+
+    let mut names = HashSet::new();
+
+    for field in &flatten_fields(fields) {
+        let name = field.name();
+
+        if name.is_empty() || name.starts_with("__") {
+            return Err(ServiceError::invalid_request(&format!(
+                "Invalid field name [{name}], names must not be empty or start with __"
+            )));
+        }
+
+        if !names.insert(name) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{name}] is defined more than once"
+            )));
+        }
+
+        // Bytes fast fields keep a single value of each document.
+        if let FieldConfig::BytesFieldConfig {
+            flags, multi: true, ..
+        }
+        | FieldConfig::IpFieldConfig {
+            flags, multi: true, ..
+        } = field
+        {
+            if flags.contains(&NumericFieldOption::FAST) {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Field [{name}] cannot be both multi-valued and FAST"
+                )));
+            }
+        }
+
+        if let FieldConfig::VectorFieldConfig { dimensions, .. } = field {
+            if !(1..=MAX_VECTOR_DIMENSIONS).contains(dimensions) {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Vector field [{name}] must have 1 to {MAX_VECTOR_DIMENSIONS} dimensions"
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that object fields have sub-fields of scalar kinds or objects, named without dots since
+/// their dotted paths name the fields they are flattened to.
+fn check_objects(fields: &[FieldConfig], in_object: bool) -> Result<(), ServiceError> {
+    for field in fields {
+        let name = field.name();
+
+        if in_object && (name.is_empty() || name.contains('.')) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Invalid field name [{name}] in an object, names must not be empty or contain dots"
+            )));
+        }
+
+        if in_object
+            && matches!(
+                field,
+                FieldConfig::JsonFieldConfig { .. }
+                    | FieldConfig::VectorFieldConfig { .. }
+                    | FieldConfig::GeoPointFieldConfig { .. }
+            )
+        {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{name}] of an object must be of a scalar kind or an object"
+            )));
+        }
+
+        if let FieldConfig::ObjectFieldConfig { fields, .. } = field {
+            if name.contains('.') {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Invalid object field name [{name}], names must not contain dots"
+                )));
+            }
+
+            if fields.is_empty() {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Object field [{name}] must have at least one field"
+                )));
+            }
+
+            check_objects(fields, true)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that `next` keeps every field of `previous` unchanged. Fields can only be added since
+/// documents already indexed with a field depend on how it is indexed.
+pub fn check_compatible(
+    previous: &[FieldConfig],
+    next: &[FieldConfig],
+) -> Result<(), ServiceError> {
+    let previous_schema = build_schema(previous);
+    let next_schema = build_schema(next);
+
+    // Object fields are compared by the fields they are flattened to.
+    let previous = flatten_fields(previous);
+    let next = flatten_fields(next);
+
+    for field in &previous {
+        let name = field.name();
+
+        let previous_entry = previous_schema.get_field_entry(
+            previous_schema
+                .get_field(name)
+                .expect("field should be in schema"),
+        );
+
+        match next_schema.get_field(name) {
+            None => {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Field [{name}] cannot be removed"
+                )))
+            }
+            Some(next_field) if next_schema.get_field_entry(next_field) != previous_entry => {
+                return Err(ServiceError::invalid_request(&format!(
+                    "Field [{name}] cannot change kind or flags"
+                )))
+            }
+            Some(_) => {}
+        }
+
+        // Documents indexed with several values of a multi-valued field would no longer be valid.
+        let next_field = next.iter().find(|next_field| next_field.name() == name);
+
+        if field.is_multi() && next_field.is_some_and(|next_field| !next_field.is_multi()) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{name}] cannot become single-valued"
+            )));
+        }
+
+        // Encoded fields are stored like fields of other kinds, whatever their dimensions or
+        // metric.
+        let is_encoded = field.is_encoded() || next_field.is_some_and(FieldConfig::is_encoded);
+
+        if is_encoded && next_field != Some(field) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Field [{name}] cannot change kind, dimensions or metric"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tantivy::schema::FieldType;
+
+    use super::*;
+
+    #[test]
+    fn parse_test_config() {
+        let config = json!({
+                "indexes": [{
+                    "prefix": "book-index-v1-",
+                    "fields": [
+                        {
+                            "name": "title",
+                            "flags": ["TEXT"],
+                            "kind": "text",
+                        },
+                        {
+                            "name": "author",
+                            "flags": ["STRING"],
+                            "kind": "text",
+                        },
+                        {
+                            "name": "date_added",
+                            "flags": ["INDEXED", "FAST"],
+                            "kind": "date",
+                        },
+                        {
+                            "name": "year",
+                            "flags": ["INDEXED", "FAST"],
+                            "kind": "i64",
+                        },
+                        {
+                            "name": "meta",
+                            "flags": ["TEXT"],
+                            "kind": "json"
+                        }
+                    ],
+            }]
+        });
+
+        serde_json::from_value::<PatheryConfig>(config).expect("should not throw");
+    }
+
+    #[test]
+    fn positions_flag() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "title", "kind": "text", "flags": ["TEXT", "POSITIONS"] },
+            { "name": "summary", "kind": "text", "flags": ["POSITIONS"] },
+            { "name": "isbn", "kind": "text", "flags": ["STRING"] },
+        ]))
+        .unwrap();
+
+        let schema = build_schema(&fields);
+        let has_positions = |name: &str| {
+            schema
+                .get_field_entry(schema.get_field(name).unwrap())
+                .field_type()
+                .get_index_record_option()
+                .is_some_and(|option| option.has_positions())
+        };
+
+        assert!(has_positions("title"));
+        assert!(has_positions("summary"));
+        assert!(!has_positions("isbn"));
+
+        // Positions are already indexed for TEXT fields, so the flag can be added to them.
+        let text: Vec<FieldConfig> =
+            json::from_value(json!([{ "name": "title", "kind": "text", "flags": ["TEXT"] }]))
+                .unwrap();
+
+        check_compatible(&text, &fields).unwrap();
+    }
+
+    #[test]
+    fn vector_fields() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "embedding", "kind": "vector", "dimensions": 3, "metric": "cosine" },
+        ]))
+        .unwrap();
+
+        validate_fields(&fields).unwrap();
+
+        let schema = build_schema(&fields);
+        let entry = schema.get_field_entry(schema.get_field("embedding").unwrap());
+
+        assert!(entry.is_fast());
+        assert!(!entry.is_indexed());
+
+        let changed: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "embedding", "kind": "vector", "dimensions": 3, "metric": "euclidean" },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            400,
+            check_compatible(&fields, &changed).unwrap_err().status()
+        );
+
+        let empty: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "embedding", "kind": "vector", "dimensions": 0, "metric": "cosine" },
+        ]))
+        .unwrap();
+
+        assert_eq!(400, validate_fields(&empty).unwrap_err().status());
+
+        let geo: Vec<FieldConfig> =
+            json::from_value(json!([{ "name": "embedding", "kind": "geo_point" }])).unwrap();
+
+        assert_eq!(400, check_compatible(&fields, &geo).unwrap_err().status());
+    }
+
+    #[test]
+    fn additional_field_kinds() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "views", "kind": "u64", "flags": ["INDEXED", "FAST"] },
+            { "name": "price", "kind": "f64", "flags": ["INDEXED", "FAST", "STORED"] },
+            { "name": "in_stock", "kind": "bool", "flags": ["INDEXED"] },
+            { "name": "checksum", "kind": "bytes", "flags": ["INDEXED", "STORED"] },
+            { "name": "client_ip", "kind": "ip", "flags": ["INDEXED", "FAST"] },
+        ]))
+        .unwrap();
+
+        validate_fields(&fields).unwrap();
+
+        let schema = build_schema(&fields);
+        let entry = |name: &str| {
+            schema
+                .get_field_entry(schema.get_field(name).unwrap())
+                .clone()
+        };
+
+        assert!(matches!(entry("views").field_type(), FieldType::U64(_)));
+        assert!(matches!(entry("price").field_type(), FieldType::F64(_)));
+        assert!(entry("price").is_stored());
+        assert!(matches!(entry("in_stock").field_type(), FieldType::U64(_)));
+        assert!(matches!(
+            entry("checksum").field_type(),
+            FieldType::Bytes(_)
+        ));
+        assert!(entry("client_ip").is_indexed() && entry("client_ip").is_fast());
+
+        assert!(fields[0].is_fast_numeric());
+        assert!(!fields[2].is_fast_numeric());
+
+        // Booleans are stored in u64 fields, so their kind cannot change either.
+        let changed: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "views", "kind": "bool", "flags": ["INDEXED", "FAST"] },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            400,
+            check_compatible(&fields[..1], &changed)
+                .unwrap_err()
+                .status()
+        );
+    }
+
+    #[test]
+    fn multi_valued_fields() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "tags", "kind": "text", "flags": ["STRING"], "multi": true },
+            { "name": "ratings", "kind": "u64", "flags": ["INDEXED", "FAST"], "multi": true },
+            { "name": "year", "kind": "i64", "flags": ["FAST"] },
+        ]))
+        .unwrap();
+
+        validate_fields(&fields).unwrap();
+
+        assert!(fields[0].is_multi());
+        assert!(!fields[2].is_multi());
+
+        let schema = build_schema(&fields);
+        let cardinality = |name: &str| match schema
+            .get_field_entry(schema.get_field(name).unwrap())
+            .field_type()
+        {
+            FieldType::U64(options) | FieldType::I64(options) => {
+                options.get_fastfield_cardinality()
+            }
+            _ => None,
+        };
+
+        assert_eq!(Some(Cardinality::MultiValues), cardinality("ratings"));
+        assert_eq!(Some(Cardinality::SingleValue), cardinality("year"));
+
+        let single: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "tags", "kind": "text", "flags": ["STRING"] },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            400,
+            check_compatible(&fields[..1], &single)
+                .unwrap_err()
+                .status()
+        );
+        check_compatible(&single, &fields[..1]).unwrap();
+
+        let fast_bytes: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "checksums", "kind": "bytes", "flags": ["FAST"], "multi": true },
+        ]))
+        .unwrap();
+
+        assert_eq!(400, validate_fields(&fast_bytes).unwrap_err().status());
+    }
+
+    #[test]
+    fn object_fields() {
+        let fields: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "title", "kind": "text", "flags": ["TEXT"] },
+            {
+                "name": "props",
+                "kind": "object",
+                "fields": [
+                    { "name": "price", "kind": "f64", "flags": ["INDEXED", "FAST"] },
+                    {
+                        "name": "size",
+                        "kind": "object",
+                        "fields": [{ "name": "width", "kind": "i64", "flags": ["INDEXED"] }]
+                    }
+                ]
+            },
+        ]))
+        .unwrap();
+
+        validate_fields(&fields).unwrap();
+
+        let names: Vec<_> = flatten_fields(&fields)
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect();
+
+        assert_eq!(vec!["title", "props.price", "props.size.width"], names);
+
+        let schema = build_schema(&fields);
+
+        assert!(matches!(
+            schema
+                .get_field_entry(schema.get_field("props.price").unwrap())
+                .field_type(),
+            FieldType::F64(_)
+        ));
+
+        for invalid in [
+            json!([{ "name": "props", "kind": "object", "fields": [] }]),
+            json!([{
+                "name": "props",
+                "kind": "object",
+                "fields": [{ "name": "a.b", "kind": "i64", "flags": [] }]
+            }]),
+            json!([{
+                "name": "props",
+                "kind": "object",
+                "fields": [{ "name": "meta", "kind": "json", "flags": [] }]
+            }]),
+            json!([
+                { "name": "props.price", "kind": "i64", "flags": [] },
+                {
+                    "name": "props",
+                    "kind": "object",
+                    "fields": [{ "name": "price", "kind": "i64", "flags": [] }]
+                }
+            ]),
+        ] {
+            let fields: Vec<FieldConfig> = json::from_value(invalid).unwrap();
+
+            assert_eq!(400, validate_fields(&fields).unwrap_err().status());
+        }
+
+        // Removing a sub-field removes the field it is flattened to.
+        let removed: Vec<FieldConfig> = json::from_value(json!([
+            { "name": "title", "kind": "text", "flags": ["TEXT"] },
+            {
+                "name": "props",
+                "kind": "object",
+                "fields": [{ "name": "price", "kind": "f64", "flags": ["INDEXED", "FAST"] }]
+            },
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            400,
+            check_compatible(&fields, &removed).unwrap_err().status()
+        );
+        check_compatible(&removed, &fields).unwrap();
+    }
+
+    #[test]
+    fn serialize_schema() {
+        let mut schema = Schema::builder();
+
+        schema.add_text_field("title", schema::STORED | schema::TEXT);
+        schema.add_text_field("author", schema::STORED | schema::STRING);
+        schema.add_date_field(
+            "created_date",
+            schema::STORED | schema::INDEXED | schema::FAST,
+        );
+
+        let schema = schema.build();
+
+        println!("{}", json::to_string_pretty(&schema).expect("ok"));
+    }
+}
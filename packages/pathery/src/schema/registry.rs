@@ -0,0 +1,379 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tantivy::schema::Schema;
+
+use super::{build_schema, check_compatible, validate_fields, FieldConfig, SchemaLoader};
+use crate::alias::AliasRegistry;
+use crate::config::{Config, ConfigError};
+use crate::index::validate_index_id;
+use crate::quota::Limits;
+use crate::schema::SchemaProvider;
+use crate::service::ServiceError;
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::store::record::{RecordCache, RecordStore};
+use crate::util;
+
+/// How long a loaded schema is used before it is read from the store again.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// A version of the schema registered for an index.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SchemaDefinition {
+    pub index_id: String,
+
+    /// Starts at 1 and increases by one with every update.
+    pub version: u32,
+
+    pub fields: Vec<FieldConfig>,
+
+    pub created_at: String,
+}
+
+fn latest_key(index_id: &str) -> String {
+    format!("schema|{index_id}")
+}
+
+fn version_key(index_id: &str, version: u32) -> String {
+    format!("schema|{index_id}|{version}")
+}
+
+/// Loads index schemas registered at runtime and stored in the document store.
+///
+/// Indexes without a registered schema fall back to the static index config, if provided. Index
+/// aliases are resolved with the [`AliasRegistry`] kept in the same store.
+/// Loaded schemas are cached for a short time, so updates can take up to 30 seconds to be seen by
+/// other processes.
+pub struct SchemaRegistry {
+    records: RecordStore,
+
+    fallback: Option<SchemaProvider>,
+
+    cache: RecordCache<SchemaDefinition>,
+
+    aliases: Arc<AliasRegistry>,
+}
+
+impl SchemaRegistry {
+    pub async fn create(config: &Config) -> Result<SchemaRegistry, ConfigError> {
+        let document_store = DDBDocumentStore::create(config).await?;
+
+        let fallback = config
+            .index_config_path()
+            .exists()
+            .then(|| SchemaProvider::from_file(config.index_config_path()));
+
+        Ok(SchemaRegistry::new(Arc::new(document_store), fallback))
+    }
+
+    pub fn new(
+        document_store: Arc<dyn DocumentStore>,
+        fallback: Option<SchemaProvider>,
+    ) -> SchemaRegistry {
+        SchemaRegistry {
+            aliases: Arc::new(AliasRegistry::new(Arc::clone(&document_store))),
+            records: RecordStore::new(document_store),
+            fallback,
+            cache: RecordCache::new(CACHE_TTL),
+        }
+    }
+
+    pub fn aliases(&self) -> Arc<AliasRegistry> {
+        Arc::clone(&self.aliases)
+    }
+
+    /// Returns the latest schema registered for the index.
+    pub async fn latest(&self, index_id: &str) -> Result<Option<SchemaDefinition>, ServiceError> {
+        if let Some(definition) = self.cache.get(index_id) {
+            return Ok(definition);
+        }
+
+        let definition: Option<SchemaDefinition> = self.records.get(&latest_key(index_id)).await?;
+
+        self.cache.insert(index_id, definition.clone());
+
+        Ok(definition)
+    }
+
+    pub async fn version(
+        &self,
+        index_id: &str,
+        version: u32,
+    ) -> Result<Option<SchemaDefinition>, ServiceError> {
+        self.records.get(&version_key(index_id, version)).await
+    }
+
+    /// Registers a new version of the schema for an index.
+    ///
+    /// The new fields must keep every field of the previous version, or of the static index config
+    /// when no version is registered, unchanged. Indexes that already exist keep the schema they
+    /// were created with until they are reindexed.
+    ///
+    /// Each version can only be registered once, so concurrent registrations based on the same
+    /// previous version fail with a conflict, except for the first one, instead of overwriting
+    /// each other.
+    pub async fn register(
+        &self,
+        index_id: &str,
+        fields: Vec<FieldConfig>,
+    ) -> Result<SchemaDefinition, ServiceError> {
+        validate_index_id(index_id)?;
+        validate_fields(&fields)?;
+
+        let previous: Option<SchemaDefinition> = self.records.get(&latest_key(index_id)).await?;
+
+        let previous_fields = match &previous {
+            Some(previous) => Some(previous.fields.as_slice()),
+            None => self
+                .fallback
+                .as_ref()
+                .and_then(|fallback| fallback.fields(index_id)),
+        };
+
+        if let Some(previous_fields) = previous_fields {
+            check_compatible(previous_fields, &fields)?;
+        }
+
+        let definition = SchemaDefinition {
+            index_id: index_id.to_string(),
+            version: previous.map_or(1, |previous| previous.version + 1),
+            fields,
+            created_at: util::timestamp(),
+        };
+
+        self.records
+            .insert(&version_key(index_id, definition.version), &definition)
+            .await
+            .map_err(|err| match err {
+                ServiceError::Conflict(_) => ServiceError::conflict(&format!(
+                    "Schema for index [{index_id}] was updated concurrently, retry the request"
+                )),
+                err => err,
+            })?;
+
+        self.records
+            .put(vec![(latest_key(index_id), &definition)])
+            .await?;
+
+        self.cache.insert(index_id, Some(definition.clone()));
+
+        Ok(definition)
+    }
+}
+
+#[async_trait]
+impl SchemaLoader for SchemaRegistry {
+    async fn load_schema(&self, index_id: &str) -> Result<Schema, ServiceError> {
+        Ok(build_schema(&self.load_fields(index_id).await?))
+    }
+
+    async fn load_fields(&self, index_id: &str) -> Result<Vec<FieldConfig>, ServiceError> {
+        match self.latest(index_id).await? {
+            Some(definition) => Ok(definition.fields),
+            None => match &self.fallback {
+                Some(fallback) => fallback.load_fields(index_id).await,
+                None => Err(ServiceError::not_found(&format!(
+                    "Schema for index [{index_id}] not found"
+                ))),
+            },
+        }
+    }
+
+    async fn resolve_write_index(&self, name: &str) -> Result<String, ServiceError> {
+        self.aliases.resolve_write_index(name).await
+    }
+
+    async fn resolve_read_indexes(&self, name: &str) -> Result<Vec<String>, ServiceError> {
+        self.aliases.resolve_read_indexes(name).await
+    }
+
+    async fn index_limits(&self, index_id: &str) -> Result<Limits, ServiceError> {
+        match &self.fallback {
+            Some(fallback) => fallback.index_limits(index_id).await,
+            None => Ok(Limits::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn registry(ctx: &TestContext) -> SchemaRegistry {
+        SchemaRegistry::new(
+            Arc::new(ctx.document_store().clone()),
+            Some(ctx.schema_loader().clone()),
+        )
+    }
+
+    fn fields(value: json::Value) -> Vec<FieldConfig> {
+        json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn register_and_load_schema() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        let definition = registry
+            .register(
+                "books",
+                fields(json!([{ "name": "title", "kind": "text", "flags": ["TEXT"] }])),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(1, definition.version);
+
+        let schema = registry.load_schema("books").await.unwrap();
+
+        assert!(schema.get_field("title").is_some());
+        assert!(schema.get_field("__id").is_some());
+    }
+
+    #[tokio::test]
+    async fn update_adds_fields_and_keeps_versions() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+        let year = json!({ "name": "year", "kind": "i64", "flags": ["INDEXED", "FAST"] });
+
+        registry
+            .register("books", fields(json!([title])))
+            .await
+            .unwrap();
+
+        let definition = registry
+            .register("books", fields(json!([title, year])))
+            .await
+            .unwrap();
+
+        assert_eq!(2, definition.version);
+
+        let schema = registry.load_schema("books").await.unwrap();
+        assert!(schema.get_field("year").is_some());
+
+        let first = registry.version("books", 1).await.unwrap().unwrap();
+        assert_eq!(1, first.fields.len());
+    }
+
+    #[tokio::test]
+    async fn concurrent_registrations_conflict() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+
+        // Another process registered version 1 after this one last read the latest version.
+        registry
+            .records
+            .insert(
+                &version_key("books", 1),
+                SchemaDefinition {
+                    index_id: "books".into(),
+                    version: 1,
+                    fields: fields(json!([title])),
+                    created_at: util::timestamp(),
+                },
+            )
+            .await
+            .unwrap();
+
+        let err = registry
+            .register("books", fields(json!([title])))
+            .await
+            .unwrap_err();
+
+        assert_eq!(409, err.status());
+    }
+
+    #[tokio::test]
+    async fn reject_incompatible_changes() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        registry
+            .register(
+                "books",
+                fields(json!([
+                    { "name": "title", "kind": "text", "flags": ["TEXT"] },
+                    { "name": "year", "kind": "i64", "flags": ["INDEXED", "FAST"] }
+                ])),
+            )
+            .await
+            .unwrap();
+
+        let removed = registry
+            .register(
+                "books",
+                fields(json!([{ "name": "title", "kind": "text", "flags": ["TEXT"] }])),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!("Field [year] cannot be removed", removed.message());
+
+        let changed = registry
+            .register(
+                "books",
+                fields(json!([
+                    { "name": "title", "kind": "text", "flags": ["STRING"] },
+                    { "name": "year", "kind": "i64", "flags": ["FAST", "INDEXED"] }
+                ])),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            "Field [title] cannot change kind or flags",
+            changed.message()
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_to_static_schemas_must_be_compatible() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        // The test index config defines title as TEXT.
+        let err = registry
+            .register(
+                "test",
+                fields(json!([{ "name": "title", "kind": "text", "flags": ["STRING"] }])),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(400, err.status());
+
+        // Falls back to the static config when no schema is registered.
+        assert!(registry.load_schema("test").await.is_ok());
+        assert_eq!(
+            404,
+            registry.load_schema("books").await.unwrap_err().status()
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_fields() {
+        let ctx = setup();
+        let registry = registry(&ctx);
+
+        let err = registry
+            .register(
+                "books",
+                fields(json!([
+                    { "name": "title", "kind": "text", "flags": ["TEXT"] },
+                    { "name": "title", "kind": "text", "flags": ["STRING"] }
+                ])),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!("Field [title] is defined more than once", err.message());
+    }
+}
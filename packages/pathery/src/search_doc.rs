@@ -4,8 +4,9 @@ use tantivy::schema::{DocParsingError, Schema};
 use tantivy::Document;
 use thiserror::Error;
 
+use crate::schema::{self, FieldConfig};
 use crate::serialize::compressed_json;
-use crate::util;
+use crate::{coerce, geo, util, vector};
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum SearchDocError {
@@ -20,8 +21,14 @@ pub enum SearchDocError {
 
     #[error("cannot index empty document")]
     EmptyDocument,
+
+    #[error("__id cannot start with {RESERVED_ID_PREFIX:?}")]
+    ReservedId,
 }
 
+/// Prefix of the ids of records pathery keeps in the document store alongside documents.
+pub(crate) const RESERVED_ID_PREFIX: &str = "__pathery|";
+
 impl From<DocParsingError> for SearchDocError {
     fn from(err: DocParsingError) -> Self {
         SearchDocError::SchemaValidationError(err.to_string())
@@ -34,8 +41,19 @@ pub struct DDBKey {
     pub sk: String,
 }
 
-impl From<SearchDocId> for DDBKey {
-    fn from(id: SearchDocId) -> Self {
+impl DDBKey {
+    /// Key of a document of an index. Documents are keyed by index so that indexes, and the
+    /// tenants that own them, can use the same `__id` without overwriting each other's documents.
+    pub fn document(index_id: &str, id: &SearchDocId) -> DDBKey {
+        DDBKey {
+            pk: format!("document|{index_id}|{}", id.0),
+            sk: format!("document|{index_id}|{}", id.0),
+        }
+    }
+
+    /// Key documents were stored under before they were keyed by index. Documents that have not
+    /// been saved again since are still read and deleted by this key.
+    pub fn legacy_document(id: &SearchDocId) -> DDBKey {
         DDBKey {
             pk: format!("document|{}", id.0),
             sk: format!("document|{}", id.0),
@@ -49,10 +67,11 @@ pub struct SearchDocId(String);
 
 impl From<DDBKey> for SearchDocId {
     fn from(key: DDBKey) -> Self {
+        // Index ids cannot contain `|`, document ids can.
         let doc_id = key
             .pk
-            .split("|")
-            .nth(1)
+            .splitn(3, '|')
+            .nth(2)
             .expect("key should be formatted correctly");
 
         Self(doc_id.into())
@@ -89,11 +108,15 @@ impl SearchDoc {
             .entry("__id")
             .or_insert_with(|| json!(util::generate_id()))
             .as_str()
-            .ok_or_else(|| SearchDocError::InvalidIdType)?
+            .ok_or(SearchDocError::InvalidIdType)?
             .to_string();
 
+        if id.starts_with(RESERVED_ID_PREFIX) {
+            return Err(SearchDocError::ReservedId);
+        }
+
         // Validate the document against the provided schema.
-        let document = schema.json_object_to_doc(json_object.clone())?;
+        let document = to_document(schema, json_object.clone())?;
 
         if document.field_values().len() <= 1 {
             return Err(SearchDocError::EmptyDocument);
@@ -105,15 +128,125 @@ impl SearchDoc {
         })
     }
 
+    pub(crate) fn from_parts(id: SearchDocId, content: Map<String, Value>) -> SearchDoc {
+        SearchDoc { id, content }
+    }
+
     pub fn id(&self) -> &SearchDocId {
         &self.id
     }
 
+    pub fn content(&self) -> &Map<String, Value> {
+        &self.content
+    }
+
     pub fn document(&self, schema: &Schema) -> Document {
-        schema
-            .json_object_to_doc(self.content.clone())
-            .expect("should succeed since from_json validates")
+        to_document(schema, self.content.clone()).expect("should succeed since from_json validates")
     }
+
+    /// Checks the document against the parts of its fields that the tantivy schema does not
+    /// describe, such as the dimensions of vectors, the range of geo points, booleans or whether
+    /// fields are multi-valued.
+    pub fn check_fields(&self, fields: &[FieldConfig]) -> Result<(), SearchDocError> {
+        check_objects(fields, &self.content, "")?;
+
+        let fields = schema::flatten_fields(fields);
+        let content = flatten_document(self.content.clone(), |name| {
+            fields.iter().any(|field| field.name() == name)
+        });
+        let fields = &fields[..];
+
+        for field in fields {
+            // The array of a vector is its single value.
+            let is_single =
+                !field.is_multi() && !matches!(field, FieldConfig::VectorFieldConfig { .. });
+
+            if is_single && content.get(field.name()).is_some_and(Value::is_array) {
+                return Err(SearchDocError::SchemaValidationError(format!(
+                    "Field [{}] is single-valued and cannot be an array",
+                    field.name()
+                )));
+            }
+        }
+
+        vector::check_document(fields, &content)?;
+        geo::check_document(fields, &content)?;
+        coerce::check_document(fields, &content)
+    }
+}
+
+/// Checks that the values of object fields are objects.
+fn check_objects(
+    fields: &[FieldConfig],
+    content: &Map<String, Value>,
+    prefix: &str,
+) -> Result<(), SearchDocError> {
+    for field in fields {
+        let FieldConfig::ObjectFieldConfig { name, fields } = field else {
+            continue;
+        };
+
+        match content.get(name) {
+            None => {}
+            Some(Value::Object(content)) => {
+                check_objects(fields, content, &format!("{prefix}{name}."))?
+            }
+            Some(value) => {
+                return Err(SearchDocError::SchemaValidationError(format!(
+                    "Field [{prefix}{name}] expects an object, got {value}"
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Flattens the objects of a document into keys of their dotted path, e.g. `{"props": {"price":
+/// 1}}` into `{"props.price": 1}`, except for the objects that are the value of a field, such as
+/// JSON fields and geo points.
+pub fn flatten_document(
+    content: Map<String, Value>,
+    is_field: impl Fn(&str) -> bool,
+) -> Map<String, Value> {
+    fn flatten(
+        content: Map<String, Value>,
+        prefix: &str,
+        is_field: &impl Fn(&str) -> bool,
+        flattened: &mut Map<String, Value>,
+    ) {
+        for (key, value) in content {
+            let path = format!("{prefix}{key}");
+
+            match value {
+                Value::Object(content) if !is_field(&path) => {
+                    flatten(content, &format!("{path}."), is_field, flattened)
+                }
+                value => {
+                    flattened.insert(path, value);
+                }
+            }
+        }
+    }
+
+    let mut flattened = Map::new();
+    flatten(content, "", &is_field, &mut flattened);
+
+    flattened
+}
+
+/// Converts the JSON content of a document to a tantivy document of the schema.
+pub fn to_document(
+    schema: &Schema,
+    content: Map<String, Value>,
+) -> Result<Document, SearchDocError> {
+    let mut content = flatten_document(content, |name| schema.get_field(name).is_some());
+
+    vector::encode_document(schema, &mut content);
+    geo::encode_document(schema, &mut content);
+    coerce::coerce_document(schema, &mut content)?;
+
+    Ok(schema.json_object_to_doc(content)?)
 }
 
 #[cfg(test)]
@@ -138,7 +271,7 @@ mod tests {
 
         let search_doc = SearchDoc::from_json(&schema, value).unwrap();
 
-        assert!(search_doc.id.0.len() > 0);
+        assert!(!search_doc.id.0.is_empty());
     }
 
     #[test]
@@ -152,6 +285,16 @@ mod tests {
         assert_eq!(id, search_doc.id.0);
     }
 
+    #[test]
+    fn from_json_rejects_reserved_id() {
+        let schema = setup();
+        let value = json!({ "__id": "__pathery|schema", "name": "world" });
+
+        let err = SearchDoc::from_json(&schema, value).unwrap_err();
+
+        assert_eq!(SearchDocError::ReservedId, err);
+    }
+
     #[test]
     fn from_json_returns_validation_error_when_schema_does_not_match() {
         let schema = setup();
@@ -168,4 +311,59 @@ mod tests {
             search_doc,
         );
     }
+
+    #[test]
+    fn to_document_flattens_objects() {
+        let mut schema = Schema::builder();
+        let id = schema.add_text_field("__id", schema::STRING);
+        let price = schema.add_i64_field("props.price", schema::INDEXED);
+        let meta = schema.add_json_field("meta", schema::STORED);
+        let schema = schema.build();
+
+        let document = to_document(
+            &schema,
+            json!({
+                "__id": "a",
+                "props": { "price": 5, "unknown": "ignored" },
+                "meta": { "color": "red" }
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(5),
+            document.get_first(price).and_then(|value| value.as_i64())
+        );
+        assert!(document.get_first(id).is_some());
+        assert!(document.get_first(meta).is_some());
+    }
+
+    #[test]
+    fn check_fields_of_objects() {
+        let fields: Vec<FieldConfig> = serde_json::from_value(json!([
+            {
+                "name": "details",
+                "kind": "object",
+                "fields": [{ "name": "pages", "kind": "u64", "flags": ["INDEXED"] }]
+            },
+        ]))
+        .unwrap();
+        let check = |value: Value| {
+            let content = value.as_object().unwrap().clone();
+
+            SearchDoc::from_parts(SearchDocId::parse("a"), content).check_fields(&fields)
+        };
+
+        assert!(check(json!({ "details": { "pages": "212" } })).is_ok());
+
+        for invalid in [
+            json!({ "details": 5 }),
+            json!({ "details": { "pages": [1, 2] } }),
+        ] {
+            assert!(check(invalid).is_err());
+        }
+    }
 }
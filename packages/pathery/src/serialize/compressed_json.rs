@@ -2,11 +2,21 @@ use serde::de::Visitor;
 use serde::{Deserializer, Serializer};
 use serde_json::{Map, Value};
 
+/// Encodes a JSON object as zstd compressed JSON bytes.
+pub fn encode(input: &Map<String, Value>) -> Vec<u8> {
+    let json_bytes = serde_json::to_vec(input).unwrap();
+    zstd::encode_all(json_bytes.as_slice(), 0).unwrap()
+}
+
+/// Decodes bytes produced by [`encode`].
+pub fn decode(input: &[u8]) -> Map<String, Value> {
+    let decoded_bytes = zstd::decode_all(input).unwrap();
+    serde_json::from_slice(&decoded_bytes).unwrap()
+}
+
 pub fn serialize<S>(input: &Map<String, Value>, serializer: S) -> Result<S::Ok, S::Error>
 where S: Serializer {
-    let json_bytes = serde_json::to_vec(input).unwrap();
-    let encoded_bytes = zstd::encode_all(json_bytes.as_slice(), 0).unwrap();
-    serializer.serialize_bytes(&encoded_bytes)
+    serializer.serialize_bytes(&encode(input))
 }
 
 struct CompressedJsonVisitor;
@@ -20,9 +30,7 @@ impl<'de> Visitor<'de> for CompressedJsonVisitor {
 
     fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
     where E: serde::de::Error {
-        let decoded_bytes = zstd::decode_all(v).unwrap();
-        let deserialized = serde_json::from_slice(&decoded_bytes).unwrap();
-        Ok(deserialized)
+        Ok(decode(v))
     }
 }
 
@@ -0,0 +1,1653 @@
+//! HTTP server that serves the pathery API from a single process, without API Gateway or Lambda.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use http::Method;
+use hyper::service::{make_service_fn, service_fn};
+use lambda_http::{Body, RequestExt};
+use tracing::error;
+
+use crate::auth::{ApiKeyStore, Authorizer};
+use crate::config::{AuthMode, Config, ConfigError};
+use crate::function::query_index_partition::client::LocalQueryIndexPartitionClient;
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::schema::registry::SchemaRegistry;
+use crate::schema::SchemaProvider;
+use crate::service::alias::{DeleteAliasService, GetAliasService, PutAliasService};
+use crate::service::doc::{DeleteDocService, SimilarDocsService};
+use crate::service::index::{
+    BatchIndexService, CreateIndexService, DeleteIndexService, DescribeIndexService,
+    ListIndexesService, PostIndexService, QueryIndexService, ReindexIndexService,
+    StatsIndexService,
+};
+use crate::service::schema::{GetSchemaService, PutSchemaService};
+use crate::service::{map_error_response, ServiceError, ServiceHandler};
+use crate::store::document::DocumentStore;
+use crate::worker::{async_delete, index_writer};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Route {
+    ListIndexes,
+    CreateIndex,
+    DescribeIndex,
+    DeleteIndex,
+    PostIndex,
+    BatchIndex,
+    QueryIndex,
+    ReindexIndex,
+    StatsIndex,
+    DeleteDoc,
+    SimilarDocs,
+    PutSchema,
+    GetSchema,
+    PutAlias,
+    GetAlias,
+    DeleteAlias,
+}
+
+/// Matches a request against the API routes, returning the route and its path parameters.
+fn route(method: &Method, path: &str) -> Option<(Route, HashMap<String, String>)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let (route, params) = match (method, segments.as_slice()) {
+        (&Method::GET, ["index"]) => (Route::ListIndexes, vec![]),
+        (&Method::PUT, ["index", index_id]) => (Route::CreateIndex, vec![("index_id", index_id)]),
+        (&Method::GET, ["index", index_id]) => (Route::DescribeIndex, vec![("index_id", index_id)]),
+        (&Method::DELETE, ["index", index_id]) => {
+            (Route::DeleteIndex, vec![("index_id", index_id)])
+        }
+        (&Method::POST, ["index", index_id]) => (Route::PostIndex, vec![("index_id", index_id)]),
+        (&Method::POST, ["index", index_id, "batch"]) => {
+            (Route::BatchIndex, vec![("index_id", index_id)])
+        }
+        (&Method::POST, ["index", index_id, "query"]) => {
+            (Route::QueryIndex, vec![("index_id", index_id)])
+        }
+        (&Method::POST, ["index", index_id, "reindex"]) => {
+            (Route::ReindexIndex, vec![("index_id", index_id)])
+        }
+        (&Method::PUT, ["index", index_id, "schema"]) => {
+            (Route::PutSchema, vec![("index_id", index_id)])
+        }
+        (&Method::GET, ["index", index_id, "schema"]) => {
+            (Route::GetSchema, vec![("index_id", index_id)])
+        }
+        (&Method::GET, ["index", index_id, "stats"]) => {
+            (Route::StatsIndex, vec![("index_id", index_id)])
+        }
+        (&Method::DELETE, ["index", index_id, "doc", doc_id]) => (
+            Route::DeleteDoc,
+            vec![("index_id", index_id), ("doc_id", doc_id)],
+        ),
+        (&Method::POST, ["index", index_id, "doc", doc_id, "similar"]) => (
+            Route::SimilarDocs,
+            vec![("index_id", index_id), ("doc_id", doc_id)],
+        ),
+        (&Method::PUT, ["alias", alias]) => (Route::PutAlias, vec![("alias", alias)]),
+        (&Method::GET, ["alias", alias]) => (Route::GetAlias, vec![("alias", alias)]),
+        (&Method::DELETE, ["alias", alias]) => (Route::DeleteAlias, vec![("alias", alias)]),
+        _ => return None,
+    };
+
+    let params = params
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    Some((route, params))
+}
+
+/// Dispatches requests to the service handling each API route.
+pub struct Server {
+    pub authorizer: Authorizer,
+
+    pub list_indexes: ListIndexesService,
+
+    pub create_index: CreateIndexService,
+
+    pub describe_index: DescribeIndexService,
+
+    pub delete_index: DeleteIndexService,
+
+    pub post_index: PostIndexService,
+
+    pub batch_index: BatchIndexService,
+
+    pub query_index: QueryIndexService,
+
+    pub reindex_index: ReindexIndexService,
+
+    pub stats_index: StatsIndexService,
+
+    pub delete_doc: DeleteDocService,
+
+    pub similar_docs: SimilarDocsService,
+
+    pub put_schema: PutSchemaService,
+
+    pub get_schema: GetSchemaService,
+
+    pub put_alias: PutAliasService,
+
+    pub get_alias: GetAliasService,
+
+    pub delete_alias: DeleteAliasService,
+}
+
+impl Server {
+    /// Creates a server that stores indexes under the configured data directory and runs the query
+    /// partition worker in-process. The index writer and async delete workers run in-process or
+    /// behind SQS queues depending on the configured queue kind.
+    pub async fn local(
+        config: &Config,
+        document_store: Arc<dyn DocumentStore>,
+    ) -> Result<Server, ConfigError> {
+        let fallback = config
+            .index_config_path()
+            .exists()
+            .then(|| SchemaProvider::from_file(config.index_config_path()));
+
+        let registry = Arc::new(SchemaRegistry::new(Arc::clone(&document_store), fallback));
+
+        let async_delete_client = async_delete::client::create_client(config).await?;
+
+        let index_loader: Arc<dyn IndexLoader> = Arc::new(LambdaIndexLoader::new(
+            config.data_dir(),
+            registry.clone(),
+            registry.aliases(),
+            async_delete_client,
+        ));
+
+        let writer_client = index_writer::client::create_shared_client(
+            config,
+            Arc::clone(&index_loader),
+            Arc::clone(&document_store),
+        )
+        .await?;
+
+        let partition_client = Arc::new(LocalQueryIndexPartitionClient::new(Arc::clone(
+            &index_loader,
+        )));
+
+        let authorizer = match config.auth() {
+            AuthMode::None => Authorizer::disabled(),
+            AuthMode::ApiKey => {
+                Authorizer::new(Arc::new(ApiKeyStore::new(Arc::clone(&document_store))))
+            }
+        };
+
+        Ok(Server {
+            authorizer,
+            list_indexes: ListIndexesService::new(Arc::clone(&index_loader)),
+            create_index: CreateIndexService::new(Arc::clone(&index_loader)),
+            describe_index: DescribeIndexService::new(Arc::clone(&index_loader)),
+            delete_index: DeleteIndexService::new(
+                Arc::clone(&index_loader),
+                Arc::clone(&document_store),
+            ),
+            post_index: PostIndexService::new(
+                registry.clone(),
+                Arc::clone(&document_store),
+                writer_client.clone(),
+            ),
+            batch_index: BatchIndexService::new(
+                registry.clone(),
+                Arc::clone(&document_store),
+                writer_client.clone(),
+            ),
+            query_index: QueryIndexService::new(
+                Arc::clone(&index_loader),
+                Arc::clone(&document_store),
+                partition_client.clone(),
+            ),
+            similar_docs: SimilarDocsService::new(
+                Arc::clone(&index_loader),
+                document_store,
+                partition_client,
+            ),
+            reindex_index: ReindexIndexService::new(
+                Arc::clone(&index_loader),
+                writer_client.clone(),
+            ),
+            stats_index: StatsIndexService::new(index_loader),
+            delete_doc: DeleteDocService::new(registry.clone(), writer_client),
+            put_schema: PutSchemaService::new(registry.clone()),
+            put_alias: PutAliasService::new(registry.aliases()),
+            get_alias: GetAliasService::new(registry.aliases()),
+            delete_alias: DeleteAliasService::new(registry.aliases()),
+            get_schema: GetSchemaService::new(registry),
+        })
+    }
+
+    pub async fn handle(
+        &self,
+        request: lambda_http::Request,
+    ) -> Result<lambda_http::Response<Body>, lambda_http::Error> {
+        let Some((route, params)) = route(request.method(), request.uri().path()) else {
+            return map_error_response(ServiceError::not_found("Route not found"));
+        };
+
+        let query = request.uri().query().unwrap_or_default();
+
+        let query: Vec<(String, String)> = match serde_urlencoded::from_str(query) {
+            Ok(query) => query,
+            Err(err) => return map_error_response(ServiceError::invalid_request(&err.to_string())),
+        };
+
+        let mut query_params: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, value) in query {
+            query_params.entry(name).or_default().push(value);
+        }
+
+        let request = request
+            .with_path_parameters(params)
+            .with_query_string_parameters(query_params);
+
+        match route {
+            Route::ListIndexes => {
+                self.list_indexes
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::CreateIndex => {
+                self.create_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::DescribeIndex => {
+                self.describe_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::DeleteIndex => {
+                self.delete_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::PostIndex => {
+                self.post_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::BatchIndex => {
+                self.batch_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::QueryIndex => {
+                self.query_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::ReindexIndex => {
+                self.reindex_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::StatsIndex => {
+                self.stats_index
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::DeleteDoc => {
+                self.delete_doc
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::SimilarDocs => {
+                self.similar_docs
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::PutSchema => {
+                self.put_schema
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::GetSchema => {
+                self.get_schema
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+            Route::PutAlias => self.put_alias.handle_event(&self.authorizer, request).await,
+            Route::GetAlias => self.get_alias.handle_event(&self.authorizer, request).await,
+            Route::DeleteAlias => {
+                self.delete_alias
+                    .handle_event(&self.authorizer, request)
+                    .await
+            }
+        }
+    }
+
+    async fn handle_http(
+        &self,
+        request: hyper::Request<hyper::Body>,
+    ) -> hyper::Response<hyper::Body> {
+        let (parts, body) = request.into_parts();
+
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(bytes) if bytes.is_empty() => Body::Empty,
+            Ok(bytes) => match String::from_utf8(bytes.to_vec()) {
+                Ok(text) => Body::Text(text),
+                Err(err) => Body::Binary(err.into_bytes()),
+            },
+            Err(err) => {
+                error!(message = "request_body_error", error = %err);
+                return internal_error_response();
+            }
+        };
+
+        match self.handle(http::Request::from_parts(parts, body)).await {
+            Ok(response) => {
+                let (parts, body) = response.into_parts();
+                let body = match body {
+                    Body::Empty => hyper::Body::empty(),
+                    Body::Text(text) => hyper::Body::from(text),
+                    Body::Binary(bytes) => hyper::Body::from(bytes),
+                };
+                hyper::Response::from_parts(parts, body)
+            }
+            Err(err) => {
+                error!(message = "request_error", error = %err);
+                internal_error_response()
+            }
+        }
+    }
+
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), hyper::Error> {
+        let server = Arc::new(self);
+
+        let make_service = make_service_fn(move |_conn| {
+            let server = Arc::clone(&server);
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = Arc::clone(&server);
+
+                    async move { Ok::<_, Infallible>(server.handle_http(request).await) }
+                }))
+            }
+        });
+
+        hyper::Server::bind(&addr).serve(make_service).await
+    }
+}
+
+fn internal_error_response() -> hyper::Response<hyper::Body> {
+    hyper::Response::builder()
+        .status(500)
+        .header("Content-Type", "application/json")
+        .body(hyper::Body::from(r#"{"message":"Internal server error"}"#))
+        .expect("response should build")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Permission, Tenant};
+    use crate::function::query_index_partition::client::LocalQueryIndexPartitionClient;
+    use crate::json;
+    use crate::schema::SchemaLoader;
+    use crate::search_doc::{SearchDoc, SearchDocId};
+    use crate::store::document::SearchDocRef;
+    use crate::test_utils::*;
+
+    fn test_server(ctx: &TestContext) -> Server {
+        let document_store = Arc::new(ctx.document_store().clone());
+        let writer_client = Arc::new(ctx.writer_client().clone());
+        let index_loader = Arc::new(ctx.index_loader().clone());
+        let registry = ctx.schema_registry().clone();
+
+        Server {
+            authorizer: Authorizer::disabled(),
+            list_indexes: ListIndexesService::new(index_loader.clone()),
+            create_index: CreateIndexService::new(index_loader.clone()),
+            describe_index: DescribeIndexService::new(index_loader.clone()),
+            delete_index: DeleteIndexService::new(index_loader.clone(), document_store.clone()),
+            post_index: PostIndexService::new(
+                registry.clone(),
+                document_store.clone(),
+                writer_client.clone(),
+            ),
+            batch_index: BatchIndexService::new(
+                registry.clone(),
+                document_store.clone(),
+                writer_client.clone(),
+            ),
+            query_index: QueryIndexService::new(
+                index_loader.clone(),
+                document_store.clone(),
+                Arc::new(LocalQueryIndexPartitionClient::new(index_loader.clone())),
+            ),
+            similar_docs: SimilarDocsService::new(
+                index_loader.clone(),
+                document_store,
+                Arc::new(LocalQueryIndexPartitionClient::new(index_loader.clone())),
+            ),
+            reindex_index: ReindexIndexService::new(index_loader.clone(), writer_client.clone()),
+            stats_index: StatsIndexService::new(index_loader),
+            delete_doc: DeleteDocService::new(registry.clone(), writer_client),
+            put_schema: PutSchemaService::new(registry.clone()),
+            put_alias: PutAliasService::new(registry.aliases()),
+            get_alias: GetAliasService::new(registry.aliases()),
+            delete_alias: DeleteAliasService::new(registry.aliases()),
+            get_schema: GetSchemaService::new(registry),
+        }
+    }
+
+    fn request(method: Method, path: &str, body: json::Value) -> lambda_http::Request {
+        http::Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::Text(body.to_string()))
+            .unwrap()
+    }
+
+    fn response_json(response: lambda_http::Response<Body>) -> json::Value {
+        match response.body() {
+            Body::Text(text) => json::from_str(text).unwrap(),
+            _ => panic!("expected text body"),
+        }
+    }
+
+    /// Queries the `test` index and returns the response body.
+    async fn query(server: &Server, body: json::Value) -> json::Value {
+        response_json(
+            server
+                .handle(request(Method::POST, "/index/test/query", body))
+                .await
+                .unwrap(),
+        )
+    }
+
+    /// Returns the string values of a field of the matched documents in match order.
+    fn field_values<'a>(body: &'a json::Value, name: &str) -> Vec<&'a str> {
+        body["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["doc"][name].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn route_matches_api_paths() {
+        assert_eq!(
+            Some((
+                Route::DeleteDoc,
+                HashMap::from([
+                    ("index_id".to_string(), "test".to_string()),
+                    ("doc_id".to_string(), "abc".to_string())
+                ])
+            )),
+            route(&Method::DELETE, "/index/test/doc/abc")
+        );
+        assert_eq!(
+            Route::QueryIndex,
+            route(&Method::POST, "/index/test/query").unwrap().0
+        );
+        assert_eq!(
+            Route::SimilarDocs,
+            route(&Method::POST, "/index/test/doc/abc/similar")
+                .unwrap()
+                .0
+        );
+        assert_eq!(
+            Route::ReindexIndex,
+            route(&Method::POST, "/index/test/reindex").unwrap().0
+        );
+        assert_eq!(Route::ListIndexes, route(&Method::GET, "/index").unwrap().0);
+        assert_eq!(
+            Route::PutAlias,
+            route(&Method::PUT, "/alias/books").unwrap().0
+        );
+        assert_eq!(
+            Route::CreateIndex,
+            route(&Method::PUT, "/index/test").unwrap().0
+        );
+        assert_eq!(
+            Route::DeleteIndex,
+            route(&Method::DELETE, "/index/test").unwrap().0
+        );
+        assert_eq!(None, route(&Method::GET, "/index/test/query"));
+        assert_eq!(None, route(&Method::GET, "/unknown"));
+    }
+
+    #[tokio::test]
+    async fn index_and_query_document() {
+        let ctx = setup();
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test",
+                json!({ "__id": "zen", "title": "Zen and the Art of Motorcycle Maintenance" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let body = response_json(response);
+
+        assert_eq!(json!("zen"), body["matches"][0]["doc"]["__id"]);
+    }
+
+    #[tokio::test]
+    async fn batch_index_documents() {
+        let ctx = setup();
+        let server = test_server(&ctx);
+
+        let batch = |size: usize| {
+            (0..size)
+                .map(|n| json!({ "__id": format!("doc-{n}"), "title": "batch" }))
+                .collect::<Vec<_>>()
+        };
+
+        // Batches are not limited to a single DynamoDB batch write.
+        let response = server
+            .handle(request(Method::POST, "/index/test/batch", json!(batch(30))))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let refs = batch(30)
+            .iter()
+            .map(|doc| SearchDocRef::from(SearchDocId::parse(doc["__id"].as_str().unwrap())))
+            .collect();
+        let documents = ctx
+            .document_store()
+            .get_documents("test", refs)
+            .await
+            .unwrap();
+
+        assert_eq!(30, documents.len());
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/batch",
+                json!(batch(1001)),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(400, response.status());
+    }
+
+    #[tokio::test]
+    async fn query_with_highlight_options() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "title": "Zen and the Art of Motorcycle Maintenance",
+                    "author": "Robert M. Pirsig"
+                })],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let query = |highlight: json::Value| {
+            server.handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "zen pirsig", "highlight": highlight }),
+            ))
+        };
+
+        let body = response_json(
+            query(json!({ "fields": ["title"], "pre_tag": "<em>", "post_tag": "</em>" }))
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(
+            json!({ "title": "<em>Zen</em> and the Art of Motorcycle Maintenance" }),
+            body["matches"][0]["snippets"]
+        );
+
+        let body = response_json(query(json!({ "format": "offsets" })).await.unwrap());
+
+        assert_eq!(
+            json!([{ "text": "Robert M. Pirsig", "highlights": [[10, 16]] }]),
+            body["matches"][0]["snippets"]["author"]
+        );
+
+        let response = query(json!({ "fields": ["year"] })).await.unwrap();
+
+        assert_eq!(400, response.status());
+    }
+
+    #[tokio::test]
+    async fn query_with_json_field_snippets() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![json!({
+                    "title": "Zen and the Art of Motorcycle Maintenance",
+                    "props": { "description": "A book about quality", "tags": ["quality"] }
+                })],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = response_json(
+            server
+                .handle(request(
+                    Method::POST,
+                    "/index/test/query",
+                    json!({ "query": "props.description:quality" }),
+                ))
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(
+            json!({ "props.description": "A book about <b>quality</b>" }),
+            body["matches"][0]["snippets"]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_vectors_and_hybrid() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "Zen and the Art of Motorcycle Maintenance", "embedding": [1, 0, 0] }),
+                    json!({ "title": "Motorcycle Repair", "embedding": [0, 1, 0] }),
+                    json!({ "title": "Dune", "embedding": [0.9, 0.1, 0] }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = query(
+            &server,
+            json!({
+                "query": { "knn": { "field": "embedding", "vector": [1, 0, 0] } }
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            vec![
+                "Zen and the Art of Motorcycle Maintenance",
+                "Dune",
+                "Motorcycle Repair"
+            ],
+            field_values(&body, "title")
+        );
+        assert_eq!(json!([1, 0, 0]), body["matches"][0]["doc"]["embedding"]);
+
+        let body = query(
+            &server,
+            json!({
+                "query": {
+                    "hybrid": {
+                        "queries": [
+                            "motorcycle",
+                            { "knn": { "field": "embedding", "vector": [0, 1, 0] } }
+                        ]
+                    }
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            vec![
+                "Motorcycle Repair",
+                "Zen and the Art of Motorcycle Maintenance",
+                "Dune"
+            ],
+            field_values(&body, "title")
+        );
+
+        let next = query(
+            &server,
+            json!({ "query": "ignored", "pagination_token": body["pagination_token"] }),
+        )
+        .await;
+
+        assert!(field_values(&next, "title").is_empty());
+    }
+
+    #[tokio::test]
+    async fn query_multi_valued_fields() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({
+                        "__id": "shop-class",
+                        "title": "Shop Class as Soulcraft",
+                        "author": ["Matthew B. Crawford", "Robert M. Pirsig"],
+                        "year": 2009
+                    }),
+                    // Indexed before single-valued fields rejected arrays.
+                    json!({ "__id": "zen", "title": ["Zen", "Art"], "year": 1974 }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "author:pirsig" }),
+            ))
+            .await
+            .unwrap();
+        let body = response_json(response);
+
+        assert_eq!(
+            json!({
+                "__id": "shop-class",
+                "title": "Shop Class as Soulcraft",
+                "author": ["Matthew B. Crawford", "Robert M. Pirsig"],
+                "year": 2009
+            }),
+            body["matches"][0]["doc"]
+        );
+        assert_eq!(
+            json!("Robert M. <b>Pirsig</b>"),
+            body["matches"][0]["snippets"]["author"]
+        );
+
+        let body = query(&server, json!({ "query": "year:1974" })).await;
+
+        assert_eq!(json!(["Zen", "Art"]), body["matches"][0]["doc"]["title"]);
+
+        // A single value of a multi-valued field is returned as an array.
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test",
+                json!({ "title": "Zen", "author": "Robert M. Pirsig" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test",
+                json!({ "title": ["Zen", "Art"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(400, response.status());
+    }
+
+    #[tokio::test]
+    async fn query_additional_field_kinds() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({
+                        "title": "Zen Book",
+                        "copies": 12,
+                        "price": 9.5,
+                        "in_print": true,
+                        "checksum": "aGk=",
+                        "publisher_ip": "10.0.0.1"
+                    }),
+                    json!({
+                        "title": "Art Book",
+                        "copies": "3",
+                        "price": 24,
+                        "in_print": false,
+                        "publisher_ip": "2001:db8::1"
+                    }),
+                    json!({
+                        "title": "Motorcycle Book",
+                        "copies": 7,
+                        "price": 15.25,
+                        "in_print": true,
+                        "publisher_ip": "10.0.0.200"
+                    }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = query(
+            &server,
+            json!({
+                "query": { "term": { "field": "in_print", "value": true } },
+                "sort": { "value": { "field": "price", "order": "desc" } }
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            vec!["Motorcycle Book", "Zen Book"],
+            field_values(&body, "title")
+        );
+        assert_eq!(json!(true), body["matches"][0]["doc"]["in_print"]);
+        assert_eq!(
+            json!("10.0.0.200"),
+            body["matches"][0]["doc"]["publisher_ip"]
+        );
+
+        let body = query(
+            &server,
+            json!({
+                "query": {
+                    "range": { "field": "publisher_ip", "gte": "10.0.0.0", "lte": "10.0.0.255" }
+                },
+                "sort": { "value": { "field": "copies" } }
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            vec!["Motorcycle Book", "Zen Book"],
+            field_values(&body, "title")
+        );
+
+        let body = query(&server, json!({ "query": "price:[10 TO 30]" })).await;
+        let mut priced = field_values(&body, "title");
+        priced.sort();
+
+        assert_eq!(vec!["Art Book", "Motorcycle Book"], priced);
+
+        let body = query(
+            &server,
+            json!({ "query": { "range": { "field": "copies", "gt": 3, "lt": 12 } } }),
+        )
+        .await;
+
+        assert_eq!(vec!["Motorcycle Book"], field_values(&body, "title"));
+
+        let body = query(
+            &server,
+            json!({ "query": { "term": { "field": "checksum", "value": "aGk=" } } }),
+        )
+        .await;
+
+        assert_eq!(vec!["Zen Book"], field_values(&body, "title"));
+
+        let unsortable = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({
+                    "query": "book",
+                    "sort": { "value": { "field": "publisher_ip" } }
+                }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(400, unsortable.status());
+
+        let stats = response_json(
+            server
+                .handle(request(Method::GET, "/index/test/stats", json!(null)))
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(json!({ "min": 3, "max": 12 }), stats["fields"]["copies"]);
+        assert_eq!(json!({ "min": 9.5, "max": 24.0 }), stats["fields"]["price"]);
+        assert_eq!(
+            json!({ "min": false, "max": true }),
+            stats["fields"]["in_print"]
+        );
+    }
+
+    #[tokio::test]
+    async fn query_object_fields() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({
+                        "__id": "zen",
+                        "title": "Zen Book",
+                        "details": { "publisher": "Morrow", "pages": 418 }
+                    }),
+                    json!({
+                        "__id": "art",
+                        "title": "Art Book",
+                        "details": { "publisher": "Penguin", "pages": "212" }
+                    }),
+                    json!({
+                        "__id": "shop",
+                        "title": "Shop Book",
+                        "details": { "publisher": "Penguin", "pages": 256 }
+                    }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = query(
+            &server,
+            json!({
+                "query": { "range": { "field": "details.pages", "gte": 250 } },
+                "sort": { "value": { "field": "details.pages", "order": "desc" } }
+            }),
+        )
+        .await;
+
+        assert_eq!(vec!["zen", "shop"], field_values(&body, "__id"));
+        assert_eq!(
+            json!({ "publisher": "Morrow", "pages": 418 }),
+            body["matches"][0]["doc"]["details"]
+        );
+
+        let body = query(
+            &server,
+            json!({
+                "query": "details.publisher:Penguin",
+                "sort": { "value": { "field": "details.pages" } }
+            }),
+        )
+        .await;
+
+        assert_eq!(vec!["art", "shop"], field_values(&body, "__id"));
+
+        let body = query(&server, json!({ "query": "details.pages:[200 TO 300]" })).await;
+        let mut ids = field_values(&body, "__id");
+        ids.sort();
+
+        assert_eq!(vec!["art", "shop"], ids);
+    }
+
+    #[tokio::test]
+    async fn query_geo_points() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "Amsterdam Store", "location": { "lat": 52.3676, "lon": 4.9041 } }),
+                    json!({ "title": "Rotterdam Store", "location": { "lat": 51.9244, "lon": 4.4777 } }),
+                    json!({ "title": "Utrecht Store", "location": { "lat": 52.0907, "lon": 5.1214 } }),
+                    json!({ "title": "Online Store" }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = query(
+            &server,
+            json!({
+                "query": {
+                    "geo_distance": {
+                        "field": "location",
+                        "origin": { "lat": 52.37, "lon": 4.89 },
+                        "distance_km": 10
+                    }
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(vec!["Amsterdam Store"], field_values(&body, "title"));
+        assert_eq!(
+            json!({ "lat": 52.3676, "lon": 4.9041 }),
+            body["matches"][0]["doc"]["location"]
+        );
+
+        let body = query(
+            &server,
+            json!({
+                "query": {
+                    "geo_bounding_box": {
+                        "field": "location",
+                        "top_left": { "lat": 52.5, "lon": 4.8 },
+                        "bottom_right": { "lat": 52.0, "lon": 5.2 }
+                    }
+                }
+            }),
+        )
+        .await;
+
+        let mut boxed = field_values(&body, "title");
+        boxed.sort();
+
+        assert_eq!(vec!["Amsterdam Store", "Utrecht Store"], boxed);
+
+        let body = query(
+            &server,
+            json!({
+                "query": "store",
+                "sort": {
+                    "geo_distance": {
+                        "field": "location",
+                        "origin": { "lat": 52.0907, "lon": 5.1214 }
+                    }
+                }
+            }),
+        )
+        .await;
+
+        assert_eq!(
+            vec![
+                "Utrecht Store",
+                "Amsterdam Store",
+                "Rotterdam Store",
+                "Online Store"
+            ],
+            field_values(&body, "title")
+        );
+        assert_eq!(0.0, body["matches"][0]["distance_km"]);
+        assert!(body["matches"][3].get("distance_km").is_none());
+
+        let hybrid_sort = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({
+                    "query": { "hybrid": { "queries": ["store"] } },
+                    "sort": {
+                        "geo_distance": {
+                            "field": "location",
+                            "origin": { "lat": 52.0907, "lon": 5.1214 }
+                        }
+                    }
+                }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(400, hybrid_sort.status());
+    }
+
+    #[tokio::test]
+    async fn query_phrases_with_slop() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "title": "Zen and the Art of Motorcycle Maintenance" }),
+                    json!({ "title": "Motorcycle Art" }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let body = query(&server, json!({ "query": "\"art motorcycle\"" })).await;
+
+        assert!(field_values(&body, "title").is_empty());
+
+        let body = query(&server, json!({ "query": "title:\"art motorcycle\"~1" })).await;
+
+        assert_eq!(
+            vec!["Zen and the Art of Motorcycle Maintenance"],
+            field_values(&body, "title")
+        );
+
+        let body = query(
+            &server,
+            json!({ "query": { "phrase": { "field": "title", "text": "zen art", "slop": 2 } } }),
+        )
+        .await;
+
+        assert_eq!(
+            vec!["Zen and the Art of Motorcycle Maintenance"],
+            field_values(&body, "title")
+        );
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "\"art motorcycle\"~many" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(400, response.status());
+    }
+
+    #[tokio::test]
+    async fn query_indexes_that_do_not_exist() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "Zen" })])
+            .await;
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test-missing/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+        assert_eq!(json!([]), response_json(response)["matches"]);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test,test-missing/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+        let body = response_json(response);
+
+        assert_eq!(vec!["Zen"], field_values(&body, "title"));
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test,test-missing/query",
+                json!({ "query": "zen", "pagination_token": body["pagination_token"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+        assert_eq!(json!([]), response_json(response)["matches"]);
+
+        // Queries do not create the indexes they read.
+        assert!(!ctx
+            .index_loader()
+            .index_exists("test-missing")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn similar_documents() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "zen", "title": "Zen and the Art of Motorcycle Maintenance" }),
+                    json!({ "__id": "art", "title": "The Art of Motorcycle Repair" }),
+                    json!({ "__id": "dune", "title": "Dune", "author": "Frank Herbert" }),
+                    json!({ "__id": "shop", "title": "Shop Class", "details": { "publisher": "Penguin" } }),
+                    json!({ "__id": "walden", "title": "Walden", "details": { "publisher": "Penguin" } }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let similar = |doc_id: &str| {
+            server.handle(
+                http::Request::builder()
+                    .method(Method::POST)
+                    .uri(format!("/index/test/doc/{doc_id}/similar"))
+                    .body(Body::Empty)
+                    .unwrap(),
+            )
+        };
+
+        let body = response_json(similar("zen").await.unwrap());
+
+        assert_eq!(vec!["art"], field_values(&body, "__id"));
+        assert_eq!("test", body["matches"][0]["index_id"]);
+
+        let body = response_json(similar("shop").await.unwrap());
+
+        assert_eq!(vec!["walden"], field_values(&body, "__id"));
+
+        let response = similar("unknown").await.unwrap();
+
+        assert_eq!(404, response.status());
+    }
+
+    #[tokio::test]
+    async fn similar_documents_of_documents_in_the_index() {
+        let ctx = setup()
+            .with_documents(
+                "test-a",
+                vec![json!({ "__id": "art", "title": "The Art of Motorcycle Repair" })],
+            )
+            .await
+            .with_documents(
+                "test-b",
+                vec![
+                    json!({ "__id": "zen", "title": "Zen and the Art of Motorcycle Maintenance" }),
+                ],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        // A document that was saved but not indexed yet.
+        let schema = ctx.schema_loader().load_schema("test-a").await.unwrap();
+        ctx.document_store()
+            .save_documents(
+                "test-a",
+                vec![
+                    SearchDoc::from_json(&schema, json!({ "__id": "dune", "title": "Dune" }))
+                        .unwrap(),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let similar = |path: &str| {
+            server.handle(
+                http::Request::builder()
+                    .method(Method::POST)
+                    .uri(path)
+                    .body(Body::Empty)
+                    .unwrap(),
+            )
+        };
+
+        let response = similar("/index/test-a/doc/dune/similar").await.unwrap();
+        assert_eq!(404, response.status());
+
+        let response = similar("/index/test-a/doc/zen/similar").await.unwrap();
+        assert_eq!(404, response.status());
+
+        // The document is loaded from the first index that contains it.
+        let body = response_json(
+            similar("/index/test-a,test-b/doc/zen/similar")
+                .await
+                .unwrap(),
+        );
+
+        assert_eq!(json!("art"), body["matches"][0]["doc"]["__id"]);
+        assert_eq!(1, body["matches"].as_array().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn register_and_get_schema_version() {
+        let ctx = setup();
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::PUT,
+                "/index/books/schema",
+                json!({ "fields": [{ "name": "title", "kind": "text", "flags": ["TEXT"] }] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let response = server
+            .handle(request(
+                Method::GET,
+                "/index/books/schema?version=1",
+                json!({}),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+        assert_eq!(json!(1), response_json(response)["version"]);
+    }
+
+    #[tokio::test]
+    async fn index_and_query_through_alias() {
+        let ctx = setup();
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::PUT,
+                "/alias/books",
+                json!({ "indexes": ["test-v1"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        server
+            .handle(request(
+                Method::POST,
+                "/index/books",
+                json!({ "__id": "zen", "title": "Zen" }),
+            ))
+            .await
+            .unwrap();
+
+        assert!(ctx.index_loader().index_exists("test-v1").await.unwrap());
+        assert!(!ctx.index_loader().index_exists("books").await.unwrap());
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/books/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            json!("zen"),
+            response_json(response)["matches"][0]["doc"]["__id"]
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_requests_resolve_aliases() {
+        let ctx = setup()
+            .with_documents("test-v1", vec![json!({ "__id": "zen", "title": "Zen" })])
+            .await;
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::PUT,
+                "/alias/test-v1",
+                json!({ "indexes": ["test-v2"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(409, response.status());
+
+        server
+            .handle(request(
+                Method::PUT,
+                "/alias/books",
+                json!({ "indexes": ["test-v1"], "write_index": "test-v1" }),
+            ))
+            .await
+            .unwrap();
+
+        let response = server
+            .handle(request(Method::GET, "/index/books", json!(null)))
+            .await
+            .unwrap();
+
+        let body = response_json(response);
+        assert_eq!(json!("test-v1"), body["index_id"]);
+        assert_eq!(json!(1), body["num_docs"]);
+
+        let response = server
+            .handle(request(Method::GET, "/index/books/stats", json!(null)))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+
+        let response = server
+            .handle(request(Method::GET, "/index/missing/stats", json!(null)))
+            .await
+            .unwrap();
+
+        assert_eq!(404, response.status());
+        assert!(!ctx.index_loader().index_exists("missing").await.unwrap());
+
+        let response = server
+            .handle(request(Method::DELETE, "/index/books", json!(null)))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+        assert!(!ctx.index_loader().index_exists("test-v1").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn query_multiple_indexes() {
+        let ctx = setup()
+            .with_documents("test-a", vec![json!({ "__id": "a", "title": "Zen" })])
+            .await
+            .with_documents(
+                "test-b",
+                vec![json!({ "__id": "b", "title": "Zen and more zen" })],
+            )
+            .await;
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test-a,test-b/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+
+        let body = response_json(response);
+
+        let mut hits: Vec<_> = body["matches"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| (hit["index_id"].clone(), hit["doc"]["__id"].clone()))
+            .collect();
+        hits.sort_by_key(|(index_id, _)| index_id.to_string());
+
+        assert_eq!(
+            vec![(json!("test-a"), json!("a")), (json!("test-b"), json!("b"))],
+            hits
+        );
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test-a,test-b/query",
+                json!({ "query": "zen", "pagination_token": body["pagination_token"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(json!([]), response_json(response)["matches"]);
+    }
+
+    #[tokio::test]
+    async fn pagination_token_reads_the_index_from_before_a_reindex() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "__id": "a", "title": "zen" })])
+            .await;
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "zen" }),
+            ))
+            .await
+            .unwrap();
+
+        let body = response_json(response);
+        assert_eq!(1, body["matches"].as_array().unwrap().len());
+
+        let response = server
+            .handle(request(Method::POST, "/index/test/reindex", json!({})))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test",
+                json!({ "__id": "b", "title": "zen" }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status());
+
+        // The rebuilt index has a second match, which a page of the previous index does not see.
+        let response = server
+            .handle(request(
+                Method::POST,
+                "/index/test/query",
+                json!({ "query": "zen", "pagination_token": body["pagination_token"] }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(200, response.status());
+        assert_eq!(json!([]), response_json(response)["matches"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() {
+        let ctx = setup();
+        let server = test_server(&ctx);
+
+        let response = server
+            .handle(request(Method::GET, "/unknown", json!({})))
+            .await
+            .unwrap();
+
+        assert_eq!(404, response.status());
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_scoped_to_tenant() {
+        let ctx = setup();
+        let api_keys = Arc::new(ApiKeyStore::new(Arc::new(ctx.document_store().clone())));
+        let server = Server {
+            authorizer: Authorizer::new(api_keys.clone()),
+            ..test_server(&ctx)
+        };
+
+        let (api_key, _) = api_keys
+            .create_key(Tenant {
+                tenant_id: "acme".into(),
+                index_prefixes: vec!["test-acme".into()],
+                permissions: vec![Permission::Write],
+                limits: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let authorized = |method: Method, path: &str| {
+            let mut request = request(method, path, json!({ "title": "Zen" }));
+            request.headers_mut().insert(
+                http::header::AUTHORIZATION,
+                format!("Bearer {api_key}").parse().unwrap(),
+            );
+            request
+        };
+
+        let status = |response: lambda_http::Response<Body>| response.status().as_u16();
+
+        assert_eq!(
+            200,
+            status(
+                server
+                    .handle(authorized(Method::POST, "/index/test-acme"))
+                    .await
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            401,
+            status(
+                server
+                    .handle(request(Method::POST, "/index/test-acme", json!({})))
+                    .await
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            403,
+            status(
+                server
+                    .handle(authorized(Method::POST, "/index/test-other"))
+                    .await
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            403,
+            status(
+                server
+                    .handle(authorized(Method::POST, "/index/test-acme/query"))
+                    .await
+                    .unwrap()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn tenants_can_index_the_same_id() {
+        let ctx = setup();
+        let api_keys = Arc::new(ApiKeyStore::new(Arc::new(ctx.document_store().clone())));
+        let server = Server {
+            authorizer: Authorizer::new(api_keys.clone()),
+            ..test_server(&ctx)
+        };
+
+        let mut keys = vec![];
+
+        for tenant_id in ["acme", "other"] {
+            let (api_key, _) = api_keys
+                .create_key(Tenant {
+                    tenant_id: tenant_id.into(),
+                    index_prefixes: vec![format!("test-{tenant_id}")],
+                    permissions: vec![Permission::Read, Permission::Write],
+                    limits: Default::default(),
+                })
+                .await
+                .unwrap();
+
+            keys.push((tenant_id, api_key));
+        }
+
+        let authorized = |api_key: &str, path: &str, body: json::Value| {
+            let mut request = request(Method::POST, path, body);
+            request.headers_mut().insert(
+                http::header::AUTHORIZATION,
+                format!("Bearer {api_key}").parse().unwrap(),
+            );
+            request
+        };
+
+        for (tenant_id, api_key) in &keys {
+            let response = server
+                .handle(authorized(
+                    api_key,
+                    &format!("/index/test-{tenant_id}"),
+                    json!({ "__id": "x", "title": format!("zen {tenant_id}") }),
+                ))
+                .await
+                .unwrap();
+
+            assert_eq!(200, response.status());
+        }
+
+        for (tenant_id, api_key) in &keys {
+            let response = server
+                .handle(authorized(
+                    api_key,
+                    &format!("/index/test-{tenant_id}/query"),
+                    json!({ "query": "zen" }),
+                ))
+                .await
+                .unwrap();
+
+            let body = response_json(response);
+
+            assert_eq!(1, body["matches"].as_array().unwrap().len());
+            assert_eq!(
+                json!(format!("zen {tenant_id}")),
+                body["matches"][0]["doc"]["title"]
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::alias::{AliasRegistry, IndexAlias};
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PutAliasRequest {
+    pub indexes: Vec<String>,
+
+    pub write_index: Option<String>,
+}
+
+/// Creates an alias or atomically replaces the indexes it points to.
+pub struct PutAliasService {
+    aliases: Arc<AliasRegistry>,
+}
+
+#[async_trait]
+impl ServiceHandler<PutAliasRequest, IndexAlias> for PutAliasService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<PutAliasRequest>,
+    ) -> ServiceResponse<IndexAlias> {
+        let alias = request.path_param("alias")?;
+        let body = request.body()?;
+
+        // The alias name is authorized from the path, but its indexes must belong to the tenant
+        // too, or the alias could be used to read and write other tenants' indexes.
+        if let Some(tenant) = request.tenant() {
+            tenant.authorize(Permission::Admin, body.indexes.iter().map(String::as_str))?;
+        }
+
+        self.aliases
+            .put(&alias, body.indexes, body.write_index)
+            .await
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl PutAliasService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        Ok(PutAliasService::new(Arc::new(
+            AliasRegistry::create(config).await?,
+        )))
+    }
+
+    pub fn new(aliases: Arc<AliasRegistry>) -> Self {
+        PutAliasService { aliases }
+    }
+}
+
+pub struct GetAliasService {
+    aliases: Arc<AliasRegistry>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, IndexAlias> for GetAliasService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<IndexAlias> {
+        let alias = request.path_param("alias")?;
+
+        self.aliases
+            .get(&alias)
+            .await?
+            .ok_or_else(|| ServiceError::not_found(&format!("Alias [{alias}] not found")))
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
+
+impl GetAliasService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        Ok(GetAliasService::new(Arc::new(
+            AliasRegistry::create(config).await?,
+        )))
+    }
+
+    pub fn new(aliases: Arc<AliasRegistry>) -> Self {
+        GetAliasService { aliases }
+    }
+}
+
+/// Removes an alias. The indexes it points to are not affected.
+pub struct DeleteAliasService {
+    aliases: Arc<AliasRegistry>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, IndexAlias> for DeleteAliasService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<IndexAlias> {
+        let alias = request.path_param("alias")?;
+
+        self.aliases.delete(&alias).await
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl DeleteAliasService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        Ok(DeleteAliasService::new(Arc::new(
+            AliasRegistry::create(config).await?,
+        )))
+    }
+
+    pub fn new(aliases: Arc<AliasRegistry>) -> Self {
+        DeleteAliasService { aliases }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Tenant;
+    use crate::test_utils::*;
+
+    fn put_request(alias: &str, body: json::Value) -> ServiceRequest<PutAliasRequest> {
+        ServiceRequest::create(json::from_value(body).unwrap()).with_path_param("alias", alias)
+    }
+
+    fn request(alias: &str) -> ServiceRequest<json::Value> {
+        ServiceRequest::create(json!({})).with_path_param("alias", alias)
+    }
+
+    #[tokio::test]
+    async fn put_get_and_delete_alias() {
+        let ctx = setup();
+        let aliases = ctx.schema_registry().aliases();
+
+        let put = PutAliasService::new(aliases.clone());
+        let get = GetAliasService::new(aliases.clone());
+        let delete = DeleteAliasService::new(aliases);
+
+        put.handle_request(put_request("books", json!({ "indexes": ["test-v1"] })))
+            .await
+            .unwrap();
+
+        let swapped = put
+            .handle_request(put_request(
+                "books",
+                json!({ "indexes": ["test-v1", "test-v2"], "write_index": "test-v2" }),
+            ))
+            .await
+            .unwrap();
+
+        assert_eq!(swapped, get.handle_request(request("books")).await.unwrap());
+
+        delete.handle_request(request("books")).await.unwrap();
+
+        let err = get.handle_request(request("books")).await.unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+
+    #[tokio::test]
+    async fn tenant_cannot_alias_other_indexes() {
+        let ctx = setup();
+        let put = PutAliasService::new(ctx.schema_registry().aliases());
+
+        let tenant = Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: vec!["acme-".into()],
+            permissions: vec![Permission::Admin],
+            limits: Default::default(),
+        };
+
+        let err = put
+            .handle_request(
+                put_request("acme-books", json!({ "indexes": ["other-books"] }))
+                    .with_tenant(tenant),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(403, err.status());
+    }
+}
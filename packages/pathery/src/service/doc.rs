@@ -1,10 +1,28 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
+use tantivy::collector::Count;
+use tantivy::query::TermQuery;
+use tantivy::schema::{FieldType, IndexRecordOption};
+use tantivy::{Index, Term};
 
-use super::{ServiceHandler, ServiceRequest, ServiceResponse};
-use crate::search_doc::SearchDocId;
-use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use super::index::{QueryIndexService, QueryRequest, QueryResponse};
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::function::query_index_partition::client::{
+    LambdaQueryIndexPartitionClient, QueryIndexPartitionClient,
+};
+use crate::highlight::HighlightOptions;
+use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::query::{SearchQuery, StructuredQuery};
+use crate::schema::registry::SchemaRegistry;
+use crate::schema::SchemaLoader;
+use crate::search_doc::{flatten_document, SearchDocId};
+use crate::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use crate::worker::index_writer::client::{self, IndexWriterClient};
 use crate::worker::index_writer::job::Job;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,7 +37,9 @@ pub struct DeleteDocResponse {
 }
 
 pub struct DeleteDocService {
-    client: Box<dyn IndexWriterClient>,
+    schema_loader: Arc<dyn SchemaLoader>,
+
+    client: Arc<dyn IndexWriterClient>,
 }
 
 #[async_trait]
@@ -28,10 +48,13 @@ impl ServiceHandler<json::Value, DeleteDocResponse> for DeleteDocService {
         &self,
         request: ServiceRequest<json::Value>,
     ) -> ServiceResponse<DeleteDocResponse> {
-        let index_id = request.path_param("index_id")?;
+        let index_id = self
+            .schema_loader
+            .resolve_write_index(&request.path_param("index_id")?)
+            .await?;
         let doc_id = request.path_param("doc_id")?;
 
-        let mut job = Job::create(&index_id);
+        let mut job = Job::create(&index_id).with_tenant(request.tenant());
 
         job.delete_doc(SearchDocId::parse(&doc_id));
 
@@ -39,14 +62,177 @@ impl ServiceHandler<json::Value, DeleteDocResponse> for DeleteDocService {
 
         Ok(DeleteDocResponse { job_id })
     }
+
+    fn permission(&self) -> Permission {
+        Permission::Write
+    }
 }
 
 impl DeleteDocService {
-    pub async fn create() -> Self {
-        let client = LambdaIndexWriterClient::create(None).await;
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let schema_loader = SchemaRegistry::create(config).await?;
+        let client = client::create_client(config).await?;
+
+        Ok(DeleteDocService::new(Arc::new(schema_loader), client))
+    }
 
+    pub fn new(schema_loader: Arc<dyn SchemaLoader>, client: Arc<dyn IndexWriterClient>) -> Self {
         DeleteDocService {
-            client: Box::new(client),
+            schema_loader,
+            client,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SimilarDocsRequest {
+    /// Text fields of the document compared with other documents. Defaults to every indexed text
+    /// field.
+    pub fields: Option<Vec<String>>,
+
+    pub pagination_token: Option<String>,
+}
+
+/// Finds the documents of an index most similar to one of its documents.
+pub struct SimilarDocsService {
+    index_loader: Arc<dyn IndexLoader>,
+
+    document_store: Arc<dyn DocumentStore>,
+
+    query_index: QueryIndexService,
+}
+
+#[async_trait]
+impl ServiceHandler<SimilarDocsRequest, QueryResponse> for SimilarDocsService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<SimilarDocsRequest>,
+    ) -> ServiceResponse<QueryResponse> {
+        let body = request.body_or_default()?;
+        let names = request.path_param("index_id")?;
+        let doc_id = request.path_param("doc_id")?;
+
+        let mut source = None;
+
+        for index_id in self.query_index.resolve_indexes(&names).await? {
+            if !self.index_loader.index_exists(&index_id).await? {
+                continue;
+            }
+
+            let index = self.index_loader.load_index(&index_id, None).await?;
+
+            if contains_document(&index, &doc_id)? {
+                source = Some((index_id, index.schema()));
+                break;
+            }
+        }
+
+        let (index_id, schema) = source
+            .ok_or_else(|| ServiceError::not_found(&format!("Document [{doc_id}] not found")))?;
+
+        let doc = self
+            .document_store
+            .get_documents(
+                &index_id,
+                vec![SearchDocRef::from(SearchDocId::parse(&doc_id))],
+            )
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ServiceError::not_found(&format!("Document [{doc_id}] not found")))?;
+
+        let fields = body.fields.unwrap_or_else(|| {
+            schema
+                .fields()
+                .map(|(_, entry)| entry)
+                .filter(|entry| {
+                    entry.is_indexed()
+                        && matches!(entry.field_type(), FieldType::Str(_))
+                        && !entry.name().starts_with("__")
+                })
+                .map(|entry| entry.name().to_string())
+                .collect()
+        });
+
+        // Sub-fields of object fields are named by their dotted path.
+        let like = flatten_document(doc.content().clone(), |name| {
+            schema.get_field(name).is_some()
+        })
+        .into_iter()
+        .filter(|(name, _)| fields.contains(name))
+        .collect();
+
+        let query = SearchQuery::Structured(StructuredQuery::MoreLikeThis {
+            like,
+            exclude: vec![doc_id],
+        });
+
+        self.query_index
+            .search(
+                request.tenant(),
+                &names,
+                QueryRequest {
+                    query,
+                    pagination_token: body.pagination_token,
+                    highlight: HighlightOptions::default(),
+                    sort: None,
+                },
+            )
+            .await
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
+
+/// Whether the document is in the index. The document store also holds documents that are not
+/// indexed yet or were deleted from the index.
+fn contains_document(index: &Index, doc_id: &str) -> Result<bool, ServiceError> {
+    let searcher = index
+        .reader()
+        .map_err(ServiceError::internal_error)?
+        .searcher();
+
+    let query = TermQuery::new(
+        Term::from_field_text(index.id_field(), doc_id),
+        IndexRecordOption::Basic,
+    );
+
+    let count = searcher
+        .search(&query, &Count)
+        .map_err(ServiceError::internal_error)?;
+
+    Ok(count > 0)
+}
+
+impl SimilarDocsService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let document_store = DDBDocumentStore::create(config).await?;
+        let index_loader = LambdaIndexLoader::create(config).await?;
+        let partition_client = LambdaQueryIndexPartitionClient::create(config).await?;
+
+        Ok(SimilarDocsService::new(
+            Arc::new(index_loader),
+            Arc::new(document_store),
+            Arc::new(partition_client),
+        ))
+    }
+
+    pub fn new(
+        index_loader: Arc<dyn IndexLoader>,
+        document_store: Arc<dyn DocumentStore>,
+        partition_client: Arc<dyn QueryIndexPartitionClient>,
+    ) -> Self {
+        SimilarDocsService {
+            query_index: QueryIndexService::new(
+                index_loader.clone(),
+                document_store.clone(),
+                partition_client,
+            ),
+            index_loader,
+            document_store,
         }
     }
 }
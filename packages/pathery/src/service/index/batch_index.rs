@@ -1,25 +1,37 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use serde::Serialize;
 
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
 use crate::json;
-use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::quota::Quotas;
+use crate::schema::registry::SchemaRegistry;
+use crate::schema::SchemaLoader;
 use crate::search_doc::SearchDoc;
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore};
-use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::client::{self, IndexWriterClient};
 use crate::worker::index_writer::job::Job;
 
+/// Maximum number of documents accepted in a single batch request. The document store splits the
+/// documents into as many DynamoDB batch writes as needed, so this only bounds the request size.
+const MAX_BATCH_SIZE: usize = 1000;
+
 #[derive(Serialize)]
 pub struct BatchIndexResponse {
     pub job_id: String,
 }
 
 pub struct BatchIndexService {
-    schema_loader: Box<dyn SchemaLoader>,
+    schema_loader: Arc<dyn SchemaLoader>,
+
+    document_store: Arc<dyn DocumentStore>,
 
-    document_store: Box<dyn DocumentStore>,
+    index_writer: Arc<dyn IndexWriterClient>,
 
-    index_writer: Box<dyn IndexWriterClient>,
+    quotas: Quotas,
 }
 
 #[async_trait]
@@ -30,15 +42,28 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
     ) -> ServiceResponse<BatchIndexResponse> {
         let body = request.body()?;
 
-        let index_id = request.path_param("index_id")?;
+        if body.len() > MAX_BATCH_SIZE {
+            return Err(ServiceError::invalid_request(&format!(
+                "Too many documents in request, max {MAX_BATCH_SIZE}."
+            )));
+        }
+
+        let index_id = self
+            .schema_loader
+            .resolve_write_index(&request.path_param("index_id")?)
+            .await?;
 
-        let schema = self.schema_loader.load_schema(&index_id)?;
+        let schema = self.schema_loader.load_schema(&index_id).await?;
+        let fields = self.schema_loader.load_fields(&index_id).await?;
 
-        let mut job = Job::create(&index_id);
+        let mut job = Job::create(&index_id).with_tenant(request.tenant());
 
         let documents = body
             .into_iter()
-            .map(|value| SearchDoc::from_json(&schema, value))
+            .map(|value| {
+                SearchDoc::from_json(&schema, value)
+                    .and_then(|document| document.check_fields(&fields).map(|_| document))
+            })
             .collect::<Vec<_>>();
 
         let error = documents
@@ -50,8 +75,7 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
         if let Some((idx, error)) = error.first() {
             return Err(ServiceError::invalid_request(&format!(
                 "Error parsing document (path: [{}]): {}",
-                idx,
-                error.to_string()
+                idx, error
             )));
         }
 
@@ -60,7 +84,16 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
             .filter_map(Result::ok)
             .collect::<Vec<_>>();
 
-        let doc_refs = self.document_store.save_documents(documents).await?;
+        let limits = self.schema_loader.index_limits(&index_id).await?;
+
+        self.quotas
+            .check_write(request.tenant(), &index_id, &limits, documents.len())
+            .await?;
+
+        let doc_refs = self
+            .document_store
+            .save_documents(&index_id, documents)
+            .await?;
 
         for doc_ref in doc_refs {
             job.index_doc(doc_ref)
@@ -70,18 +103,36 @@ impl ServiceHandler<Vec<json::Value>, BatchIndexResponse> for BatchIndexService
 
         Ok(BatchIndexResponse { job_id })
     }
+
+    fn permission(&self) -> Permission {
+        Permission::Write
+    }
 }
 
 impl BatchIndexService {
-    pub async fn create() -> Self {
-        let document_store = DDBDocumentStore::create(None).await;
-        let writer_client = LambdaIndexWriterClient::create(None).await;
-        let schema_loader = SchemaProvider::lambda();
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let document_store: Arc<dyn DocumentStore> =
+            Arc::new(DDBDocumentStore::create(config).await?);
+        let writer_client = client::create_client(config).await?;
+        let schema_loader = SchemaRegistry::create(config).await?;
+
+        Ok(BatchIndexService::new(
+            Arc::new(schema_loader),
+            document_store,
+            writer_client,
+        ))
+    }
 
+    pub fn new(
+        schema_loader: Arc<dyn SchemaLoader>,
+        document_store: Arc<dyn DocumentStore>,
+        index_writer: Arc<dyn IndexWriterClient>,
+    ) -> Self {
         BatchIndexService {
-            document_store: Box::new(document_store),
-            index_writer: Box::new(writer_client),
-            schema_loader: Box::new(schema_loader),
+            schema_loader,
+            quotas: Quotas::new(document_store.clone()),
+            document_store,
+            index_writer,
         }
     }
 }
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::describe_index::DescribeIndexResponse;
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+
+pub struct CreateIndexService {
+    index_loader: Arc<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, DescribeIndexResponse> for CreateIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<DescribeIndexResponse> {
+        let index_id = request.path_param("index_id")?;
+
+        let index = self
+            .index_loader
+            .create_index(&index_id)
+            .await
+            .map_err(|err| match err {
+                // The index id is valid but no schema is configured for it.
+                ServiceError::NotFound(message) => ServiceError::InvalidRequest(message),
+                err => err,
+            })?;
+
+        DescribeIndexResponse::describe(&index_id, &index)
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl CreateIndexService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader = LambdaIndexLoader::create(config).await?;
+
+        Ok(CreateIndexService::new(Arc::new(index_loader)))
+    }
+
+    pub fn new(index_loader: Arc<dyn IndexLoader>) -> Self {
+        CreateIndexService { index_loader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> CreateIndexService {
+        CreateIndexService::new(Arc::new(ctx.index_loader().clone()))
+    }
+
+    fn request(index_id: &str) -> ServiceRequest<json::Value> {
+        ServiceRequest::create(json!({})).with_path_param("index_id", index_id)
+    }
+
+    #[tokio::test]
+    async fn create_index() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        let response = service.handle_request(request("test-books")).await.unwrap();
+
+        assert_eq!("test-books", response.index_id);
+        assert_eq!(0, response.num_docs);
+        assert!(ctx.index_loader().index_exists("test-books").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn create_existing_index() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        service.handle_request(request("test")).await.unwrap();
+
+        let response = service.handle_request(request("test")).await.unwrap_err();
+
+        assert_eq!(409, response.status());
+    }
+
+    #[tokio::test]
+    async fn create_index_without_schema() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        let response = service.handle_request(request("books")).await.unwrap_err();
+
+        assert_eq!(400, response.status());
+        assert_eq!("Schema for index [books] not found", response.message());
+    }
+
+    #[tokio::test]
+    async fn create_index_with_invalid_id() {
+        let ctx = setup();
+        let service = test_service(&ctx);
+
+        let response = service
+            .handle_request(request("test/../books"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(400, response.status());
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::quota::delete_index_usage;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+
+#[derive(Serialize, Debug)]
+pub struct DeleteIndexResponse {
+    pub index_id: String,
+
+    /// Number of documents purged from the document store.
+    pub deleted_docs: usize,
+}
+
+pub struct DeleteIndexService {
+    index_loader: Arc<dyn IndexLoader>,
+
+    document_store: Arc<dyn DocumentStore>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, DeleteIndexResponse> for DeleteIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<DeleteIndexResponse> {
+        let index_id = self
+            .index_loader
+            .resolve_write_index(&request.path_param("index_id")?)
+            .await?;
+
+        if !self.index_loader.index_exists(&index_id).await? {
+            return Err(ServiceError::not_found(&format!(
+                "Index [{index_id}] not found"
+            )));
+        }
+
+        let index = self.index_loader.load_index(&index_id, None).await?;
+
+        let doc_ids = index.document_ids()?;
+        let deleted_docs = doc_ids.len();
+
+        // Documents are purged before the index is removed so that a failed purge can be retried.
+        // They are stored per index, so other indexes keep their documents with the same ids.
+        self.document_store
+            .delete_documents(&index_id, doc_ids)
+            .await?;
+
+        self.index_loader.delete_index(&index_id).await?;
+
+        delete_index_usage(self.document_store.as_ref(), &index_id).await?;
+
+        Ok(DeleteIndexResponse {
+            index_id,
+            deleted_docs,
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl DeleteIndexService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader = LambdaIndexLoader::create(config).await?;
+        let document_store = DDBDocumentStore::create(config).await?;
+
+        Ok(DeleteIndexService::new(
+            Arc::new(index_loader),
+            Arc::new(document_store),
+        ))
+    }
+
+    pub fn new(index_loader: Arc<dyn IndexLoader>, document_store: Arc<dyn DocumentStore>) -> Self {
+        DeleteIndexService {
+            index_loader,
+            document_store,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_doc::SearchDocId;
+    use crate::store::document::SearchDocRef;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn delete_index_and_documents() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "zen", "title": "Zen" }),
+                    json!({ "__id": "dune", "title": "Dune" }),
+                ],
+            )
+            .await;
+
+        let service = DeleteIndexService::new(
+            Arc::new(ctx.index_loader().clone()),
+            Arc::new(ctx.document_store().clone()),
+        );
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(2, response.deleted_docs);
+        assert!(!ctx.index_loader().index_exists("test").await.unwrap());
+
+        let docs = ctx
+            .document_store()
+            .get_documents("test", vec![SearchDocRef::from(SearchDocId::parse("zen"))])
+            .await
+            .unwrap();
+
+        assert!(docs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn delete_index_keeps_documents_of_other_indexes() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "__id": "zen", "title": "Zen" })])
+            .await
+            .with_documents("test-other", vec![json!({ "__id": "zen", "title": "Zen" })])
+            .await;
+
+        let service = DeleteIndexService::new(
+            Arc::new(ctx.index_loader().clone()),
+            Arc::new(ctx.document_store().clone()),
+        );
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        service.handle_request(request).await.unwrap();
+
+        let docs = ctx
+            .document_store()
+            .get_documents(
+                "test-other",
+                vec![SearchDocRef::from(SearchDocId::parse("zen"))],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(1, docs.len());
+    }
+
+    #[tokio::test]
+    async fn delete_missing_index() {
+        let ctx = setup();
+
+        let service = DeleteIndexService::new(
+            Arc::new(ctx.index_loader().clone()),
+            Arc::new(ctx.document_store().clone()),
+        );
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap_err();
+
+        assert_eq!(404, response.status());
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use tantivy::schema::Schema;
+use tantivy::{Index, IndexSettings};
+
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+
+#[derive(Serialize, Debug)]
+pub struct DescribeIndexResponse {
+    pub index_id: String,
+
+    pub schema: Schema,
+
+    pub settings: IndexSettings,
+
+    pub num_docs: u64,
+
+    pub num_segments: usize,
+}
+
+impl DescribeIndexResponse {
+    pub(crate) fn describe(index_id: &str, index: &Index) -> Result<Self, ServiceError> {
+        let searcher = index
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+
+        Ok(DescribeIndexResponse {
+            index_id: index_id.to_string(),
+            schema: index.schema(),
+            settings: index.settings().clone(),
+            num_docs: searcher.num_docs(),
+            num_segments: searcher.segment_readers().len(),
+        })
+    }
+}
+
+pub struct DescribeIndexService {
+    index_loader: Arc<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, DescribeIndexResponse> for DescribeIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<DescribeIndexResponse> {
+        let index_id = self
+            .index_loader
+            .resolve_read_index(&request.path_param("index_id")?)
+            .await?;
+
+        if !self.index_loader.index_exists(&index_id).await? {
+            return Err(ServiceError::not_found(&format!(
+                "Index [{index_id}] not found"
+            )));
+        }
+
+        let index = self.index_loader.load_index(&index_id, None).await?;
+
+        DescribeIndexResponse::describe(&index_id, &index)
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
+
+impl DescribeIndexService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader = LambdaIndexLoader::create(config).await?;
+
+        Ok(DescribeIndexService::new(Arc::new(index_loader)))
+    }
+
+    pub fn new(index_loader: Arc<dyn IndexLoader>) -> Self {
+        DescribeIndexService { index_loader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn describe_existing_index() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "Zen" })])
+            .await;
+
+        let service = DescribeIndexService::new(Arc::new(ctx.index_loader().clone()));
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap();
+
+        assert_eq!(1, response.num_docs);
+        assert!(response.schema.get_field("title").is_some());
+    }
+
+    #[tokio::test]
+    async fn describe_missing_index() {
+        let ctx = setup();
+
+        let service = DescribeIndexService::new(Arc::new(ctx.index_loader().clone()));
+
+        let request = ServiceRequest::create(json!({})).with_path_param("index_id", "test");
+
+        let response = service.handle_request(request).await.unwrap_err();
+
+        assert_eq!(404, response.status());
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+
+#[derive(Serialize, Debug)]
+pub struct IndexSummary {
+    pub index_id: String,
+
+    pub num_docs: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListIndexesResponse {
+    pub indexes: Vec<IndexSummary>,
+}
+
+pub struct ListIndexesService {
+    index_loader: Arc<dyn IndexLoader>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, ListIndexesResponse> for ListIndexesService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<ListIndexesResponse> {
+        let mut indexes = vec![];
+
+        for index_id in self.index_loader.list_indexes().await? {
+            // API keys only see the indexes of their tenant.
+            if let Some(tenant) = request.tenant() {
+                if !tenant.can_access(&index_id) {
+                    continue;
+                }
+            }
+
+            let index = self.index_loader.load_index(&index_id, None).await?;
+
+            let num_docs = index
+                .reader()
+                .map_err(ServiceError::internal_error)?
+                .searcher()
+                .num_docs();
+
+            indexes.push(IndexSummary { index_id, num_docs });
+        }
+
+        Ok(ListIndexesResponse { indexes })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
+
+impl ListIndexesService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader = LambdaIndexLoader::create(config).await?;
+
+        Ok(ListIndexesService::new(Arc::new(index_loader)))
+    }
+
+    pub fn new(index_loader: Arc<dyn IndexLoader>) -> Self {
+        ListIndexesService { index_loader }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Tenant;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn list_indexes_with_doc_counts() {
+        let ctx = setup()
+            .with_documents(
+                "test-b",
+                vec![json!({ "title": "Zen" }), json!({ "title": "Dune" })],
+            )
+            .await
+            .with_documents("test-a", vec![json!({ "title": "Emma" })])
+            .await;
+
+        let service = ListIndexesService::new(Arc::new(ctx.index_loader().clone()));
+
+        let response = service
+            .handle_request(ServiceRequest::create(json!({})))
+            .await
+            .unwrap();
+
+        let indexes: Vec<_> = response
+            .indexes
+            .iter()
+            .map(|index| (index.index_id.as_str(), index.num_docs))
+            .collect();
+
+        assert_eq!(vec![("test-a", 1), ("test-b", 2)], indexes);
+    }
+
+    #[tokio::test]
+    async fn list_indexes_of_tenant() {
+        let ctx = setup()
+            .with_documents("test-acme", vec![json!({ "title": "Zen" })])
+            .await
+            .with_documents("test-other", vec![json!({ "title": "Emma" })])
+            .await;
+
+        let service = ListIndexesService::new(Arc::new(ctx.index_loader().clone()));
+
+        let tenant = Tenant {
+            tenant_id: "acme".into(),
+            index_prefixes: vec!["test-acme".into()],
+            permissions: vec![Permission::Read],
+            limits: Default::default(),
+        };
+
+        let response = service
+            .handle_request(ServiceRequest::create(json!({})).with_tenant(tenant))
+            .await
+            .unwrap();
+
+        let index_ids: Vec<_> = response
+            .indexes
+            .iter()
+            .map(|index| index.index_id.as_str())
+            .collect();
+
+        assert_eq!(vec!["test-acme"], index_ids);
+    }
+}
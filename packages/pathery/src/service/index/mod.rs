@@ -1,9 +1,19 @@
 mod batch_index;
+mod create_index;
+mod delete_index;
+mod describe_index;
+mod list_indexes;
 mod post_index;
 mod query_index;
+mod reindex_index;
 mod stats_index;
 
 pub use batch_index::BatchIndexService;
+pub use create_index::CreateIndexService;
+pub use delete_index::DeleteIndexService;
+pub use describe_index::DescribeIndexService;
+pub use list_indexes::ListIndexesService;
 pub use post_index::PostIndexService;
-pub use query_index::QueryIndexService;
+pub use query_index::{QueryIndexService, QueryRequest, QueryResponse};
+pub use reindex_index::ReindexIndexService;
 pub use stats_index::StatsIndexService;
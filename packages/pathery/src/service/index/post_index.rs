@@ -1,11 +1,17 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use serde::Serialize;
 
-use crate::schema::{SchemaLoader, SchemaProvider};
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::quota::Quotas;
+use crate::schema::registry::SchemaRegistry;
+use crate::schema::SchemaLoader;
 use crate::search_doc::SearchDoc;
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore};
-use crate::worker::index_writer::client::{IndexWriterClient, LambdaIndexWriterClient};
+use crate::worker::index_writer::client::{self, IndexWriterClient};
 use crate::worker::index_writer::job::Job;
 use crate::{json, util};
 
@@ -16,11 +22,13 @@ pub struct PostIndexResponse {
 }
 
 pub struct PostIndexService {
-    schema_loader: Box<dyn SchemaLoader>,
+    schema_loader: Arc<dyn SchemaLoader>,
+
+    document_store: Arc<dyn DocumentStore>,
 
-    document_store: Box<dyn DocumentStore>,
+    writer_client: Arc<dyn IndexWriterClient>,
 
-    writer_client: Box<dyn IndexWriterClient>,
+    quotas: Quotas,
 }
 
 #[async_trait]
@@ -31,16 +39,31 @@ impl ServiceHandler<json::Value, PostIndexResponse> for PostIndexService {
     ) -> ServiceResponse<PostIndexResponse> {
         let body = request.body()?;
 
-        let index_id = request.path_param("index_id")?;
+        let index_id = self
+            .schema_loader
+            .resolve_write_index(&request.path_param("index_id")?)
+            .await?;
+
+        let schema = self.schema_loader.load_schema(&index_id).await?;
 
-        let schema = self.schema_loader.load_schema(&index_id)?;
+        let fields = self.schema_loader.load_fields(&index_id).await?;
 
         let document = SearchDoc::from_json(&schema, body)
+            .and_then(|document| document.check_fields(&fields).map(|_| document))
             .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
 
-        let doc_refs = self.document_store.save_documents(vec![document]).await?;
+        let limits = self.schema_loader.index_limits(&index_id).await?;
+
+        self.quotas
+            .check_write(request.tenant(), &index_id, &limits, 1)
+            .await?;
+
+        let doc_refs = self
+            .document_store
+            .save_documents(&index_id, vec![document])
+            .await?;
 
-        let mut job = Job::create(&index_id);
+        let mut job = Job::create(&index_id).with_tenant(request.tenant());
 
         for doc_ref in doc_refs {
             job.index_doc(doc_ref);
@@ -53,18 +76,36 @@ impl ServiceHandler<json::Value, PostIndexResponse> for PostIndexService {
             updated_at: util::timestamp(),
         })
     }
+
+    fn permission(&self) -> Permission {
+        Permission::Write
+    }
 }
 
 impl PostIndexService {
-    pub async fn create() -> Self {
-        let document_store = DDBDocumentStore::create(None).await;
-        let writer_client = LambdaIndexWriterClient::create(None).await;
-        let schema_loader = SchemaProvider::lambda();
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let document_store: Arc<dyn DocumentStore> =
+            Arc::new(DDBDocumentStore::create(config).await?);
+        let writer_client = client::create_client(config).await?;
+        let schema_loader = SchemaRegistry::create(config).await?;
+
+        Ok(PostIndexService::new(
+            Arc::new(schema_loader),
+            document_store,
+            writer_client,
+        ))
+    }
 
+    pub fn new(
+        schema_loader: Arc<dyn SchemaLoader>,
+        document_store: Arc<dyn DocumentStore>,
+        writer_client: Arc<dyn IndexWriterClient>,
+    ) -> Self {
         PostIndexService {
-            document_store: Box::new(document_store),
-            writer_client: Box::new(writer_client),
-            schema_loader: Box::new(schema_loader),
+            schema_loader,
+            quotas: Quotas::new(document_store.clone()),
+            document_store,
+            writer_client,
         }
     }
 }
@@ -77,15 +118,11 @@ mod tests {
     pub fn test_service() -> PostIndexService {
         let ctx = setup();
 
-        let schema_loader = Box::new(ctx.schema_loader().clone());
-        let document_store = Box::new(ctx.document_store().clone());
-        let writer_client = Box::new(ctx.writer_client().clone());
+        let schema_loader = Arc::new(ctx.schema_loader().clone());
+        let document_store = Arc::new(ctx.document_store().clone());
+        let writer_client = Arc::new(ctx.writer_client().clone());
 
-        PostIndexService {
-            schema_loader,
-            document_store,
-            writer_client,
-        }
+        PostIndexService::new(schema_loader, document_store, writer_client)
     }
 
     #[tokio::test]
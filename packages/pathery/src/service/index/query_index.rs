@@ -1,32 +1,57 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use tantivy::query::QueryParser;
-use tantivy::schema::{Field, FieldType};
-use tantivy::{Index, SnippetGenerator, TantivyError};
+use tantivy::Index;
 use tracing::info;
 
-use crate::function::query_index_partition::client::LambdaQueryIndexPartitionClient;
-use crate::function::query_index_partition::PartitionSearchHit;
-use crate::index::{IndexExt, IndexLoader, LambdaIndexLoader};
+use crate::auth::{Permission, Tenant};
+use crate::config::{Config, ConfigError};
+use crate::function::query_index_partition::client::{
+    LambdaQueryIndexPartitionClient, QueryIndexPartitionClient,
+};
+use crate::function::query_index_partition::{
+    PartitionSearchHit, QueryRequest as PartitionQueryRequest,
+};
+use crate::highlight::{HighlightOptions, Highlighter};
+use crate::index::{IndexLoader, LambdaIndexLoader};
 use crate::json;
 use crate::pagination::PaginationToken;
+use crate::query::{SearchQuery, Sort, StructuredQuery};
+use crate::quota::Quotas;
+use crate::schema::{flatten_fields, FieldConfig};
+use crate::search_doc::{flatten_document, SearchDocId};
 use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 use crate::store::document::{DDBDocumentStore, DocumentStore};
 
+/// Number of matches in a page of matches.
+const PAGE_SIZE: usize = 10;
+
+/// Number of top matches of each query of a hybrid query that are fused.
+const HYBRID_WINDOW: usize = 100;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QueryRequest {
-    pub query: String,
+    pub query: SearchQuery,
     pub pagination_token: Option<String>,
+    #[serde(default)]
+    pub highlight: HighlightOptions,
+    #[serde(default)]
+    pub sort: Option<Sort>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct SearchHit {
+    /// Index the document matched in.
+    pub index_id: String,
     pub doc: json::Value,
     pub snippets: json::Value,
     pub score: f32,
+    /// Distance to the origin of a geo distance sort, if the document has a point.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_km: Option<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -36,11 +61,13 @@ pub struct QueryResponse {
 }
 
 pub struct QueryIndexService {
-    index_loader: Box<dyn IndexLoader>,
+    index_loader: Arc<dyn IndexLoader>,
+
+    document_store: Arc<dyn DocumentStore>,
 
-    document_store: Box<dyn DocumentStore>,
+    query_index_paritition_client: Arc<dyn QueryIndexPartitionClient>,
 
-    query_index_paritition_client: Arc<LambdaQueryIndexPartitionClient>,
+    quotas: Quotas,
 }
 
 #[async_trait]
@@ -49,159 +76,238 @@ impl ServiceHandler<QueryRequest, QueryResponse> for QueryIndexService {
         &self,
         request: ServiceRequest<QueryRequest>,
     ) -> ServiceResponse<QueryResponse> {
-        let body = request.body()?;
+        self.search(
+            request.tenant(),
+            &request.path_param("index_id")?,
+            request.body()?,
+        )
+        .await
+    }
 
-        let index_id = request.path_param("index_id")?;
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
 
-        let index = self.index_loader.load_index(&index_id, None)?;
+impl QueryIndexService {
+    /// Searches the comma separated indexes or aliases across their partitions.
+    pub async fn search(
+        &self,
+        tenant: Option<&Tenant>,
+        names: &str,
+        body: QueryRequest,
+    ) -> ServiceResponse<QueryResponse> {
+        let index_ids = self.resolve_indexes(names).await?;
 
-        let metas = index.load_metas().unwrap();
-        let num_docs: u32 = metas.segments.iter().map(|seg| seg.num_docs()).sum();
-        info!("Doc count: {}", num_docs);
+        let mut limits = vec![];
 
-        let total_partitions = (num_docs / 60_000) + 1;
-        info!("Total partitions: {}", total_partitions);
+        for index_id in &index_ids {
+            limits.push((
+                index_id.clone(),
+                self.index_loader.index_limits(index_id).await?,
+            ));
+        }
 
-        let mut pagination_token = match body.pagination_token {
-            Some(token) => PaginationToken::parse(token),
-            None => {
-                let mut pagination_token =
-                    PaginationToken::new(&body.query, total_partitions as usize);
-                let metas = index.load_metas().unwrap();
-                let segments = metas.segments;
-                let segments_json = serde_json::to_value(segments).unwrap();
-                pagination_token.import_segments_json(segments_json);
-                pagination_token
+        self.quotas.check_read(tenant, &limits).await?;
+
+        let token = match body.pagination_token {
+            Some(token) => {
+                let token = PaginationToken::parse(token)
+                    .ok_or_else(|| ServiceError::invalid_request("Invalid pagination token"))?;
+
+                let token_index_ids: Vec<_> = token
+                    .indexes()
+                    .iter()
+                    .map(|index| index.index_id())
+                    .collect();
+
+                if token_index_ids != index_ids {
+                    return Err(ServiceError::invalid_request(
+                        "Pagination token does not match the queried indexes",
+                    ));
+                }
+
+                Some(token)
             }
+            None => None,
         };
 
-        let requests: Vec<_> = (0..total_partitions)
-            .map(|partition_n| {
-                let query_client = Arc::clone(&self.query_index_paritition_client);
-                let index_id = index_id.clone();
-                let ro_token = pagination_token.clone();
-
-                tokio::spawn(async move {
-                    query_client
-                        .query_partition(
-                            index_id.clone(),
-                            ro_token.get_query(),
-                            ro_token.get_offset(partition_n as usize),
-                            partition_n as usize,
-                            ro_token.segments_for_partition(partition_n as usize),
-                        )
-                        .await
-                })
-            })
-            .collect();
+        // Pages of a pagination token read the directories the first page was read from, which
+        // stay readable for a while after the indexes are reindexed. Indexes that do not exist yet
+        // are not created by queries, they have no directory and match nothing.
+        let mut directories = vec![];
+
+        for (index_n, index_id) in index_ids.iter().enumerate() {
+            let pinned = token.as_ref().map(|token| &token.indexes()[index_n]);
+
+            let directory = match pinned {
+                Some(pinned) if pinned.total_partitions() == 0 => None,
+                Some(pinned) if pinned.directory().is_some() => {
+                    pinned.directory().map(str::to_string)
+                }
+                _ if self.index_loader.index_exists(index_id).await? => {
+                    Some(self.index_loader.index_directory(index_id).await?)
+                }
+                _ => None,
+            };
+
+            directories.push(directory);
+        }
 
-        let mut matches: Vec<PartitionSearchHit> = Vec::new();
+        let mut indexes = vec![];
 
-        for request in requests {
-            let mut response = request.await.unwrap();
-            let response = response.matches.as_mut();
-            matches.append(response);
+        for (index_id, directory) in index_ids.iter().zip(&directories) {
+            let index = match directory {
+                Some(directory) => {
+                    self.index_loader
+                        .load_index_directory(index_id, directory, None)
+                        .await?
+                }
+                None => Index::create_in_ram(self.index_loader.load_schema(index_id).await?),
+            };
+
+            indexes.push(index);
         }
 
-        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
-        matches.truncate(10);
+        // Snippets are generated with a single schema, so every index must share it.
+        let schema = indexes[0].schema();
 
-        for match_one in &matches {
-            pagination_token.inc_offset(match_one.partition_n)
+        if let Some(position) = indexes.iter().position(|index| index.schema() != schema) {
+            return Err(ServiceError::invalid_request(&format!(
+                "Indexes [{}] and [{}] have different schemas",
+                index_ids[0], index_ids[position]
+            )));
         }
 
+        let fields = self.index_loader.load_fields(&index_ids[0]).await?;
+
+        let mut pagination_token = match token {
+            Some(token) => token,
+            None => {
+                let query = body.query.resolve(&flatten_fields(&fields))?;
+
+                if let Some(sort) = &body.sort {
+                    if let SearchQuery::Structured(StructuredQuery::Hybrid { .. }) = query {
+                        return Err(ServiceError::invalid_request(
+                            "Hybrid queries are ranked by fusion and cannot be sorted",
+                        ));
+                    }
+
+                    sort.check(&flatten_fields(&fields))?;
+                }
+
+                let mut pagination_token = PaginationToken::new(query).with_sort(body.sort);
+
+                for ((index_id, directory), index) in
+                    index_ids.iter().zip(&directories).zip(&indexes)
+                {
+                    let Some(directory) = directory else {
+                        pagination_token.add_missing_index(index_id.clone());
+                        continue;
+                    };
+
+                    let metas = index.load_metas().unwrap();
+                    let num_docs: u32 = metas.segments.iter().map(|seg| seg.num_docs()).sum();
+                    info!("Doc count: {}", num_docs);
+
+                    let total_partitions = (num_docs / 60_000) + 1;
+                    info!("Total partitions: {}", total_partitions);
+
+                    let segments_json = serde_json::to_value(metas.segments).unwrap();
+                    pagination_token.add_index(
+                        index_id.clone(),
+                        directory.clone(),
+                        total_partitions as usize,
+                        segments_json,
+                    );
+                }
+
+                pagination_token
+            }
+        };
+
+        // Partitions search with the query of the pagination token, so snippets highlight it too.
+        let query = pagination_token.get_query().parse(&indexes[0])?;
+        let highlighter = Highlighter::create(&indexes[0], query.as_ref(), &body.highlight)?;
+
+        // Hits are paired with the position of their index in the pagination token.
+        let matches = match pagination_token.get_query() {
+            SearchQuery::Structured(StructuredQuery::Hybrid {
+                queries,
+                rank_constant,
+            }) => {
+                self.fuse_partitions(&mut pagination_token, queries, rank_constant)
+                    .await?
+            }
+            query => {
+                let mut matches = self
+                    .search_partitions(&pagination_token, query, PAGE_SIZE)
+                    .await?;
+                matches.truncate(PAGE_SIZE);
+
+                for (index_n, match_one) in &matches {
+                    pagination_token.inc_offset(*index_n, match_one.partition_n)
+                }
+
+                matches
+            }
+        };
+
         println!("{}", serde_json::to_string(&pagination_token).unwrap());
 
-        if matches.len() == 0 {
+        if matches.is_empty() {
             return Ok(QueryResponse {
                 matches: vec![],
                 pagination_token: None,
             });
         }
 
-        let retrieved_matches = self
-            .document_store
-            .get_documents(
-                matches
-                    .iter()
-                    .map(|one_match| one_match.doc_ref.clone())
-                    .collect(),
-            )
-            .await
-            .unwrap();
-
-        let snippet_index = Index::create_in_ram(index.schema());
-        let mut snippet_writer = snippet_index.default_writer();
-        let snippet_reader = snippet_index.reader().unwrap();
-        let snippet_schema = snippet_index.schema();
-
-        let query_parser = QueryParser::for_index(
-            &snippet_index,
-            snippet_schema
-                .fields()
-                .filter_map(|(field, entry)| {
-                    if !entry.is_indexed() {
-                        return None;
-                    }
-                    match entry.field_type() {
-                        FieldType::Str(_) => Some(field),
-                        _ => None,
-                    }
-                })
-                .collect::<Vec<Field>>(),
-        );
-
-        let query = query_parser
-            .parse_query(&body.query)
-            .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
-
-        let matches = retrieved_matches
-            .iter()
-            .zip(matches)
-            .map(|(search_doc, one_match)| {
-                let document = search_doc.document(&snippet_schema);
-                let named_doc = snippet_schema.to_named_doc(&document);
-                snippet_writer.add_document(document.clone()).unwrap();
-                snippet_writer.commit().unwrap();
-                snippet_reader.reload().unwrap();
-                let snippet_searcher = snippet_reader.searcher();
-
-                let snippets: HashMap<String, String> = document
-                    .field_values()
-                    .iter()
-                    .filter_map(|field_value| {
-                        // Only text fields are supported for snippets
-                        let text = field_value.value().as_text()?;
-
-                        let generator = match SnippetGenerator::create(
-                            &snippet_searcher,
-                            &query,
-                            field_value.field(),
-                        ) {
-                            Ok(generator) => Some(generator),
-                            // InvalidArgument is returned when field is not indexed
-                            Err(TantivyError::InvalidArgument(_)) => None,
-                            Err(err) => panic!("{}", err.to_string()),
-                        }?;
-
-                        let snippet = generator.snippet(text).to_html();
-
-                        if snippet.is_empty() {
-                            None
-                        } else {
-                            Some((
-                                snippet_schema.get_field_name(field_value.field()).into(),
-                                snippet,
-                            ))
-                        }
-                    })
-                    .collect();
+        // Documents are stored per index, so they are looked up by the position of their index
+        // and their id. The same id can match in several indexes with different content.
+        let mut retrieved_docs = HashMap::new();
 
-                SearchHit {
+        for (index_n, index) in pagination_token.indexes().iter().enumerate() {
+            let doc_refs: Vec<_> = matches
+                .iter()
+                .filter(|(match_index_n, _)| *match_index_n == index_n)
+                .map(|(_, one_match)| one_match.doc_ref.clone())
+                .collect();
+
+            if doc_refs.is_empty() {
+                continue;
+            }
+
+            for doc in self
+                .document_store
+                .get_documents(index.index_id(), doc_refs)
+                .await
+                .unwrap()
+            {
+                retrieved_docs.insert((index_n, doc.id().clone()), doc);
+            }
+        }
+
+        let matches = matches
+            .into_iter()
+            .filter_map(|(index_n, one_match)| {
+                let search_doc = retrieved_docs.get(&(index_n, one_match.doc_ref.id().clone()))?;
+                let document = search_doc.document(&schema);
+                let named_doc = schema.to_named_doc(&document);
+                let snippets = highlighter.highlight(&document);
+
+                let doc = render_doc(
+                    &fields,
+                    json::to_value(named_doc).expect("named doc should serialize"),
+                    search_doc.content(),
+                );
+
+                Some(SearchHit {
+                    index_id: pagination_token.indexes()[index_n].index_id().to_string(),
                     score: one_match.score,
-                    doc: json::to_value(named_doc).expect("named doc should serialize"),
+                    distance_km: one_match.distance_km,
+                    doc,
                     snippets: json::to_value(snippets).expect("snippets should serialize"),
-                }
+                })
             })
             .collect();
 
@@ -210,21 +316,244 @@ impl ServiceHandler<QueryRequest, QueryResponse> for QueryIndexService {
             pagination_token: Some(pagination_token.serialize()),
         })
     }
-}
 
-impl QueryIndexService {
-    pub async fn create() -> QueryIndexService {
-        let document_store = DDBDocumentStore::create(None).await;
-        let index_loader = LambdaIndexLoader::create();
+    /// Searches every partition of the indexes of the pagination token from their offsets, and
+    /// returns up to `limit` matches of each partition in the order of the sort of the token, or
+    /// by descending score, paired with the position of their index in the pagination token.
+    async fn search_partitions(
+        &self,
+        pagination_token: &PaginationToken,
+        query: SearchQuery,
+        limit: usize,
+    ) -> Result<Vec<(usize, PartitionSearchHit)>, ServiceError> {
+        let mut requests = vec![];
+
+        for (index_n, index) in pagination_token.indexes().iter().enumerate() {
+            for partition_n in 0..index.total_partitions() {
+                let query_client = Arc::clone(&self.query_index_paritition_client);
+                let input = PartitionQueryRequest {
+                    index_id: index.index_id().to_string(),
+                    directory: index.directory().map(str::to_string),
+                    query: query.clone(),
+                    offset: index.get_offset(partition_n),
+                    limit,
+                    partition_n,
+                    segments: index.segments_for_partition(partition_n),
+                    sort: pagination_token.sort().cloned(),
+                };
+
+                let request =
+                    tokio::spawn(async move { query_client.query_partition(input).await });
+
+                requests.push((index_n, request));
+            }
+        }
+
+        let mut matches: Vec<(usize, PartitionSearchHit)> = Vec::new();
+
+        for (index_n, request) in requests {
+            let response = request.await.unwrap()?;
+            matches.extend(response.matches.into_iter().map(|hit| (index_n, hit)));
+        }
+
+        match pagination_token.sort() {
+            Some(Sort::GeoDistance { .. }) => matches.sort_by(|(_, a), (_, b)| {
+                let distance = |hit: &PartitionSearchHit| hit.distance_km.unwrap_or(f64::INFINITY);
+
+                distance(a)
+                    .total_cmp(&distance(b))
+                    .then(b.score.total_cmp(&a.score))
+            }),
+            Some(Sort::Value { .. }) => matches.sort_by(|(_, a), (_, b)| {
+                b.sort_key
+                    .cmp(&a.sort_key)
+                    .then(b.score.total_cmp(&a.score))
+            }),
+            None => matches.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score)),
+        }
+
+        Ok(matches)
+    }
+
+    /// Fuses the top matches of each query of a hybrid query by reciprocal rank and returns the
+    /// next page of the fused matches, see [`StructuredQuery::Hybrid`].
+    async fn fuse_partitions(
+        &self,
+        pagination_token: &mut PaginationToken,
+        queries: Vec<SearchQuery>,
+        rank_constant: u32,
+    ) -> Result<Vec<(usize, PartitionSearchHit)>, ServiceError> {
+        let mut fused: Vec<(usize, PartitionSearchHit)> = vec![];
+        let mut positions: HashMap<(usize, SearchDocId), usize> = HashMap::new();
+
+        for query in queries {
+            let mut matches = self
+                .search_partitions(pagination_token, query, HYBRID_WINDOW)
+                .await?;
+            matches.truncate(HYBRID_WINDOW);
+
+            for (rank, (index_n, hit)) in matches.into_iter().enumerate() {
+                let score = 1.0 / (rank_constant as f32 + rank as f32 + 1.0);
+
+                match positions.entry((index_n, hit.doc_ref.id().clone())) {
+                    Entry::Occupied(position) => fused[*position.get()].1.score += score,
+                    Entry::Vacant(position) => {
+                        position.insert(fused.len());
+                        fused.push((index_n, PartitionSearchHit { score, ..hit }));
+                    }
+                }
+            }
+        }
+
+        fused.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score));
+
+        let page: Vec<_> = fused
+            .into_iter()
+            .skip(pagination_token.fused_offset())
+            .take(PAGE_SIZE)
+            .collect();
+
+        pagination_token.inc_fused_offset(page.len());
+
+        Ok(page)
+    }
+
+    /// Resolves comma separated index ids or aliases to distinct index ids, in the order they are
+    /// given.
+    pub(crate) async fn resolve_indexes(&self, names: &str) -> Result<Vec<String>, ServiceError> {
+        let mut index_ids = vec![];
+
+        for name in names.split(',') {
+            for index_id in self.index_loader.resolve_read_indexes(name.trim()).await? {
+                if !index_ids.contains(&index_id) {
+                    index_ids.push(index_id);
+                }
+            }
+        }
+
+        Ok(index_ids)
+    }
+
+    pub async fn create(config: &Config) -> Result<QueryIndexService, ConfigError> {
+        let document_store = DDBDocumentStore::create(config).await?;
+        let index_loader = LambdaIndexLoader::create(config).await?;
+        let query_index_paritition_client = LambdaQueryIndexPartitionClient::create(config).await?;
 
+        Ok(QueryIndexService::new(
+            Arc::new(index_loader),
+            Arc::new(document_store),
+            Arc::new(query_index_paritition_client),
+        ))
+    }
+
+    pub fn new(
+        index_loader: Arc<dyn IndexLoader>,
+        document_store: Arc<dyn DocumentStore>,
+        query_index_paritition_client: Arc<dyn QueryIndexPartitionClient>,
+    ) -> QueryIndexService {
         QueryIndexService {
-            document_store: Box::new(document_store),
-            index_loader: Box::new(index_loader.await),
-            query_index_paritition_client: Arc::new(
-                LambdaQueryIndexPartitionClient::create().await,
-            ),
+            quotas: Quotas::new(document_store.clone()),
+            index_loader,
+            document_store,
+            query_index_paritition_client,
+        }
+    }
+}
+
+/// Renders the values of a named document, which are all arrays, as single values for
+/// single-valued fields and as arrays for multi-valued fields. The sub-fields of object fields are
+/// nested back into objects.
+///
+/// Documents indexed before fields had to be multi-valued to take arrays can still store several
+/// values for a single-valued field, so those are kept as arrays rather than cut to their first.
+///
+/// Encoded fields are stored as values of another type, such as bytes, so they are returned as they
+/// were indexed.
+fn render_doc(
+    fields: &[FieldConfig],
+    mut named_doc: json::Value,
+    content: &json::Map<String, json::Value>,
+) -> json::Value {
+    let single = |values: &mut json::Value| {
+        if let json::Value::Array(values) = values {
+            if !values.is_empty() {
+                return values.swap_remove(0);
+            }
+        }
+
+        json::Value::Null
+    };
+
+    let flattened = flatten_fields(fields);
+    let content = flatten_document(content.clone(), |name| {
+        flattened.iter().any(|field| field.name() == name)
+    });
+
+    for field in &flattened {
+        let name = field.name();
+
+        let Some(values) = named_doc.get_mut(name) else {
+            continue;
+        };
+
+        *values = match content.get(name) {
+            Some(value) if field.is_encoded() => match value {
+                json::Value::Array(_) if field.is_multi() => value.clone(),
+                _ if field.is_multi() => json::Value::Array(vec![value.clone()]),
+                _ => value.clone(),
+            },
+            _ if field.is_multi() || values.as_array().is_some_and(|values| values.len() > 1) => {
+                values.take()
+            }
+            _ => single(values),
+        };
+    }
+
+    if let Some(id) = named_doc.get_mut("__id") {
+        *id = single(id);
+    }
+
+    if let json::Value::Object(named_doc) = &mut named_doc {
+        for field in fields {
+            if let FieldConfig::ObjectFieldConfig { name, fields } = field {
+                let object = nest_object(fields, &format!("{name}."), named_doc);
+
+                if !object.is_empty() {
+                    named_doc.insert(name.clone(), json::Value::Object(object));
+                }
+            }
+        }
+    }
+
+    named_doc
+}
+
+/// Removes the values of the sub-fields of an object field from a named document and returns them
+/// as an object.
+fn nest_object(
+    fields: &[FieldConfig],
+    prefix: &str,
+    named_doc: &mut json::Map<String, json::Value>,
+) -> json::Map<String, json::Value> {
+    let mut object = json::Map::new();
+
+    for field in fields {
+        let path = format!("{prefix}{}", field.name());
+
+        let value = match field {
+            FieldConfig::ObjectFieldConfig { fields, .. } => {
+                let nested = nest_object(fields, &format!("{path}."), named_doc);
+                (!nested.is_empty()).then_some(json::Value::Object(nested))
+            }
+            _ => named_doc.remove(&path),
+        };
+
+        if let Some(value) = value {
+            object.insert(field.name().to_string(), value);
         }
     }
+
+    object
 }
 
 // #[cfg(test)]
@@ -234,8 +563,8 @@ impl QueryIndexService {
 
 //     fn test_service(ctx: &TestContext) -> QueryIndexService {
 //         QueryIndexService {
-//             document_store: Box::new(ctx.document_store().clone()),
-//             index_loader: Box::new(ctx.index_loader().clone()),
+//             document_store: Arc::new(ctx.document_store().clone()),
+//             index_loader: Arc::new(ctx.index_loader().clone()),
 //         }
 //     }
 
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexLoader, LambdaIndexLoader};
+use crate::json;
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::worker::index_writer::client::{self, IndexWriterClient};
+use crate::worker::index_writer::job::Job;
+
+#[derive(Serialize, Debug)]
+pub struct ReindexIndexResponse {
+    pub index_id: String,
+
+    pub job_id: String,
+}
+
+/// Submits a job that rebuilds an index with its latest schema.
+///
+/// The job is queued behind earlier writes to the index, and writes submitted after it are applied
+/// to the rebuilt index.
+pub struct ReindexIndexService {
+    index_loader: Arc<dyn IndexLoader>,
+
+    writer_client: Arc<dyn IndexWriterClient>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, ReindexIndexResponse> for ReindexIndexService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<ReindexIndexResponse> {
+        let index_id = self
+            .index_loader
+            .resolve_write_index(&request.path_param("index_id")?)
+            .await?;
+
+        if !self.index_loader.index_exists(&index_id).await? {
+            return Err(ServiceError::not_found(&format!(
+                "Index [{index_id}] not found"
+            )));
+        }
+
+        let job_id = self
+            .writer_client
+            .submit_job(Job::reindex(&index_id).with_tenant(request.tenant()))
+            .await?;
+
+        Ok(ReindexIndexResponse { index_id, job_id })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl ReindexIndexService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader: Arc<dyn IndexLoader> = Arc::new(LambdaIndexLoader::create(config).await?);
+        let writer_client = client::create_client(config).await?;
+
+        Ok(ReindexIndexService::new(index_loader, writer_client))
+    }
+
+    pub fn new(
+        index_loader: Arc<dyn IndexLoader>,
+        writer_client: Arc<dyn IndexWriterClient>,
+    ) -> Self {
+        ReindexIndexService {
+            index_loader,
+            writer_client,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SchemaLoader;
+    use crate::test_utils::*;
+
+    fn test_service(ctx: &TestContext) -> ReindexIndexService {
+        ReindexIndexService::new(
+            Arc::new(ctx.index_loader().clone()),
+            Arc::new(ctx.writer_client().clone()),
+        )
+    }
+
+    fn request(index_id: &str) -> ServiceRequest<json::Value> {
+        ServiceRequest::create(json!({})).with_path_param("index_id", index_id)
+    }
+
+    #[tokio::test]
+    async fn reindex_index() {
+        let ctx = setup()
+            .with_documents("test", vec![json!({ "title": "Zen" })])
+            .await;
+
+        let directory = ctx.index_loader().index_directory("test").await.unwrap();
+
+        // Fields added to the schema are only in the index once it is rebuilt.
+        let mut fields = ctx.schema_loader().load_fields("test").await.unwrap();
+        fields.push(
+            json::from_value(json!({ "name": "summary", "kind": "text", "flags": ["TEXT"] }))
+                .unwrap(),
+        );
+        ctx.schema_registry()
+            .register("test", fields)
+            .await
+            .unwrap();
+
+        let response = test_service(&ctx)
+            .handle_request(request("test"))
+            .await
+            .unwrap();
+
+        assert_eq!("test", response.index_id);
+
+        let index = ctx.index_loader().load_index("test", None).await.unwrap();
+
+        assert_ne!(
+            directory,
+            ctx.index_loader().index_directory("test").await.unwrap()
+        );
+        assert!(index.schema().get_field("summary").is_some());
+        assert_eq!(1, index.reader().unwrap().searcher().num_docs());
+    }
+
+    #[tokio::test]
+    async fn reindex_missing_index() {
+        let ctx = setup();
+
+        let err = test_service(&ctx)
+            .handle_request(request("test"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+}
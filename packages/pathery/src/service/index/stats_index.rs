@@ -1,11 +1,17 @@
-use std::fs;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json as json;
+use tantivy::fastfield::{FastFieldReader, FastValue};
+use tantivy::{Directory, Searcher};
 
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
 use crate::index::{IndexLoader, LambdaIndexLoader};
-use crate::service::{ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::schema::{flatten_fields, FieldConfig};
+use crate::service::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
 
 #[derive(Serialize, Deserialize)]
 pub struct SegmentStats {
@@ -15,13 +21,22 @@ pub struct SegmentStats {
     index_size: f64,
 }
 
+/// Least and greatest values of a field among the documents of an index.
+#[derive(Serialize, Deserialize)]
+pub struct FieldStats {
+    min: json::Value,
+    max: json::Value,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct IndexStatsResponse {
     segments: Vec<SegmentStats>,
+    /// Stats of the fast numeric fields by name, see [`FieldConfig::is_fast_numeric`].
+    fields: BTreeMap<String, FieldStats>,
 }
 
 pub struct StatsIndexService {
-    index_loader: Box<dyn IndexLoader>,
+    index_loader: Arc<dyn IndexLoader>,
 }
 
 #[async_trait]
@@ -30,35 +45,32 @@ impl ServiceHandler<json::Value, IndexStatsResponse> for StatsIndexService {
         &self,
         request: ServiceRequest<json::Value>,
     ) -> ServiceResponse<IndexStatsResponse> {
-        let index_id = request.path_param("index_id")?;
+        let index_id = self
+            .index_loader
+            .resolve_read_index(&request.path_param("index_id")?)
+            .await?;
+
+        if !self.index_loader.index_exists(&index_id).await? {
+            return Err(ServiceError::not_found(&format!(
+                "Index [{index_id}] not found"
+            )));
+        }
 
-        let index = self.index_loader.load_index(&index_id, None)?;
+        let index = self.index_loader.load_index(&index_id, None).await?;
 
         let metas = index.load_metas().unwrap();
 
-        let segment_files = fs::read_dir(format!("/mnt/pathery-data/{index_id}"))
-            .unwrap()
-            .filter_map(|entry| entry.ok())
-            .collect::<Vec<_>>();
+        let directory = index.directory();
 
         let segments = metas
             .segments
             .iter()
             .map(|s| {
-                let segment_id = s.id().uuid_string();
-
-                let index_size_bytes: u64 = segment_files
+                let index_size_bytes: u64 = s
+                    .list_files()
                     .iter()
-                    .filter_map(|entry| {
-                        let filename = entry.file_name();
-                        let filename = filename.to_str()?;
-
-                        filename
-                            .starts_with(&segment_id)
-                            .then(|| entry.metadata())
-                            .and_then(Result::ok)
-                            .map(|m| m.len())
-                    })
+                    .filter_map(|path| directory.get_file_handle(path).ok())
+                    .map(|handle| handle.len() as u64)
                     .sum();
 
                 let index_size_mb: f64 = index_size_bytes as f64 / 1_000_000f64;
@@ -72,16 +84,88 @@ impl ServiceHandler<json::Value, IndexStatsResponse> for StatsIndexService {
             })
             .collect();
 
-        Ok(IndexStatsResponse { segments })
+        let fields = self.index_loader.load_fields(&index_id).await?;
+        let searcher = index
+            .reader()
+            .map_err(ServiceError::internal_error)?
+            .searcher();
+
+        let fields = flatten_fields(&fields)
+            .iter()
+            .filter_map(|field| Some((field.name().to_string(), field_stats(&searcher, field)?)))
+            .collect();
+
+        Ok(IndexStatsResponse { segments, fields })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
     }
 }
 
+/// Returns the least and greatest values of a fast numeric field among the documents that are not
+/// deleted. Documents without a value of a single-valued field count as zero. Dates are not
+/// reported.
+fn field_stats(searcher: &Searcher, field: &FieldConfig) -> Option<FieldStats> {
+    if !field.is_fast_numeric() || matches!(field, FieldConfig::DateFieldConfig { .. }) {
+        return None;
+    }
+
+    let schema_field = searcher.schema().get_field(field.name())?;
+
+    // Fast fields keep every value as the u64 tantivy maps it to in order.
+    let (min, max) = searcher
+        .segment_readers()
+        .iter()
+        .filter_map(|segment_reader| {
+            let fast_fields = segment_reader.fast_fields();
+
+            let values: Box<dyn Iterator<Item = u64>> = if field.is_multi() {
+                let values = fast_fields.u64s_lenient(schema_field).ok()?;
+
+                Box::new(segment_reader.doc_ids_alive().flat_map(move |doc| {
+                    let mut doc_values = vec![];
+                    values.get_vals(doc, &mut doc_values);
+                    doc_values
+                }))
+            } else {
+                let values = fast_fields.u64_lenient(schema_field).ok()?;
+
+                Box::new(
+                    segment_reader
+                        .doc_ids_alive()
+                        .map(move |doc| values.get(doc)),
+                )
+            };
+
+            values.fold(None, |bounds, value| match bounds {
+                None => Some((value, value)),
+                Some((min, max)) => Some((value.min(min), value.max(max))),
+            })
+        })
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))?;
+
+    let to_json = |value: u64| match field {
+        FieldConfig::IntegerFieldConfig { .. } => json::Value::from(i64::from_u64(value)),
+        FieldConfig::FloatFieldConfig { .. } => json::Value::from(f64::from_u64(value)),
+        FieldConfig::BoolFieldConfig { .. } => json::Value::from(value != 0),
+        _ => json::Value::from(value),
+    };
+
+    Some(FieldStats {
+        min: to_json(min),
+        max: to_json(max),
+    })
+}
+
 impl StatsIndexService {
-    pub async fn create() -> Self {
-        let index_loader = LambdaIndexLoader::create();
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        let index_loader = LambdaIndexLoader::create(config).await?;
 
-        StatsIndexService {
-            index_loader: Box::new(index_loader.await),
-        }
+        Ok(StatsIndexService::new(Arc::new(index_loader)))
+    }
+
+    pub fn new(index_loader: Arc<dyn IndexLoader>) -> Self {
+        StatsIndexService { index_loader }
     }
 }
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::marker::PhantomData;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use http::Response;
@@ -8,10 +9,14 @@ use lambda_http::{Body, RequestExt};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
+use crate::auth::{Authorizer, Permission, Tenant};
+use crate::config::Config;
 use crate::util;
 
+pub mod alias;
 pub mod doc;
 pub mod index;
+pub mod schema;
 
 #[derive(thiserror::Error, Debug)]
 pub enum ServiceError {
@@ -26,6 +31,28 @@ pub enum ServiceError {
 
     #[error("{0}")]
     NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{message}")]
+    QuotaExceeded {
+        message: String,
+        /// How long until the request can succeed, if it can.
+        retry_after: Option<Duration>,
+    },
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+}
+
+impl From<std::io::Error> for ServiceError {
+    fn from(err: std::io::Error) -> Self {
+        ServiceError::internal_error(err)
+    }
 }
 
 impl ServiceError {
@@ -51,10 +78,44 @@ impl ServiceError {
         ServiceError::NotFound(message.into())
     }
 
+    pub fn conflict(message: &str) -> Self {
+        ServiceError::Conflict(message.into())
+    }
+
+    pub fn quota_exceeded(message: &str, retry_after: Option<Duration>) -> Self {
+        ServiceError::QuotaExceeded {
+            message: message.into(),
+            retry_after,
+        }
+    }
+
+    pub fn unauthorized(message: &str) -> Self {
+        ServiceError::Unauthorized(message.into())
+    }
+
+    pub fn forbidden(message: &str) -> Self {
+        ServiceError::Forbidden(message.into())
+    }
+
     pub fn rate_limit() -> Self {
         ServiceError::RateLimit
     }
 
+    /// Whether the failed operation may succeed if retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ServiceError::RateLimit | ServiceError::InternalError { .. }
+        )
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            ServiceError::QuotaExceeded { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
     pub fn status(&self) -> u16 {
         use ServiceError::*;
         match self {
@@ -62,6 +123,10 @@ impl ServiceError {
             InternalError { .. } => 500,
             RateLimit => 429,
             NotFound(_) => 404,
+            Conflict(_) => 409,
+            QuotaExceeded { .. } => 429,
+            Unauthorized(_) => 401,
+            Forbidden(_) => 403,
         }
     }
 
@@ -72,6 +137,10 @@ impl ServiceError {
             InvalidRequest(message) => message,
             RateLimit => String::from("Too many requests"),
             NotFound(message) => message,
+            Conflict(message) => message,
+            QuotaExceeded { message, .. } => message,
+            Unauthorized(message) => message,
+            Forbidden(message) => message,
         }
     }
 }
@@ -101,6 +170,12 @@ where B: for<'de> Deserialize<'de>
         }
     }
 
+    /// Useful for testing
+    pub fn with_tenant(mut self, tenant: Tenant) -> Self {
+        self.inner.extensions_mut().insert(tenant);
+        self
+    }
+
     /// Useful for testing
     pub fn with_path_param(mut self, name: &str, value: &str) -> Self {
         let updated = self
@@ -112,10 +187,26 @@ where B: for<'de> Deserialize<'de>
         self
     }
 
+    /// Useful for testing
+    pub fn with_query_param(mut self, name: &str, value: &str) -> Self {
+        let mut params: HashMap<String, String> = self
+            .inner
+            .query_string_parameters()
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        params.insert(name.into(), value.into());
+
+        self.inner = self.inner.with_query_string_parameters(params);
+
+        self
+    }
+
     pub fn body(&self) -> Result<B, ServiceError> {
         if let Body::Text(body) = self.inner.body() {
             Ok(serde_json::from_str(body).map_err(|err| {
-                ServiceError::InvalidRequest(format!("Unable to parse body: {}", err.to_string()))
+                ServiceError::InvalidRequest(format!("Unable to parse body: {}", err))
             })?)
         } else {
             Err(ServiceError::InvalidRequest(String::from(
@@ -124,26 +215,86 @@ where B: for<'de> Deserialize<'de>
         }
     }
 
+    /// Parses the body, or returns the default body if the request has none.
+    pub fn body_or_default(&self) -> Result<B, ServiceError>
+    where B: Default {
+        match self.inner.body() {
+            Body::Empty => Ok(B::default()),
+            _ => self.body(),
+        }
+    }
+
     pub fn path_param(&self, name: &str) -> Result<String, ServiceError> {
         let path_params = self.inner.path_parameters();
         let value = path_params
             .first(name)
-            .expect(&format!("missing path param: {}", name));
+            .unwrap_or_else(|| panic!("missing path param: {}", name));
 
         Ok(String::from(value))
     }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.inner
+            .query_string_parameters()
+            .first(name)
+            .map(String::from)
+    }
+
+    /// Tenant of the request's API key, or `None` if authentication is disabled.
+    pub fn tenant(&self) -> Option<&Tenant> {
+        self.inner.extensions().get::<Tenant>()
+    }
+}
+
+/// Checks the request's API key and stores its tenant in the request.
+///
+/// The tenant needs access to the indexes or alias named in the path, including each index of a
+/// comma separated list.
+async fn authorize_event(
+    authorizer: &Authorizer,
+    permission: Permission,
+    event: &mut lambda_http::Request,
+) -> Result<(), ServiceError> {
+    let authorization = event
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+
+    let path_params = event.path_parameters();
+    let index_ids = path_params
+        .first("index_id")
+        .into_iter()
+        .flat_map(|index_ids| index_ids.split(','))
+        .chain(path_params.first("alias"));
+
+    let tenant = authorizer
+        .authorize(authorization, permission, index_ids)
+        .await?;
+
+    if let Some(tenant) = tenant {
+        event.extensions_mut().insert(tenant);
+    }
+
+    Ok(())
 }
 
-fn map_error_response(
+pub(crate) fn map_error_response(
     error: ServiceError,
 ) -> Result<lambda_http::Response<lambda_http::Body>, lambda_http::Error> {
     let status = error.status();
+    let retry_after = error.retry_after();
     let message = error.message();
 
-    let response = Response::builder()
+    let mut response = Response::builder()
         .header("Content-Type", "application/json")
         .status(status);
 
+    if let Some(retry_after) = retry_after {
+        // Retry-After is in whole seconds, so round up to avoid retrying too early.
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        response = response.header("Retry-After", seconds.to_string());
+    }
+
     let body = serde_json::to_string(&serde_json::json!({ "message": message }))?;
 
     Ok(response.body(Body::Text(body))?)
@@ -168,8 +319,13 @@ where
 {
     async fn handle_event(
         &self,
-        event: lambda_http::Request,
+        authorizer: &Authorizer,
+        mut event: lambda_http::Request,
     ) -> Result<lambda_http::Response<lambda_http::Body>, lambda_http::Error> {
+        if let Err(err) = authorize_event(authorizer, self.permission(), &mut event).await {
+            return map_error_response(err);
+        }
+
         let request = ServiceRequest {
             inner: event,
             body: PhantomData,
@@ -181,9 +337,13 @@ where
     }
 
     async fn handle_request(&self, request: ServiceRequest<B>) -> ServiceResponse<R>;
+
+    /// Permission that API keys need on the indexes of a request.
+    fn permission(&self) -> Permission;
 }
 
 pub async fn start_service<B, R>(
+    config: &Config,
     service: &dyn ServiceHandler<B, R>,
 ) -> Result<(), lambda_http::Error>
 where
@@ -197,8 +357,10 @@ where
         .without_time()
         .init();
 
+    let authorizer = Authorizer::create(config).await?;
+
     lambda_http::run(lambda_http::service_fn(|event| async {
-        service.handle_event(event).await
+        service.handle_event(&authorizer, event).await
     }))
     .await?;
 
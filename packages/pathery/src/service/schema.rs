@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+use super::{ServiceError, ServiceHandler, ServiceRequest, ServiceResponse};
+use crate::auth::Permission;
+use crate::config::{Config, ConfigError};
+use crate::schema::registry::{SchemaDefinition, SchemaRegistry};
+use crate::schema::FieldConfig;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PutSchemaRequest {
+    pub fields: Vec<FieldConfig>,
+}
+
+/// Registers a new version of the schema of an index.
+pub struct PutSchemaService {
+    registry: Arc<SchemaRegistry>,
+}
+
+#[async_trait]
+impl ServiceHandler<PutSchemaRequest, SchemaDefinition> for PutSchemaService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<PutSchemaRequest>,
+    ) -> ServiceResponse<SchemaDefinition> {
+        let index_id = request.path_param("index_id")?;
+        let body = request.body()?;
+
+        self.registry.register(&index_id, body.fields).await
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Admin
+    }
+}
+
+impl PutSchemaService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        Ok(PutSchemaService::new(Arc::new(
+            SchemaRegistry::create(config).await?,
+        )))
+    }
+
+    pub fn new(registry: Arc<SchemaRegistry>) -> Self {
+        PutSchemaService { registry }
+    }
+}
+
+/// Returns the latest schema registered for an index, or the version in the `version` query
+/// parameter.
+pub struct GetSchemaService {
+    registry: Arc<SchemaRegistry>,
+}
+
+#[async_trait]
+impl ServiceHandler<json::Value, SchemaDefinition> for GetSchemaService {
+    async fn handle_request(
+        &self,
+        request: ServiceRequest<json::Value>,
+    ) -> ServiceResponse<SchemaDefinition> {
+        let index_id = request.path_param("index_id")?;
+
+        let definition = match request.query_param("version") {
+            Some(version) => {
+                let version = version.parse().map_err(|_| {
+                    ServiceError::invalid_request(&format!("Invalid schema version [{version}]"))
+                })?;
+
+                self.registry.version(&index_id, version).await?
+            }
+            None => self.registry.latest(&index_id).await?,
+        };
+
+        definition.ok_or_else(|| {
+            ServiceError::not_found(&format!("No schema registered for index [{index_id}]"))
+        })
+    }
+
+    fn permission(&self) -> Permission {
+        Permission::Read
+    }
+}
+
+impl GetSchemaService {
+    pub async fn create(config: &Config) -> Result<Self, ConfigError> {
+        Ok(GetSchemaService::new(Arc::new(
+            SchemaRegistry::create(config).await?,
+        )))
+    }
+
+    pub fn new(registry: Arc<SchemaRegistry>) -> Self {
+        GetSchemaService { registry }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn put_request(index_id: &str, fields: json::Value) -> ServiceRequest<PutSchemaRequest> {
+        ServiceRequest::create(json::from_value(json!({ "fields": fields })).unwrap())
+            .with_path_param("index_id", index_id)
+    }
+
+    #[tokio::test]
+    async fn put_and_get_schema_versions() {
+        let ctx = setup();
+        let registry = Arc::new(SchemaRegistry::new(
+            Arc::new(ctx.document_store().clone()),
+            None,
+        ));
+
+        let put = PutSchemaService::new(registry.clone());
+        let get = GetSchemaService::new(registry);
+
+        let title = json!({ "name": "title", "kind": "text", "flags": ["TEXT"] });
+        let author = json!({ "name": "author", "kind": "text", "flags": ["TEXT"] });
+
+        put.handle_request(put_request("books", json!([title])))
+            .await
+            .unwrap();
+        put.handle_request(put_request("books", json!([title, author])))
+            .await
+            .unwrap();
+
+        let latest = get
+            .handle_request(ServiceRequest::create(json!({})).with_path_param("index_id", "books"))
+            .await
+            .unwrap();
+
+        assert_eq!(2, latest.version);
+
+        let first = get
+            .handle_request(
+                ServiceRequest::create(json!({}))
+                    .with_path_param("index_id", "books")
+                    .with_query_param("version", "1"),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(1, first.fields.len());
+    }
+
+    #[tokio::test]
+    async fn get_unregistered_schema() {
+        let ctx = setup();
+        let get = GetSchemaService::new(Arc::new(SchemaRegistry::new(
+            Arc::new(ctx.document_store().clone()),
+            None,
+        )));
+
+        let err = get
+            .handle_request(ServiceRequest::create(json!({})).with_path_param("index_id", "books"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+}
@@ -1,17 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::result::Result as StdResult;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use aws_sdk_dynamodb as ddb;
-use ddb::model::{AttributeValue, KeysAndAttributes, PutRequest, WriteRequest};
+use ddb::model::{AttributeValue, DeleteRequest, KeysAndAttributes, PutRequest, WriteRequest};
+use ddb::output::{BatchGetItemOutput, BatchWriteItemOutput};
 use ddb::types::SdkError;
 use serde::{Deserialize, Serialize};
 use tantivy::schema::NamedFieldDocument;
 
+use crate::config::{Config, ConfigError};
+use crate::json::Value;
 use crate::search_doc::{DDBKey, SearchDoc, SearchDocId};
 use crate::service::ServiceError;
-use crate::util;
 
 impl<T> From<SdkError<T>> for ServiceError
 where T: Error + Sync + Send + 'static
@@ -32,6 +36,18 @@ type Result<T> = StdResult<T, ServiceError>;
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct SearchDocRef(SearchDocId);
 
+impl From<SearchDocId> for SearchDocRef {
+    fn from(id: SearchDocId) -> Self {
+        SearchDocRef(id)
+    }
+}
+
+impl SearchDocRef {
+    pub fn id(&self) -> &SearchDocId {
+        &self.0
+    }
+}
+
 impl From<NamedFieldDocument> for SearchDocRef {
     fn from(doc: NamedFieldDocument) -> Self {
         let id = doc
@@ -49,109 +65,441 @@ impl From<NamedFieldDocument> for SearchDocRef {
 
 #[async_trait]
 pub trait DocumentStore: Send + Sync {
-    /// Get documents by reference.
-    async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>>;
+    /// Get documents of an index by reference.
+    async fn get_documents(
+        &self,
+        index_id: &str,
+        refs: Vec<SearchDocRef>,
+    ) -> Result<Vec<SearchDoc>>;
+
+    /// Save documents of an index such that they can be retrieved with get_documents. Documents
+    /// are scoped to the index, so the same id can be saved for different indexes.
+    async fn save_documents(
+        &self,
+        index_id: &str,
+        documents: Vec<SearchDoc>,
+    ) -> Result<Vec<SearchDocRef>>;
+
+    /// Save a document of an index only if no document with its id exists, failing with a
+    /// conflict otherwise.
+    async fn insert_document(&self, index_id: &str, document: SearchDoc) -> Result<SearchDocRef>;
+
+    /// Save a document of an index only if the `field` of the stored document still has the
+    /// `expected` value, or if no document exists when `expected` is `None`, failing with a
+    /// conflict otherwise. This updates a document read earlier without overwriting concurrent
+    /// updates, as long as every write of the document goes through this method.
+    async fn update_document(
+        &self,
+        index_id: &str,
+        document: SearchDoc,
+        field: &str,
+        expected: Option<&Value>,
+    ) -> Result<SearchDocRef>;
+
+    /// Delete documents of an index by id. Ids that do not exist are ignored.
+    async fn delete_documents(&self, index_id: &str, ids: Vec<SearchDocId>) -> Result<()>;
+}
+
+/// Sends the requests of a [`DDBDocumentStore`]. Tests substitute it to return the partial
+/// responses DynamoDB returns when throttled.
+#[async_trait]
+pub trait RequestExecutor: Send + Sync {
+    async fn batch_get_item(
+        &self,
+        request_items: HashMap<String, KeysAndAttributes>,
+    ) -> Result<BatchGetItemOutput>;
+
+    async fn batch_write_item(
+        &self,
+        request_items: HashMap<String, Vec<WriteRequest>>,
+    ) -> Result<BatchWriteItemOutput>;
+
+    /// Puts an item unless an item with the same key exists, failing with a conflict otherwise.
+    async fn put_new_item(
+        &self,
+        table_name: &str,
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<()>;
+
+    /// Puts an item if its version attribute has the expected value, failing with a conflict
+    /// otherwise.
+    async fn put_item_version(
+        &self,
+        table_name: &str,
+        item: HashMap<String, AttributeValue>,
+        expected: AttributeValue,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+impl RequestExecutor for ddb::Client {
+    async fn batch_get_item(
+        &self,
+        request_items: HashMap<String, KeysAndAttributes>,
+    ) -> Result<BatchGetItemOutput> {
+        Ok(self
+            .batch_get_item()
+            .set_request_items(Some(request_items))
+            .send()
+            .await?)
+    }
+
+    async fn batch_write_item(
+        &self,
+        request_items: HashMap<String, Vec<WriteRequest>>,
+    ) -> Result<BatchWriteItemOutput> {
+        Ok(self
+            .batch_write_item()
+            .set_request_items(Some(request_items))
+            .send()
+            .await?)
+    }
+
+    async fn put_new_item(
+        &self,
+        table_name: &str,
+        item: HashMap<String, AttributeValue>,
+    ) -> Result<()> {
+        let result = self
+            .put_item()
+            .table_name(table_name)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(pk)")
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Err(ServiceError::conflict("Document already exists"))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 
-    /// Save a document such that it can be retrieved with get_documents.
-    async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>>;
+    async fn put_item_version(
+        &self,
+        table_name: &str,
+        item: HashMap<String, AttributeValue>,
+        expected: AttributeValue,
+    ) -> Result<()> {
+        let result = self
+            .put_item()
+            .table_name(table_name)
+            .set_item(Some(item))
+            .condition_expression("#version = :expected")
+            .expression_attribute_names("#version", VERSION_ATTRIBUTE)
+            .expression_attribute_values(":expected", expected)
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(SdkError::ServiceError { err, .. })
+                if err.is_conditional_check_failed_exception() =>
+            {
+                Err(ServiceError::conflict("Document was updated concurrently"))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 }
 
 pub struct DDBDocumentStore {
     table_name: String,
-    client: ddb::Client,
+    executor: Arc<dyn RequestExecutor>,
 }
 
-#[async_trait]
-impl DocumentStore for DDBDocumentStore {
-    async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>> {
-        let mut request = self.client.batch_get_item();
+/// Attribute holding the field that [`DocumentStore::update_document`] compares, since the content
+/// of documents is stored compressed.
+const VERSION_ATTRIBUTE: &str = "version";
 
-        let mut keys_and_attrs = KeysAndAttributes::builder();
+/// Maximum number of keys in a single BatchGetItem request.
+const BATCH_GET_LIMIT: usize = 100;
 
-        for doc_ref in refs {
-            let key = DDBKey::from(doc_ref.0);
-            keys_and_attrs = keys_and_attrs.keys(serde_dynamo::to_item(key)?);
-        }
+/// Maximum number of writes in a single BatchWriteItem request.
+const BATCH_WRITE_LIMIT: usize = 25;
+
+/// Number of attempts made to process unprocessed keys or items before failing with a rate limit.
+const BATCH_MAX_ATTEMPTS: u32 = 4;
+
+async fn backoff(attempt: u32) {
+    tokio::time::sleep(Duration::from_millis(50 * 2u64.pow(attempt))).await;
+}
 
-        request = request.request_items(&self.table_name, keys_and_attrs.build());
+impl DDBDocumentStore {
+    fn to_item(index_id: &str, document: &SearchDoc) -> Result<HashMap<String, AttributeValue>> {
+        let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(document)?;
+
+        let key: HashMap<String, AttributeValue> =
+            serde_dynamo::to_item(DDBKey::document(index_id, document.id()))?;
+
+        item.extend(key);
 
-        let response = request.send().await?;
+        Ok(item)
+    }
 
-        let documents = response
-            .responses()
-            .expect("responses should be present")
-            .values()
-            .flatten()
-            .map(|item| serde_dynamo::from_item(item.clone()))
-            .collect::<StdResult<Vec<SearchDoc>, _>>()?;
+    async fn get_keys(&self, keys: Vec<DDBKey>) -> Result<Vec<SearchDoc>> {
+        let keys = keys
+            .into_iter()
+            .map(serde_dynamo::to_item)
+            .collect::<StdResult<Vec<HashMap<String, AttributeValue>>, _>>()?;
 
-        let unprocessed_ids = response
-            .unprocessed_keys()
-            .expect("unprocessed keys should be present")
-            .values()
-            .filter_map(KeysAndAttributes::keys)
-            .flatten()
-            .collect::<Vec<_>>();
+        let mut documents = vec![];
 
-        if unprocessed_ids.len() > 0 {
-            return Err(ServiceError::rate_limit());
+        for chunk in keys.chunks(BATCH_GET_LIMIT) {
+            documents.extend(self.batch_get(chunk.to_vec()).await?);
         }
 
         Ok(documents)
     }
 
-    async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>> {
-        if documents.len() > 25 {
-            return Err(ServiceError::invalid_request(
-                "Too many documents in request, max 25.",
-            ));
+    async fn batch_get(
+        &self,
+        keys: Vec<HashMap<String, AttributeValue>>,
+    ) -> Result<Vec<SearchDoc>> {
+        let mut documents = vec![];
+
+        // Reads are strongly consistent so that a flipped index directory or a just saved document
+        // is seen by the next request.
+        let mut request_keys = KeysAndAttributes::builder()
+            .set_keys(Some(keys))
+            .consistent_read(true)
+            .build();
+
+        for attempt in 0..BATCH_MAX_ATTEMPTS {
+            if attempt > 0 {
+                backoff(attempt).await;
+            }
+
+            let response = self
+                .executor
+                .batch_get_item(HashMap::from([(self.table_name.clone(), request_keys)]))
+                .await?;
+
+            for item in response
+                .responses()
+                .into_iter()
+                .flat_map(|r| r.values())
+                .flatten()
+            {
+                documents.push(serde_dynamo::from_item(item.clone())?);
+            }
+
+            match response
+                .unprocessed_keys()
+                .and_then(|unprocessed| unprocessed.get(&self.table_name))
+                .filter(|unprocessed| unprocessed.keys().is_some_and(|keys| !keys.is_empty()))
+            {
+                Some(unprocessed) => request_keys = unprocessed.clone(),
+                None => return Ok(documents),
+            }
         }
 
-        let mut writes = vec![];
+        Err(ServiceError::rate_limit())
+    }
 
-        for document in &documents {
-            let mut item: HashMap<String, AttributeValue> = serde_dynamo::to_item(document)?;
+    async fn batch_write(&self, writes: Vec<WriteRequest>) -> Result<()> {
+        let mut request_writes = writes;
+
+        for attempt in 0..BATCH_MAX_ATTEMPTS {
+            if attempt > 0 {
+                backoff(attempt).await;
+            }
+
+            let response = self
+                .executor
+                .batch_write_item(HashMap::from([(self.table_name.clone(), request_writes)]))
+                .await?;
+
+            match response
+                .unprocessed_items()
+                .and_then(|unprocessed| unprocessed.get(&self.table_name))
+                .filter(|unprocessed| !unprocessed.is_empty())
+            {
+                Some(unprocessed) => request_writes = unprocessed.clone(),
+                None => return Ok(()),
+            }
+        }
+
+        Err(ServiceError::rate_limit())
+    }
+}
+
+#[async_trait]
+impl DocumentStore for DDBDocumentStore {
+    async fn get_documents(
+        &self,
+        index_id: &str,
+        refs: Vec<SearchDocRef>,
+    ) -> Result<Vec<SearchDoc>> {
+        // BatchGetItem rejects requests containing duplicate keys.
+        let mut seen = HashSet::new();
+
+        let ids: Vec<_> = refs
+            .into_iter()
+            .map(|doc_ref| doc_ref.0)
+            .filter(|id| seen.insert(id.clone()))
+            .collect();
+
+        let mut documents = self
+            .get_keys(
+                ids.iter()
+                    .map(|id| DDBKey::document(index_id, id))
+                    .collect(),
+            )
+            .await?;
+
+        // Documents that are not found by their key of the index may still be stored by their
+        // legacy key.
+        let found: HashSet<_> = documents.iter().map(|doc| doc.id().clone()).collect();
 
-            let key: HashMap<String, AttributeValue> =
-                serde_dynamo::to_item(DDBKey::from(document.id().clone()))?;
+        let legacy_keys: Vec<_> = ids
+            .iter()
+            .filter(|id| !found.contains(id))
+            .map(DDBKey::legacy_document)
+            .collect();
 
-            item.extend(key);
+        if !legacy_keys.is_empty() {
+            documents.extend(self.get_keys(legacy_keys).await?);
+        }
+
+        Ok(documents)
+    }
+
+    async fn save_documents(
+        &self,
+        index_id: &str,
+        documents: Vec<SearchDoc>,
+    ) -> Result<Vec<SearchDocRef>> {
+        let mut writes = vec![];
+
+        for document in &documents {
+            let item = Self::to_item(index_id, document)?;
 
             let put_request = PutRequest::builder().set_item(Some(item)).build();
 
             writes.push(WriteRequest::builder().put_request(put_request).build())
         }
 
-        let response = self
-            .client
-            .batch_write_item()
-            .request_items(&self.table_name, writes)
-            .send()
-            .await?;
-
-        if let Some(items) = response.unprocessed_items() {
-            let unhandled_writes = items.values().flatten().collect::<Vec<_>>();
-            if unhandled_writes.len() > 0 {
-                return Err(ServiceError::rate_limit());
-            }
-        };
+        for chunk in writes.chunks(BATCH_WRITE_LIMIT) {
+            self.batch_write(chunk.to_vec()).await?;
+        }
 
         Ok(documents
             .into_iter()
             .map(|doc| SearchDocRef(doc.id().clone()))
             .collect())
     }
+
+    async fn insert_document(&self, index_id: &str, document: SearchDoc) -> Result<SearchDocRef> {
+        let item = Self::to_item(index_id, &document)?;
+
+        self.executor.put_new_item(&self.table_name, item).await?;
+
+        Ok(SearchDocRef(document.id().clone()))
+    }
+
+    async fn update_document(
+        &self,
+        index_id: &str,
+        document: SearchDoc,
+        field: &str,
+        expected: Option<&Value>,
+    ) -> Result<SearchDocRef> {
+        let mut item = Self::to_item(index_id, &document)?;
+
+        let version = document.content().get(field).unwrap_or(&Value::Null);
+        item.insert(
+            VERSION_ATTRIBUTE.to_string(),
+            serde_dynamo::to_attribute_value(version)?,
+        );
+
+        match expected {
+            Some(expected) => {
+                self.executor
+                    .put_item_version(
+                        &self.table_name,
+                        item,
+                        serde_dynamo::to_attribute_value(expected)?,
+                    )
+                    .await?
+            }
+            None => self.executor.put_new_item(&self.table_name, item).await?,
+        }
+
+        Ok(SearchDocRef(document.id().clone()))
+    }
+
+    async fn delete_documents(&self, index_id: &str, ids: Vec<SearchDocId>) -> Result<()> {
+        let mut seen = HashSet::new();
+
+        let mut writes = vec![];
+
+        // The legacy key is deleted as well, so that a document that was not saved again since it
+        // was keyed by index is not read by its legacy key after it is deleted.
+        let keys = ids
+            .into_iter()
+            .filter(|id| seen.insert(id.clone()))
+            .flat_map(|id| {
+                [
+                    DDBKey::document(index_id, &id),
+                    DDBKey::legacy_document(&id),
+                ]
+            });
+
+        for key in keys {
+            let key: HashMap<String, AttributeValue> = serde_dynamo::to_item(key)?;
+
+            let delete_request = DeleteRequest::builder().set_key(Some(key)).build();
+
+            writes.push(
+                WriteRequest::builder()
+                    .delete_request(delete_request)
+                    .build(),
+            )
+        }
+
+        for chunk in writes.chunks(BATCH_WRITE_LIMIT) {
+            self.batch_write(chunk.to_vec()).await?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DDBDocumentStore {
-    pub async fn create(table_name: Option<&str>) -> DDBDocumentStore {
-        let table_name = table_name
-            .map(String::from)
-            .unwrap_or_else(|| util::require_env("DATA_TABLE_NAME"));
+    /// Creates a store for the configured data table.
+    ///
+    /// Requests are sent to the configured endpoint url when set, which allows running against a
+    /// local DynamoDB-compatible endpoint.
+    pub async fn create(config: &Config) -> StdResult<DDBDocumentStore, ConfigError> {
+        let table_name = config.require_data_table_name()?.to_string();
+        let endpoint_url = config.data_table_endpoint_url();
+
         let sdk_config = aws_config::load_from_env().await;
-        let client = aws_sdk_dynamodb::Client::new(&sdk_config);
+        let mut config = ddb::config::Builder::from(&sdk_config);
+
+        if let Some(endpoint_url) = endpoint_url {
+            let uri = endpoint_url.parse().map_err(|err| {
+                ConfigError::Invalid(format!("data_table_endpoint_url {endpoint_url:?}: {err}"))
+            })?;
+            config = config.endpoint_resolver(ddb::Endpoint::immutable(uri));
+        }
+
+        let client = ddb::Client::from_conf(config.build());
+
+        Ok(Self::with_executor(&table_name, Arc::new(client)))
+    }
 
-        DDBDocumentStore { table_name, client }
+    /// Creates a store for `table_name` that sends its requests with `executor`.
+    pub fn with_executor(table_name: &str, executor: Arc<dyn RequestExecutor>) -> DDBDocumentStore {
+        DDBDocumentStore {
+            table_name: table_name.to_string(),
+            executor,
+        }
     }
 }
 
@@ -164,16 +512,23 @@ pub mod test_util {
 
     #[derive(Clone, Debug)]
     pub struct TestDocumentStore {
-        db: Arc<Mutex<HashMap<SearchDocId, SearchDoc>>>,
+        db: Arc<Mutex<HashMap<(String, SearchDocId), SearchDoc>>>,
     }
 
     #[async_trait]
     impl DocumentStore for TestDocumentStore {
-        async fn save_documents(&self, documents: Vec<SearchDoc>) -> Result<Vec<SearchDocRef>> {
+        async fn save_documents(
+            &self,
+            index_id: &str,
+            documents: Vec<SearchDoc>,
+        ) -> Result<Vec<SearchDocRef>> {
             let mut db = self.db.lock().unwrap();
 
             for document in &documents {
-                (*db).insert(document.id().clone(), document.clone());
+                (*db).insert(
+                    (index_id.to_string(), document.id().clone()),
+                    document.clone(),
+                );
             }
 
             Ok(documents
@@ -182,14 +537,70 @@ pub mod test_util {
                 .collect())
         }
 
-        async fn get_documents(&self, refs: Vec<SearchDocRef>) -> Result<Vec<SearchDoc>> {
+        async fn get_documents(
+            &self,
+            index_id: &str,
+            refs: Vec<SearchDocRef>,
+        ) -> Result<Vec<SearchDoc>> {
             let db = self.db.lock().unwrap();
 
             Ok(refs
-                .iter()
-                .map(|doc_ref| (*db).get(&doc_ref.0).unwrap().clone())
+                .into_iter()
+                .filter_map(|doc_ref| (*db).get(&(index_id.to_string(), doc_ref.0)).cloned())
                 .collect())
         }
+
+        async fn insert_document(
+            &self,
+            index_id: &str,
+            document: SearchDoc,
+        ) -> Result<SearchDocRef> {
+            let mut db = self.db.lock().unwrap();
+
+            let key = (index_id.to_string(), document.id().clone());
+
+            if (*db).contains_key(&key) {
+                return Err(ServiceError::conflict("Document already exists"));
+            }
+
+            (*db).insert(key, document.clone());
+
+            Ok(SearchDocRef(document.id().clone()))
+        }
+
+        async fn update_document(
+            &self,
+            index_id: &str,
+            document: SearchDoc,
+            field: &str,
+            expected: Option<&Value>,
+        ) -> Result<SearchDocRef> {
+            let mut db = self.db.lock().unwrap();
+
+            let key = (index_id.to_string(), document.id().clone());
+
+            let stored = (*db)
+                .get(&key)
+                .map(|doc| doc.content().get(field).unwrap_or(&Value::Null));
+
+            if stored != expected {
+                return Err(ServiceError::conflict("Document was updated concurrently"));
+            }
+
+            (*db).insert(key, document.clone());
+
+            Ok(SearchDocRef(document.id().clone()))
+        }
+
+        async fn delete_documents(&self, index_id: &str, ids: Vec<SearchDocId>) -> Result<()> {
+            let mut db = self.db.lock().unwrap();
+
+            for id in ids {
+                (*db).remove(&(index_id.to_string(), id));
+            }
+
+            Ok(())
+        }
     }
 
     impl TestDocumentStore {
@@ -200,3 +611,261 @@ pub mod test_util {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use serde_json::json;
+    use tantivy::schema::{self, Schema};
+
+    use super::*;
+
+    const TABLE: &str = "documents";
+
+    const INDEX: &str = "test";
+
+    /// Returns scripted responses and records the requests it was sent.
+    #[derive(Default)]
+    struct ScriptedExecutor {
+        get_responses: Mutex<VecDeque<BatchGetItemOutput>>,
+        write_responses: Mutex<VecDeque<BatchWriteItemOutput>>,
+        get_requests: Mutex<Vec<KeysAndAttributes>>,
+        write_requests: Mutex<Vec<Vec<WriteRequest>>>,
+    }
+
+    #[async_trait]
+    impl RequestExecutor for ScriptedExecutor {
+        async fn batch_get_item(
+            &self,
+            mut request_items: HashMap<String, KeysAndAttributes>,
+        ) -> Result<BatchGetItemOutput> {
+            let keys = request_items
+                .remove(TABLE)
+                .expect("table should be requested");
+            self.get_requests.lock().unwrap().push(keys);
+
+            Ok(self
+                .get_responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("response should be scripted"))
+        }
+
+        async fn batch_write_item(
+            &self,
+            mut request_items: HashMap<String, Vec<WriteRequest>>,
+        ) -> Result<BatchWriteItemOutput> {
+            let writes = request_items
+                .remove(TABLE)
+                .expect("table should be requested");
+            self.write_requests.lock().unwrap().push(writes);
+
+            Ok(self
+                .write_responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .expect("response should be scripted"))
+        }
+
+        async fn put_new_item(
+            &self,
+            _table_name: &str,
+            _item: HashMap<String, AttributeValue>,
+        ) -> Result<()> {
+            Err(ServiceError::invalid_request(
+                "conditional puts are not scripted",
+            ))
+        }
+
+        async fn put_item_version(
+            &self,
+            _table_name: &str,
+            _item: HashMap<String, AttributeValue>,
+            _expected: AttributeValue,
+        ) -> Result<()> {
+            Err(ServiceError::invalid_request(
+                "conditional puts are not scripted",
+            ))
+        }
+    }
+
+    fn doc(id: &str) -> SearchDoc {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_text_field("title", schema::TEXT);
+        let schema = schema.build();
+
+        SearchDoc::from_json(&schema, json!({ "__id": id, "title": id })).unwrap()
+    }
+
+    fn key(id: &str) -> HashMap<String, AttributeValue> {
+        serde_dynamo::to_item(DDBKey::document(INDEX, &SearchDocId::parse(id))).unwrap()
+    }
+
+    fn legacy_key(id: &str) -> HashMap<String, AttributeValue> {
+        serde_dynamo::to_item(DDBKey::legacy_document(&SearchDocId::parse(id))).unwrap()
+    }
+
+    fn item(id: &str) -> HashMap<String, AttributeValue> {
+        serde_dynamo::to_item(doc(id)).unwrap()
+    }
+
+    fn get_output(
+        items: Vec<HashMap<String, AttributeValue>>,
+        unprocessed: Vec<HashMap<String, AttributeValue>>,
+    ) -> BatchGetItemOutput {
+        let mut output = BatchGetItemOutput::builder().responses(TABLE, items);
+
+        if !unprocessed.is_empty() {
+            output = output.unprocessed_keys(
+                TABLE,
+                KeysAndAttributes::builder()
+                    .set_keys(Some(unprocessed))
+                    .build(),
+            );
+        }
+
+        output.build()
+    }
+
+    fn write_output(unprocessed: Vec<WriteRequest>) -> BatchWriteItemOutput {
+        let mut output = BatchWriteItemOutput::builder();
+
+        if !unprocessed.is_empty() {
+            output = output.unprocessed_items(TABLE, unprocessed);
+        }
+
+        output.build()
+    }
+
+    fn store(executor: &Arc<ScriptedExecutor>) -> DDBDocumentStore {
+        DDBDocumentStore::with_executor(TABLE, executor.clone())
+    }
+
+    #[tokio::test]
+    async fn get_retries_unprocessed_keys() {
+        let executor = Arc::new(ScriptedExecutor::default());
+        executor.get_responses.lock().unwrap().extend([
+            get_output(vec![item("a")], vec![key("b")]),
+            get_output(vec![item("b")], vec![]),
+        ]);
+
+        let docs = store(&executor)
+            .get_documents(
+                INDEX,
+                vec![
+                    SearchDocRef::from(SearchDocId::parse("a")),
+                    SearchDocRef::from(SearchDocId::parse("b")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = docs.iter().map(|doc| doc.id().id()).collect();
+        assert_eq!(vec!["a", "b"], ids);
+
+        // Only the unprocessed key is requested again.
+        let requests = executor.get_requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(Some(&[key("b")][..]), requests[1].keys());
+    }
+
+    #[tokio::test]
+    async fn get_falls_back_to_legacy_keys() {
+        let executor = Arc::new(ScriptedExecutor::default());
+        executor.get_responses.lock().unwrap().extend([
+            get_output(vec![item("a")], vec![]),
+            get_output(vec![item("b")], vec![]),
+        ]);
+
+        let docs = store(&executor)
+            .get_documents(
+                INDEX,
+                vec![
+                    SearchDocRef::from(SearchDocId::parse("a")),
+                    SearchDocRef::from(SearchDocId::parse("b")),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let ids: Vec<_> = docs.iter().map(|doc| doc.id().id()).collect();
+        assert_eq!(vec!["a", "b"], ids);
+
+        // Only the document that is not found is requested by its legacy key.
+        let requests = executor.get_requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(Some(&[legacy_key("b")][..]), requests[1].keys());
+    }
+
+    #[tokio::test]
+    async fn delete_deletes_legacy_keys() {
+        let executor = Arc::new(ScriptedExecutor::default());
+        executor
+            .write_responses
+            .lock()
+            .unwrap()
+            .push_back(write_output(vec![]));
+
+        store(&executor)
+            .delete_documents(INDEX, vec![SearchDocId::parse("a")])
+            .await
+            .unwrap();
+
+        let deleted: Vec<_> = executor.write_requests.lock().unwrap()[0]
+            .iter()
+            .map(|write| write.delete_request().unwrap().key().unwrap().clone())
+            .collect();
+
+        assert_eq!(vec![key("a"), legacy_key("a")], deleted);
+    }
+
+    #[tokio::test]
+    async fn save_retries_unprocessed_items() {
+        let unprocessed = WriteRequest::builder()
+            .put_request(PutRequest::builder().set_item(Some(item("b"))).build())
+            .build();
+
+        let executor = Arc::new(ScriptedExecutor::default());
+        executor.write_responses.lock().unwrap().extend([
+            write_output(vec![unprocessed.clone()]),
+            write_output(vec![]),
+        ]);
+
+        store(&executor)
+            .save_documents(INDEX, vec![doc("a"), doc("b")])
+            .await
+            .unwrap();
+
+        // Only the unprocessed write is sent again.
+        let requests = executor.write_requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert_eq!(2, requests[0].len());
+        assert_eq!(vec![unprocessed], requests[1]);
+    }
+
+    #[tokio::test]
+    async fn unprocessed_keys_after_max_attempts_rate_limit() {
+        let executor = Arc::new(ScriptedExecutor::default());
+        executor
+            .get_responses
+            .lock()
+            .unwrap()
+            .extend((0..BATCH_MAX_ATTEMPTS).map(|_| get_output(vec![], vec![key("a")])));
+
+        let err = store(&executor)
+            .get_documents(INDEX, vec![SearchDocRef::from(SearchDocId::parse("a"))])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, ServiceError::RateLimit));
+        assert_eq!(
+            BATCH_MAX_ATTEMPTS as usize,
+            executor.get_requests.lock().unwrap().len()
+        );
+    }
+}
@@ -0,0 +1,751 @@
+//! Document store persisted to an append-only log on local disk.
+//!
+//! Each record in the log is framed as:
+//!
+//! ```text
+//! | crc32 (u32 LE) | length (u32 LE) | kind (u8) | index length (u32 LE) | index | id length (u32 LE) | id | content |
+//! ```
+//!
+//! where `index` is the id of the index the document belongs to, `crc32` and `length` cover
+//! everything after the length field, and `content` is the document content encoded with
+//! [`compressed_json`]. A record of kind `DELETE` has no content. The latest record for an index
+//! and id wins. An incomplete or corrupt record at the end of the log, left behind by a crash
+//! mid-write, is truncated when the store is opened. A corrupt record followed by other records
+//! fails the open instead, since truncating it would drop the valid records after it.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use super::document::{DocumentStore, SearchDocRef};
+use crate::json;
+use crate::search_doc::{SearchDoc, SearchDocId};
+use crate::serialize::compressed_json;
+use crate::service::ServiceError;
+
+const LOG_FILE: &str = "documents.log";
+
+const COMPACT_FILE: &str = "documents.log.compact";
+
+const RECORD_PUT: u8 = 1;
+
+const RECORD_DELETE: u8 = 2;
+
+/// Size of the crc and length fields preceding each record body.
+const HEADER_LEN: u64 = 8;
+
+/// Identifies a document by the index it belongs to and its id.
+type Key = (String, SearchDocId);
+
+/// Location of the latest body for a document within the log.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    offset: u64,
+    len: u32,
+}
+
+#[derive(Debug)]
+struct Log {
+    file: File,
+
+    entries: HashMap<Key, Entry>,
+
+    /// Bytes of the log taken up by records that have been superseded.
+    dead_bytes: u64,
+}
+
+#[derive(Debug)]
+pub struct FileDocumentStore {
+    dir: PathBuf,
+
+    log: Arc<Mutex<Log>>,
+}
+
+fn encode_record(kind: u8, (index_id, id): &Key, content: &[u8]) -> Vec<u8> {
+    let index_id = index_id.as_bytes();
+    let id = id.id().as_bytes();
+
+    let mut body = Vec::with_capacity(9 + index_id.len() + id.len() + content.len());
+    body.push(kind);
+    body.extend_from_slice(&(index_id.len() as u32).to_le_bytes());
+    body.extend_from_slice(index_id);
+    body.extend_from_slice(&(id.len() as u32).to_le_bytes());
+    body.extend_from_slice(id);
+    body.extend_from_slice(content);
+
+    let mut record = Vec::with_capacity(HEADER_LEN as usize + body.len());
+    record.extend_from_slice(&crc32fast::hash(&body).to_le_bytes());
+    record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    record.extend_from_slice(&body);
+    record
+}
+
+/// Splits a record body into its kind, key and content.
+fn decode_body(body: &[u8]) -> Option<(u8, Key, &[u8])> {
+    let kind = *body.first()?;
+    let (index_id, rest) = decode_str(&body[1..])?;
+    let (id, content) = decode_str(rest)?;
+    Some((
+        kind,
+        (index_id.to_string(), SearchDocId::parse(id)),
+        content,
+    ))
+}
+
+/// Splits a length-prefixed string off the start of `bytes`.
+fn decode_str(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let len = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) as usize;
+    let value = std::str::from_utf8(bytes.get(4..4 + len)?).ok()?;
+    Some((value, &bytes[4 + len..]))
+}
+
+/// A record read from the log.
+enum Record {
+    Body(Vec<u8>),
+
+    /// The end of the log.
+    End,
+
+    /// A record cut short by the end of the log.
+    Incomplete,
+
+    /// A complete record of a body of `len` bytes that does not match its checksum.
+    Corrupt {
+        len: u32,
+    },
+}
+
+/// Reads the next record body.
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Record> {
+    let mut header = vec![];
+    reader.take(HEADER_LEN).read_to_end(&mut header)?;
+
+    match header.len() {
+        0 => return Ok(Record::End),
+        len if len < HEADER_LEN as usize => return Ok(Record::Incomplete),
+        _ => {}
+    }
+
+    let crc = u32::from_le_bytes(header[0..4].try_into().unwrap());
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+    let mut body = vec![];
+    reader.take(len as u64).read_to_end(&mut body)?;
+
+    if body.len() != len as usize {
+        return Ok(Record::Incomplete);
+    }
+
+    if crc32fast::hash(&body) != crc || decode_body(&body).is_none() {
+        return Ok(Record::Corrupt { len });
+    }
+
+    Ok(Record::Body(body))
+}
+
+impl Log {
+    fn open(path: &Path) -> io::Result<Log> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let file_len = file.metadata()?.len();
+
+        let mut entries = HashMap::new();
+        let mut dead_bytes = 0;
+        let mut offset = 0;
+
+        {
+            let mut reader = BufReader::new(&mut file);
+            reader.seek(SeekFrom::Start(0))?;
+
+            loop {
+                let body = match read_record(&mut reader)? {
+                    Record::Body(body) => body,
+                    Record::End | Record::Incomplete => break,
+                    Record::Corrupt { len } if offset + HEADER_LEN + len as u64 == file_len => {
+                        break
+                    }
+                    Record::Corrupt { .. } => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("corrupt document record at offset {offset}"),
+                        ))
+                    }
+                };
+
+                let record_len = HEADER_LEN + body.len() as u64;
+
+                let (kind, key, _) = decode_body(&body).expect("record bodies are checked on read");
+
+                let previous = match kind {
+                    RECORD_PUT => entries.insert(
+                        key,
+                        Entry {
+                            offset: offset + HEADER_LEN,
+                            len: body.len() as u32,
+                        },
+                    ),
+                    _ => {
+                        dead_bytes += record_len;
+                        entries.remove(&key)
+                    }
+                };
+
+                if let Some(previous) = previous {
+                    dead_bytes += HEADER_LEN + previous.len as u64;
+                }
+
+                offset += record_len;
+            }
+        }
+
+        if file_len > offset {
+            tracing::warn!(message = "document_log_truncated", offset);
+            file.set_len(offset)?;
+        }
+
+        Ok(Log {
+            file,
+            entries,
+            dead_bytes,
+        })
+    }
+
+    fn read(&mut self, entry: Entry) -> io::Result<Vec<u8>> {
+        let mut body = vec![0u8; entry.len as usize];
+        self.file.seek(SeekFrom::Start(entry.offset))?;
+        self.file.read_exact(&mut body)?;
+        Ok(body)
+    }
+
+    /// Appends records and makes them durable before updating the in-memory entries.
+    fn append(&mut self, records: Vec<(u8, Key, Vec<u8>)>) -> io::Result<()> {
+        let mut offset = self.file.seek(SeekFrom::End(0))?;
+
+        let mut buffer = vec![];
+        let mut updates = vec![];
+
+        for (kind, key, content) in records {
+            let record = encode_record(kind, &key, &content);
+            let body_len = record.len() as u64 - HEADER_LEN;
+
+            updates.push((
+                kind,
+                key,
+                Entry {
+                    offset: offset + HEADER_LEN,
+                    len: body_len as u32,
+                },
+            ));
+
+            offset += record.len() as u64;
+            buffer.extend(record);
+        }
+
+        self.file.write_all(&buffer)?;
+        self.file.sync_data()?;
+
+        for (kind, key, entry) in updates {
+            let previous = if kind == RECORD_PUT {
+                self.entries.insert(key, entry)
+            } else {
+                self.dead_bytes += HEADER_LEN + entry.len as u64;
+                self.entries.remove(&key)
+            };
+
+            if let Some(previous) = previous {
+                self.dead_bytes += HEADER_LEN + previous.len as u64;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn corrupt_record() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt document record")
+}
+
+impl FileDocumentStore {
+    /// Opens the store in `dir`, creating it if it does not exist.
+    pub fn open<P>(dir: P) -> Result<FileDocumentStore, ServiceError>
+    where P: AsRef<Path> {
+        let dir = dir.as_ref().to_owned();
+
+        fs::create_dir_all(&dir)?;
+
+        let log = Log::open(&dir.join(LOG_FILE))?;
+
+        Ok(FileDocumentStore {
+            dir,
+            log: Arc::new(Mutex::new(log)),
+        })
+    }
+
+    /// Bytes of the log taken up by superseded or deleted documents, reclaimable by
+    /// [`FileDocumentStore::compact`].
+    pub fn dead_bytes(&self) -> u64 {
+        self.log.lock().unwrap().dead_bytes
+    }
+
+    /// Rewrites the log keeping only the latest version of each document.
+    pub fn compact(&self) -> Result<(), ServiceError> {
+        let mut log = self.log.lock().unwrap();
+
+        let compact_path = self.dir.join(COMPACT_FILE);
+        let mut compact_file = File::create(&compact_path)?;
+
+        let entries: Vec<_> = log.entries.iter().map(|(id, e)| (id.clone(), *e)).collect();
+
+        for (key, entry) in entries {
+            let body = log.read(entry)?;
+            let (kind, _, content) = decode_body(&body).ok_or_else(corrupt_record)?;
+
+            compact_file.write_all(&encode_record(kind, &key, content))?;
+        }
+
+        compact_file.sync_all()?;
+
+        let log_path = self.dir.join(LOG_FILE);
+        fs::rename(&compact_path, &log_path)?;
+
+        // The rename is only durable once the directory is synced.
+        File::open(&self.dir)?.sync_all()?;
+
+        *log = Log::open(&log_path)?;
+
+        Ok(())
+    }
+
+    /// Runs `f` with the log on the blocking thread pool, since reading and syncing the log blocks.
+    async fn with_log<F, T>(&self, f: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce(&mut Log) -> Result<T, ServiceError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let log = Arc::clone(&self.log);
+
+        tokio::task::spawn_blocking(move || f(&mut log.lock().unwrap()))
+            .await
+            .map_err(ServiceError::internal_error)?
+    }
+}
+
+#[async_trait]
+impl DocumentStore for FileDocumentStore {
+    async fn get_documents(
+        &self,
+        index_id: &str,
+        refs: Vec<SearchDocRef>,
+    ) -> Result<Vec<SearchDoc>, ServiceError> {
+        let index_id = index_id.to_string();
+
+        self.with_log(move |log| {
+            let mut documents = vec![];
+
+            for doc_ref in refs {
+                let key = (index_id.clone(), doc_ref.id().clone());
+
+                let Some(entry) = log.entries.get(&key).copied() else {
+                    continue;
+                };
+
+                let body = log.read(entry)?;
+
+                let (_, (_, id), content) = decode_body(&body).ok_or_else(corrupt_record)?;
+
+                documents.push(SearchDoc::from_parts(id, compressed_json::decode(content)));
+            }
+
+            Ok(documents)
+        })
+        .await
+    }
+
+    async fn save_documents(
+        &self,
+        index_id: &str,
+        documents: Vec<SearchDoc>,
+    ) -> Result<Vec<SearchDocRef>, ServiceError> {
+        let records = documents
+            .iter()
+            .map(|doc| {
+                (
+                    RECORD_PUT,
+                    (index_id.to_string(), doc.id().clone()),
+                    compressed_json::encode(doc.content()),
+                )
+            })
+            .collect();
+
+        self.with_log(move |log| Ok(log.append(records)?)).await?;
+
+        Ok(documents
+            .into_iter()
+            .map(|doc| SearchDocRef::from(doc.id().clone()))
+            .collect())
+    }
+
+    async fn insert_document(
+        &self,
+        index_id: &str,
+        document: SearchDoc,
+    ) -> Result<SearchDocRef, ServiceError> {
+        let key = (index_id.to_string(), document.id().clone());
+        let content = compressed_json::encode(document.content());
+
+        self.with_log(move |log| {
+            if log.entries.contains_key(&key) {
+                return Err(ServiceError::conflict("Document already exists"));
+            }
+
+            Ok(log.append(vec![(RECORD_PUT, key, content)])?)
+        })
+        .await?;
+
+        Ok(SearchDocRef::from(document.id().clone()))
+    }
+
+    async fn update_document(
+        &self,
+        index_id: &str,
+        document: SearchDoc,
+        field: &str,
+        expected: Option<&json::Value>,
+    ) -> Result<SearchDocRef, ServiceError> {
+        let key = (index_id.to_string(), document.id().clone());
+        let content = compressed_json::encode(document.content());
+        let field = field.to_string();
+        let expected = expected.cloned();
+
+        self.with_log(move |log| {
+            let stored = match log.entries.get(&key).copied() {
+                Some(entry) => {
+                    let body = log.read(entry)?;
+                    let (_, _, content) = decode_body(&body).ok_or_else(corrupt_record)?;
+                    Some(compressed_json::decode(content))
+                }
+                None => None,
+            };
+
+            let version = stored
+                .as_ref()
+                .map(|content| content.get(&field).unwrap_or(&json::Value::Null));
+
+            if version != expected.as_ref() {
+                return Err(ServiceError::conflict("Document was updated concurrently"));
+            }
+
+            Ok(log.append(vec![(RECORD_PUT, key, content)])?)
+        })
+        .await?;
+
+        Ok(SearchDocRef::from(document.id().clone()))
+    }
+
+    async fn delete_documents(
+        &self,
+        index_id: &str,
+        ids: Vec<SearchDocId>,
+    ) -> Result<(), ServiceError> {
+        let index_id = index_id.to_string();
+
+        self.with_log(move |log| {
+            let records = ids
+                .into_iter()
+                .map(|id| (index_id.clone(), id))
+                .filter(|key| log.entries.contains_key(key))
+                .map(|key| (RECORD_DELETE, key, vec![]))
+                .collect::<Vec<_>>();
+
+            if records.is_empty() {
+                return Ok(());
+            }
+
+            Ok(log.append(records)?)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tantivy::schema::{self, Schema};
+
+    use super::*;
+    use crate::util;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("pathery-docs-{}", util::generate_id()))
+    }
+
+    fn doc(id: &str, title: &str) -> SearchDoc {
+        let mut schema = Schema::builder();
+        schema.add_text_field("__id", schema::STRING);
+        schema.add_text_field("title", schema::TEXT);
+        let schema = schema.build();
+
+        SearchDoc::from_json(&schema, json!({ "__id": id, "title": title })).unwrap()
+    }
+
+    const INDEX: &str = "test";
+
+    fn key(id: &str) -> Key {
+        (INDEX.to_string(), SearchDocId::parse(id))
+    }
+
+    fn doc_ref(id: &str) -> SearchDocRef {
+        SearchDocRef::from(SearchDocId::parse(id))
+    }
+
+    async fn titles(store: &FileDocumentStore, ids: &[&str]) -> Vec<String> {
+        store
+            .get_documents(INDEX, ids.iter().map(|id| doc_ref(id)).collect())
+            .await
+            .unwrap()
+            .iter()
+            .map(|doc| doc.content()["title"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn save_get_and_delete() {
+        let dir = temp_dir();
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        store
+            .save_documents(INDEX, vec![doc("a", "first"), doc("b", "second")])
+            .await
+            .unwrap();
+        store
+            .save_documents(INDEX, vec![doc("a", "updated")])
+            .await
+            .unwrap();
+        store
+            .delete_documents(INDEX, vec![SearchDocId::parse("b")])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec!["updated"],
+            titles(&store, &["a", "b", "missing"]).await
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn documents_are_scoped_to_their_index() {
+        let dir = temp_dir();
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        store
+            .save_documents(INDEX, vec![doc("a", "first")])
+            .await
+            .unwrap();
+        store
+            .save_documents("other", vec![doc("a", "other")])
+            .await
+            .unwrap();
+        store
+            .delete_documents("other", vec![SearchDocId::parse("a")])
+            .await
+            .unwrap();
+
+        assert_eq!(vec!["first"], titles(&store, &["a"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn insert_conflicts_with_existing_document() {
+        let dir = temp_dir();
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        store
+            .insert_document(INDEX, doc("a", "first"))
+            .await
+            .unwrap();
+
+        let err = store
+            .insert_document(INDEX, doc("a", "second"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(409, err.status());
+        assert_eq!(vec!["first"], titles(&store, &["a"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn update_conflicts_with_changed_document() {
+        let dir = temp_dir();
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        store
+            .update_document(INDEX, doc("a", "first"), "title", None)
+            .await
+            .unwrap();
+
+        store
+            .update_document(INDEX, doc("a", "second"), "title", Some(&json!("first")))
+            .await
+            .unwrap();
+
+        let err = store
+            .update_document(INDEX, doc("a", "third"), "title", Some(&json!("first")))
+            .await
+            .unwrap_err();
+
+        assert_eq!(409, err.status());
+        assert_eq!(vec!["second"], titles(&store, &["a"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn documents_survive_reopen() {
+        let dir = temp_dir();
+
+        {
+            let store = FileDocumentStore::open(&dir).unwrap();
+            store
+                .save_documents(INDEX, vec![doc("a", "first"), doc("b", "second")])
+                .await
+                .unwrap();
+            store
+                .delete_documents(INDEX, vec![SearchDocId::parse("a")])
+                .await
+                .unwrap();
+        }
+
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        assert_eq!(vec!["second"], titles(&store, &["a", "b"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn truncated_tail_is_discarded() {
+        let dir = temp_dir();
+
+        {
+            let store = FileDocumentStore::open(&dir).unwrap();
+            store
+                .save_documents(INDEX, vec![doc("a", "first")])
+                .await
+                .unwrap();
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LOG_FILE))
+            .unwrap();
+        let partial = encode_record(RECORD_PUT, &key("b"), b"partial");
+        file.write_all(&partial[..partial.len() - 3]).unwrap();
+        drop(file);
+
+        let store = FileDocumentStore::open(&dir).unwrap();
+        store
+            .save_documents(INDEX, vec![doc("c", "third")])
+            .await
+            .unwrap();
+
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        assert_eq!(
+            vec!["first", "third"],
+            titles(&store, &["a", "b", "c"]).await
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_record_before_others_fails_open() {
+        let dir = temp_dir();
+
+        {
+            let store = FileDocumentStore::open(&dir).unwrap();
+            store
+                .save_documents(INDEX, vec![doc("a", "first")])
+                .await
+                .unwrap();
+            store
+                .save_documents(INDEX, vec![doc("b", "second")])
+                .await
+                .unwrap();
+        }
+
+        let mut log = fs::read(dir.join(LOG_FILE)).unwrap();
+        log[HEADER_LEN as usize + 6] ^= 0xff;
+        fs::write(dir.join(LOG_FILE), &log).unwrap();
+
+        assert!(FileDocumentStore::open(&dir).is_err());
+        assert_eq!(log, fs::read(dir.join(LOG_FILE)).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn corrupt_last_record_is_discarded() {
+        let dir = temp_dir();
+
+        {
+            let store = FileDocumentStore::open(&dir).unwrap();
+            store
+                .save_documents(INDEX, vec![doc("a", "first")])
+                .await
+                .unwrap();
+            store
+                .save_documents(INDEX, vec![doc("b", "second")])
+                .await
+                .unwrap();
+        }
+
+        let mut log = fs::read(dir.join(LOG_FILE)).unwrap();
+        let last = log.len() - 1;
+        log[last] ^= 0xff;
+        fs::write(dir.join(LOG_FILE), &log).unwrap();
+
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        assert_eq!(vec!["first"], titles(&store, &["a", "b"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn compact_reclaims_dead_bytes() {
+        let dir = temp_dir();
+        let store = FileDocumentStore::open(&dir).unwrap();
+
+        store
+            .save_documents(INDEX, vec![doc("a", "first")])
+            .await
+            .unwrap();
+        store
+            .save_documents(INDEX, vec![doc("a", "updated")])
+            .await
+            .unwrap();
+
+        assert!(store.dead_bytes() > 0);
+
+        store.compact().unwrap();
+
+        assert_eq!(0, store.dead_bytes());
+        assert_eq!(vec!["updated"], titles(&store, &["a"]).await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1 +1,3 @@
 pub mod document;
+pub mod file;
+pub mod record;
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{ser, Serialize};
+
+use crate::json;
+use crate::search_doc::{SearchDoc, SearchDocId, RESERVED_ID_PREFIX};
+use crate::service::ServiceError;
+use crate::store::document::{DocumentStore, SearchDocRef};
+
+/// Keeps pathery's own JSON records, such as index schemas, in the document store alongside
+/// documents.
+///
+/// Records are stored under ids with a reserved prefix that documents cannot use, so any document
+/// store can hold them without a separate table.
+#[derive(Clone)]
+pub struct RecordStore {
+    document_store: Arc<dyn DocumentStore>,
+}
+
+/// Index id under which records are stored. An index with this id cannot overwrite records since
+/// documents cannot use the reserved id prefix.
+pub(crate) const RECORD_INDEX_ID: &str = "__pathery";
+
+fn record_id(key: &str) -> SearchDocId {
+    SearchDocId::parse(&format!("{RESERVED_ID_PREFIX}{key}"))
+}
+
+/// Builds the document that holds a record, for writers that only borrow a document store.
+pub(crate) fn record_doc<T>(key: &str, record: T) -> Result<SearchDoc, ServiceError>
+where T: Serialize {
+    match json::to_value(record) {
+        Ok(json::Value::Object(content)) => Ok(SearchDoc::from_parts(record_id(key), content)),
+        Ok(_) => Err(ServiceError::internal_error(
+            <json::Error as ser::Error>::custom(format!(
+                "record [{key}] does not serialize to an object"
+            )),
+        )),
+        Err(err) => Err(ServiceError::internal_error(err)),
+    }
+}
+
+/// Gets a record, for readers that only borrow a document store.
+pub(crate) async fn get_record<T>(
+    document_store: &dyn DocumentStore,
+    key: &str,
+) -> Result<Option<T>, ServiceError>
+where
+    T: DeserializeOwned,
+{
+    let docs = document_store
+        .get_documents(RECORD_INDEX_ID, vec![SearchDocRef::from(record_id(key))])
+        .await?;
+
+    docs.into_iter()
+        .next()
+        .map(|doc| {
+            json::from_value(json::Value::Object(doc.content().clone()))
+                .map_err(ServiceError::internal_error)
+        })
+        .transpose()
+}
+
+/// Updates a record, for writers that only borrow a document store, see [`RecordStore::update`].
+pub(crate) async fn update_record<T>(
+    document_store: &dyn DocumentStore,
+    key: &str,
+    record: T,
+    field: &str,
+    expected: Option<&json::Value>,
+) -> Result<(), ServiceError>
+where
+    T: Serialize,
+{
+    document_store
+        .update_document(RECORD_INDEX_ID, record_doc(key, record)?, field, expected)
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes records, for writers that only borrow a document store.
+pub(crate) async fn delete_records(
+    document_store: &dyn DocumentStore,
+    keys: &[String],
+) -> Result<(), ServiceError> {
+    document_store
+        .delete_documents(
+            RECORD_INDEX_ID,
+            keys.iter().map(|key| record_id(key)).collect(),
+        )
+        .await
+}
+
+impl RecordStore {
+    pub fn new(document_store: Arc<dyn DocumentStore>) -> RecordStore {
+        RecordStore { document_store }
+    }
+
+    pub async fn get<T>(&self, key: &str) -> Result<Option<T>, ServiceError>
+    where T: DeserializeOwned {
+        get_record(self.document_store.as_ref(), key).await
+    }
+
+    /// Saves records by key. Each record must serialize to a JSON object.
+    pub async fn put<T>(&self, records: Vec<(String, T)>) -> Result<(), ServiceError>
+    where T: Serialize {
+        let docs = records
+            .into_iter()
+            .map(|(key, record)| record_doc(&key, record))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.document_store
+            .save_documents(RECORD_INDEX_ID, docs)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Saves a record unless a record with the key exists, failing with a conflict otherwise.
+    pub async fn insert<T>(&self, key: &str, record: T) -> Result<(), ServiceError>
+    where T: Serialize {
+        self.document_store
+            .insert_document(RECORD_INDEX_ID, record_doc(key, record)?)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Saves a record only if the `field` of the stored record still has the `expected` value, or
+    /// if no record exists when `expected` is `None`, failing with a conflict otherwise. Records
+    /// updated this way must not be written with [`RecordStore::put`].
+    pub async fn update<T>(
+        &self,
+        key: &str,
+        record: T,
+        field: &str,
+        expected: Option<&json::Value>,
+    ) -> Result<(), ServiceError>
+    where
+        T: Serialize,
+    {
+        update_record(self.document_store.as_ref(), key, record, field, expected).await
+    }
+
+    pub async fn delete(&self, keys: &[String]) -> Result<(), ServiceError> {
+        delete_records(self.document_store.as_ref(), keys).await
+    }
+}
+
+/// Keeps records loaded from a [`RecordStore`], including records that were not found, for a
+/// limited time.
+pub struct RecordCache<T> {
+    ttl: Duration,
+
+    entries: Mutex<HashMap<String, (Option<T>, Instant)>>,
+}
+
+impl<T> RecordCache<T>
+where T: Clone
+{
+    pub fn new(ttl: Duration) -> RecordCache<T> {
+        RecordCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached record, or `None` if the key is not cached or has expired.
+    pub fn get(&self, key: &str) -> Option<Option<T>> {
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .get(key)
+            .filter(|(_, loaded_at)| loaded_at.elapsed() < self.ttl)
+            .map(|(record, _)| record.clone())
+    }
+
+    pub fn insert(&self, key: &str, record: Option<T>) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (record, Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Record {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn put_get_and_delete() {
+        let ctx = setup();
+        let records = RecordStore::new(Arc::new(ctx.document_store().clone()));
+
+        let record = Record { name: "zen".into() };
+
+        records.put(vec![("a".to_string(), &record)]).await.unwrap();
+
+        assert_eq!(Some(record), records.get("a").await.unwrap());
+        assert_eq!(None, records.get::<Record>("b").await.unwrap());
+
+        records.delete(&["a".to_string()]).await.unwrap();
+
+        assert_eq!(None, records.get::<Record>("a").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn insert_conflicts_with_existing_record() {
+        let ctx = setup();
+        let records = RecordStore::new(Arc::new(ctx.document_store().clone()));
+
+        let record = Record { name: "zen".into() };
+
+        records.insert("a", &record).await.unwrap();
+
+        let err = records.insert("a", &record).await.unwrap_err();
+
+        assert_eq!(409, err.status());
+    }
+
+    #[tokio::test]
+    async fn update_conflicts_with_changed_record() {
+        let ctx = setup();
+        let records = RecordStore::new(Arc::new(ctx.document_store().clone()));
+
+        records
+            .update("a", &Record { name: "zen".into() }, "name", None)
+            .await
+            .unwrap();
+
+        records
+            .update(
+                "a",
+                &Record { name: "tao".into() },
+                "name",
+                Some(&json!("zen")),
+            )
+            .await
+            .unwrap();
+
+        let err = records
+            .update(
+                "a",
+                &Record { name: "way".into() },
+                "name",
+                Some(&json!("zen")),
+            )
+            .await
+            .unwrap_err();
+
+        assert_eq!(409, err.status());
+        assert_eq!(
+            Some(Record { name: "tao".into() }),
+            records.get("a").await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn put_rejects_records_that_are_not_objects() {
+        let ctx = setup();
+        let records = RecordStore::new(Arc::new(ctx.document_store().clone()));
+
+        let err = records
+            .put(vec![("a".to_string(), "zen")])
+            .await
+            .unwrap_err();
+
+        assert_eq!(500, err.status());
+    }
+}
@@ -14,5 +14,5 @@ pub fn timestamp() -> String {
 }
 
 pub fn require_env(var_name: &str) -> String {
-    std::env::var(var_name).expect(&format!("{var_name:?} should be set"))
+    std::env::var(var_name).unwrap_or_else(|_| panic!("{var_name:?} should be set"))
 }
@@ -0,0 +1,282 @@
+//! Dense vector fields and their nearest neighbour search.
+//!
+//! Vectors are stored as little endian `f32` values in a bytes fast field, so they are written
+//! and merged with the segments of the index. [`VectorQuery`] searches them by brute force,
+//! scoring every document of a segment that has a vector.
+
+use base64::Engine;
+use serde_json::{Map, Value};
+use tantivy::query::{Explanation, Query, Scorer, Weight};
+use tantivy::schema::{Field, FieldType, Schema};
+use tantivy::{DocId, DocSet, Score, Searcher, SegmentReader, TantivyError, TERMINATED};
+
+use crate::schema::{FieldConfig, VectorMetric};
+use crate::search_doc::SearchDocError;
+
+/// Encodes a vector as the bytes stored in its fast field.
+pub fn encode(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+/// Decodes the bytes stored in the fast field of a vector.
+pub fn decode(bytes: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes(chunk.try_into().expect("chunk should have 4 bytes")))
+}
+
+/// Returns the vector of a JSON value, if it is an array of numbers.
+fn json_vector(value: &Value) -> Option<Vec<f32>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|value| value.as_f64().map(|value| value as f32))
+        .collect()
+}
+
+/// Replaces the vectors of the bytes fields of a document with the base64 encoded bytes that
+/// tantivy parses bytes fields from.
+pub fn encode_document(schema: &Schema, content: &mut Map<String, Value>) {
+    for (name, value) in content.iter_mut() {
+        let Some(field) = schema.get_field(name) else {
+            continue;
+        };
+
+        if !matches!(
+            schema.get_field_entry(field).field_type(),
+            FieldType::Bytes(_)
+        ) {
+            continue;
+        }
+
+        if let Some(vector) = json_vector(value) {
+            *value =
+                Value::String(base64::engine::general_purpose::STANDARD.encode(encode(&vector)));
+        }
+    }
+}
+
+/// Checks that the vectors of a document have the dimensions of their fields.
+pub fn check_document(
+    fields: &[FieldConfig],
+    content: &Map<String, Value>,
+) -> Result<(), SearchDocError> {
+    for field in fields {
+        let FieldConfig::VectorFieldConfig {
+            name, dimensions, ..
+        } = field
+        else {
+            continue;
+        };
+
+        let Some(value) = content.get(name) else {
+            continue;
+        };
+
+        match json_vector(value) {
+            Some(vector) if vector.len() == *dimensions => {}
+            Some(vector) => {
+                return Err(SearchDocError::SchemaValidationError(format!(
+                    "Vector field [{name}] expects {dimensions} dimensions, got {}",
+                    vector.len()
+                )))
+            }
+            None => {
+                return Err(SearchDocError::SchemaValidationError(format!(
+                    "Vector field [{name}] expects an array of numbers"
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Scores how similar two vectors of the same dimensions are, higher scores being more similar.
+///
+/// Cosine similarity is between -1 and 1 and the dot product is unbounded. Euclidean distances
+/// are scored as `1 / (1 + d²)`, between 0 and 1.
+pub fn similarity(metric: VectorMetric, a: &[f32], b: impl Iterator<Item = f32>) -> Score {
+    match metric {
+        VectorMetric::Cosine => {
+            let (dot, norm_a, norm_b) = a
+                .iter()
+                .zip(b)
+                .fold((0.0, 0.0, 0.0), |(dot, norm_a, norm_b), (a, b)| {
+                    (dot + a * b, norm_a + a * a, norm_b + b * b)
+                });
+
+            if norm_a == 0.0 || norm_b == 0.0 {
+                0.0
+            } else {
+                dot / (norm_a * norm_b).sqrt()
+            }
+        }
+        VectorMetric::DotProduct => a.iter().zip(b).map(|(a, b)| a * b).sum(),
+        VectorMetric::Euclidean => {
+            let distance: f32 = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+
+            1.0 / (1.0 + distance)
+        }
+    }
+}
+
+/// Matches the documents with a vector in a field, scored by their similarity to a vector.
+#[derive(Clone, Debug)]
+pub struct VectorQuery {
+    field: Field,
+    vector: Vec<f32>,
+    metric: VectorMetric,
+}
+
+impl VectorQuery {
+    pub fn new(field: Field, vector: Vec<f32>, metric: VectorMetric) -> VectorQuery {
+        VectorQuery {
+            field,
+            vector,
+            metric,
+        }
+    }
+}
+
+impl Query for VectorQuery {
+    fn weight(&self, _: &Searcher, _: bool) -> tantivy::Result<Box<dyn Weight>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl Weight for VectorQuery {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        let mut scorer = VectorScorer {
+            query: self.clone(),
+            vectors: reader.fast_fields().bytes(self.field)?,
+            doc: 0,
+            max_doc: reader.max_doc(),
+            boost,
+        };
+
+        if scorer.max_doc > 0 && !scorer.has_vector(0) {
+            scorer.advance();
+        }
+
+        if scorer.max_doc == 0 {
+            scorer.doc = TERMINATED;
+        }
+
+        Ok(Box::new(scorer))
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = self.scorer(reader, 1.0)?;
+
+        if scorer.seek(doc) != doc {
+            return Err(TantivyError::InvalidArgument(format!(
+                "Document #({doc}) does not match"
+            )));
+        }
+
+        Ok(Explanation::new("VectorQuery", scorer.score()))
+    }
+}
+
+struct VectorScorer {
+    query: VectorQuery,
+    vectors: tantivy::fastfield::BytesFastFieldReader,
+    doc: DocId,
+    max_doc: DocId,
+    boost: Score,
+}
+
+impl VectorScorer {
+    fn has_vector(&self, doc: DocId) -> bool {
+        self.vectors.num_bytes(doc) == self.query.vector.len() * 4
+    }
+}
+
+impl DocSet for VectorScorer {
+    fn advance(&mut self) -> DocId {
+        loop {
+            if self.doc + 1 >= self.max_doc {
+                self.doc = TERMINATED;
+                return TERMINATED;
+            }
+
+            self.doc += 1;
+
+            if self.has_vector(self.doc) {
+                return self.doc;
+            }
+        }
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.max_doc
+    }
+}
+
+impl Scorer for VectorScorer {
+    fn score(&mut self) -> Score {
+        let vector = decode(self.vectors.get_bytes(self.doc));
+
+        similarity(self.query.metric, &self.query.vector, vector) * self.boost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn similarity_by_metric() {
+        let a = [1.0, 0.0];
+        let b = [3.0, 4.0];
+
+        assert_eq!(0.6, similarity(VectorMetric::Cosine, &a, b.iter().copied()));
+        assert_eq!(
+            3.0,
+            similarity(VectorMetric::DotProduct, &a, b.iter().copied())
+        );
+        assert_eq!(
+            0.1,
+            similarity(VectorMetric::Euclidean, &a, [1.0, 3.0].into_iter())
+        );
+        assert_eq!(
+            0.0,
+            similarity(VectorMetric::Cosine, &a, [0.0, 0.0].into_iter())
+        );
+    }
+
+    #[test]
+    fn search_nearest_vectors() {
+        let (index, embedding) = bytes_field_index(
+            "embedding",
+            vec![("far", encode(&[0.0, 1.0])), ("near", encode(&[1.0, 0.1]))],
+        );
+
+        let query = VectorQuery::new(embedding, vec![1.0, 0.0], VectorMetric::Euclidean);
+
+        assert_eq!(vec!["near", "far"], search_ids(&index, &query));
+    }
+
+    #[test]
+    fn check_document_dimensions() {
+        let fields = vec![FieldConfig::VectorFieldConfig {
+            name: "embedding".into(),
+            dimensions: 2,
+            metric: VectorMetric::Cosine,
+        }];
+        let content = |value| field_content("embedding", value);
+
+        assert!(check_document(&fields, &content(serde_json::json!([1, 0.5]))).is_ok());
+        assert!(check_document(&fields, &content(serde_json::json!([1]))).is_err());
+        assert!(check_document(&fields, &content(serde_json::json!("a"))).is_err());
+    }
+}
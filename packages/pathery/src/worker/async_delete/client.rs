@@ -1,10 +1,17 @@
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use tokio::time::Instant;
+use tracing::error;
 
+use super::fs_delete;
 use super::job::AsyncDeleteJob;
+use crate::config::{Config, ConfigError};
 use crate::service::ServiceError;
-use crate::util;
+use crate::worker::local::LocalQueue;
+use crate::worker::QueueKind;
 
 #[async_trait]
 pub trait AsyncDeleteClient: Sync + Send + Debug {
@@ -40,14 +47,75 @@ impl AsyncDeleteClient for LambdaAsyncDeleteClient {
 }
 
 impl LambdaAsyncDeleteClient {
-    pub async fn create(queue_url: Option<&str>) -> LambdaAsyncDeleteClient {
+    pub async fn create(config: &Config) -> Result<LambdaAsyncDeleteClient, ConfigError> {
+        Ok(Self::with_queue_url(config.async_delete_queue_url()?).await)
+    }
+
+    pub async fn with_queue_url(queue_url: &str) -> LambdaAsyncDeleteClient {
         let sdk_config = aws_config::load_from_env().await;
 
         LambdaAsyncDeleteClient {
-            queue_url: queue_url
-                .map(String::from)
-                .unwrap_or_else(|| util::require_env("ASYNC_DELETE_QUEUE_URL")),
+            queue_url: queue_url.to_string(),
             client: aws_sdk_sqs::Client::new(&sdk_config),
         }
     }
 }
+
+/// Delay before the local client deletes files, matching the delivery delay of the SQS queue.
+pub const LOCAL_DELETE_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Async delete client that deletes files in a background task of the current process.
+///
+/// Like the SQS queue, files are only deleted after a delay so that pagination tokens pinned to a
+/// retired index directory keep working for a while. Jobs that are still waiting when the process
+/// exits are dropped and leave their files behind.
+#[derive(Debug, Clone)]
+pub struct LocalAsyncDeleteClient {
+    queue: LocalQueue<(Instant, AsyncDeleteJob)>,
+
+    delay: Duration,
+}
+
+#[async_trait]
+impl AsyncDeleteClient for LocalAsyncDeleteClient {
+    async fn submit_job(&self, job: AsyncDeleteJob) -> Result<String, ServiceError> {
+        self.queue.send((Instant::now() + self.delay, job))
+    }
+}
+
+impl LocalAsyncDeleteClient {
+    pub fn spawn() -> LocalAsyncDeleteClient {
+        Self::with_delay(LOCAL_DELETE_DELAY)
+    }
+
+    /// Spawns a client that waits `delay` after a job is submitted before handling it.
+    pub fn with_delay(delay: Duration) -> LocalAsyncDeleteClient {
+        // Every job waits the same delay, so jobs become due in submission order.
+        let queue = LocalQueue::spawn(|(due, job): (Instant, AsyncDeleteJob)| async move {
+            tokio::time::sleep_until(due).await;
+
+            match job {
+                AsyncDeleteJob::FSDelete(path) => {
+                    if let Err(err) = fs_delete(&path) {
+                        error!(message = "fs_delete_failed", path = ?path, error = %err);
+                    }
+                }
+            }
+        });
+
+        LocalAsyncDeleteClient { queue, delay }
+    }
+
+    /// Waits until every job submitted before the call has been handled, including its delay.
+    pub async fn flush(&self) -> Result<(), ServiceError> {
+        self.queue.flush().await
+    }
+}
+
+/// Creates the async delete client of the queue kind selected by `config`.
+pub async fn create_client(config: &Config) -> Result<Arc<dyn AsyncDeleteClient>, ConfigError> {
+    Ok(match config.queue_kind() {
+        QueueKind::Sqs => Arc::new(LambdaAsyncDeleteClient::create(config).await?),
+        QueueKind::Local => Arc::new(LocalAsyncDeleteClient::spawn()),
+    })
+}
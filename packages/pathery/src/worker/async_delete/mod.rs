@@ -1,15 +1,19 @@
 pub mod client;
 pub mod job;
 
-use std::fs;
-use std::path::PathBuf;
+use std::path::Path;
+use std::{fs, io};
 
 use serde_json as json;
 
 use crate::lambda::{self, sqs};
 
-pub fn fs_delete(path: PathBuf) {
-    fs::remove_file(path).expect("should be able to delete file");
+pub fn fs_delete(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
 }
 
 pub async fn handle_event(event: sqs::SqsEvent) -> Result<(), lambda::Error> {
@@ -28,7 +32,9 @@ pub async fn handle_event(event: sqs::SqsEvent) -> Result<(), lambda::Error> {
     for ele in jobs {
         print!("{:?}", ele);
         match ele {
-            job::AsyncDeleteJob::FSDelete(path) => fs_delete(path),
+            job::AsyncDeleteJob::FSDelete(path) => {
+                fs_delete(&path).expect("should be able to delete file")
+            }
         }
     }
 
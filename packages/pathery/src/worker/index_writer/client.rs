@@ -1,12 +1,32 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use thiserror::Error;
+use tracing::{error, info};
 
 use super::job::Job;
+use super::run_job;
+use crate::config::{Config, ConfigError};
+use crate::index::{IndexLoader, LambdaIndexLoader};
 use crate::service::ServiceError;
-use crate::util;
+use crate::store::document::{DDBDocumentStore, DocumentStore};
+use crate::worker::local::LocalQueue;
+use crate::worker::QueueKind;
 
 #[derive(Debug, Error)]
-pub enum IndexWriterClientError {}
+pub enum IndexWriterClientError {
+    #[error("SQS returned no message id")]
+    MissingMessageId,
+
+    /// Some messages of a split job were sent before sending the next one failed. Since the queue
+    /// deduplicates messages by content, submitting the same job again within the deduplication
+    /// interval does not apply the sent messages twice.
+    #[error("sent messages [{}] of the job before failing", message_ids.join(","))]
+    PartiallySubmitted {
+        message_ids: Vec<String>,
+        source: ServiceError,
+    },
+}
 
 #[async_trait]
 pub trait IndexWriterClient: Sync + Send {
@@ -18,10 +38,55 @@ pub struct LambdaIndexWriterClient {
     client: aws_sdk_sqs::Client,
 }
 
+/// Maximum size of an SQS message body, in bytes.
+const MAX_MESSAGE_BYTES: usize = 256 * 1024;
+
 #[async_trait]
 impl IndexWriterClient for LambdaIndexWriterClient {
+    /// Submits the job, splitting it across several messages in the same FIFO group when it would
+    /// exceed the SQS message size limit. The returned job id is the comma separated list of
+    /// message ids.
+    ///
+    /// When a message fails to send after others of the job were sent, the error is an
+    /// [`IndexWriterClientError::PartiallySubmitted`] with the ids of the sent messages.
     async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
-        let body = serde_json::to_string(&job).expect("job should serialize");
+        let mut message_ids = vec![];
+
+        for job in job.split(MAX_MESSAGE_BYTES) {
+            match self.send_message(job).await {
+                Ok(message_id) => message_ids.push(message_id),
+                Err(source) if message_ids.is_empty() => return Err(source),
+                Err(source) => {
+                    return Err(ServiceError::internal_error(
+                        IndexWriterClientError::PartiallySubmitted {
+                            message_ids,
+                            source,
+                        },
+                    ))
+                }
+            }
+        }
+
+        Ok(message_ids.join(","))
+    }
+}
+
+impl LambdaIndexWriterClient {
+    pub async fn create(config: &Config) -> Result<LambdaIndexWriterClient, ConfigError> {
+        Ok(Self::with_queue_url(config.index_writer_queue_url()?).await)
+    }
+
+    pub async fn with_queue_url(queue_url: &str) -> LambdaIndexWriterClient {
+        let sdk_config = aws_config::load_from_env().await;
+
+        LambdaIndexWriterClient {
+            queue_url: queue_url.to_string(),
+            client: aws_sdk_sqs::Client::new(&sdk_config),
+        }
+    }
+
+    async fn send_message(&self, job: Job) -> Result<String, ServiceError> {
+        let body = serde_json::to_string(&job).map_err(ServiceError::internal_error)?;
 
         let response = self
             .client
@@ -30,37 +95,87 @@ impl IndexWriterClient for LambdaIndexWriterClient {
             .message_body(body)
             .message_group_id(job.index_id)
             .send()
-            .await
-            .expect("job should queue");
+            .await?;
 
-        Ok(response
+        response
             .message_id()
-            .expect("message id should exist")
-            .to_string())
+            .map(str::to_string)
+            .ok_or_else(|| ServiceError::internal_error(IndexWriterClientError::MissingMessageId))
     }
 }
 
-impl LambdaIndexWriterClient {
-    pub async fn create(queue_url: Option<&str>) -> LambdaIndexWriterClient {
-        let sdk_config = aws_config::load_from_env().await;
+/// Index writer client that applies jobs in a background task of the current process.
+#[derive(Clone)]
+pub struct LocalIndexWriterClient {
+    queue: LocalQueue<Job>,
+}
 
-        LambdaIndexWriterClient {
-            queue_url: queue_url
-                .map(String::from)
-                .unwrap_or_else(|| util::require_env("INDEX_WRITER_QUEUE_URL")),
-            client: aws_sdk_sqs::Client::new(&sdk_config),
-        }
+#[async_trait]
+impl IndexWriterClient for LocalIndexWriterClient {
+    async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
+        self.queue.send(job)
+    }
+}
+
+impl LocalIndexWriterClient {
+    pub fn spawn(
+        index_loader: Arc<dyn IndexLoader>,
+        document_store: Arc<dyn DocumentStore>,
+    ) -> LocalIndexWriterClient {
+        let queue = LocalQueue::spawn(move |job: Job| {
+            let index_loader = Arc::clone(&index_loader);
+            let document_store = Arc::clone(&document_store);
+
+            async move {
+                let index_id = job.index_id.clone();
+
+                match run_job(document_store.as_ref(), index_loader.as_ref(), job).await {
+                    Ok(()) => info!(message = "index_commit", index = index_id),
+                    Err(err) => error!(message = "job_failed", index = index_id, error = %err),
+                }
+            }
+        });
+
+        LocalIndexWriterClient { queue }
+    }
+
+    /// Waits until every job submitted before the call has been applied.
+    pub async fn flush(&self) -> Result<(), ServiceError> {
+        self.queue.flush().await
     }
 }
 
+/// Creates the index writer client of the queue kind selected by `config`. The index loader and
+/// document store that a local client applies jobs with are only created for the local queue.
+pub async fn create_client(config: &Config) -> Result<Arc<dyn IndexWriterClient>, ConfigError> {
+    Ok(match config.queue_kind() {
+        QueueKind::Sqs => Arc::new(LambdaIndexWriterClient::create(config).await?),
+        QueueKind::Local => Arc::new(LocalIndexWriterClient::spawn(
+            Arc::new(LambdaIndexLoader::create(config).await?),
+            Arc::new(DDBDocumentStore::create(config).await?),
+        )),
+    })
+}
+
+/// Like [`create_client`], but a local client writes with the `index_loader` and `document_store`
+/// of the caller.
+pub async fn create_shared_client(
+    config: &Config,
+    index_loader: Arc<dyn IndexLoader>,
+    document_store: Arc<dyn DocumentStore>,
+) -> Result<Arc<dyn IndexWriterClient>, ConfigError> {
+    Ok(match config.queue_kind() {
+        QueueKind::Sqs => Arc::new(LambdaIndexWriterClient::create(config).await?),
+        QueueKind::Local => Arc::new(LocalIndexWriterClient::spawn(index_loader, document_store)),
+    })
+}
+
 #[cfg(test)]
 pub mod test_utils {
     use super::*;
     use crate::index::test_util::TestIndexLoader;
-    use crate::index::{IndexExt, IndexLoader};
     use crate::store::document::test_util::TestDocumentStore;
     use crate::util;
-    use crate::worker::index_writer::handle_job;
 
     #[derive(Clone)]
     pub struct TestIndexWriterClient {
@@ -72,13 +187,7 @@ pub mod test_utils {
     #[async_trait]
     impl IndexWriterClient for TestIndexWriterClient {
         async fn submit_job(&self, job: Job) -> Result<String, ServiceError> {
-            let index = self.index_loader.load_index(&job.index_id, None)?;
-
-            let mut writer = index.default_writer();
-
-            handle_job(&mut writer, &self.document_store, job).await;
-
-            writer.commit().unwrap();
+            run_job(&self.document_store, &self.index_loader, job).await?;
 
             Ok(util::generate_id())
         }
@@ -93,3 +202,49 @@ pub mod test_utils {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::SchemaLoader;
+    use crate::search_doc::SearchDoc;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn local_client_applies_jobs() {
+        let ctx = setup();
+
+        let client = LocalIndexWriterClient::spawn(
+            Arc::new(ctx.index_loader().clone()),
+            Arc::new(ctx.document_store().clone()),
+        );
+
+        let schema = ctx.schema_loader().load_schema("test").await.unwrap();
+        let doc = SearchDoc::from_json(&schema, json!({ "title": "hello" })).unwrap();
+        let doc_refs = ctx
+            .document_store()
+            .save_documents("test", vec![doc])
+            .await
+            .unwrap();
+
+        let mut job = Job::create("test");
+        for doc_ref in doc_refs {
+            job.index_doc(doc_ref);
+        }
+
+        client.submit_job(job).await.unwrap();
+        client.flush().await.unwrap();
+
+        let num_docs = ctx
+            .index_loader()
+            .load_index("test", None)
+            .await
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+
+        assert_eq!(1, num_docs);
+    }
+}
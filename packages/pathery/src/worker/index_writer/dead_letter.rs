@@ -0,0 +1,323 @@
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json as json;
+
+use super::client::IndexWriterClient;
+use super::job::Job;
+use crate::config::{Config, ConfigError};
+use crate::service::ServiceError;
+
+/// Maximum number of messages SQS returns from a single receive.
+const SQS_MAX_RECEIVE: usize = 10;
+
+/// Seconds received dead letters stay hidden from other consumers.
+const SQS_VISIBILITY_TIMEOUT: i32 = 60;
+
+/// A failed index writer job.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    /// Identifies the dead letter within its source.
+    pub id: String,
+
+    /// The raw job payload, kept as-is so malformed jobs can be inspected.
+    pub body: String,
+
+    #[serde(skip)]
+    handle: String,
+}
+
+impl DeadLetter {
+    pub fn job(&self) -> Result<Job, json::Error> {
+        json::from_str(&self.body)
+    }
+}
+
+#[async_trait]
+pub trait DeadLetterSource: Send + Sync + Debug {
+    /// Reads up to `max` dead letters without removing them from the source.
+    async fn receive(&self, max: usize) -> Result<Vec<DeadLetter>, ServiceError>;
+
+    /// Removes a dead letter from the source, typically once it has been replayed.
+    async fn remove(&self, letter: &DeadLetter) -> Result<(), ServiceError>;
+}
+
+/// Reads dead letters from the SQS dead-letter queue of the index writer queue.
+#[derive(Debug)]
+pub struct SqsDeadLetterSource {
+    queue_url: String,
+
+    client: aws_sdk_sqs::Client,
+}
+
+#[async_trait]
+impl DeadLetterSource for SqsDeadLetterSource {
+    async fn receive(&self, max: usize) -> Result<Vec<DeadLetter>, ServiceError> {
+        let mut letters: Vec<DeadLetter> = vec![];
+
+        while letters.len() < max {
+            let batch_size = (max - letters.len()).min(SQS_MAX_RECEIVE);
+
+            let response = self
+                .client
+                .receive_message()
+                .queue_url(&self.queue_url)
+                .max_number_of_messages(batch_size as i32)
+                .visibility_timeout(SQS_VISIBILITY_TIMEOUT)
+                .send()
+                .await?;
+
+            let messages = response.messages().unwrap_or_default();
+
+            if messages.is_empty() {
+                break;
+            }
+
+            for message in messages {
+                letters.push(DeadLetter {
+                    id: message.message_id().unwrap_or_default().to_string(),
+                    body: message.body().unwrap_or_default().to_string(),
+                    handle: message.receipt_handle().unwrap_or_default().to_string(),
+                });
+            }
+        }
+
+        Ok(letters)
+    }
+
+    async fn remove(&self, letter: &DeadLetter) -> Result<(), ServiceError> {
+        self.client
+            .delete_message()
+            .queue_url(&self.queue_url)
+            .receipt_handle(&letter.handle)
+            .send()
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl SqsDeadLetterSource {
+    pub async fn create(config: &Config) -> Result<SqsDeadLetterSource, ConfigError> {
+        Ok(Self::with_queue_url(config.index_writer_dlq_url()?).await)
+    }
+
+    pub async fn with_queue_url(queue_url: &str) -> SqsDeadLetterSource {
+        let sdk_config = aws_config::load_from_env().await;
+
+        SqsDeadLetterSource {
+            queue_url: queue_url.to_string(),
+            client: aws_sdk_sqs::Client::new(&sdk_config),
+        }
+    }
+}
+
+/// Reads dead letters from a local file containing one job payload per line.
+///
+/// Removed dead letters are dropped from the file. Dead letters are identified by their line
+/// number in the file as it was when opened.
+#[derive(Debug)]
+pub struct FileDeadLetterSource {
+    path: PathBuf,
+
+    letters: Mutex<Vec<DeadLetter>>,
+}
+
+#[async_trait]
+impl DeadLetterSource for FileDeadLetterSource {
+    async fn receive(&self, max: usize) -> Result<Vec<DeadLetter>, ServiceError> {
+        let letters = self.letters.lock().unwrap();
+
+        Ok(letters.iter().take(max).cloned().collect())
+    }
+
+    async fn remove(&self, letter: &DeadLetter) -> Result<(), ServiceError> {
+        let mut letters = self.letters.lock().unwrap();
+
+        letters.retain(|existing| existing.handle != letter.handle);
+
+        let content: String = letters
+            .iter()
+            .map(|letter| format!("{}\n", letter.body))
+            .collect();
+
+        fs::write(&self.path, content).map_err(ServiceError::internal_error)
+    }
+}
+
+impl FileDeadLetterSource {
+    pub fn open<P>(path: P) -> Result<FileDeadLetterSource, ServiceError>
+    where P: AsRef<Path> {
+        let content = fs::read_to_string(&path).map_err(ServiceError::internal_error)?;
+
+        let letters = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(idx, line)| DeadLetter {
+                id: (idx + 1).to_string(),
+                body: line.to_string(),
+                handle: (idx + 1).to_string(),
+            })
+            .collect();
+
+        Ok(FileDeadLetterSource {
+            path: path.as_ref().to_owned(),
+            letters: Mutex::new(letters),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResult {
+    /// Id of the replayed dead letter.
+    pub id: String,
+
+    /// Job id returned by the index writer client, if the job was resubmitted.
+    pub job_id: Option<String>,
+
+    pub error: Option<String>,
+}
+
+/// Resubmits dead letters through the index writer client and removes them from the source.
+///
+/// When `ids` is provided only the matching dead letters are replayed. Dead letters that cannot be
+/// parsed or resubmitted are left in the source.
+pub async fn replay(
+    source: &dyn DeadLetterSource,
+    client: &dyn IndexWriterClient,
+    max: usize,
+    ids: Option<&[String]>,
+) -> Result<Vec<ReplayResult>, ServiceError> {
+    let letters = source.receive(max).await?;
+
+    let mut results = vec![];
+
+    for letter in letters {
+        if let Some(ids) = ids {
+            if !ids.contains(&letter.id) {
+                continue;
+            }
+        }
+
+        let job = match letter.job() {
+            Ok(job) => job,
+            Err(err) => {
+                results.push(ReplayResult {
+                    id: letter.id,
+                    job_id: None,
+                    error: Some(format!("Unable to parse job: {err}")),
+                });
+                continue;
+            }
+        };
+
+        match client.submit_job(job).await {
+            Ok(job_id) => {
+                source.remove(&letter).await?;
+                results.push(ReplayResult {
+                    id: letter.id,
+                    job_id: Some(job_id),
+                    error: None,
+                });
+            }
+            Err(err) => results.push(ReplayResult {
+                id: letter.id,
+                job_id: None,
+                error: Some(err.to_string()),
+            }),
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexLoader;
+    use crate::schema::SchemaLoader;
+    use crate::search_doc::SearchDoc;
+    use crate::store::document::DocumentStore;
+    use crate::test_utils::*;
+    use crate::util;
+
+    async fn dead_letter_file(ctx: &TestContext, lines: Vec<String>) -> PathBuf {
+        let schema = ctx.schema_loader().load_schema("test").await.unwrap();
+
+        let doc = SearchDoc::from_json(&schema, json!({ "__id": "a", "title": "hello" })).unwrap();
+
+        let doc_refs = ctx
+            .document_store()
+            .save_documents("test", vec![doc])
+            .await
+            .unwrap();
+
+        let mut job = Job::create("test");
+        for doc_ref in doc_refs {
+            job.index_doc(doc_ref);
+        }
+
+        let mut content = vec![json::to_string(&job).unwrap()];
+        content.extend(lines);
+
+        let path = std::env::temp_dir().join(format!("pathery-dlq-{}", util::generate_id()));
+        fs::write(&path, content.join("\n")).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn replay_resubmits_jobs_and_removes_them() {
+        let ctx = setup();
+
+        let path = dead_letter_file(&ctx, vec!["not a job".into()]).await;
+
+        let source = FileDeadLetterSource::open(&path).unwrap();
+
+        let results = replay(&source, ctx.writer_client(), 10, None)
+            .await
+            .unwrap();
+
+        assert_eq!(2, results.len());
+        assert!(results[0].job_id.is_some());
+        assert!(results[1].error.is_some());
+
+        let num_docs = ctx
+            .index_loader()
+            .load_index("test", None)
+            .await
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs();
+
+        assert_eq!(1, num_docs);
+        assert_eq!("not a job\n", fs::read_to_string(&path).unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replay_only_selected_ids() {
+        let ctx = setup();
+
+        let path = dead_letter_file(&ctx, vec!["not a job".into()]).await;
+
+        let source = FileDeadLetterSource::open(&path).unwrap();
+
+        let results = replay(&source, ctx.writer_client(), 10, Some(&["2".into()]))
+            .await
+            .unwrap();
+
+        assert_eq!(1, results.len());
+        assert_eq!("2", results[0].id);
+        assert_eq!(2, source.receive(10).await.unwrap().len());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,19 +1,33 @@
 use serde::{Deserialize, Serialize};
 
+use crate::auth::Tenant;
 use crate::search_doc::SearchDocId;
 use crate::store::document::SearchDocRef;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub enum IndexWriterOp {
-    IndexDoc { doc_ref: SearchDocRef },
+    IndexDoc {
+        doc_ref: SearchDocRef,
+    },
 
-    DeleteDoc { doc_id: SearchDocId },
+    DeleteDoc {
+        doc_id: SearchDocId,
+    },
+
+    /// Rebuilds the index with its latest schema from the document store. Must be the only op of
+    /// its job.
+    Reindex,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct Job {
     pub index_id: String,
     pub ops: Vec<IndexWriterOp>,
+
+    /// Tenant that submitted the job, whose usage includes the size of the index once it is
+    /// committed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
 }
 
 impl Job {
@@ -21,9 +35,31 @@ impl Job {
         Job {
             index_id: index_id.into(),
             ops: vec![],
+            tenant_id: None,
+        }
+    }
+
+    /// Creates a job that rebuilds the index with its latest schema.
+    pub fn reindex(index_id: &str) -> Job {
+        Job {
+            index_id: index_id.into(),
+            ops: vec![IndexWriterOp::Reindex],
+            tenant_id: None,
         }
     }
 
+    /// Sets the tenant that submits the job, if the request was made by one.
+    pub fn with_tenant(self, tenant: Option<&Tenant>) -> Job {
+        Job {
+            tenant_id: tenant.map(|tenant| tenant.tenant_id.clone()),
+            ..self
+        }
+    }
+
+    pub fn is_reindex(&self) -> bool {
+        self.ops.contains(&IndexWriterOp::Reindex)
+    }
+
     pub fn index_doc(&mut self, doc_ref: SearchDocRef) {
         self.ops.push(IndexWriterOp::IndexDoc { doc_ref })
     }
@@ -31,4 +67,105 @@ impl Job {
     pub fn delete_doc(&mut self, doc_id: SearchDocId) {
         self.ops.push(IndexWriterOp::DeleteDoc { doc_id })
     }
+
+    /// Splits the job into consecutive jobs whose serialized size does not exceed `max_bytes`.
+    ///
+    /// Op ordering is preserved across the returned jobs. An op that does not fit into `max_bytes`
+    /// on its own is placed in a job by itself.
+    pub fn split(self, max_bytes: usize) -> Vec<Job> {
+        let Job {
+            index_id,
+            ops,
+            tenant_id,
+        } = self;
+
+        let empty = || Job {
+            index_id: index_id.clone(),
+            ops: vec![],
+            tenant_id: tenant_id.clone(),
+        };
+
+        let base_size = serde_json::to_vec(&empty())
+            .expect("job should serialize")
+            .len();
+
+        let mut jobs = vec![];
+        let mut current = empty();
+        let mut current_size = base_size;
+
+        for op in ops {
+            // Each op after the first is preceded by a comma separator.
+            let op_size = serde_json::to_vec(&op).expect("op should serialize").len() + 1;
+
+            if !current.ops.is_empty() && current_size + op_size > max_bytes {
+                jobs.push(current);
+                current = empty();
+                current_size = base_size;
+            }
+
+            current_size += op_size;
+            current.ops.push(op);
+        }
+
+        if !current.ops.is_empty() || jobs.is_empty() {
+            jobs.push(current);
+        }
+
+        jobs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job_with_deletes(n: usize) -> Job {
+        let mut job = Job::create("test");
+        for i in 0..n {
+            job.delete_doc(SearchDocId::parse(&format!("doc-{i:04}")));
+        }
+        job
+    }
+
+    #[test]
+    fn split_small_job_is_unchanged() {
+        let jobs = job_with_deletes(3).split(1024);
+
+        assert_eq!(vec![job_with_deletes(3)], jobs);
+    }
+
+    #[test]
+    fn split_empty_job_returns_single_job() {
+        let jobs = Job::create("test").split(1024);
+
+        assert_eq!(vec![Job::create("test")], jobs);
+    }
+
+    #[test]
+    fn split_respects_max_bytes_and_preserves_order() {
+        let max_bytes = 256;
+
+        let jobs = job_with_deletes(50).split(max_bytes);
+
+        assert!(jobs.len() > 1);
+
+        for job in &jobs {
+            assert_eq!("test", job.index_id);
+            assert!(serde_json::to_vec(job).unwrap().len() <= max_bytes);
+        }
+
+        let ops: Vec<_> = jobs.into_iter().flat_map(|job| job.ops).collect();
+
+        assert_eq!(job_with_deletes(50).ops, ops);
+    }
+
+    #[test]
+    fn split_keeps_tenant() {
+        let mut job = job_with_deletes(50);
+        job.tenant_id = Some("acme".into());
+
+        for job in job.split(256) {
+            assert_eq!(Some("acme"), job.tenant_id.as_deref());
+        }
+    }
 }
@@ -1,17 +1,28 @@
 pub mod client;
+pub mod dead_letter;
 pub mod job;
+pub mod reindex;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
+use aws_lambda_events::sqs::SqsMessage;
 use serde_json as json;
 use tantivy::{Document, IndexWriter, Term};
-use tracing::info;
+use tracing::{error, info, warn};
 
 use self::job::{IndexWriterOp, Job};
+use self::reindex::reindex;
 use crate::index::{IndexExt, IndexLoader};
 use crate::lambda::{self, sqs};
+use crate::quota::record_index_usage;
+use crate::search_doc::{to_document, SearchDoc, SearchDocId};
+use crate::service::ServiceError;
 use crate::store::document::{DocumentStore, SearchDocRef};
 
+/// Number of attempts made to fetch the documents of a job before the job is failed.
+const GET_DOCUMENTS_MAX_ATTEMPTS: u32 = 3;
+
 fn delete_doc(writer: &IndexWriter, doc_id: &str) {
     let index = writer.index();
     let id_field = index.id_field();
@@ -20,7 +31,7 @@ fn delete_doc(writer: &IndexWriter, doc_id: &str) {
     tracing::info!(message = "doc_deleted", doc_id);
 }
 
-fn index_doc(writer: &IndexWriter, doc: Document) {
+fn index_doc(writer: &IndexWriter, doc: Document) -> Result<(), ServiceError> {
     let index = writer.index();
     let id_field = index.id_field();
     let doc_id = doc
@@ -32,75 +43,302 @@ fn index_doc(writer: &IndexWriter, doc: Document) {
     delete_doc(writer, &doc_id);
     writer
         .add_document(doc)
-        .expect("Adding a document should not error");
+        .map_err(ServiceError::internal_error)?;
     tracing::info!(message = "doc_indexed", doc_id);
+
+    Ok(())
+}
+
+/// Fetches documents from the store, retrying transient failures with exponential backoff.
+async fn get_documents(
+    document_store: &dyn DocumentStore,
+    index_id: &str,
+    doc_refs: Vec<SearchDocRef>,
+) -> Result<Vec<SearchDoc>, ServiceError> {
+    let mut attempt = 1;
+
+    loop {
+        match document_store
+            .get_documents(index_id, doc_refs.clone())
+            .await
+        {
+            Ok(docs) => return Ok(docs),
+            Err(err) if err.is_retryable() && attempt < GET_DOCUMENTS_MAX_ATTEMPTS => {
+                warn!(message = "get_documents_retry", attempt, error = %err);
+                tokio::time::sleep(Duration::from_millis(100 * 2u64.pow(attempt))).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
 }
 
-pub async fn handle_job(writer: &mut IndexWriter, document_store: &dyn DocumentStore, job: Job) {
+/// Applies the ops of a job to the writer in order.
+///
+/// Documents are fetched before any op is applied so that a failure to fetch leaves the writer
+/// untouched. Reindex jobs replace the index of the writer and cannot be applied with it, see
+/// [`run_job`].
+pub async fn handle_job(
+    writer: &mut IndexWriter,
+    document_store: &dyn DocumentStore,
+    job: Job,
+) -> Result<(), ServiceError> {
+    if job.is_reindex() {
+        return Err(ServiceError::invalid_request(
+            "Reindex jobs cannot be applied to an index writer",
+        ));
+    }
+
     let schema = writer.index().schema();
 
-    let mut doc_refs: Vec<SearchDocRef> = vec![];
+    let doc_refs: Vec<SearchDocRef> = job
+        .ops
+        .iter()
+        .filter_map(|op| match op {
+            IndexWriterOp::IndexDoc { doc_ref } => Some(doc_ref.clone()),
+            IndexWriterOp::DeleteDoc { .. } | IndexWriterOp::Reindex => None,
+        })
+        .collect();
+
+    let mut docs: HashMap<SearchDocId, SearchDoc> = if doc_refs.is_empty() {
+        HashMap::new()
+    } else {
+        get_documents(document_store, &job.index_id, doc_refs)
+            .await?
+            .into_iter()
+            .map(|doc| (doc.id().clone(), doc))
+            .collect()
+    };
+
+    // Every document is built before the writer is touched, so that a job that fails is not
+    // partially applied and then committed along with the other jobs of the batch.
+    let mut ops = vec![];
 
     for op in job.ops {
         match op {
-            IndexWriterOp::IndexDoc { doc_ref } => doc_refs.push(doc_ref),
+            IndexWriterOp::IndexDoc { doc_ref } => match docs.remove(doc_ref.id()) {
+                Some(doc) => {
+                    let document = to_document(&schema, doc.content().clone())
+                        .map_err(|err| ServiceError::invalid_request(&err.to_string()))?;
+
+                    ops.push(WriterOp::Index(document));
+                }
+                None => warn!(message = "doc_not_found", doc_id = doc_ref.id().id()),
+            },
 
-            IndexWriterOp::DeleteDoc { doc_id } => delete_doc(writer, doc_id.id()),
+            IndexWriterOp::DeleteDoc { doc_id } => ops.push(WriterOp::Delete(doc_id)),
+
+            IndexWriterOp::Reindex => unreachable!("reindex jobs are rejected above"),
         }
     }
 
-    let docs = document_store.get_documents(doc_refs).await.unwrap();
+    for op in ops {
+        match op {
+            WriterOp::Index(document) => index_doc(writer, document)?,
+            WriterOp::Delete(doc_id) => delete_doc(writer, doc_id.id()),
+        }
+    }
 
-    for doc in docs {
-        let document = doc.document(&schema);
-        index_doc(writer, document);
+    Ok(())
+}
+
+/// An op of a job, ready to be applied to a writer.
+enum WriterOp {
+    Index(Document),
+    Delete(SearchDocId),
+}
+
+/// Applies a single job to its index and commits it.
+pub async fn run_job(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    job: Job,
+) -> Result<(), ServiceError> {
+    let tenant_ids: Vec<String> = job.tenant_id.iter().cloned().collect();
+
+    if job.is_reindex() {
+        reindex(document_store, index_loader, &job.index_id, &tenant_ids).await?;
+        return Ok(());
     }
+
+    let index_id = job.index_id.clone();
+    let index = index_loader.load_index(&index_id, None).await?;
+
+    let mut writer = index.default_writer();
+
+    handle_job(&mut writer, document_store, job).await?;
+
+    writer.commit().map_err(ServiceError::internal_error)?;
+
+    record_index_usage(document_store, &index_id, &index, &tenant_ids).await;
+
+    Ok(())
 }
 
+fn parse_job(message: &SqsMessage) -> Option<Job> {
+    let message_id = message.message_id.as_deref().unwrap_or_default();
+
+    let Some(body) = message.body.as_ref() else {
+        error!(
+            message = "job_malformed",
+            message_id,
+            error = "missing body"
+        );
+        return None;
+    };
+
+    match json::from_str::<Job>(body) {
+        Ok(job) => Some(job),
+        Err(err) => {
+            error!(message = "job_malformed", message_id, error = %err);
+            None
+        }
+    }
+}
+
+/// Applies a batch of jobs and reports the messages that could not be committed.
+///
+/// Malformed messages are logged and dropped since retrying them cannot succeed. Once a message
+/// for an index fails, later messages for the same index are reported as failed without being
+/// processed so that the FIFO ordering of the queue is preserved.
 pub async fn handle_event(
     document_store: &dyn DocumentStore,
     index_loader: &dyn IndexLoader,
     event: sqs::SqsEvent,
-) -> Result<(), lambda::Error> {
+) -> Result<sqs::SqsEventResponse, lambda::Error> {
     let records = event.payload.records;
 
-    let jobs = records
-        .iter()
-        .map(|message| message.body.as_ref().expect("Body should be present"))
-        .map(|body| {
-            let msg =
-                json::from_str::<Job>(body.as_str()).expect("Message should be deserializable");
-            msg
-        })
-        .collect::<Vec<_>>();
-
     let mut writers: HashMap<String, IndexWriter> = HashMap::new();
 
-    for job in jobs {
-        let index_id = &job.index_id;
-        let mut writer = writers.entry(index_id.to_string()).or_insert_with(|| {
-            index_loader
-                .load_index(&index_id, None)
-                .unwrap()
-                .default_writer()
-        });
+    // Message ids applied to a writer but not yet committed, by index id.
+    let mut pending: HashMap<String, Vec<String>> = HashMap::new();
+
+    // Tenants that submitted the jobs applied to a writer, by index id.
+    let mut tenants: HashMap<String, Vec<String>> = HashMap::new();
+
+    let mut failed_indexes: HashSet<String> = HashSet::new();
+
+    let mut failed: Vec<String> = vec![];
+
+    for message in &records {
+        let message_id = message.message_id.clone().unwrap_or_default();
+
+        let Some(job) = parse_job(message) else {
+            continue;
+        };
+
+        let index_id = job.index_id.clone();
+
+        if failed_indexes.contains(&index_id) {
+            failed.push(message_id);
+            continue;
+        }
+
+        let tenant_id = job.tenant_id.clone();
+
+        if job.is_reindex() {
+            // Earlier jobs are committed first so that the reindex includes their documents.
+            let committed = match writers.remove(&index_id) {
+                Some(writer) => {
+                    let message_ids = pending.remove(&index_id).unwrap_or_default();
+                    commit(&index_id, writer, message_ids, &mut failed)
+                }
+                None => true,
+            };
+
+            // The reindex records the usage of the index, including for the tenants of the jobs
+            // committed before it.
+            let mut tenant_ids = tenants.remove(&index_id).unwrap_or_default();
+            tenant_ids.extend(tenant_id);
+
+            let result = if committed {
+                reindex(document_store, index_loader, &index_id, &tenant_ids).await
+            } else {
+                Err(ServiceError::internal_error(IndexCommitFailed))
+            };
+
+            if let Err(err) = result {
+                error!(message = "job_failed", message_id, index = index_id, error = %err);
+                failed_indexes.insert(index_id);
+                failed.push(message_id);
+            }
+
+            continue;
+        }
+
+        let writer = match writers.get_mut(&index_id) {
+            Some(writer) => Ok(writer),
+            None => index_loader.load_index(&index_id, None).await.map(|index| {
+                writers
+                    .entry(index_id.clone())
+                    .or_insert_with(|| index.default_writer())
+            }),
+        };
 
-        handle_job(&mut writer, document_store, job).await;
+        let result = match writer {
+            Ok(writer) => handle_job(writer, document_store, job).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {
+                tenants
+                    .entry(index_id.clone())
+                    .or_default()
+                    .extend(tenant_id);
+                pending.entry(index_id).or_default().push(message_id)
+            }
+            Err(err) => {
+                error!(message = "job_failed", message_id, index = index_id, error = %err);
+                failed_indexes.insert(index_id);
+                failed.push(message_id);
+            }
+        }
     }
 
-    for (index, mut writer) in writers.into_iter() {
-        writer.commit().expect("commit should succeed");
-        info!(message = "index_commit", index);
-        writer
-            .wait_merging_threads()
-            .expect("merge should finish without error");
+    for (index_id, writer) in writers.into_iter() {
+        let message_ids = pending.remove(&index_id).unwrap_or_default();
+        let index = writer.index().clone();
+
+        if commit(&index_id, writer, message_ids, &mut failed) {
+            let tenant_ids = tenants.remove(&index_id).unwrap_or_default();
+            record_index_usage(document_store, &index_id, &index, &tenant_ids).await;
+        }
     }
 
-    Ok(())
+    Ok(sqs::batch_item_failures(failed))
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("index commit failed")]
+struct IndexCommitFailed;
+
+/// Commits the writer, reporting the messages applied to it as failed if the commit fails.
+/// Returns whether the commit succeeded.
+fn commit(
+    index: &str,
+    mut writer: IndexWriter,
+    message_ids: Vec<String>,
+    failed: &mut Vec<String>,
+) -> bool {
+    if let Err(err) = writer.commit() {
+        error!(message = "index_commit_failed", index, error = %err);
+        failed.extend(message_ids);
+        return false;
+    }
+
+    info!(message = "index_commit", index);
+
+    if let Err(err) = writer.wait_merging_threads() {
+        error!(message = "index_merge_failed", index, error = %err);
+    }
+
+    true
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
 
     use aws_lambda_events::sqs::{self, SqsMessage};
     use lambda_http::Context;
@@ -108,46 +346,45 @@ mod tests {
 
     use super::job::Job;
     use super::{handle_event, *};
+    use crate::quota::TenantUsage;
     use crate::schema::SchemaLoader;
     use crate::search_doc::SearchDoc;
+    use crate::store::record::RecordStore;
     use crate::test_utils::*;
 
-    #[tokio::test]
-    async fn test_indexing() {
-        let ctx = setup();
-
-        let schema = ctx.schema_loader().load_schema("test").unwrap();
+    async fn save_job(ctx: &TestContext, index_id: &str, docs: Vec<json::Value>) -> Job {
+        let schema = ctx.schema_loader().load_schema("test").await.unwrap();
 
-        let mut job = Job::create("test");
-
-        let document = SearchDoc::from_json(
-            &schema,
-            json!({
-                "year": 1989
-            }),
-        )
-        .unwrap();
+        let documents = docs
+            .into_iter()
+            .map(|doc| SearchDoc::from_json(&schema, doc).unwrap())
+            .collect();
 
         let doc_refs = ctx
             .document_store()
-            .save_documents(vec![document])
+            .save_documents(index_id, documents)
             .await
             .unwrap();
 
+        let mut job = Job::create(index_id);
         for doc_ref in doc_refs {
             job.index_doc(doc_ref);
         }
+        job
+    }
 
-        let message = SqsMessage {
-            body: Some(json::to_string(&job).unwrap()),
+    fn message(message_id: &str, body: &str) -> SqsMessage {
+        SqsMessage {
+            message_id: Some(message_id.into()),
+            body: Some(body.into()),
             ..Default::default()
-        };
+        }
+    }
 
-        let event = sqs::SqsEvent {
-            records: vec![message],
-        };
+    async fn send(ctx: &TestContext, records: Vec<SqsMessage>) -> Vec<String> {
+        let event = sqs::SqsEvent { records };
 
-        handle_event(
+        let response = handle_event(
             ctx.document_store(),
             ctx.index_loader(),
             LambdaEvent::new(event, Context::default()),
@@ -155,15 +392,164 @@ mod tests {
         .await
         .unwrap();
 
-        assert_eq!(
-            1,
-            ctx.index_loader()
-                .load_index("test", None)
-                .unwrap()
-                .reader()
+        response
+            .batch_item_failures
+            .into_iter()
+            .filter_map(|failure| failure.item_identifier)
+            .collect()
+    }
+
+    async fn num_docs(ctx: &TestContext, index_id: &str) -> u64 {
+        ctx.index_loader()
+            .load_index(index_id, None)
+            .await
+            .unwrap()
+            .reader()
+            .unwrap()
+            .searcher()
+            .num_docs()
+    }
+
+    #[tokio::test]
+    async fn test_indexing() {
+        let ctx = setup();
+
+        let job = save_job(&ctx, "test", vec![json!({ "year": 1989 })]).await;
+
+        let failures = send(&ctx, vec![message("m1", &json::to_string(&job).unwrap())]).await;
+
+        assert_eq!(Vec::<String>::new(), failures);
+        assert_eq!(1, num_docs(&ctx, "test").await);
+    }
+
+    #[tokio::test]
+    async fn ops_are_applied_in_order() {
+        let ctx = setup();
+
+        let mut job = save_job(&ctx, "test", vec![json!({ "__id": "a", "year": 1989 })]).await;
+        job.delete_doc(SearchDocId::parse("a"));
+
+        let failures = send(&ctx, vec![message("m1", &json::to_string(&job).unwrap())]).await;
+
+        assert_eq!(Vec::<String>::new(), failures);
+        assert_eq!(0, num_docs(&ctx, "test").await);
+    }
+
+    #[tokio::test]
+    async fn reindex_keeps_writes_in_order() {
+        let ctx = setup();
+
+        let before = save_job(&ctx, "test", vec![json!({ "year": 1989 })]).await;
+        let after = save_job(&ctx, "test", vec![json!({ "year": 1990 })]).await;
+
+        let failures = send(
+            &ctx,
+            vec![
+                message("m1", &json::to_string(&before).unwrap()),
+                message("m2", &json::to_string(&Job::reindex("test")).unwrap()),
+                message("m3", &json::to_string(&after).unwrap()),
+            ],
+        )
+        .await;
+
+        assert_eq!(Vec::<String>::new(), failures);
+        assert_eq!(2, num_docs(&ctx, "test").await);
+    }
+
+    #[tokio::test]
+    async fn usage_is_recorded_for_tenants() {
+        let ctx = setup();
+
+        let mut job = save_job(&ctx, "test", vec![json!({ "year": 1989 })]).await;
+        job.tenant_id = Some("acme".into());
+
+        let failures = send(
+            &ctx,
+            vec![
+                message("m1", &json::to_string(&job).unwrap()),
+                message("m2", &json::to_string(&Job::reindex("test")).unwrap()),
+            ],
+        )
+        .await;
+
+        assert_eq!(Vec::<String>::new(), failures);
+
+        let usage: TenantUsage = RecordStore::new(Arc::new(ctx.document_store().clone()))
+            .get("usage|tenant|acme")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(usage.indexes["test"] > 0);
+    }
+
+    #[tokio::test]
+    async fn malformed_jobs_are_skipped() {
+        let ctx = setup();
+
+        let job = save_job(&ctx, "test", vec![json!({ "year": 1989 })]).await;
+
+        let failures = send(
+            &ctx,
+            vec![
+                message("m1", "not a job"),
+                message("m2", &json::to_string(&job).unwrap()),
+            ],
+        )
+        .await;
+
+        assert_eq!(Vec::<String>::new(), failures);
+        assert_eq!(1, num_docs(&ctx, "test").await);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_are_not_partially_applied() {
+        let ctx = setup();
+
+        let mut job = save_job(&ctx, "test", vec![json!({ "__id": "a", "year": 1989 })]).await;
+
+        // Stored documents no longer match a schema whose field changed kind.
+        let invalid = SearchDoc::from_parts(
+            SearchDocId::parse("b"),
+            json!({ "__id": "b", "year": "unknown" })
+                .as_object()
                 .unwrap()
-                .searcher()
-                .num_docs()
+                .clone(),
         );
+        let doc_refs = ctx
+            .document_store()
+            .save_documents("test", vec![invalid])
+            .await
+            .unwrap();
+        for doc_ref in doc_refs {
+            job.index_doc(doc_ref);
+        }
+
+        let failures = send(&ctx, vec![message("m1", &json::to_string(&job).unwrap())]).await;
+
+        assert_eq!(vec!["m1"], failures);
+        assert_eq!(0, num_docs(&ctx, "test").await);
+    }
+
+    #[tokio::test]
+    async fn failed_jobs_are_reported_with_later_jobs_for_the_same_index() {
+        let ctx = setup();
+
+        let unknown_job = save_job(&ctx, "unknown", vec![json!({ "year": 1989 })]).await;
+        let unknown_job = json::to_string(&unknown_job).unwrap();
+        let test_job = save_job(&ctx, "test", vec![json!({ "year": 1989 })]).await;
+
+        let failures = send(
+            &ctx,
+            vec![
+                message("m1", &unknown_job),
+                message("m2", &json::to_string(&test_job).unwrap()),
+                message("m3", &unknown_job),
+            ],
+        )
+        .await;
+
+        assert_eq!(vec!["m1", "m3"], failures);
+        assert_eq!(1, num_docs(&ctx, "test").await);
     }
 }
@@ -0,0 +1,134 @@
+use tracing::info;
+
+use super::get_documents;
+use crate::index::{IndexExt, IndexLoader, StagedIndex};
+use crate::quota::record_index_usage;
+use crate::search_doc::{to_document, SearchDocId};
+use crate::service::ServiceError;
+use crate::store::document::{DocumentStore, SearchDocRef};
+
+/// Number of documents fetched from the document store at a time while reindexing.
+const REINDEX_BATCH_SIZE: usize = 100;
+
+/// Replays documents from the document store into the staged index and returns the number of
+/// documents it contains.
+async fn fill_index(
+    document_store: &dyn DocumentStore,
+    staged: &StagedIndex,
+    index_id: &str,
+    doc_ids: Vec<SearchDocId>,
+) -> Result<u64, ServiceError> {
+    let index = staged.index();
+    let schema = index.schema();
+    let mut writer = index.default_writer();
+
+    for chunk in doc_ids.chunks(REINDEX_BATCH_SIZE) {
+        let doc_refs = chunk.iter().cloned().map(SearchDocRef::from).collect();
+
+        for doc in get_documents(document_store, index_id, doc_refs).await? {
+            // Documents were validated against the schema they were indexed with, which may not
+            // match the latest schema if the index config was edited.
+            let document = to_document(&schema, doc.content().clone())
+                .map_err(ServiceError::internal_error)?;
+
+            writer
+                .add_document(document)
+                .map_err(ServiceError::internal_error)?;
+        }
+    }
+
+    writer.commit().map_err(ServiceError::internal_error)?;
+    writer
+        .wait_merging_threads()
+        .map_err(ServiceError::internal_error)?;
+
+    Ok(index
+        .reader()
+        .map_err(ServiceError::internal_error)?
+        .searcher()
+        .num_docs())
+}
+
+/// Rebuilds an index with its latest schema and returns the number of documents it contains.
+///
+/// The documents of the current index are replayed from the document store into a staged index,
+/// which then replaces the current index. Queries keep using the current index until the staged
+/// index is promoted. The usage of the index is recorded for the tenants in `tenant_ids` as well.
+pub async fn reindex(
+    document_store: &dyn DocumentStore,
+    index_loader: &dyn IndexLoader,
+    index_id: &str,
+    tenant_ids: &[String],
+) -> Result<u64, ServiceError> {
+    if !index_loader.index_exists(index_id).await? {
+        return Err(ServiceError::not_found(&format!(
+            "Index [{index_id}] not found"
+        )));
+    }
+
+    let doc_ids = index_loader
+        .load_index(index_id, None)
+        .await?
+        .document_ids()?;
+
+    let staged = index_loader.stage_index(index_id).await?;
+
+    match fill_index(document_store, &staged, index_id, doc_ids).await {
+        Ok(num_docs) => {
+            let index = staged.index().clone();
+            index_loader.promote_index(staged).await?;
+            record_index_usage(document_store, index_id, &index, tenant_ids).await;
+            info!(message = "index_reindexed", index = index_id, num_docs);
+            Ok(num_docs)
+        }
+        Err(err) => {
+            index_loader.discard_index(staged).await?;
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[tokio::test]
+    async fn reindex_replays_documents() {
+        let ctx = setup()
+            .with_documents(
+                "test",
+                vec![
+                    json!({ "__id": "zen", "title": "Zen" }),
+                    json!({ "__id": "dune", "title": "Dune" }),
+                ],
+            )
+            .await;
+
+        let before = ctx.index_loader().load_index("test", None).await.unwrap();
+
+        let num_docs = reindex(ctx.document_store(), ctx.index_loader(), "test", &[])
+            .await
+            .unwrap();
+
+        let after = ctx.index_loader().load_index("test", None).await.unwrap();
+
+        assert_eq!(2, num_docs);
+        assert_eq!(2, after.reader().unwrap().searcher().num_docs());
+        assert_ne!(
+            before.searchable_segment_ids().unwrap(),
+            after.searchable_segment_ids().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reindex_missing_index() {
+        let ctx = setup();
+
+        let err = reindex(ctx.document_store(), ctx.index_loader(), "test", &[])
+            .await
+            .unwrap_err();
+
+        assert_eq!(404, err.status());
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt::Debug;
+use std::future::Future;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::service::ServiceError;
+use crate::util;
+
+enum Message<J> {
+    Job(J),
+
+    Flush(oneshot::Sender<()>),
+}
+
+/// In-process queue that hands jobs to a background task in submission order.
+///
+/// Used in place of SQS when pathery runs as a single process.
+pub struct LocalQueue<J> {
+    sender: mpsc::UnboundedSender<Message<J>>,
+}
+
+impl<J> Clone for LocalQueue<J> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<J> Debug for LocalQueue<J> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalQueue").finish()
+    }
+}
+
+impl<J> LocalQueue<J>
+where J: Send + 'static
+{
+    /// Spawns the background task that calls `handler` for each job. Must be called from within a
+    /// tokio runtime.
+    pub fn spawn<F, Fut>(handler: F) -> LocalQueue<J>
+    where
+        F: Fn(J) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message<J>>();
+
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    Message::Job(job) => handler(job).await,
+                    Message::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+
+        LocalQueue { sender }
+    }
+
+    /// Queues a job and returns its generated id.
+    pub fn send(&self, job: J) -> Result<String, ServiceError> {
+        self.sender
+            .send(Message::Job(job))
+            .map_err(|_| ServiceError::internal_error(LocalQueueClosed))?;
+
+        Ok(util::generate_id())
+    }
+
+    /// Waits until every job queued before the call has been handled.
+    pub async fn flush(&self) -> Result<(), ServiceError> {
+        let (done, wait) = oneshot::channel();
+
+        self.sender
+            .send(Message::Flush(done))
+            .map_err(|_| ServiceError::internal_error(LocalQueueClosed))?;
+
+        wait.await
+            .map_err(|_| ServiceError::internal_error(LocalQueueClosed))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("local queue worker has stopped")]
+struct LocalQueueClosed;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn jobs_are_handled_in_order() {
+        let handled = Arc::new(Mutex::new(vec![]));
+
+        let queue = {
+            let handled = Arc::clone(&handled);
+            LocalQueue::spawn(move |job: usize| {
+                let handled = Arc::clone(&handled);
+                async move { handled.lock().unwrap().push(job) }
+            })
+        };
+
+        for job in 0..5 {
+            queue.send(job).unwrap();
+        }
+
+        queue.flush().await.unwrap();
+
+        assert_eq!(vec![0, 1, 2, 3, 4], *handled.lock().unwrap());
+    }
+}
@@ -1,2 +1,17 @@
 pub mod async_delete;
 pub mod index_writer;
+pub mod local;
+
+use serde::{Deserialize, Serialize};
+
+/// Selects how jobs are handed to the workers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueKind {
+    /// Jobs are sent to the SQS queues consumed by the worker lambdas.
+    #[default]
+    Sqs,
+
+    /// Jobs are handled by background tasks in the current process.
+    Local,
+}
@@ -0,0 +1,273 @@
+//! Tests for `DDBDocumentStore` against a local DynamoDB-compatible endpoint such as DynamoDB
+//! Local.
+//!
+//! ```bash
+//! docker run -p 8000:8000 amazon/dynamodb-local
+//! cargo test --features ddb-local-tests --test ddb_document_store
+//! ```
+//!
+//! The endpoint defaults to `http://localhost:8000` and can be changed with `DDB_LOCAL_ENDPOINT`.
+#![cfg(feature = "ddb-local-tests")]
+
+use aws_sdk_dynamodb as ddb;
+use ddb::model::{
+    AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType,
+    ScalarAttributeType,
+};
+use pathery::config::Config;
+use pathery::search_doc::{SearchDoc, SearchDocId};
+use pathery::store::document::{DDBDocumentStore, DocumentStore, SearchDocRef};
+use pathery::util;
+use serde_json::json;
+use tantivy::schema::{self, Schema};
+
+struct TestTable {
+    name: String,
+    client: ddb::Client,
+    store: DDBDocumentStore,
+}
+
+fn endpoint() -> String {
+    std::env::var("DDB_LOCAL_ENDPOINT").unwrap_or_else(|_| "http://localhost:8000".into())
+}
+
+async fn setup() -> TestTable {
+    // DynamoDB Local accepts any credentials but the SDK still requires them to be set.
+    for (var, value) in [
+        ("AWS_ACCESS_KEY_ID", "local"),
+        ("AWS_SECRET_ACCESS_KEY", "local"),
+        ("AWS_REGION", "us-east-1"),
+    ] {
+        if std::env::var(var).is_err() {
+            std::env::set_var(var, value);
+        }
+    }
+
+    let name = format!("pathery-test-{}", util::generate_id());
+
+    let sdk_config = aws_config::load_from_env().await;
+    let config = ddb::config::Builder::from(&sdk_config)
+        .endpoint_resolver(ddb::Endpoint::immutable(endpoint().parse().unwrap()))
+        .build();
+    let client = ddb::Client::from_conf(config);
+
+    client
+        .create_table()
+        .table_name(&name)
+        .billing_mode(BillingMode::PayPerRequest)
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name("pk")
+                .attribute_type(ScalarAttributeType::S)
+                .build(),
+        )
+        .attribute_definitions(
+            AttributeDefinition::builder()
+                .attribute_name("sk")
+                .attribute_type(ScalarAttributeType::S)
+                .build(),
+        )
+        .key_schema(
+            KeySchemaElement::builder()
+                .attribute_name("pk")
+                .key_type(KeyType::Hash)
+                .build(),
+        )
+        .key_schema(
+            KeySchemaElement::builder()
+                .attribute_name("sk")
+                .key_type(KeyType::Range)
+                .build(),
+        )
+        .send()
+        .await
+        .expect("table should be created");
+
+    let config = Config::builder()
+        .data_table_name(&name)
+        .data_table_endpoint_url(&endpoint())
+        .build()
+        .unwrap();
+    let store = DDBDocumentStore::create(&config).await.unwrap();
+
+    TestTable {
+        name,
+        client,
+        store,
+    }
+}
+
+impl TestTable {
+    async fn teardown(self) {
+        self.client
+            .delete_table()
+            .table_name(&self.name)
+            .send()
+            .await
+            .expect("table should be deleted");
+    }
+}
+
+const INDEX: &str = "test";
+
+fn doc(id: &str, title: &str) -> SearchDoc {
+    let mut schema = Schema::builder();
+    schema.add_text_field("__id", schema::STRING);
+    schema.add_text_field("title", schema::TEXT);
+    let schema = schema.build();
+
+    SearchDoc::from_json(&schema, json!({ "__id": id, "title": title })).unwrap()
+}
+
+fn doc_ref(id: &str) -> SearchDocRef {
+    SearchDocRef::from(SearchDocId::parse(id))
+}
+
+#[tokio::test]
+async fn round_trip_compressed_content() {
+    let table = setup().await;
+
+    let title = "Zen and the Art of Motorcycle Maintenance ".repeat(100);
+
+    table
+        .store
+        .save_documents(INDEX, vec![doc("zen", &title)])
+        .await
+        .unwrap();
+
+    let docs = table
+        .store
+        .get_documents(INDEX, vec![doc_ref("zen")])
+        .await
+        .unwrap();
+
+    assert_eq!(1, docs.len());
+    assert_eq!("zen", docs[0].id().id());
+    assert_eq!(json!(title), docs[0].content()["title"]);
+
+    table.teardown().await;
+}
+
+#[tokio::test]
+async fn content_is_stored_compressed() {
+    let table = setup().await;
+
+    table
+        .store
+        .save_documents(INDEX, vec![doc("zen", "Zen")])
+        .await
+        .unwrap();
+
+    let items = table
+        .client
+        .scan()
+        .table_name(&table.name)
+        .send()
+        .await
+        .unwrap();
+    let items = items.items().unwrap_or_default();
+
+    assert_eq!(1, items.len());
+    // Content is stored as zstd compressed binary rather than a JSON map.
+    assert!(matches!(
+        items[0].get("content"),
+        Some(AttributeValue::B(_))
+    ));
+
+    table.teardown().await;
+}
+
+#[tokio::test]
+async fn insert_conflicts_with_existing_document() {
+    let table = setup().await;
+
+    table
+        .store
+        .insert_document(INDEX, doc("zen", "Zen"))
+        .await
+        .unwrap();
+
+    let err = table
+        .store
+        .insert_document(INDEX, doc("zen", "Other"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(409, err.status());
+
+    table.teardown().await;
+}
+
+#[tokio::test]
+async fn update_conflicts_with_changed_document() {
+    let table = setup().await;
+
+    table
+        .store
+        .update_document(INDEX, doc("zen", "Zen"), "title", None)
+        .await
+        .unwrap();
+
+    let err = table
+        .store
+        .update_document(INDEX, doc("zen", "Other"), "title", None)
+        .await
+        .unwrap_err();
+
+    assert_eq!(409, err.status());
+
+    table
+        .store
+        .update_document(INDEX, doc("zen", "Tao"), "title", Some(&json!("Zen")))
+        .await
+        .unwrap();
+
+    let err = table
+        .store
+        .update_document(INDEX, doc("zen", "Other"), "title", Some(&json!("Zen")))
+        .await
+        .unwrap_err();
+
+    assert_eq!(409, err.status());
+
+    table.teardown().await;
+}
+
+#[tokio::test]
+async fn batches_are_chunked() {
+    let table = setup().await;
+
+    // More than the BatchWriteItem (25) and BatchGetItem (100) limits.
+    let ids: Vec<String> = (0..130).map(|n| format!("doc-{n}")).collect();
+
+    table
+        .store
+        .save_documents(INDEX, ids.iter().map(|id| doc(id, "title")).collect())
+        .await
+        .unwrap();
+
+    let mut refs: Vec<_> = ids.iter().map(|id| doc_ref(id)).collect();
+    // Duplicate and missing keys are tolerated.
+    refs.push(doc_ref("doc-0"));
+    refs.push(doc_ref("missing"));
+
+    let docs = table.store.get_documents(INDEX, refs).await.unwrap();
+
+    assert_eq!(130, docs.len());
+
+    table
+        .store
+        .delete_documents(INDEX, ids.iter().map(|id| SearchDocId::parse(id)).collect())
+        .await
+        .unwrap();
+
+    let docs = table
+        .store
+        .get_documents(INDEX, ids.iter().map(|id| doc_ref(id)).collect())
+        .await
+        .unwrap();
+
+    assert!(docs.is_empty());
+
+    table.teardown().await;
+}